@@ -1,3 +1,129 @@
+// Every command is listed here so Tauri generates `allow-<command>` permissions for it.
+// Commands missing from the list cannot be granted, and a command is only callable from
+// windows whose capability includes it (see permissions/ and capabilities/).
+const COMMANDS: &[&str] = &[
+  "process_text",
+  "list_printers",
+  "list_all_printers",
+  "run_discovery_now",
+  "load_fingerprint_rules",
+  "update_oui_registry",
+  "snmp_get",
+  "snmp_walk",
+  "snmp_bulkwalk",
+  "snmp_set",
+  "get_inventory",
+  "update_inventory_entry",
+  "remove_inventory_entry",
+  "get_pools",
+  "save_pool",
+  "remove_pool",
+  "rank_pool",
+  "export_inventory",
+  "import_inventory",
+  "collect_page_counters",
+  "get_usage_report",
+  "export_usage_report",
+  "get_supply_forecast",
+  "get_alert_rules",
+  "save_alert_rules",
+  "get_alert_history",
+  "test_alert_action",
+  "get_schedules",
+  "save_schedules",
+  "get_change_reports",
+  "get_change_report",
+  "export_change_report",
+  "get_settings",
+  "update_settings",
+  "get_logs",
+  "get_vault_status",
+  "unlock_vault",
+  "lock_vault",
+  "store_credential",
+  "list_credentials",
+  "remove_credential",
+  "find_credential",
+  "print_file",
+  "get_print_jobs",
+  "print_receipt",
+  "get_receipt_printer_status",
+  "print_label",
+  "print_label_batch",
+  "get_label_printer_status",
+  "print_test_page",
+  "get_virtual_printer_status",
+  "get_captured_jobs",
+  "get_trust_store",
+  "trust_certificate",
+  "forget_certificate",
+  "import_trusted_ca",
+  "remove_trusted_ca",
+];
+
 fn main() {
-  tauri_build::build()
+  bundle_oui_registry();
+  tauri_build::try_build(
+    tauri_build::Attributes::new().app_manifest(tauri_build::AppManifest::new().commands(COMMANDS)),
+  )
+  .expect("failed to run tauri-build");
+}
+
+// The OUI registry compiled into the app: the file `OUI_REGISTRY` points at (a copy of the
+// IEEE MA-L file, plain or gzipped), or else the current file downloaded from the IEEE.
+// Builds without network access fall back to the printer-vendor snapshot in data/, which
+// only names the printer manufacturers' own assignments.
+fn bundle_oui_registry() {
+  println!("cargo:rerun-if-env-changed=OUI_REGISTRY");
+  println!("cargo:rerun-if-changed=data/oui.csv.gz");
+  let target = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("oui.csv.gz");
+
+  let (data, source) = match std::env::var("OUI_REGISTRY") {
+    Ok(source) => {
+      println!("cargo:rerun-if-changed={}", source);
+      let data = std::fs::read(&source).unwrap_or_else(|e| panic!("failed to read OUI_REGISTRY {}: {}", source, e));
+      (data, source)
+    }
+    Err(_) => match download_oui_registry() {
+      Ok(data) => (data, OUI_REGISTRY_URL.to_string()),
+      Err(e) => {
+        println!("cargo:warning=bundling the printer-vendor OUI snapshot only: {}", e);
+        std::fs::copy("data/oui.csv.gz", &target).expect("failed to copy data/oui.csv.gz");
+        return;
+      }
+    },
+  };
+  if data.starts_with(&[0x1f, 0x8b]) {
+    std::fs::write(&target, data).expect("failed to write oui.csv.gz");
+  } else {
+    assert!(data.starts_with(b"Registry,"), "{} is not an IEEE oui.csv file", source);
+    std::fs::write(&target, gzip(&data)).expect("failed to write oui.csv.gz");
+  }
+}
+
+const OUI_REGISTRY_URL: &str = "https://standards-oui.ieee.org/oui/oui.csv";
+
+fn download_oui_registry() -> Result<Vec<u8>, String> {
+  let client = reqwest::blocking::Client::builder()
+    .timeout(std::time::Duration::from_secs(120))
+    .build()
+    .map_err(|e| e.to_string())?;
+  let data = client
+    .get(OUI_REGISTRY_URL)
+    .send()
+    .and_then(|response| response.error_for_status())
+    .and_then(|response| response.bytes())
+    .map_err(|e| format!("failed to download {}: {}", OUI_REGISTRY_URL, e))?;
+  if !data.starts_with(b"Registry,") {
+    return Err(format!("{} did not return an IEEE oui.csv file", OUI_REGISTRY_URL));
+  }
+  Ok(data.to_vec())
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+  use std::io::Write;
+
+  let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+  encoder.write_all(data).expect("failed to compress OUI registry");
+  encoder.finish().expect("failed to compress OUI registry")
 }
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::str::FromStr;
+
+/// Reserved domain (RFC 2606) for the names [`HostAddr::request_host`] gives scoped
+/// link-local addresses, so they never reach a real resolver.
+pub const LINK_LOCAL_DOMAIN: &str = "link-local.invalid";
+
+/// A printer's IP address, including the IPv6 zone (scope id) needed to reach
+/// link-local addresses such as `fe80::1%eth0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HostAddr {
+    pub ip: IpAddr,
+    pub scope_id: u32,
+}
+
+impl HostAddr {
+    /// Parses `192.168.1.10`, `2001:db8::10`, `fe80::1%3`, `fe80::1%eth0` and the bracketed
+    /// forms used in URLs (`[fe80::1%253]`).
+    pub fn parse(input: &str) -> Option<HostAddr> {
+        let input = input.trim();
+        // Inside brackets the zone separator is percent-encoded (`%25`)
+        let (input, separator) = match input.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            Some(inner) => (inner, "%25"),
+            None => (input, "%"),
+        };
+
+        if let Ok(ip) = Ipv4Addr::from_str(input) {
+            return Some(HostAddr::from(ip));
+        }
+
+        let (address, zone) = match input.split_once(separator) {
+            Some((address, zone)) => (address, Some(zone)),
+            None => (input, None),
+        };
+        let ip = Ipv6Addr::from_str(address).ok()?;
+        let scope_id = match zone {
+            Some(zone) => zone_to_scope_id(zone)?,
+            None => 0,
+        };
+
+        Some(HostAddr { ip: IpAddr::V6(ip), scope_id })
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        self.ip.is_ipv6()
+    }
+
+    pub fn socket_addr(&self, port: u16) -> SocketAddr {
+        match self.ip {
+            IpAddr::V4(ip) => SocketAddr::from((ip, port)),
+            IpAddr::V6(ip) => SocketAddr::V6(SocketAddrV6::new(ip, port, 0, self.scope_id)),
+        }
+    }
+
+    /// The zone as written after `%`: the interface name where the OS has one for the
+    /// scope id, the number otherwise.
+    fn zone(&self) -> String {
+        scope_id_to_zone(self.scope_id).unwrap_or_else(|| self.scope_id.to_string())
+    }
+
+    /// Host part of a URL: IPv6 addresses are bracketed and the zone separator is
+    /// percent-encoded as RFC 6874 requires (`[fe80::1%25eth0]`).
+    pub fn url_host(&self) -> String {
+        match self.ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) if self.scope_id != 0 => format!("[{}%25{}]", ip, self.zone()),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        }
+    }
+
+    /// Host part of the URL handed to the HTTP client. Its URL parser knows no zones, so a
+    /// scoped link-local address becomes a name under [`LINK_LOCAL_DOMAIN`] that
+    /// `trust::http_client` resolves back to it; other addresses are their `url_host`.
+    pub fn request_host(&self) -> String {
+        match self.ip {
+            IpAddr::V6(ip) if self.scope_id != 0 => {
+                // All eight groups, as a DNS label cannot start with the `-` of a leading `::`
+                let groups: Vec<String> = ip.segments().iter().map(|group| format!("{:x}", group)).collect();
+                format!("{}s{}.{}", groups.join("-"), self.scope_id, LINK_LOCAL_DOMAIN)
+            }
+            _ => self.url_host(),
+        }
+    }
+
+    /// The address behind a [`HostAddr::request_host`] name, e.g. for the TLS server name.
+    pub fn from_request_host(name: &str) -> Option<HostAddr> {
+        let (address, scope_id) = name.strip_suffix(LINK_LOCAL_DOMAIN)?.strip_suffix('.')?.rsplit_once('s')?;
+        let ip = Ipv6Addr::from_str(&address.replace('-', ":")).ok()?;
+        Some(HostAddr { ip: IpAddr::V6(ip), scope_id: scope_id.parse().ok()? })
+    }
+}
+
+impl From<Ipv4Addr> for HostAddr {
+    fn from(ip: Ipv4Addr) -> Self {
+        HostAddr { ip: IpAddr::V4(ip), scope_id: 0 }
+    }
+}
+
+impl From<IpAddr> for HostAddr {
+    fn from(ip: IpAddr) -> Self {
+        HostAddr { ip, scope_id: 0 }
+    }
+}
+
+impl From<SocketAddr> for HostAddr {
+    fn from(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(v4) => HostAddr::from(*v4.ip()),
+            SocketAddr::V6(v6) => HostAddr { ip: IpAddr::V6(*v6.ip()), scope_id: v6.scope_id() },
+        }
+    }
+}
+
+impl fmt::Display for HostAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            IpAddr::V6(ip) if self.scope_id != 0 => write!(f, "{}%{}", ip, self.zone()),
+            ip => write!(f, "{}", ip),
+        }
+    }
+}
+
+/// Largest range accepted for a scan: a /16.
+pub const MAX_RANGE_HOSTS: u32 = 65_536;
+
+/// Expands an IPv4 scan range: a CIDR block (`10.1.2.0/24`, network and broadcast
+/// addresses skipped), a dash range (`10.1.2.10-10.1.2.50` or `10.1.2.10-50`) or a single
+/// address.
+pub fn parse_ipv4_range(spec: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let spec = spec.trim();
+    let invalid = || format!("Invalid IPv4 range '{}'", spec);
+
+    let (start, end) = if let Some((network, prefix)) = spec.split_once('/') {
+        let network = u32::from(Ipv4Addr::from_str(network).map_err(|_| invalid())?);
+        let prefix: u32 = prefix.parse().map_err(|_| invalid())?;
+        if prefix > 32 {
+            return Err(invalid());
+        }
+        let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+        let first = network & mask;
+        let last = first | !mask;
+        if prefix >= 31 {
+            (first, last)
+        } else {
+            (first + 1, last - 1)
+        }
+    } else if let Some((first, last)) = spec.split_once('-') {
+        let first = Ipv4Addr::from_str(first.trim()).map_err(|_| invalid())?;
+        let last = match Ipv4Addr::from_str(last.trim()) {
+            Ok(last) => last,
+            // Short form: only the last octet is given
+            Err(_) => {
+                let octet: u8 = last.trim().parse().map_err(|_| invalid())?;
+                let [a, b, c, _] = first.octets();
+                Ipv4Addr::new(a, b, c, octet)
+            }
+        };
+        (u32::from(first), u32::from(last))
+    } else {
+        let ip = u32::from(Ipv4Addr::from_str(spec).map_err(|_| invalid())?);
+        (ip, ip)
+    };
+
+    if end < start {
+        return Err(format!("Range '{}' ends before it starts", spec));
+    }
+    if end - start >= MAX_RANGE_HOSTS {
+        return Err(format!("Range '{}' is larger than a /16", spec));
+    }
+    Ok((start..=end).map(Ipv4Addr::from).collect())
+}
+
+/// An IPv4 or IPv6 network in CIDR notation. A bare address is a single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    pub network: IpAddr,
+    pub prefix: u8,
+}
+
+impl Subnet {
+    pub fn parse(spec: &str) -> Result<Subnet, String> {
+        let spec = spec.trim();
+        let invalid = || format!("Invalid subnet '{}'", spec);
+        let (address, prefix) = match spec.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (spec, None),
+        };
+        let network = IpAddr::from_str(address).map_err(|_| invalid())?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(invalid());
+        }
+        Ok(Subnet { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (u128::from(u32::from(network)), u128::from(u32::from(ip)), 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let host_bits = bits - u32::from(self.prefix);
+        host_bits == bits || network >> host_bits == ip >> host_bits
+    }
+}
+
+/// Zones are numeric on Windows; on Unix they are usually interface names.
+fn zone_to_scope_id(zone: &str) -> Option<u32> {
+    if let Ok(index) = zone.parse::<u32>() {
+        return Some(index);
+    }
+
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone).ok()?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index != 0 {
+            return Some(index);
+        }
+    }
+
+    None
+}
+
+fn scope_id_to_zone(scope_id: u32) -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        let found = unsafe { libc::if_indextoname(scope_id, name.as_mut_ptr()) };
+        if !found.is_null() {
+            let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+            return name.to_str().ok().map(str::to_string);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = scope_id;
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_parse_with_and_without_zones() {
+        let v4 = HostAddr::parse(" 192.168.1.10 ").unwrap();
+        assert_eq!((v4.ip, v4.scope_id), (IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)), 0));
+        assert_eq!(v4.to_string(), "192.168.1.10");
+
+        let global = HostAddr::parse("2001:db8::10").unwrap();
+        assert_eq!(global.scope_id, 0);
+        assert_eq!(HostAddr::parse("[2001:db8::10]"), Some(global));
+
+        let scoped = HostAddr::parse("fe80::1%4242").unwrap();
+        assert_eq!(scoped.scope_id, 4242);
+        assert_eq!(HostAddr::parse("[fe80::1%254242]"), Some(scoped));
+        assert_eq!(scoped.socket_addr(631), "[fe80::1%4242]:631".parse().unwrap());
+
+        for invalid in ["", "printer.local", "300.1.1.1", "fe80::1%", "fe80::1%no-such-interface", "[fe80::1%4242]"] {
+            assert_eq!(HostAddr::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn addresses_format_for_display_and_urls() {
+        let v4 = HostAddr::parse("10.0.0.5").unwrap();
+        assert_eq!(v4.url_host(), "10.0.0.5");
+        assert_eq!(v4.request_host(), "10.0.0.5");
+        assert_eq!(HostAddr::parse("2001:db8::10").unwrap().url_host(), "[2001:db8::10]");
+
+        // No interface has this index, so the zone stays numeric
+        let scoped = HostAddr::parse("fe80::1%4242").unwrap();
+        assert_eq!(scoped.to_string(), "fe80::1%4242");
+        assert_eq!(scoped.url_host(), "[fe80::1%254242]");
+        assert_eq!(HostAddr::parse(&scoped.url_host()), Some(scoped));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn zones_use_interface_names() {
+        let scoped = HostAddr::parse("fe80::1%lo").unwrap();
+        assert_ne!(scoped.scope_id, 0);
+        assert_eq!(scoped.to_string(), "fe80::1%lo");
+        assert_eq!(scoped.url_host(), "[fe80::1%25lo]");
+        assert_eq!(HostAddr::parse("[fe80::1%25lo]"), Some(scoped));
+    }
+
+    #[test]
+    fn scoped_addresses_get_a_resolvable_request_name() {
+        let scoped = HostAddr::parse("fe80::a:1%4242").unwrap();
+        let name = scoped.request_host();
+        assert_eq!(name, "fe80-0-0-0-0-0-a-1s4242.link-local.invalid");
+        assert_eq!(HostAddr::from_request_host(&name), Some(scoped));
+        assert!(reqwest::Url::parse(&format!("http://{}:631/ipp/print", name)).is_ok());
+
+        assert_eq!(HostAddr::from_request_host("printer.example.com"), None);
+        assert_eq!(HostAddr::from_request_host("fe80--1.link-local.invalid"), None);
+    }
+}
@@ -0,0 +1,1082 @@
+use crate::inventory::{Inventory, InventoryEntry};
+use crate::monitor::MonitorSample;
+use crate::vault::{Credential, CredentialInput, CredentialKind, CredentialScope, CredentialVault};
+use chrono::{DateTime, Duration, Local, NaiveTime, Utc};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_notification::NotificationExt;
+use tracing::{info, warn};
+
+const ACTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const DEFAULT_REPEAT_MINUTES: u64 = 60;
+/// The history log is rotated to `<name>.1` once it grows past this size.
+const MAX_HISTORY_BYTES: u64 = 1024 * 1024;
+/// JSON array of the programs `Command` actions may run, kept by the administrator in the
+/// config dir next to the rules.
+pub const ALERT_COMMANDS_FILE: &str = "alert_commands.json";
+
+/// A condition to watch for, the printers it applies to and what to do when it holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub condition: Condition,
+    #[serde(default)]
+    pub scope: Scope,
+    pub actions: Vec<Action>,
+    /// The condition has to hold this long before the alert fires (debounce).
+    #[serde(default)]
+    pub for_minutes: u64,
+    /// While the condition keeps holding, the alert is repeated at most this often.
+    #[serde(default = "default_repeat_minutes")]
+    pub repeat_minutes: u64,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_repeat_minutes() -> u64 {
+    DEFAULT_REPEAT_MINUTES
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    Offline,
+    /// Any supply (optionally only of one `kind`, e.g. `toner`) below `percent`.
+    SupplyBelow { percent: f64, kind: Option<String> },
+    PaperJam,
+    /// Any `hrPrinterDetectedErrorState` condition by name, e.g. `doorOpen`.
+    ErrorState { state: String },
+    /// A printer that discovery found for the first time.
+    NewPrinter,
+}
+
+/// Which printers a rule applies to. Empty fields match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scope {
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub printer_ids: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Local-time window (`"22:00"` to `"07:00"`, may wrap midnight) during which alerts are
+/// logged but no actions run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain SMTP, e.g. a local relay or test server.
+    None,
+    #[default]
+    StartTls,
+    Tls,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Notification,
+    /// POSTs the alert event as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    Email {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        security: SmtpSecurity,
+        /// Vault SMTP credential for servers that want a login. It is only sent over TLS or
+        /// STARTTLS.
+        #[serde(default)]
+        credential: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// Runs `program` directly (no shell) with the event in `ALERT_*` environment variables.
+    /// Only programs listed in the administrator's [`ALERT_COMMANDS_FILE`] are allowed.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// One fired (or quiet-hours suppressed) alert, as logged and as sent to webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub at: DateTime<Utc>,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub printer_id: String,
+    pub printer_name: String,
+    pub ip_address: String,
+    pub location: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub suppressed: bool,
+    #[serde(default)]
+    pub action_errors: Vec<String>,
+}
+
+impl Scope {
+    fn matches(&self, entry: &InventoryEntry) -> bool {
+        let location_ok = self.location.as_ref().is_none_or(|location| {
+            entry.location.as_ref().is_some_and(|l| l.eq_ignore_ascii_case(location))
+        });
+        let id_ok = self.printer_ids.is_empty() || self.printer_ids.contains(&entry.id);
+        let tags_ok = self.tags.is_empty() || self.tags.iter().any(|tag| entry.tags.contains(tag));
+        location_ok && id_ok && tags_ok
+    }
+}
+
+impl QuietHours {
+    fn contains(&self, time: NaiveTime) -> bool {
+        let parse = |t: &str| NaiveTime::parse_from_str(t, "%H:%M").ok();
+        let (Some(start), Some(end)) = (parse(&self.start), parse(&self.end)) else { return false };
+        if start <= end {
+            time >= start && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+impl Condition {
+    /// Message describing why the condition holds for `sample`, or `None` if it does not.
+    fn check(&self, sample: &MonitorSample) -> Option<String> {
+        match self {
+            Condition::Offline => (!sample.online).then(|| "Printer is offline".to_string()),
+            Condition::SupplyBelow { percent, kind } => {
+                let low: Vec<String> = sample
+                    .supplies
+                    .iter()
+                    .flatten()
+                    .filter(|s| kind.as_ref().is_none_or(|k| s.kind.eq_ignore_ascii_case(k)))
+                    .filter(|s| !s.kind.starts_with("waste"))
+                    .filter_map(|s| Some((s, s.percent?)))
+                    .filter(|(_, p)| p < percent)
+                    .map(|(s, p)| format!("{} at {:.0}%", s.description, p))
+                    .collect();
+                (!low.is_empty()).then(|| low.join(", "))
+            }
+            Condition::PaperJam => sample.errors.iter().any(|e| e == "jammed").then(|| "Paper jam".to_string()),
+            Condition::ErrorState { state } => sample
+                .errors
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(state))
+                .then(|| format!("Printer reports {}", state)),
+            Condition::NewPrinter => None,
+        }
+    }
+}
+
+impl AlertEvent {
+    fn new(rule: &AlertRule, entry: &InventoryEntry, message: String, at: DateTime<Utc>, suppressed: bool) -> AlertEvent {
+        AlertEvent {
+            at,
+            rule_id: rule.id.clone(),
+            rule_name: rule.name.clone(),
+            printer_id: entry.id.clone(),
+            printer_name: entry.display_name().to_string(),
+            ip_address: entry.printer.ip_address.clone(),
+            location: entry.location.clone(),
+            message,
+            suppressed,
+            action_errors: Vec::new(),
+        }
+    }
+
+    fn subject(&self) -> String {
+        format!("[{}] {}: {}", self.rule_name, self.printer_name, self.message)
+    }
+}
+
+/// Tracks how long a rule's condition has held for one printer.
+struct RuleState {
+    since: DateTime<Utc>,
+    last_fired: Option<DateTime<Utc>>,
+    suppressed: bool,
+}
+
+/// A new-printer alert that arrived during quiet hours, sent once they end.
+struct DeferredAlert {
+    rule_id: String,
+    printer_id: String,
+    message: String,
+}
+
+/// An SMTP login that older versions kept in plain text in the rules file, waiting for the
+/// vault to be unlocked so it can move there.
+#[derive(Debug, Clone, PartialEq)]
+struct LegacyLogin {
+    rule_id: String,
+    action: usize,
+    username: Option<String>,
+    password: String,
+}
+
+/// The `username`/`password` pairs of email actions in a rules file.
+fn legacy_logins(rules: &serde_json::Value) -> Vec<LegacyLogin> {
+    let mut logins = Vec::new();
+    for rule in rules.as_array().into_iter().flatten() {
+        let Some(rule_id) = rule["id"].as_str() else { continue };
+        for (action, value) in rule["actions"].as_array().into_iter().flatten().enumerate() {
+            if let (Some("email"), Some(password)) = (value["type"].as_str(), value["password"].as_str()) {
+                logins.push(LegacyLogin {
+                    rule_id: rule_id.to_string(),
+                    action,
+                    username: value["username"].as_str().map(str::to_string),
+                    password: password.to_string(),
+                });
+            }
+        }
+    }
+    logins
+}
+
+/// Alert rules, their per-printer state and the history log. Managed as Tauri state.
+pub struct AlertEngine {
+    rules_path: PathBuf,
+    history_path: PathBuf,
+    rules: Mutex<Vec<AlertRule>>,
+    commands: Vec<String>,
+    legacy: Mutex<Vec<LegacyLogin>>,
+    state: Mutex<HashMap<(String, String), RuleState>>,
+    deferred: Mutex<Vec<DeferredAlert>>,
+    history: Mutex<()>,
+}
+
+impl AlertEngine {
+    /// Loads rules from `rules_path` (none when missing), the programs `Command` actions may
+    /// run from `commands_path` (none when missing) and appends history to `history_path`.
+    pub fn open(rules_path: PathBuf, commands_path: &Path, history_path: PathBuf) -> Result<AlertEngine, String> {
+        let (rules, legacy) = if rules_path.exists() {
+            let json = std::fs::read_to_string(&rules_path)
+                .map_err(|e| format!("Failed to read {}: {}", rules_path.display(), e))?;
+            let value: serde_json::Value =
+                serde_json::from_str(&json).map_err(|e| format!("Invalid alert rules {}: {}", rules_path.display(), e))?;
+            let legacy = legacy_logins(&value);
+            let rules =
+                serde_json::from_value(value).map_err(|e| format!("Invalid alert rules {}: {}", rules_path.display(), e))?;
+            (rules, legacy)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        // The app never writes this file, so the webview cannot add programs to it
+        let commands = if commands_path.exists() {
+            let json = std::fs::read_to_string(commands_path)
+                .map_err(|e| format!("Failed to read {}: {}", commands_path.display(), e))?;
+            serde_json::from_str(&json).map_err(|e| format!("Invalid alert commands {}: {}", commands_path.display(), e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(AlertEngine {
+            rules_path,
+            history_path,
+            rules: Mutex::new(rules),
+            commands,
+            legacy: Mutex::new(legacy),
+            state: Mutex::new(HashMap::new()),
+            deferred: Mutex::new(Vec::new()),
+            history: Mutex::new(()),
+        })
+    }
+
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.rules.lock().unwrap().clone()
+    }
+
+    pub fn set_rules(&self, rules: Vec<AlertRule>) -> Result<(), String> {
+        validate_rules(&rules, &self.commands)?;
+        if let Some(parent) = self.rules_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&rules).map_err(|e| format!("Failed to serialize rules: {}", e))?;
+        std::fs::write(&self.rules_path, json)
+            .map_err(|e| format!("Failed to write {}: {}", self.rules_path.display(), e))?;
+
+        // Forget state of rules that no longer exist
+        let ids: Vec<&String> = rules.iter().map(|r| &r.id).collect();
+        self.state.lock().unwrap().retain(|(rule_id, _), _| ids.contains(&rule_id));
+        *self.rules.lock().unwrap() = rules;
+        Ok(())
+    }
+
+    /// Moves SMTP passwords that older versions kept in the rules file into `vault` as SMTP
+    /// credentials and rewrites the file without them. Nothing happens while the vault is
+    /// locked; call again once it is unlocked.
+    pub fn migrate_logins(&self, vault: &CredentialVault) -> Result<usize, String> {
+        let mut legacy = self.legacy.lock().unwrap();
+        if legacy.is_empty() || !vault.status().unlocked {
+            return Ok(0);
+        }
+        let mut rules = self.rules();
+        let mut moved = 0;
+        for login in legacy.iter() {
+            let Some(rule) = rules.iter_mut().find(|r| r.id == login.rule_id) else { continue };
+            let name = format!("SMTP login of alert rule {}", rule.name);
+            let Some(Action::Email { credential: credential @ None, .. }) = rule.actions.get_mut(login.action) else { continue };
+            let summary = vault.store(CredentialInput {
+                id: None,
+                name,
+                kind: CredentialKind::Smtp,
+                username: login.username.clone(),
+                attributes: BTreeMap::new(),
+                scope: CredentialScope::default(),
+                secrets: Some(BTreeMap::from([("password".to_string(), login.password.clone())])),
+            })?;
+            *credential = Some(summary.id);
+            moved += 1;
+        }
+        self.set_rules(rules)?;
+        legacy.clear();
+        info!(moved, "moved alert SMTP passwords into the credential vault");
+        Ok(moved)
+    }
+
+    /// Checks every enabled rule against the latest monitor samples and dispatches the
+    /// alerts that are due, including new-printer alerts held back by quiet hours.
+    pub fn evaluate(&self, app: &AppHandle, inventory: &Inventory, samples: &[MonitorSample]) {
+        let now = Utc::now();
+        let quiet_now = Local::now().time();
+        let rules = self.rules();
+        let mut due = self.due_deferred(inventory, now, quiet_now);
+
+        {
+            let mut state = self.state.lock().unwrap();
+            for rule in rules.iter().filter(|r| r.enabled) {
+                for sample in samples {
+                    let Some(entry) = inventory.get(&sample.printer_id) else { continue };
+                    if !rule.scope.matches(entry) {
+                        continue;
+                    }
+                    let key = (rule.id.clone(), sample.printer_id.clone());
+                    let Some(message) = rule.condition.check(sample) else {
+                        state.remove(&key);
+                        continue;
+                    };
+
+                    let current = state.entry(key).or_insert(RuleState {
+                        since: sample.at,
+                        last_fired: None,
+                        suppressed: false,
+                    });
+                    if now - current.since < Duration::minutes(rule.for_minutes as i64) {
+                        continue;
+                    }
+                    if current.last_fired.is_some_and(|at| now - at < Duration::minutes(rule.repeat_minutes as i64)) {
+                        continue;
+                    }
+
+                    let suppressed = rule.quiet_hours.as_ref().is_some_and(|q| q.contains(quiet_now));
+                    if suppressed {
+                        // Logged once; the alert fires for real once quiet hours end
+                        if current.suppressed {
+                            continue;
+                        }
+                        current.suppressed = true;
+                    } else {
+                        current.last_fired = Some(now);
+                        current.suppressed = false;
+                    }
+                    due.push((rule.clone(), AlertEvent::new(rule, entry, message, now, suppressed)));
+                }
+            }
+        }
+
+        for (rule, event) in due {
+            dispatch(app, rule, event);
+        }
+    }
+
+    /// Fires `NewPrinter` rules for printers that discovery just added to the inventory.
+    pub fn on_new_printers(&self, app: &AppHandle, inventory: &Inventory, new_ids: &[String]) {
+        for (rule, event) in self.new_printer_alerts(inventory, new_ids, Utc::now(), Local::now().time()) {
+            dispatch(app, rule, event);
+        }
+    }
+
+    /// `NewPrinter` alerts for `new_ids`. When every printer in the inventory is new this is
+    /// the first discovery, which only seeds the baseline. Alerts that fall in quiet hours
+    /// are logged as suppressed and kept until `evaluate` finds the quiet hours over.
+    fn new_printer_alerts(
+        &self,
+        inventory: &Inventory,
+        new_ids: &[String],
+        now: DateTime<Utc>,
+        quiet_now: NaiveTime,
+    ) -> Vec<(AlertRule, AlertEvent)> {
+        if new_ids.len() >= inventory.printers.len() {
+            info!(printers = new_ids.len(), "first discovery, new-printer alerts start with the next one");
+            return Vec::new();
+        }
+
+        let mut alerts = Vec::new();
+        let mut deferred = self.deferred.lock().unwrap();
+        for rule in self.rules().into_iter().filter(|r| r.enabled && matches!(r.condition, Condition::NewPrinter)) {
+            for entry in new_ids.iter().filter_map(|id| inventory.get(id)) {
+                if !rule.scope.matches(entry) {
+                    continue;
+                }
+                let suppressed = rule.quiet_hours.as_ref().is_some_and(|q| q.contains(quiet_now));
+                let message = format!("New printer on the network at {}", entry.printer.ip_address);
+                if suppressed {
+                    deferred.push(DeferredAlert {
+                        rule_id: rule.id.clone(),
+                        printer_id: entry.id.clone(),
+                        message: message.clone(),
+                    });
+                }
+                alerts.push((rule.clone(), AlertEvent::new(&rule, entry, message, now, suppressed)));
+            }
+        }
+        alerts
+    }
+
+    /// Deferred new-printer alerts whose rule is out of quiet hours. Alerts for rules or
+    /// printers that are gone by then are dropped.
+    fn due_deferred(&self, inventory: &Inventory, now: DateTime<Utc>, quiet_now: NaiveTime) -> Vec<(AlertRule, AlertEvent)> {
+        let rules = self.rules();
+        let mut due = Vec::new();
+        self.deferred.lock().unwrap().retain(|alert| {
+            let rule = rules.iter().find(|r| r.id == alert.rule_id && r.enabled);
+            let (Some(rule), Some(entry)) = (rule, inventory.get(&alert.printer_id)) else { return false };
+            if rule.quiet_hours.as_ref().is_some_and(|q| q.contains(quiet_now)) {
+                return true;
+            }
+            due.push((rule.clone(), AlertEvent::new(rule, entry, alert.message.clone(), now, false)));
+            false
+        });
+        due
+    }
+
+    fn append_history(&self, event: &AlertEvent) -> Result<(), String> {
+        let _guard = self.history.lock().unwrap();
+        if let Some(parent) = self.history_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        if std::fs::metadata(&self.history_path).is_ok_and(|m| m.len() >= MAX_HISTORY_BYTES) {
+            std::fs::rename(&self.history_path, rotated(&self.history_path))
+                .map_err(|e| format!("Failed to rotate {}: {}", self.history_path.display(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.history_path)
+            .map_err(|e| format!("Failed to open {}: {}", self.history_path.display(), e))?;
+        let line = serde_json::to_string(event).map_err(|e| format!("Failed to serialize alert: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", self.history_path.display(), e))
+    }
+
+    /// The most recent `limit` alerts, newest first.
+    pub fn history(&self, limit: usize) -> Result<Vec<AlertEvent>, String> {
+        read_history(&self.history_path, limit)
+    }
+}
+
+/// Where the previous history log is kept after rotation.
+fn rotated(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Reads the current log and, if more events are wanted, the rotated one before it.
+fn read_history(path: &Path, limit: usize) -> Result<Vec<AlertEvent>, String> {
+    let mut events = Vec::new();
+    for path in [path.to_path_buf(), rotated(path)] {
+        if events.len() >= limit || !path.exists() {
+            continue;
+        }
+        let file = std::fs::File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut older: Vec<AlertEvent> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        older.reverse();
+        events.extend(older);
+    }
+    events.truncate(limit);
+    Ok(events)
+}
+
+fn validate_rules(rules: &[AlertRule], commands: &[String]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.id.trim().is_empty() {
+            return Err(format!("Rule {} has no id", i + 1));
+        }
+        if rules.iter().filter(|r| r.id == rule.id).count() > 1 {
+            return Err(format!("Duplicate rule id '{}'", rule.id));
+        }
+        if let Some(quiet) = &rule.quiet_hours {
+            for time in [&quiet.start, &quiet.end] {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| format!("Rule '{}': invalid quiet hours time '{}' (use HH:MM)", rule.name, time))?;
+            }
+        }
+        for action in &rule.actions {
+            match action {
+                Action::Webhook { url, .. } if !(url.starts_with("http://") || url.starts_with("https://")) => {
+                    return Err(format!("Rule '{}': webhook URL must be http or https", rule.name));
+                }
+                Action::Email { to, .. } if to.is_empty() => {
+                    return Err(format!("Rule '{}': email action has no recipients", rule.name));
+                }
+                Action::Command { program, .. } => {
+                    check_program(program, commands).map_err(|e| format!("Rule '{}': {}", rule.name, e))?;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Commands run without a shell, and only by absolute path so a rule cannot pick up
+/// whatever happens to be first on `PATH`. Rules are saved from the webview, so the program
+/// must also be listed in [`ALERT_COMMANDS_FILE`].
+fn check_program(program: &str, commands: &[String]) -> Result<(), String> {
+    let path = Path::new(program);
+    if !path.is_absolute() || !path.is_file() {
+        return Err(format!("command '{}' must be the absolute path of an existing program", program));
+    }
+    if !commands.iter().any(|allowed| allowed == program) {
+        return Err(format!("command '{}' is not listed in {}", program, ALERT_COMMANDS_FILE));
+    }
+    Ok(())
+}
+
+/// Runs the actions of `rule` on a separate thread (unless suppressed) and logs the event.
+fn dispatch(app: &AppHandle, rule: AlertRule, mut event: AlertEvent) {
+    let app = app.clone();
+    thread::spawn(move || {
+        if !event.suppressed {
+            for action in &rule.actions {
+                if let Err(e) = run_action(&app, action, &event) {
+                    warn!(rule = %rule.name, "alert action failed: {}", e);
+                    event.action_errors.push(e);
+                }
+            }
+        }
+        if let Some(engine) = app.try_state::<AlertEngine>() {
+            if let Err(e) = engine.append_history(&event) {
+                warn!("failed to record alert: {}", e);
+            }
+        }
+        if let Err(e) = app.emit("alert", &event) {
+            warn!("failed to emit alert: {}", e);
+        }
+    });
+}
+
+pub fn run_action(app: &AppHandle, action: &Action, event: &AlertEvent) -> Result<(), String> {
+    match action {
+        Action::Notification => app
+            .notification()
+            .builder()
+            .title(format!("{}: {}", event.rule_name, event.printer_name))
+            .body(&event.message)
+            .show()
+            .map_err(|e| format!("Notification failed: {}", e)),
+        Action::Webhook { url, headers } => send_webhook(url, headers, event),
+        Action::Email {
+            host,
+            port,
+            security,
+            credential,
+            from,
+            to,
+        } => match credential {
+            Some(id) => {
+                let vault = app
+                    .try_state::<CredentialVault>()
+                    .ok_or_else(|| "The credential vault is not available".to_string())?;
+                let login = vault.get(id)?;
+                let (username, password) = smtp_login(&login, *security)?;
+                send_email(host, *port, *security, Some(username), password, from, to, event)
+            }
+            None => send_email(host, *port, *security, None, None, from, to, event),
+        },
+        Action::Command { program, args } => {
+            // Checked again here: the allow-list may have shrunk since the rule was saved
+            let engine = app
+                .try_state::<AlertEngine>()
+                .ok_or_else(|| "The alert engine is not available".to_string())?;
+            check_program(program, &engine.commands)?;
+            run_command(program, args, event)
+        }
+    }
+}
+
+/// The username and password of an SMTP credential, refused for any other kind so an alert
+/// rule cannot mail a printer or SNMP secret to an arbitrary server, and refused over plain
+/// SMTP where the login would cross the network in the clear.
+fn smtp_login(login: &Credential, security: SmtpSecurity) -> Result<(&str, Option<&str>), String> {
+    let summary = &login.summary;
+    if summary.kind != CredentialKind::Smtp {
+        return Err(format!("Credential '{}' is not an SMTP credential", summary.name));
+    }
+    if matches!(security, SmtpSecurity::None) {
+        return Err(format!("Credential '{}' is only sent over TLS or STARTTLS, not plain SMTP", summary.name));
+    }
+    let username = summary
+        .username
+        .as_deref()
+        .ok_or_else(|| format!("Credential '{}' has no username", summary.name))?;
+    Ok((username, login.secret("password")))
+}
+
+pub fn send_webhook(url: &str, headers: &HashMap<String, String>, event: &AlertEvent) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(ACTION_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let body = serde_json::to_string(event).map_err(|e| format!("Failed to serialize alert: {}", e))?;
+    let mut request = client.post(url).header("Content-Type", "application/json").body(body);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send().map_err(|e| format!("Webhook {} failed: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Webhook {} returned {}", url, response.status()));
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn send_email(
+    host: &str,
+    port: u16,
+    security: SmtpSecurity,
+    username: Option<&str>,
+    password: Option<&str>,
+    from: &str,
+    to: &[String],
+    event: &AlertEvent,
+) -> Result<(), String> {
+    let mut builder = Message::builder()
+        .from(from.parse().map_err(|e| format!("Invalid sender '{}': {}", from, e))?)
+        .subject(event.subject());
+    for recipient in to {
+        builder = builder.to(recipient.parse().map_err(|e| format!("Invalid recipient '{}': {}", recipient, e))?);
+    }
+    let body = format!(
+        "{}\n\nPrinter: {} ({})\nLocation: {}\nRule: {}\nTime: {}\n",
+        event.message,
+        event.printer_name,
+        event.ip_address,
+        event.location.as_deref().unwrap_or("-"),
+        event.rule_name,
+        event.at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+    );
+    let email = builder
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .map_err(|e| format!("Failed to build email: {}", e))?;
+
+    let transport = match security {
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(host),
+        SmtpSecurity::StartTls => SmtpTransport::starttls_relay(host).map_err(|e| format!("SMTP setup failed: {}", e))?,
+        SmtpSecurity::Tls => SmtpTransport::relay(host).map_err(|e| format!("SMTP setup failed: {}", e))?,
+    };
+    let mut transport = transport.port(port).timeout(Some(ACTION_TIMEOUT));
+    if let Some(username) = username {
+        transport = transport.credentials(Credentials::new(username.to_string(), password.unwrap_or_default().to_string()));
+    }
+
+    transport
+        .build()
+        .send(&email)
+        .map(|_| ())
+        .map_err(|e| format!("Sending email via {}:{} failed: {}", host, port, e))
+}
+
+pub fn run_command(program: &str, args: &[String], event: &AlertEvent) -> Result<(), String> {
+    let json = serde_json::to_string(event).map_err(|e| format!("Failed to serialize alert: {}", e))?;
+    let status = Command::new(program)
+        .args(args)
+        .env("ALERT_RULE", &event.rule_name)
+        .env("ALERT_PRINTER", &event.printer_name)
+        .env("ALERT_IP", &event.ip_address)
+        .env("ALERT_LOCATION", event.location.as_deref().unwrap_or(""))
+        .env("ALERT_MESSAGE", &event.message)
+        .env("ALERT_JSON", json)
+        .status()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_alert_rules(engine: State<'_, AlertEngine>) -> Vec<AlertRule> {
+    engine.rules()
+}
+
+#[tauri::command]
+pub fn save_alert_rules(engine: State<'_, AlertEngine>, rules: Vec<AlertRule>) -> Result<(), String> {
+    engine.set_rules(rules)
+}
+
+#[tauri::command]
+pub fn get_alert_history(engine: State<'_, AlertEngine>, limit: Option<usize>) -> Result<Vec<AlertEvent>, String> {
+    engine.history(limit.unwrap_or(200))
+}
+
+/// Runs one action with a sample event so webhook and SMTP settings can be checked.
+#[tauri::command]
+pub fn test_alert_action(app: AppHandle, action: Action) -> Result<(), String> {
+    let event = AlertEvent {
+        at: Utc::now(),
+        rule_id: "test".to_string(),
+        rule_name: "Test alert".to_string(),
+        printer_id: "test".to_string(),
+        printer_name: "Test printer".to_string(),
+        ip_address: "192.0.2.1".to_string(),
+        location: None,
+        message: "This is a test alert".to_string(),
+        suppressed: false,
+        action_errors: Vec::new(),
+    };
+    run_action(&app, &action, &event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::NetworkPrinter;
+    use std::net::TcpListener;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("alerts-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn engine(dir: &Path, rules: Vec<AlertRule>) -> AlertEngine {
+        let engine =
+            AlertEngine::open(dir.join("alert_rules.json"), &dir.join(ALERT_COMMANDS_FILE), dir.join("alert_history.jsonl"))
+                .unwrap();
+        engine.set_rules(rules).unwrap();
+        engine
+    }
+
+    fn new_printer_rule(quiet_hours: Option<QuietHours>) -> AlertRule {
+        AlertRule {
+            id: "new".to_string(),
+            name: "New printer".to_string(),
+            enabled: true,
+            condition: Condition::NewPrinter,
+            scope: Scope::default(),
+            actions: vec![Action::Notification],
+            for_minutes: 0,
+            repeat_minutes: DEFAULT_REPEAT_MINUTES,
+            quiet_hours,
+        }
+    }
+
+    fn inventory(ids: &[&str]) -> Inventory {
+        let now = Utc::now();
+        let printers = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| InventoryEntry {
+                id: id.to_string(),
+                printer: NetworkPrinter {
+                    name: format!("Printer {}", id),
+                    ip_address: format!("192.0.2.{}", i + 10),
+                    ..Default::default()
+                },
+                location: None,
+                tags: Vec::new(),
+                first_seen: now,
+                last_seen: now,
+                counters: Vec::new(),
+                supplies: Vec::new(),
+                sources: Vec::new(),
+                pjl_counters: false,
+            })
+            .collect();
+        Inventory { printers, ..Default::default() }
+    }
+
+    fn at(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    fn event(message: &str) -> AlertEvent {
+        let rule = new_printer_rule(None);
+        AlertEvent::new(&rule, &inventory(&["a"]).printers[0], message.to_string(), Utc::now(), false)
+    }
+
+    #[test]
+    fn first_discovery_only_seeds_the_baseline() {
+        let dir = temp_dir("baseline");
+        let engine = engine(&dir, vec![new_printer_rule(None)]);
+        let inventory = inventory(&["a", "b", "c"]);
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        assert!(engine.new_printer_alerts(&inventory, &ids(&["a", "b", "c"]), Utc::now(), at("12:00")).is_empty());
+        let alerts = engine.new_printer_alerts(&inventory, &ids(&["c"]), Utc::now(), at("12:00"));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].1.printer_id, "c");
+        assert!(!alerts[0].1.suppressed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quiet_hours_defer_new_printer_alerts() {
+        let dir = temp_dir("quiet");
+        let quiet = QuietHours { start: "22:00".to_string(), end: "07:00".to_string() };
+        let engine = engine(&dir, vec![new_printer_rule(Some(quiet))]);
+        let inventory = inventory(&["a", "b"]);
+
+        let alerts = engine.new_printer_alerts(&inventory, &["b".to_string()], Utc::now(), at("23:00"));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].1.suppressed);
+
+        assert!(engine.due_deferred(&inventory, Utc::now(), at("03:00")).is_empty());
+        let due = engine.due_deferred(&inventory, Utc::now(), at("07:30"));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].1.printer_id, "b");
+        assert!(!due[0].1.suppressed);
+        assert!(engine.due_deferred(&inventory, Utc::now(), at("08:00")).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deferred_alerts_for_removed_printers_are_dropped() {
+        let dir = temp_dir("dropped");
+        let quiet = QuietHours { start: "22:00".to_string(), end: "07:00".to_string() };
+        let engine = engine(&dir, vec![new_printer_rule(Some(quiet))]);
+
+        engine.new_printer_alerts(&inventory(&["a", "b"]), &["b".to_string()], Utc::now(), at("23:00"));
+        assert!(engine.due_deferred(&inventory(&["a"]), Utc::now(), at("08:00")).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_rotates_and_reads_across_files() {
+        let dir = temp_dir("history");
+        let engine = engine(&dir, Vec::new());
+        let mut written = 0;
+        while !rotated(&engine.history_path).exists() {
+            engine.append_history(&event(&format!("event {}", written))).unwrap();
+            written += 1;
+        }
+        assert!(std::fs::metadata(&engine.history_path).unwrap().len() < MAX_HISTORY_BYTES);
+
+        let history = engine.history(written).unwrap();
+        assert_eq!(history.len(), written);
+        assert_eq!(history[0].message, format!("event {}", written - 1));
+        assert_eq!(history[written - 1].message, "event 0");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_actions_need_the_allow_list() {
+        let dir = temp_dir("commands");
+        let program = std::env::current_exe().unwrap().display().to_string();
+        let mut rule = new_printer_rule(None);
+        rule.actions = vec![Action::Command { program: program.clone(), args: Vec::new() }];
+
+        let error = engine(&dir, Vec::new()).set_rules(vec![rule.clone()]).unwrap_err();
+        assert!(error.contains(ALERT_COMMANDS_FILE), "{}", error);
+
+        std::fs::write(dir.join(ALERT_COMMANDS_FILE), serde_json::to_string(&[&program]).unwrap()).unwrap();
+        engine(&dir, vec![rule]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn webhook_posts_the_event() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let receiver = thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let token = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("X-Token"))
+                .map(|h| h.value.to_string());
+            request.respond(tiny_http::Response::empty(204)).unwrap();
+            (body, token)
+        });
+
+        let headers = HashMap::from([("X-Token".to_string(), "secret".to_string())]);
+        send_webhook(&url, &headers, &event("Black at 8%")).unwrap();
+        let (body, token) = receiver.join().unwrap();
+        let received: AlertEvent = serde_json::from_str(&body).unwrap();
+        assert_eq!(received.message, "Black at 8%");
+        assert_eq!(token.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn webhook_errors_are_reported() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            let request = server.recv().unwrap();
+            request.respond(tiny_http::Response::empty(500)).unwrap();
+        });
+
+        let error = send_webhook(&url, &HashMap::new(), &event("Offline")).unwrap_err();
+        assert!(error.contains("500"), "{}", error);
+    }
+
+    /// Accepts one SMTP session, answers every command with success and returns the
+    /// transcript.
+    fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        writer.write_all(b"220 sink ESMTP\r\n").unwrap();
+
+        let mut transcript = String::new();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            transcript.push_str(&line);
+            if in_data {
+                if line == ".\r\n" {
+                    in_data = false;
+                    writer.write_all(b"250 queued\r\n").unwrap();
+                }
+                continue;
+            }
+            let command = line.to_ascii_uppercase();
+            let reply: &[u8] = if command.starts_with("EHLO") {
+                b"250-sink\r\n250 AUTH PLAIN LOGIN\r\n"
+            } else if command.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if command.starts_with("DATA") {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if command.starts_with("QUIT") {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).unwrap();
+        }
+        transcript
+    }
+
+    #[test]
+    fn email_reaches_the_smtp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = thread::spawn(move || smtp_sink(listener));
+
+        let to = vec!["ops@example.com".to_string()];
+        send_email("127.0.0.1", port, SmtpSecurity::None, Some("alerts"), Some("pw"), "alerts@example.com", &to, &event("Paper jam"))
+            .unwrap();
+        let transcript = sink.join().unwrap();
+        assert!(transcript.contains("AUTH PLAIN"), "{}", transcript);
+        assert!(transcript.contains("RCPT TO:<ops@example.com>"), "{}", transcript);
+        assert!(transcript.contains("Subject: [New printer] Printer a: Paper jam"), "{}", transcript);
+    }
+
+    fn login(kind: CredentialKind) -> Credential {
+        let now = Utc::now();
+        Credential {
+            summary: crate::vault::CredentialSummary {
+                id: "c1".to_string(),
+                name: "Mail relay".to_string(),
+                kind,
+                username: Some("alerts".to_string()),
+                attributes: BTreeMap::new(),
+                scope: CredentialScope::default(),
+                secret_fields: vec!["password".to_string()],
+                created: now,
+                updated: now,
+            },
+            secrets: BTreeMap::from([("password".to_string(), "pw".to_string())]),
+        }
+    }
+
+    #[test]
+    fn smtp_login_needs_an_smtp_credential() {
+        for kind in [CredentialKind::Ipp, CredentialKind::WebAdmin, CredentialKind::Snmpv3] {
+            let err = smtp_login(&login(kind), SmtpSecurity::Tls).unwrap_err();
+            assert!(err.contains("not an SMTP credential"), "{}", err);
+        }
+        let smtp = login(CredentialKind::Smtp);
+        assert_eq!(smtp_login(&smtp, SmtpSecurity::StartTls).unwrap(), ("alerts", Some("pw")));
+        assert_eq!(smtp_login(&smtp, SmtpSecurity::Tls).unwrap(), ("alerts", Some("pw")));
+    }
+
+    #[test]
+    fn smtp_login_is_refused_over_plain_smtp() {
+        let err = smtp_login(&login(CredentialKind::Smtp), SmtpSecurity::None).unwrap_err();
+        assert!(err.contains("not plain SMTP"), "{}", err);
+    }
+
+    #[test]
+    fn plaintext_smtp_passwords_move_into_the_vault() {
+        let dir = temp_dir("migrate");
+        let rules = r#"[{"id": "jam", "name": "Jams", "condition": {"type": "paper_jam"}, "actions": [
+            {"type": "notification"},
+            {"type": "email", "host": "mail.example.com", "port": 587, "security": "starttls",
+             "username": "alerts", "password": "hunter22", "from": "a@example.com", "to": ["ops@example.com"]}
+        ]}]"#;
+        std::fs::write(dir.join("alert_rules.json"), rules).unwrap();
+        let engine =
+            AlertEngine::open(dir.join("alert_rules.json"), &dir.join(ALERT_COMMANDS_FILE), dir.join("alert_history.jsonl"))
+                .unwrap();
+        let vault = CredentialVault::open(dir.join("credentials.json")).unwrap();
+
+        // A locked vault leaves the login waiting
+        assert_eq!(engine.migrate_logins(&vault).unwrap(), 0);
+        vault.unlock("correct horse battery").unwrap();
+        assert_eq!(engine.migrate_logins(&vault).unwrap(), 1);
+        assert_eq!(engine.migrate_logins(&vault).unwrap(), 0);
+
+        let Action::Email { credential: Some(id), .. } = &engine.rules()[0].actions[1] else { panic!("no credential") };
+        let stored = vault.get(id).unwrap();
+        assert_eq!(stored.summary.kind, CredentialKind::Smtp);
+        assert_eq!(stored.summary.username.as_deref(), Some("alerts"));
+        assert_eq!(stored.secret("password"), Some("hunter22"));
+        let written = std::fs::read_to_string(dir.join("alert_rules.json")).unwrap();
+        assert!(!written.contains("hunter22") && !written.contains("password"), "{}", written);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
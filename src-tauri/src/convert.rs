@@ -0,0 +1,401 @@
+use crate::ipp_message::{IppMessage, IppValue, PRINTER_ATTRIBUTES};
+use crate::raster::{self, ColorMode, RasterFormat, RasterOptions, RasterPage, SheetBack, Sides};
+use crate::settings::PrintingSettings;
+use crate::text_pdf::{self, PageLayout};
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+/// Picked when the printer offers it; high enough for photos, small enough to send quickly.
+const PREFERRED_DPI: u32 = 300;
+pub const MM_PER_INCH: f32 = 25.4;
+pub const POINTS_PER_MM: f32 = 72.0 / MM_PER_INCH;
+
+/// The format a `format` document should be sent in, given the printer's
+/// `document-format-supported` (an empty list means the printer did not say). Text is
+/// typeset as PDF when possible so the font and margins apply; images go as themselves,
+/// else as PWG Raster or URF. Printers that sniff `application/octet-stream` get anything
+/// else under that type.
+pub fn target_format(format: &str, supported: &[String]) -> Option<String> {
+    if supported.is_empty() {
+        return Some(format.to_string());
+    }
+    let mut candidates = Vec::new();
+    match format {
+        "text/plain" => candidates.extend(["application/pdf", "text/plain"]),
+        "image/png" | "image/jpeg" => candidates.extend([format, "image/pwg-raster", "image/urf"]),
+        _ => candidates.push(format),
+    }
+    candidates.push(OCTET_STREAM);
+    candidates
+        .into_iter()
+        .find(|candidate| supported.iter().any(|f| f == candidate))
+        .map(str::to_string)
+}
+
+/// Width and height in millimeters of a PWG self-describing media name such as
+/// `iso_a4_210x297mm` or `na_letter_8.5x11in`.
+pub fn media_size_mm(name: &str) -> Option<(f32, f32)> {
+    let size = name.rsplit('_').next()?;
+    let (size, scale) = if let Some(size) = size.strip_suffix("mm") {
+        (size, 1.0)
+    } else {
+        (size.strip_suffix("in")?, MM_PER_INCH)
+    };
+    let (width, height) = size.split_once('x')?;
+    let width: f32 = width.parse().ok()?;
+    let height: f32 = height.parse().ok()?;
+    (width > 0.0 && height > 0.0).then_some((width * scale, height * scale))
+}
+
+/// The printer's default paper, else the one from the settings, with its size.
+pub fn media(attributes: &IppMessage, settings: &PrintingSettings) -> (String, (f32, f32)) {
+    [attributes.string(PRINTER_ATTRIBUTES, "media-default"), Some(settings.paper.as_str())]
+        .into_iter()
+        .flatten()
+        .find_map(|name| Some((name.to_string(), media_size_mm(name)?)))
+        .unwrap_or_else(|| ("iso_a4_210x297mm".to_string(), (210.0, 297.0)))
+}
+
+/// Resolutions in dpi from a list of `resolution` values.
+fn resolutions(attributes: &IppMessage, name: &str) -> Vec<u32> {
+    attributes
+        .attribute(PRINTER_ATTRIBUTES, name)
+        .map(|a| a.values.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|value| match value {
+            // Units 3 are dots per inch, 4 dots per centimeter
+            IppValue::Resolution { x, units: 3, .. } => Some(*x as u32),
+            IppValue::Resolution { x, units: 4, .. } => Some((*x as f32 * 2.54).round() as u32),
+            _ => None,
+        })
+        .filter(|&dpi| dpi > 0)
+        .collect()
+}
+
+fn pick_dpi(offered: &[u32]) -> u32 {
+    if offered.is_empty() || offered.contains(&PREFERRED_DPI) {
+        PREFERRED_DPI
+    } else {
+        offered.iter().copied().min().unwrap_or(PREFERRED_DPI)
+    }
+}
+
+/// Resolution and pixel layout to rasterize for, from the printer's PWG or URF
+/// capabilities. Color images use color where the printer has it; bilevel-only
+/// printers get a dithered page.
+pub fn raster_mode(target: &str, attributes: &IppMessage, colorful: bool) -> (u32, ColorMode) {
+    if target == "image/urf" {
+        // urf-supported lists keywords like `RS300-600`, `W8` and `SRGB24`
+        let keywords = attributes.strings(PRINTER_ATTRIBUTES, "urf-supported");
+        let offered: Vec<u32> = keywords
+            .iter()
+            .filter_map(|k| k.strip_prefix("RS"))
+            .flat_map(|list| list.split('-').filter_map(|dpi| dpi.parse().ok()))
+            .collect();
+        let color = keywords.iter().any(|k| k == "SRGB24");
+        let gray = keywords.iter().any(|k| k == "W8");
+        let mode = if color && (colorful || !gray) { ColorMode::Rgb8 } else { ColorMode::Gray8 };
+        return (pick_dpi(&offered), mode);
+    }
+
+    let types = attributes.strings(PRINTER_ATTRIBUTES, "pwg-raster-document-type-supported");
+    let has = |t: &str| types.iter().any(|s| s == t);
+    let mode = if has("srgb_8") && (colorful || !has("sgray_8")) {
+        ColorMode::Rgb8
+    } else if has("sgray_8") || types.is_empty() {
+        ColorMode::Gray8
+    } else if has("black_1") {
+        ColorMode::Black1
+    } else {
+        ColorMode::Gray8
+    };
+    (pick_dpi(&resolutions(attributes, "pwg-raster-document-resolution-supported")), mode)
+}
+
+/// Whether any pixel is noticeably colored; gray scans are often saved as RGB.
+fn is_colorful(image: &DynamicImage) -> bool {
+    image.color().has_color()
+        && image.to_rgb8().pixels().any(|p| {
+            let (min, max) = (p.0.iter().min(), p.0.iter().max());
+            max.zip(min).is_some_and(|(max, min)| max - min > 8)
+        })
+}
+
+/// Copies `rgb` into `page` at (`left`, `top`), in the page's pixel layout.
+pub fn paint(rgb: RgbImage, page: &mut RasterPage, left: u32, top: u32) {
+    match page.color {
+        ColorMode::Rgb8 => {
+            for (y, row) in rgb.rows().enumerate() {
+                let line = page.row_mut(top + y as u32);
+                for (x, pixel) in row.enumerate() {
+                    let at = (left as usize + x) * 3;
+                    line[at..at + 3].copy_from_slice(&pixel.0);
+                }
+            }
+        }
+        ColorMode::Gray8 => {
+            let gray = DynamicImage::ImageRgb8(rgb).to_luma8();
+            for (y, row) in gray.rows().enumerate() {
+                let line = page.row_mut(top + y as u32);
+                for (x, pixel) in row.enumerate() {
+                    line[left as usize + x] = pixel[0];
+                }
+            }
+        }
+        ColorMode::Black1 => {
+            let gray = DynamicImage::ImageRgb8(rgb).to_luma8();
+            dither_into(&gray, page, left, top);
+        }
+    }
+}
+
+/// `image` with transparency flattened onto white paper.
+pub fn on_white(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    let mut rgb = RgbImage::new(rgba.width(), rgba.height());
+    for (target, source) in rgb.pixels_mut().zip(rgba.pixels()) {
+        let alpha = source[3] as u32;
+        for c in 0..3 {
+            target[c] = ((source[c] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+    }
+    rgb
+}
+
+/// Floyd-Steinberg dithers `gray` into the bilevel `page` at (`left`, `top`).
+pub fn dither_into(gray: &GrayImage, page: &mut RasterPage, left: u32, top: u32) {
+    let width = gray.width() as usize;
+    let mut error = vec![0i32; width + 2];
+    let mut next = vec![0i32; width + 2];
+    for y in 0..gray.height() {
+        let row = page.row_mut(top + y);
+        for x in 0..width {
+            let value = gray.get_pixel(x as u32, y)[0] as i32 + error[x + 1] / 16;
+            let black = value < 128;
+            let residual = if black { value } else { value - 255 };
+            if black {
+                let px = left as usize + x;
+                row[px / 8] |= 0x80 >> (px % 8);
+            }
+            error[x + 2] += residual * 7;
+            next[x] += residual * 3;
+            next[x + 1] += residual * 5;
+            next[x + 2] += residual;
+        }
+        std::mem::swap(&mut error, &mut next);
+        next.iter_mut().for_each(|e| *e = 0);
+    }
+}
+
+/// Fits `image` inside the margins of a page, turning it when its orientation differs
+/// from the page's, and centers it.
+fn image_page(image: &DynamicImage, paper_mm: (f32, f32), margin_mm: f32, dpi: u32, mode: ColorMode) -> RasterPage {
+    let to_px = |mm: f32| (mm / MM_PER_INCH * dpi as f32).round() as u32;
+    let (width, height) = (to_px(paper_mm.0).max(1), to_px(paper_mm.1).max(1));
+    let margin = to_px(margin_mm).min(width.min(height) / 4);
+    let (box_width, box_height) = (width - 2 * margin, height - 2 * margin);
+
+    let rotated;
+    let image = if (image.width() > image.height()) != (box_width > box_height) && image.width() != image.height() {
+        rotated = image.rotate90();
+        &rotated
+    } else {
+        image
+    };
+    let scale = (box_width as f32 / image.width() as f32).min(box_height as f32 / image.height() as f32);
+    let fitted_width = ((image.width() as f32 * scale).round() as u32).clamp(1, box_width);
+    let fitted_height = ((image.height() as f32 * scale).round() as u32).clamp(1, box_height);
+    let fitted = image.resize_exact(fitted_width, fitted_height, FilterType::Triangle);
+    let left = margin + (box_width - fitted_width) / 2;
+    let top = margin + (box_height - fitted_height) / 2;
+
+    let mut page = RasterPage::blank(width, height, dpi, mode);
+    paint(on_white(&fitted), &mut page, left, top);
+    page
+}
+
+/// Encodes `pages` printed on `media`, flipping back sides the way the printer's
+/// `pwg-raster-document-sheet-back` asks for.
+pub fn encode_raster(
+    format: RasterFormat,
+    pages: &[RasterPage],
+    sides: Sides,
+    media: String,
+    attributes: &IppMessage,
+) -> Result<Vec<u8>, String> {
+    let options = RasterOptions {
+        sides,
+        sheet_back: SheetBack::parse(
+            attributes
+                .string(PRINTER_ATTRIBUTES, "pwg-raster-document-sheet-back")
+                .unwrap_or("normal"),
+        ),
+        media: Some(media),
+    };
+    raster::encode(format, pages, &options)
+}
+
+/// Converts a `format` document to `target`, as chosen by `target_format`. Text becomes
+/// a PDF in the configured font; PNG and JPEG images become one raster page scaled to
+/// the printer's default paper, with headers for `sides`. Documents already in `target`
+/// are passed through.
+pub fn convert(
+    data: Vec<u8>,
+    format: &str,
+    target: &str,
+    sides: Sides,
+    attributes: &IppMessage,
+    settings: &PrintingSettings,
+) -> Result<Vec<u8>, String> {
+    if target == format || target == OCTET_STREAM {
+        return Ok(data);
+    }
+    let (media_name, paper) = media(attributes, settings);
+
+    match (format, target) {
+        ("text/plain", "application/pdf") => {
+            let text = String::from_utf8_lossy(&data);
+            let layout = PageLayout {
+                width: paper.0 * POINTS_PER_MM,
+                height: paper.1 * POINTS_PER_MM,
+                margin: settings.margin_mm * POINTS_PER_MM,
+            };
+            Ok(text_pdf::text_to_pdf(&text, &settings.text_font, settings.text_size, layout))
+        }
+        ("image/png" | "image/jpeg", "image/pwg-raster" | "image/urf") => {
+            let raster_format = RasterFormat::from_mime(target).ok_or("Unknown raster format")?;
+            let image_format = if format == "image/png" { ImageFormat::Png } else { ImageFormat::Jpeg };
+            let image = image::load_from_memory_with_format(&data, image_format)
+                .map_err(|e| format!("Failed to decode the image: {}", e))?;
+            let (dpi, mode) = raster_mode(target, attributes, is_colorful(&image));
+            let page = image_page(&image, paper, settings.margin_mm, dpi, mode);
+            encode_raster(raster_format, &[page], sides, media_name, attributes)
+        }
+        ("application/pdf", _) => Err(format!("PDF documents cannot be converted to {}", target)),
+        _ => Err(format!("Cannot convert {} to {}", format, target)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn printer(attributes: &[(&str, Vec<IppValue>)]) -> IppMessage {
+        let mut message = IppMessage::request(0, 1);
+        message.add(PRINTER_ATTRIBUTES, "media-default", IppValue::keyword("na_letter_8.5x11in"));
+        for (name, values) in attributes {
+            message.add_values(PRINTER_ATTRIBUTES, name, values.clone());
+        }
+        message
+    }
+
+    fn keywords(values: &[&str]) -> Vec<IppValue> {
+        values.iter().map(|value| IppValue::keyword(value)).collect()
+    }
+
+    fn convert_to(data: Vec<u8>, format: &str, target: &str, sides: Sides, attributes: &IppMessage) -> Vec<u8> {
+        convert(data, format, target, sides, attributes, &PrintingSettings::default()).unwrap()
+    }
+
+    fn red_png() -> Vec<u8> {
+        let image = RgbImage::from_pixel(40, 20, image::Rgb([200, 30, 30]));
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(image).write_to(&mut png, ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    fn letter_at(dpi: u32) -> (u32, u32) {
+        ((8.5 * dpi as f32).round() as u32, (11.0 * dpi as f32).round() as u32)
+    }
+
+    #[test]
+    fn png_to_pwg_uses_the_printer_mode_media_and_sides() {
+        let attributes = printer(&[
+            ("pwg-raster-document-type-supported", keywords(&["sgray_8", "srgb_8"])),
+            (
+                "pwg-raster-document-resolution-supported",
+                vec![IppValue::Resolution { x: 150, y: 150, units: 3 }],
+            ),
+        ]);
+        let data = convert_to(red_png(), "image/png", "image/pwg-raster", Sides::TwoSidedLongEdge, &attributes);
+
+        let pages = raster::decode(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        let (dpi, mode) = raster_mode("image/pwg-raster", &attributes, true);
+        let decoded = &pages[0];
+        assert_eq!((decoded.page.dpi, decoded.page.color), (dpi, mode));
+        assert_eq!((dpi, mode), (150, ColorMode::Rgb8));
+        assert_eq!((decoded.page.width, decoded.page.height), letter_at(150));
+        assert_eq!(decoded.sides, Sides::TwoSidedLongEdge);
+        assert_eq!(decoded.total_pages, Some(1));
+        assert_eq!(decoded.media.as_deref(), Some(media(&attributes, &PrintingSettings::default()).0.as_str()));
+
+        // The image is centered, so the middle of the page is red and the corner white
+        let (width, height) = (decoded.page.width as usize, decoded.page.height as usize);
+        let middle = decoded.page.row(height as u32 / 2);
+        assert_eq!(&middle[width / 2 * 3..width / 2 * 3 + 3], &[200, 30, 30]);
+        assert_eq!(&decoded.page.row(0)[..3], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn png_to_pwg_dithers_for_bilevel_printers() {
+        let attributes = printer(&[("pwg-raster-document-type-supported", keywords(&["black_1"]))]);
+        let data = convert_to(red_png(), "image/png", "image/pwg-raster", Sides::OneSided, &attributes);
+        let decoded = &raster::decode(&data).unwrap()[0];
+        assert_eq!(decoded.page.color, ColorMode::Black1);
+        assert_eq!(decoded.page.dpi, PREFERRED_DPI);
+        assert_eq!(decoded.sides, Sides::OneSided);
+    }
+
+    #[test]
+    fn png_to_urf_follows_urf_supported() {
+        let attributes = printer(&[("urf-supported", keywords(&["V1.4", "CP1", "W8", "RS150-600", "DM1"]))]);
+        let data = convert_to(red_png(), "image/png", "image/urf", Sides::TwoSidedShortEdge, &attributes);
+
+        let pages = raster::decode(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        let decoded = &pages[0];
+        // Gray only, and 300 dpi is not offered, so the lowest resolution is used
+        assert_eq!(raster_mode("image/urf", &attributes, true), (150, ColorMode::Gray8));
+        assert_eq!((decoded.page.dpi, decoded.page.color), (150, ColorMode::Gray8));
+        assert_eq!((decoded.page.width, decoded.page.height), letter_at(150));
+        assert_eq!(decoded.sides, Sides::TwoSidedShortEdge);
+    }
+
+    #[test]
+    fn text_to_pdf_has_a_consistent_xref_table() {
+        let attributes = printer(&[]);
+        let text = "first page\n(with parentheses)\x0csecond page";
+        let pdf = convert_to(text.as_bytes().to_vec(), "text/plain", "application/pdf", Sides::OneSided, &attributes);
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+
+        // Offsets count bytes, and the streams are binary, so search the raw bytes
+        let find = |needle: &[u8]| pdf.windows(needle.len()).rposition(|w| w == needle);
+        let at = |offset: usize, expected: &str| pdf[offset..].starts_with(expected.as_bytes());
+        let startxref = find(b"startxref\n").unwrap();
+        let tail = String::from_utf8(pdf[startxref..].to_vec()).unwrap();
+        let xref: usize = tail.lines().nth(1).unwrap().parse().unwrap();
+        assert!(at(xref, "xref\n0 "));
+
+        let table = String::from_utf8(pdf[xref..startxref].to_vec()).unwrap();
+        let mut lines = table.lines().skip(1);
+        let count: usize = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        // Catalog, page tree, font, and a page plus content stream for each of the two pages
+        assert_eq!(count, 1 + 3 + 2 * 2);
+        for number in 1..count {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len(), 19, "{:?}", entry);
+            assert!(entry.ends_with(" 00000 n "));
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(at(offset, &format!("{} 0 obj\n", number)), "object {} at {}", number, offset);
+        }
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains(&format!("/Size {} /Root 1 0 R", count)));
+        assert!(text.contains("/Count 2"));
+    }
+}
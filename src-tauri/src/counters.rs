@@ -0,0 +1,532 @@
+use crate::addr::HostAddr;
+use crate::inventory::{Inventory, InventoryStore};
+use crate::mib::resolve_oid;
+use crate::settings;
+use crate::validate;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use snmp::{SyncSession, Value};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use tauri::State;
+use tracing::warn;
+
+const PJL_TIMEOUT: Duration = Duration::from_secs(3);
+const PJL_PAGECOUNT: &[u8] = b"\x1b%-12345X@PJL INFO PAGECOUNT\r\n\x1b%-12345X";
+
+/// Enterprise-specific mono/color impression counters, keyed by the private enterprise
+/// number in `sysObjectID`. Best effort: models differ and missing OIDs are simply skipped.
+const VENDOR_COUNTERS: &[(u32, &str, &str)] = &[
+    // HP
+    (11, "1.3.6.1.4.1.11.2.3.9.4.2.1.4.1.2.6.0", "1.3.6.1.4.1.11.2.3.9.4.2.1.4.1.2.7.0"),
+    // Xerox
+    (253, "1.3.6.1.4.1.253.8.53.13.2.1.6.1.20.34", "1.3.6.1.4.1.253.8.53.13.2.1.6.1.20.33"),
+    // Kyocera
+    (1347, "1.3.6.1.4.1.1347.42.3.1.2.1.1.1.1", "1.3.6.1.4.1.1347.42.3.1.2.1.1.1.3"),
+];
+
+/// One reading of a printer's page counters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterSample {
+    pub at: DateTime<Utc>,
+    pub total: Option<u64>,
+    pub mono: Option<u64>,
+    pub color: Option<u64>,
+    /// Where the numbers came from: `snmp`, `snmp+vendor` or `pjl`.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRow {
+    pub printer_id: String,
+    pub printer_name: String,
+    pub location: Option<String>,
+    pub period: String,
+    pub pages: u64,
+    pub mono_pages: u64,
+    pub color_pages: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationUsage {
+    pub location: String,
+    pub pages: u64,
+    pub mono_pages: u64,
+    pub color_pages: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub period: Period,
+    pub rows: Vec<UsageRow>,
+    pub by_location: Vec<LocationUsage>,
+}
+
+fn number(value: Value) -> Option<u64> {
+    match value {
+        Value::Counter32(n) | Value::Unsigned32(n) => Some(u64::from(n)),
+        Value::Counter64(n) => Some(n),
+        Value::Integer(n) if n >= 0 => Some(n as u64),
+        _ => None,
+    }
+}
+
+fn get_number(session: &mut SyncSession, oid: &str) -> Option<u64> {
+    let oid = resolve_oid(oid).ok()?;
+    let mut pdu = session.get(&oid).ok()?;
+    pdu.varbinds.next().and_then(|(_, value)| number(value))
+}
+
+/// Private enterprise number from `sysObjectID` (`1.3.6.1.4.1.<enterprise>...`).
+fn enterprise(session: &mut SyncSession) -> Option<u32> {
+    let mut pdu = session.get(&[1, 3, 6, 1, 2, 1, 1, 2, 0]).ok()?;
+    let (_, value) = pdu.varbinds.next()?;
+    let Value::ObjectIdentifier(oid) = value else { return None };
+    let mut buf = [0u32; 128];
+    let arcs = oid.read_name(&mut buf).ok()?;
+    arcs.starts_with(&[1, 3, 6, 1, 4, 1]).then(|| arcs.get(6).copied()).flatten()
+}
+
+/// Reads the lifetime page counter from the Printer-MIB and vendor mono/color counters.
+/// With `pjl` set, falls back to PJL `INFO PAGECOUNT` on port 9100 when SNMP has nothing;
+/// that is only safe for printers known to speak PJL, which print anything else they get.
+pub fn collect_counters(ip: &str, community: &str, pjl: bool) -> Result<CounterSample, String> {
+    let addr = HostAddr::parse(ip).ok_or_else(|| format!("Invalid IP address: {}", ip))?;
+    let mut total = None;
+    let mut mono = None;
+    let mut color = None;
+    let mut source = "snmp";
+
+    if let Ok(mut session) = SyncSession::new(addr.socket_addr(161), community.as_bytes(), Some(settings::current().discovery.snmp_timeout()), 0) {
+        total = get_number(&mut session, "prtMarkerLifeCount.1.1");
+        if let Some(enterprise) = enterprise(&mut session) {
+            if let Some((_, mono_oid, color_oid)) = VENDOR_COUNTERS.iter().find(|(e, _, _)| *e == enterprise) {
+                mono = get_number(&mut session, mono_oid);
+                color = get_number(&mut session, color_oid);
+                if mono.is_some() || color.is_some() {
+                    source = "snmp+vendor";
+                }
+            }
+        }
+    }
+
+    if pjl && total.is_none() && mono.is_none() && color.is_none() {
+        total = pjl_page_count(&addr);
+        source = "pjl";
+    }
+    if total.is_none() && mono.is_none() && color.is_none() {
+        return Err(format!("No page counters available for {}", ip));
+    }
+
+    // Derive whichever split is missing when the total is known
+    match (total, mono, color) {
+        (Some(t), Some(m), None) if t >= m => color = Some(t - m),
+        (Some(t), None, Some(c)) if t >= c => mono = Some(t - c),
+        (None, Some(m), Some(c)) => total = Some(m + c),
+        _ => {}
+    }
+
+    Ok(CounterSample {
+        at: Utc::now(),
+        total,
+        mono,
+        color,
+        source: source.to_string(),
+    })
+}
+
+/// Asks the printer for its page count over PJL on the raw printing port.
+pub fn pjl_page_count(addr: &HostAddr) -> Option<u64> {
+    let mut stream = TcpStream::connect_timeout(&addr.socket_addr(9100), PJL_TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(PJL_TIMEOUT)).ok()?;
+    stream.write_all(PJL_PAGECOUNT).ok()?;
+
+    // The reply ends with a form feed
+    let mut reply = Vec::new();
+    let mut chunk = [0u8; 512];
+    while !reply.contains(&0x0c) && reply.len() < 8192 {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => reply.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    parse_pjl_page_count(&String::from_utf8_lossy(&reply))
+}
+
+/// Accepts both `PAGECOUNT=1234` and the `@PJL INFO PAGECOUNT` / `1234` two-line form.
+fn parse_pjl_page_count(reply: &str) -> Option<u64> {
+    let mut lines = reply.lines().skip_while(|line| !line.contains("PAGECOUNT"));
+    let header = lines.next()?;
+    if let Some((_, value)) = header.split_once('=') {
+        return value.trim().parse().ok();
+    }
+    lines
+        .map(|line| line.trim().trim_start_matches("PAGECOUNT=").trim())
+        .find(|line| !line.is_empty())
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+fn period_key(at: &DateTime<Utc>, period: Period) -> String {
+    match period {
+        Period::Day => at.format("%Y-%m-%d").to_string(),
+        Period::Week => at.format("%G-W%V").to_string(),
+        Period::Month => at.format("%Y-%m").to_string(),
+    }
+}
+
+fn delta(previous: Option<u64>, current: Option<u64>) -> Option<u64> {
+    // A counter that went backwards was reset or replaced; that interval is unknown
+    current?.checked_sub(previous?)
+}
+
+/// Pages printed between `from` and `to`, bucketed per printer and period. The pages
+/// between two samples are attributed to the period of the later one.
+pub fn usage_report(inventory: &Inventory, from: DateTime<Utc>, to: DateTime<Utc>, period: Period) -> UsageReport {
+    let mut rows: BTreeMap<(String, String), UsageRow> = BTreeMap::new();
+
+    for entry in &inventory.printers {
+        for pair in entry.counters.windows(2) {
+            let (previous, current) = (&pair[0], &pair[1]);
+            if current.at < from || current.at >= to {
+                continue;
+            }
+            let mono = delta(previous.mono, current.mono).unwrap_or(0);
+            let color = delta(previous.color, current.color).unwrap_or(0);
+            let pages = delta(previous.total, current.total).unwrap_or(mono + color);
+
+            let key = period_key(&current.at, period);
+            let row = rows.entry((entry.id.clone(), key.clone())).or_insert_with(|| UsageRow {
+                printer_id: entry.id.clone(),
+                printer_name: entry.display_name().to_string(),
+                location: entry.location.clone(),
+                period: key,
+                pages: 0,
+                mono_pages: 0,
+                color_pages: 0,
+            });
+            row.pages += pages;
+            row.mono_pages += mono;
+            row.color_pages += color;
+        }
+    }
+
+    let mut by_location: BTreeMap<String, LocationUsage> = BTreeMap::new();
+    for row in rows.values() {
+        let location = row.location.clone().unwrap_or_else(|| "Unassigned".to_string());
+        let usage = by_location.entry(location.clone()).or_insert_with(|| LocationUsage {
+            location,
+            pages: 0,
+            mono_pages: 0,
+            color_pages: 0,
+        });
+        usage.pages += row.pages;
+        usage.mono_pages += row.mono_pages;
+        usage.color_pages += row.color_pages;
+    }
+
+    UsageReport {
+        from,
+        to,
+        period,
+        rows: rows.into_values().collect(),
+        by_location: by_location.into_values().collect(),
+    }
+}
+
+/// Writes the report as CSV (one line per printer and period) or as JSON.
+pub fn export_report(report: &UsageReport, format: &str, path: &str) -> Result<(), String> {
+    match format.to_ascii_lowercase().as_str() {
+        "csv" => {
+            let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            for row in &report.rows {
+                writer.serialize(row).map_err(|e| format!("Failed to write CSV: {}", e))?;
+            }
+            writer.flush().map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        "json" => {
+            let json = serde_json::to_string_pretty(report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+            std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        other => Err(format!("Unsupported export format '{}' (expected csv or json)", other)),
+    }
+}
+
+/// Accepts `YYYY-MM-DD` (midnight UTC) or a full RFC 3339 timestamp.
+pub fn parse_date(input: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    DateTime::parse_from_rfc3339(input)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid date '{}': use YYYY-MM-DD or RFC 3339", input))
+}
+
+/// Polls counters right away for one printer, or every networked printer when `id` is
+/// omitted, and stores the samples.
+#[tauri::command]
+pub fn collect_page_counters(store: State<'_, InventoryStore>, id: Option<String>) -> Result<Vec<CounterSample>, String> {
+    if let Some(id) = &id {
+        validate::id(id)?;
+    }
+    let targets: Vec<(String, String, bool)> = store
+        .snapshot()
+        .printers
+        .iter()
+        .filter(|entry| id.as_ref().is_none_or(|id| &entry.id == id))
+        .filter_map(|entry| Some((entry.id.clone(), entry.address()?.to_string(), entry.pjl_counters)))
+        .collect();
+
+    let mut collected = Vec::new();
+    for (printer_id, ip, pjl) in targets {
+        match collect_counters(&ip, &settings::current().discovery.snmp_community, pjl) {
+            Ok(sample) => collected.push((printer_id, sample)),
+            Err(e) => warn!(ip = %ip, "counter collection failed: {}", e),
+        }
+    }
+
+    store.update(|inventory| {
+        for (printer_id, sample) in &collected {
+            if let Some(entry) = inventory.get_mut(printer_id) {
+                entry.push_counter(sample.clone());
+            }
+        }
+    })?;
+    Ok(collected.into_iter().map(|(_, sample)| sample).collect())
+}
+
+#[tauri::command]
+pub fn get_usage_report(
+    store: State<'_, InventoryStore>,
+    from: String,
+    to: String,
+    period: Period,
+) -> Result<UsageReport, String> {
+    Ok(usage_report(&store.snapshot(), parse_date(&from)?, parse_date(&to)?, period))
+}
+
+#[tauri::command]
+pub fn export_usage_report(
+    store: State<'_, InventoryStore>,
+    format: String,
+    path: String,
+    from: String,
+    to: String,
+    period: Period,
+) -> Result<(), String> {
+    let path = validate::export_file(&path, &format)?;
+    let report = usage_report(&store.snapshot(), parse_date(&from)?, parse_date(&to)?, period);
+    export_report(&report, &format, &path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::InventoryEntry;
+    use crate::printer::NetworkPrinter;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        parse_date(text).unwrap()
+    }
+
+    fn sample(when: &str, total: Option<u64>, mono: Option<u64>, color: Option<u64>) -> CounterSample {
+        CounterSample { at: at(when), total, mono, color, source: "snmp".to_string() }
+    }
+
+    fn entry(id: &str, location: Option<&str>, counters: Vec<CounterSample>) -> InventoryEntry {
+        InventoryEntry {
+            id: id.to_string(),
+            printer: NetworkPrinter { name: format!("Printer {}", id), ip_address: "192.168.1.20".to_string(), ..Default::default() },
+            location: location.map(str::to_string),
+            tags: Vec::new(),
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+            counters,
+            supplies: Vec::new(),
+            sources: Vec::new(),
+            pjl_counters: false,
+        }
+    }
+
+    fn inventory(printers: Vec<InventoryEntry>) -> Inventory {
+        Inventory { printers, ..Default::default() }
+    }
+
+    fn pages(report: &UsageReport) -> Vec<(&str, &str, u64, u64, u64)> {
+        report
+            .rows
+            .iter()
+            .map(|row| (row.printer_id.as_str(), row.period.as_str(), row.pages, row.mono_pages, row.color_pages))
+            .collect()
+    }
+
+    #[test]
+    fn deltas_are_unknown_when_a_counter_resets() {
+        assert_eq!(delta(Some(100), Some(150)), Some(50));
+        assert_eq!(delta(Some(150), Some(150)), Some(0));
+        assert_eq!(delta(Some(150), Some(20)), None);
+        assert_eq!(delta(None, Some(20)), None);
+        assert_eq!(delta(Some(20), None), None);
+    }
+
+    #[test]
+    fn pages_go_to_the_period_of_the_later_sample() {
+        let report = usage_report(
+            &inventory(vec![entry(
+                "a",
+                Some("Floor 1"),
+                vec![
+                    sample("2024-03-01T08:00:00Z", Some(1000), Some(800), Some(200)),
+                    sample("2024-03-01T17:00:00Z", Some(1100), Some(870), Some(230)),
+                    sample("2024-03-02T17:00:00Z", Some(1150), Some(900), Some(250)),
+                    sample("2024-03-02T18:00:00Z", Some(1160), Some(905), Some(255)),
+                ],
+            )]),
+            at("2024-03-01"),
+            at("2024-04-01"),
+            Period::Day,
+        );
+        assert_eq!(pages(&report), vec![("a", "2024-03-01", 100, 70, 30), ("a", "2024-03-02", 60, 35, 25)]);
+        assert_eq!(report.rows[0].printer_name, "Printer a");
+        assert_eq!(report.rows[0].location.as_deref(), Some("Floor 1"));
+    }
+
+    #[test]
+    fn reset_counters_skip_only_their_interval() {
+        let report = usage_report(
+            &inventory(vec![entry(
+                "a",
+                None,
+                vec![
+                    sample("2024-03-01T08:00:00Z", Some(5000), Some(4000), Some(1000)),
+                    // Board replaced: every counter starts again
+                    sample("2024-03-01T09:00:00Z", Some(20), Some(15), Some(5)),
+                    sample("2024-03-01T10:00:00Z", Some(50), Some(40), Some(10)),
+                ],
+            )]),
+            at("2024-03-01"),
+            at("2024-03-02"),
+            Period::Day,
+        );
+        assert_eq!(pages(&report), vec![("a", "2024-03-01", 30, 25, 5)]);
+
+        // Only the total reset: the mono and color deltas still count the pages
+        let report = usage_report(
+            &inventory(vec![entry(
+                "b",
+                None,
+                vec![
+                    sample("2024-03-01T08:00:00Z", Some(5000), Some(4000), Some(1000)),
+                    sample("2024-03-01T09:00:00Z", Some(12), Some(4010), Some(1002)),
+                ],
+            )]),
+            at("2024-03-01"),
+            at("2024-03-02"),
+            Period::Day,
+        );
+        assert_eq!(pages(&report), vec![("b", "2024-03-01", 12, 10, 2)]);
+    }
+
+    #[test]
+    fn missing_counters_fall_back_to_what_is_known() {
+        let report = usage_report(
+            &inventory(vec![
+                // Mono printer without a total
+                entry(
+                    "mono",
+                    None,
+                    vec![
+                        sample("2024-03-01T08:00:00Z", None, Some(300), None),
+                        sample("2024-03-01T09:00:00Z", None, Some(340), None),
+                    ],
+                ),
+                // PJL only reports the total
+                entry(
+                    "pjl",
+                    None,
+                    vec![
+                        sample("2024-03-01T08:00:00Z", Some(70), None, None),
+                        sample("2024-03-01T09:00:00Z", Some(75), None, None),
+                    ],
+                ),
+                // A single sample has no interval yet
+                entry("new", None, vec![sample("2024-03-01T08:00:00Z", Some(10), None, None)]),
+            ]),
+            at("2024-03-01"),
+            at("2024-03-02"),
+            Period::Day,
+        );
+        assert_eq!(pages(&report), vec![("mono", "2024-03-01", 40, 40, 0), ("pjl", "2024-03-01", 5, 0, 0)]);
+    }
+
+    #[test]
+    fn report_range_is_half_open_on_the_later_sample() {
+        let counters = vec![
+            sample("2024-02-29T20:00:00Z", Some(100), None, None),
+            sample("2024-03-01T00:00:00Z", Some(110), None, None),
+            sample("2024-03-01T12:00:00Z", Some(130), None, None),
+            sample("2024-03-02T00:00:00Z", Some(170), None, None),
+        ];
+        let report = usage_report(&inventory(vec![entry("a", None, counters)]), at("2024-03-01"), at("2024-03-02"), Period::Day);
+        // The interval ending at `from` counts; the one ending at `to` belongs to the next day
+        assert_eq!(pages(&report), vec![("a", "2024-03-01", 30, 0, 0)]);
+    }
+
+    #[test]
+    fn periods_and_locations_are_aggregated() {
+        let counters = |start: u64| {
+            vec![
+                sample("2024-12-29T12:00:00Z", Some(start), Some(start), Some(0)),
+                sample("2024-12-30T12:00:00Z", Some(start + 10), Some(start + 8), Some(2)),
+                sample("2025-01-02T12:00:00Z", Some(start + 25), Some(start + 20), Some(5)),
+            ]
+        };
+        let inventory = inventory(vec![
+            entry("a", Some("Floor 1"), counters(0)),
+            entry("b", Some("Floor 1"), counters(1000)),
+            entry("c", None, counters(50)),
+        ]);
+
+        // 2024-12-30 is in ISO week 1 of 2025
+        let report = usage_report(&inventory, at("2024-12-01"), at("2025-02-01"), Period::Week);
+        assert_eq!(pages(&report)[0], ("a", "2025-W01", 25, 20, 5));
+        let report = usage_report(&inventory, at("2024-12-01"), at("2025-02-01"), Period::Month);
+        assert_eq!(pages(&report)[..2], [("a", "2024-12", 10, 8, 2), ("a", "2025-01", 15, 12, 3)]);
+
+        let locations: Vec<(&str, u64, u64, u64)> = report
+            .by_location
+            .iter()
+            .map(|usage| (usage.location.as_str(), usage.pages, usage.mono_pages, usage.color_pages))
+            .collect();
+        assert_eq!(locations, vec![("Floor 1", 50, 40, 10), ("Unassigned", 25, 20, 5)]);
+    }
+
+    #[test]
+    fn pjl_page_counts_parse_both_reply_forms() {
+        assert_eq!(parse_pjl_page_count("@PJL INFO PAGECOUNT\r\nPAGECOUNT=12345\r\n\x0c"), Some(12345));
+        assert_eq!(parse_pjl_page_count("@PJL INFO PAGECOUNT\r\n  6789 \r\n\x0c"), Some(6789));
+        assert_eq!(parse_pjl_page_count("@PJL INFO PAGECOUNT=42\r\n"), Some(42));
+        assert_eq!(parse_pjl_page_count("@PJL INFO PAGECOUNT\r\n?\r\n"), None);
+        assert_eq!(parse_pjl_page_count("@PJL INFO STATUS\r\nCODE=10001\r\n"), None);
+    }
+
+    #[test]
+    fn dates_accept_days_and_timestamps() {
+        assert_eq!(parse_date("2024-03-01").unwrap().to_rfc3339(), "2024-03-01T00:00:00+00:00");
+        assert_eq!(parse_date("2024-03-01T10:00:00+02:00").unwrap().to_rfc3339(), "2024-03-01T08:00:00+00:00");
+        assert!(parse_date("03/01/2024").is_err());
+    }
+}
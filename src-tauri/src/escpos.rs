@@ -0,0 +1,559 @@
+use crate::addr::HostAddr;
+use crate::convert;
+use crate::inventory::InventoryStore;
+use crate::printing::{self, JobHistory, JobRecord};
+use crate::raster::{ColorMode, RasterPage};
+use crate::raw;
+use crate::text_pdf;
+use crate::validate;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::State;
+use tracing::{info, warn};
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+/// MIME type receipts are logged under in the job history.
+pub const ESCPOS_FORMAT: &str = "application/vnd.escpos";
+/// DLE EOT for the printer, offline cause and paper sensor status bytes.
+const STATUS_REQUEST: [u8; 9] = [DLE, EOT, 1, DLE, EOT, 2, DLE, EOT, 4];
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+/// Printers limit the height of one raster image command; taller images go in bands.
+const IMAGE_BAND_ROWS: u32 = 256;
+const MAX_QR_BYTES: usize = 7089;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextStyle {
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub underline: bool,
+    /// White on black.
+    #[serde(default)]
+    pub invert: bool,
+    #[serde(default)]
+    pub double_width: bool,
+    #[serde(default)]
+    pub double_height: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BarcodeKind {
+    UpcA,
+    Ean13,
+    Ean8,
+    Code39,
+    Itf,
+    Code128,
+}
+
+impl BarcodeKind {
+    /// `m` of the `GS k` function B form.
+    fn code(self) -> u8 {
+        match self {
+            BarcodeKind::UpcA => 65,
+            BarcodeKind::Ean13 => 67,
+            BarcodeKind::Ean8 => 68,
+            BarcodeKind::Code39 => 69,
+            BarcodeKind::Itf => 70,
+            BarcodeKind::Code128 => 73,
+        }
+    }
+
+    fn check(self, data: &str) -> Result<(), String> {
+        let digits = data.bytes().all(|b| b.is_ascii_digit());
+        let valid = match self {
+            BarcodeKind::UpcA => digits && (11..=12).contains(&data.len()),
+            BarcodeKind::Ean13 => digits && (12..=13).contains(&data.len()),
+            BarcodeKind::Ean8 => digits && (7..=8).contains(&data.len()),
+            BarcodeKind::Code39 => data
+                .bytes()
+                .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%+-./".contains(&b)),
+            BarcodeKind::Itf => digits && data.len().is_multiple_of(2),
+            BarcodeKind::Code128 => data.bytes().all(|b| (32..127).contains(&b)),
+        };
+        if !valid || data.is_empty() || data.len() > 253 {
+            return Err(format!("'{}' cannot be encoded as a {:?} barcode", data, self));
+        }
+        Ok(())
+    }
+}
+
+/// ESC/POS command stream for thermal receipt printers. Text is sent in Windows-1252,
+/// the code page nearly all of them have.
+pub struct EscPos {
+    data: Vec<u8>,
+}
+
+impl Default for EscPos {
+    fn default() -> Self {
+        EscPos::new()
+    }
+}
+
+impl EscPos {
+    /// Starts with a printer reset and the code page selection.
+    pub fn new() -> EscPos {
+        EscPos {
+            data: vec![ESC, b'@', ESC, b't', 16],
+        }
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            match c {
+                '\n' => self.data.push(b'\n'),
+                c if c.is_control() => {}
+                c => self.data.push(text_pdf::win_ansi(c)),
+            }
+        }
+        self
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text);
+        self.data.push(b'\n');
+        self
+    }
+
+    pub fn style(&mut self, style: TextStyle) -> &mut Self {
+        let size = (style.double_width as u8) << 4 | style.double_height as u8;
+        self.data.extend_from_slice(&[
+            ESC,
+            b'E',
+            style.bold as u8,
+            ESC,
+            b'-',
+            style.underline as u8,
+            GS,
+            b'B',
+            style.invert as u8,
+            GS,
+            b'!',
+            size,
+        ]);
+        self
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        self.data.extend_from_slice(&[ESC, b'a', align as u8]);
+        self
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.data.extend_from_slice(&[ESC, b'd', lines]);
+        self
+    }
+
+    /// A barcode `height` dots tall with its text printed below.
+    pub fn barcode(&mut self, kind: BarcodeKind, data: &str, height: u8) -> Result<&mut Self, String> {
+        kind.check(data)?;
+        let mut payload = data.as_bytes().to_vec();
+        if kind == BarcodeKind::Code128 {
+            // Code set B covers printable ASCII
+            payload.splice(0..0, *b"{B");
+        }
+        self.data.extend_from_slice(&[GS, b'h', height.max(1), GS, b'w', 2, GS, b'H', 2, GS, b'f', 0]);
+        self.data.extend_from_slice(&[GS, b'k', kind.code(), payload.len() as u8]);
+        self.data.extend_from_slice(&payload);
+        self.data.push(b'\n');
+        Ok(self)
+    }
+
+    /// A model 2 QR code with modules `module_size` dots wide (1 to 16), error
+    /// correction level M.
+    pub fn qr(&mut self, data: &str, module_size: u8) -> Result<&mut Self, String> {
+        if data.is_empty() || data.len() > MAX_QR_BYTES {
+            return Err(format!("QR code data must be 1 to {} bytes", MAX_QR_BYTES));
+        }
+        let store = (data.len() + 3) as u16;
+        self.data.extend_from_slice(&[GS, b'(', b'k', 4, 0, 49, 65, 50, 0]);
+        self.data.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 67, module_size.clamp(1, 16)]);
+        self.data.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 69, 49]);
+        self.data.extend_from_slice(&[GS, b'(', b'k', store as u8, (store >> 8) as u8, 49, 80, 48]);
+        self.data.extend_from_slice(data.as_bytes());
+        self.data.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 81, 48]);
+        Ok(self)
+    }
+
+    /// `image` dithered to black and white, scaled down to at most `max_width` dots.
+    pub fn image(&mut self, image: &DynamicImage, max_width: u32) -> &mut Self {
+        let image = if image.width() > max_width {
+            let height = (image.height() as u64 * max_width as u64 / image.width() as u64).max(1) as u32;
+            image.resize_exact(max_width, height, FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+        let gray = DynamicImage::ImageRgb8(convert::on_white(&image)).to_luma8();
+        let mut page = RasterPage::blank(gray.width(), gray.height(), 203, ColorMode::Black1);
+        convert::dither_into(&gray, &mut page, 0, 0);
+
+        let bytes_per_line = page.width.div_ceil(8);
+        let mut top = 0;
+        while top < page.height {
+            let rows = IMAGE_BAND_ROWS.min(page.height - top);
+            self.data.extend_from_slice(&[
+                GS,
+                b'v',
+                b'0',
+                0,
+                bytes_per_line as u8,
+                (bytes_per_line >> 8) as u8,
+                rows as u8,
+                (rows >> 8) as u8,
+            ]);
+            for y in top..top + rows {
+                self.data.extend_from_slice(page.row(y));
+            }
+            top += rows;
+        }
+        self
+    }
+
+    /// Feeds the paper past the cutter and cuts it, leaving a tab with `partial`.
+    pub fn cut(&mut self, partial: bool) -> &mut Self {
+        self.data.extend_from_slice(&[GS, b'V', if partial { 66 } else { 65 }, 3]);
+        self
+    }
+
+    /// Pulses the cash drawer kick connector.
+    pub fn open_drawer(&mut self) -> &mut Self {
+        self.data.extend_from_slice(&[ESC, b'p', 0, 25, 250]);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// One element of a receipt, as sent by the frontend or a POS integration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReceiptItem {
+    Text {
+        text: String,
+        #[serde(default)]
+        align: Align,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+    /// `left` and `right` on one line, e.g. an item and its price.
+    Row {
+        left: String,
+        right: String,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+    /// A dashed line across the paper.
+    Separator,
+    Feed {
+        lines: u8,
+    },
+    Barcode {
+        kind: BarcodeKind,
+        data: String,
+        #[serde(default = "default_barcode_height")]
+        height: u8,
+    },
+    Qr {
+        data: String,
+        #[serde(default = "default_qr_size")]
+        size: u8,
+    },
+    /// A PNG or JPEG logo from disk.
+    Image {
+        path: String,
+    },
+}
+
+fn default_barcode_height() -> u8 {
+    80
+}
+
+fn default_qr_size() -> u8 {
+    6
+}
+
+fn default_paper_mm() -> u32 {
+    80
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Roll width: 58 or 80 mm.
+    #[serde(default = "default_paper_mm")]
+    pub paper_mm: u32,
+    pub items: Vec<ReceiptItem>,
+    #[serde(default = "default_true")]
+    pub cut: bool,
+    #[serde(default)]
+    pub open_drawer: bool,
+}
+
+impl Receipt {
+    /// Printable width in dots and characters per line of the standard font.
+    fn width(&self) -> Result<(u32, usize), String> {
+        match self.paper_mm {
+            58 => Ok((384, 32)),
+            80 => Ok((576, 48)),
+            other => Err(format!("Unsupported paper width {} mm (use 58 or 80)", other)),
+        }
+    }
+}
+
+/// `left` then `right` pushed to the right edge of a `columns` wide line; `left` gets a
+/// line of its own when both do not fit.
+fn row_text(left: &str, right: &str, columns: usize) -> String {
+    let (left_len, right_len) = (left.chars().count(), right.chars().count());
+    if left_len + 1 + right_len <= columns {
+        format!("{}{}{}", left, " ".repeat(columns - left_len - right_len), right)
+    } else {
+        format!("{}\n{:>width$}", left, right, width = columns)
+    }
+}
+
+/// Encodes `receipt` into the ESC/POS bytes to send.
+pub fn render_receipt(receipt: &Receipt) -> Result<Vec<u8>, String> {
+    let (dots, columns) = receipt.width()?;
+    let mut out = EscPos::new();
+    for item in &receipt.items {
+        match item {
+            ReceiptItem::Text { text, align, style } => {
+                out.align(*align).style(*style).line(text);
+            }
+            ReceiptItem::Row { left, right, style } => {
+                let columns = if style.double_width { columns / 2 } else { columns };
+                out.align(Align::Left).style(*style).line(&row_text(left, right, columns));
+            }
+            ReceiptItem::Separator => {
+                out.align(Align::Left).style(TextStyle::default()).line(&"-".repeat(columns));
+            }
+            ReceiptItem::Feed { lines } => {
+                out.feed(*lines);
+            }
+            ReceiptItem::Barcode { kind, data, height } => {
+                out.align(Align::Center).style(TextStyle::default()).barcode(*kind, data, *height)?;
+            }
+            ReceiptItem::Qr { data, size } => {
+                out.align(Align::Center).qr(data, *size)?.feed(1);
+            }
+            ReceiptItem::Image { path } => {
+                let path = validate::input_file(path, &["png", "jpg", "jpeg"])?;
+                let image = image::open(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                out.align(Align::Center).image(&image, dots);
+            }
+        }
+    }
+    out.align(Align::Left).style(TextStyle::default());
+    if receipt.open_drawer {
+        out.open_drawer();
+    }
+    if receipt.cut {
+        out.feed(3).cut(true);
+    }
+    Ok(out.into_bytes())
+}
+
+/// Receipt printer state from the DLE EOT status bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptPrinterStatus {
+    pub online: bool,
+    pub cover_open: bool,
+    pub paper_out: bool,
+    pub paper_near_end: bool,
+    /// A cutter jam, overheating or another fault that stopped printing.
+    pub error: bool,
+}
+
+impl ReceiptPrinterStatus {
+    /// Why the printer cannot print right now, if anything stops it.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.cover_open {
+            Some("the cover is open")
+        } else if self.paper_out {
+            Some("it is out of paper")
+        } else if self.error {
+            Some("it reports an error")
+        } else if !self.online {
+            Some("it is offline")
+        } else {
+            None
+        }
+    }
+}
+
+/// The status bytes in a reply to `STATUS_REQUEST`. Every status byte has bits 1 and 4
+/// set and bits 0 and 7 clear, which tells them apart from other data.
+fn status_bytes(reply: &[u8]) -> Vec<u8> {
+    reply.iter().copied().filter(|b| b & 0x93 == 0x12).collect()
+}
+
+/// Parses the replies to `STATUS_REQUEST`.
+pub fn parse_status(reply: &[u8]) -> Result<ReceiptPrinterStatus, String> {
+    let [printer, offline, paper] = status_bytes(reply)[..] else {
+        return Err(format!("Unexpected status reply {:02x?}", reply));
+    };
+    Ok(ReceiptPrinterStatus {
+        online: printer & 0x08 == 0,
+        cover_open: offline & 0x04 != 0,
+        paper_out: offline & 0x20 != 0 || paper & 0x60 != 0,
+        paper_near_end: paper & 0x0c != 0,
+        error: offline & 0x40 != 0,
+    })
+}
+
+pub fn query_status(addr: &HostAddr) -> Result<ReceiptPrinterStatus, String> {
+    // One byte answers each of the three requests; no need to wait out the timeout after that
+    let complete = |reply: &[u8]| status_bytes(reply).len() >= 3;
+    let reply = raw::query_until(addr, raw::RAW_PORT, &STATUS_REQUEST, STATUS_TIMEOUT, complete)?;
+    parse_status(&reply)
+}
+
+#[tauri::command]
+pub fn get_receipt_printer_status(
+    inventory: State<'_, InventoryStore>,
+    printer_id: String,
+) -> Result<ReceiptPrinterStatus, String> {
+    let (_, addr) = printing::network_printer(&inventory.snapshot(), &printer_id)?;
+    query_status(&addr)
+}
+
+/// Prints `receipt` on the ESC/POS printer `printer_id` over the raw port. The printer's
+/// status is checked first so a missing roll or open cover is reported instead of the
+/// job silently waiting; printers that do not answer status requests get the job anyway.
+#[tauri::command]
+pub fn print_receipt(
+    inventory: State<'_, InventoryStore>,
+    history: State<'_, JobHistory>,
+    printer_id: String,
+    receipt: Receipt,
+) -> Result<JobRecord, String> {
+    let (entry, addr) = printing::network_printer(&inventory.snapshot(), &printer_id)?;
+    let data = render_receipt(&receipt)?;
+    let user = printing::current_user();
+    let mut record = JobRecord::new(&entry, &user, "Receipt", ESCPOS_FORMAT, 1, data.len());
+    record.uri = Some(format!("socket://{}:{}", addr.url_host(), raw::RAW_PORT));
+
+    let result = match query_status(&addr) {
+        Ok(status) => match status.problem() {
+            Some(problem) => Err(format!("{} cannot print: {}", entry.display_name(), problem)),
+            None => {
+                if status.paper_near_end {
+                    warn!(printer = %entry.id, "receipt paper is running low");
+                }
+                raw::send(&addr, raw::RAW_PORT, &data)
+            }
+        },
+        Err(e) => {
+            warn!(printer = %entry.id, "no status from receipt printer, printing anyway: {}", e);
+            raw::send(&addr, raw::RAW_PORT, &data)
+        }
+    };
+    if let Err(e) = &result {
+        warn!(printer = %entry.id, "receipt failed: {}", e);
+        record.error = Some(e.clone());
+    } else {
+        info!(printer = %entry.id, bytes = data.len(), "receipt printed");
+    }
+    if let Err(e) = history.append(&record) {
+        warn!("{}", e);
+    }
+    result.map(|_| record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The commands after the reset and code page selection every stream starts with.
+    fn commands(out: EscPos) -> Vec<u8> {
+        out.into_bytes()[5..].to_vec()
+    }
+
+    #[test]
+    fn status_bits_are_decoded() {
+        let ready = parse_status(&[0x12, 0x12, 0x12]).unwrap();
+        assert_eq!(ready, ReceiptPrinterStatus { online: true, ..Default::default() });
+        assert_eq!(ready.problem(), None);
+
+        let offline = parse_status(&[0x1a, 0x12, 0x12]).unwrap();
+        assert!(!offline.online);
+        assert_eq!(offline.problem(), Some("it is offline"));
+        assert!(parse_status(&[0x12, 0x16, 0x12]).unwrap().cover_open);
+        assert!(parse_status(&[0x12, 0x32, 0x12]).unwrap().paper_out);
+        assert!(parse_status(&[0x12, 0x12, 0x72]).unwrap().paper_out);
+        assert!(parse_status(&[0x12, 0x52, 0x12]).unwrap().error);
+        let low = parse_status(&[0x12, 0x12, 0x1e]).unwrap();
+        assert!(low.paper_near_end && low.problem().is_none());
+
+        // Other bytes in the reply are skipped, a missing status byte is an error
+        assert!(parse_status(&[0x00, 0x12, 0xff, 0x12, 0x0a, 0x12]).unwrap().online);
+        assert!(parse_status(&[0x12, 0x12]).is_err());
+    }
+
+    #[test]
+    fn rows_push_the_right_column_to_the_edge() {
+        assert_eq!(row_text("Coffee", "3.50", 12), "Coffee  3.50");
+        assert_eq!(row_text("Espresso", "3.50", 13), "Espresso 3.50");
+        assert_eq!(row_text("Oat milk latte", "4.20", 12), "Oat milk latte\n        4.20");
+        assert_eq!(row_text("Crème", "€2", 8), "Crème €2");
+    }
+
+    #[test]
+    fn barcodes_use_function_b() {
+        let mut out = EscPos::new();
+        out.barcode(BarcodeKind::Ean13, "4006381333931", 80).unwrap();
+        let mut expected = vec![GS, b'h', 80, GS, b'w', 2, GS, b'H', 2, GS, b'f', 0, GS, b'k', 67, 13];
+        expected.extend_from_slice(b"4006381333931\n");
+        assert_eq!(commands(out), expected);
+
+        let mut out = EscPos::new();
+        out.barcode(BarcodeKind::Code128, "AB", 0).unwrap();
+        assert_eq!(&commands(out)[..3], &[GS, b'h', 1]);
+
+        let mut out = EscPos::new();
+        out.barcode(BarcodeKind::Code128, "AB", 50).unwrap();
+        assert!(commands(out).ends_with(&[GS, b'k', 73, 4, b'{', b'B', b'A', b'B', b'\n']));
+
+        assert!(EscPos::new().barcode(BarcodeKind::Ean13, "40063813", 80).is_err());
+        assert!(EscPos::new().barcode(BarcodeKind::Itf, "123", 80).is_err());
+        assert!(EscPos::new().barcode(BarcodeKind::Code39, "lower", 80).is_err());
+    }
+
+    #[test]
+    fn qr_codes_are_stored_then_printed() {
+        let mut out = EscPos::new();
+        out.qr("hi", 20).unwrap();
+        assert_eq!(
+            commands(out),
+            [
+                GS, b'(', b'k', 4, 0, 49, 65, 50, 0, // model 2
+                GS, b'(', b'k', 3, 0, 49, 67, 16, // module size, clamped
+                GS, b'(', b'k', 3, 0, 49, 69, 49, // error correction M
+                GS, b'(', b'k', 5, 0, 49, 80, 48, b'h', b'i', // store
+                GS, b'(', b'k', 3, 0, 49, 81, 48, // print
+            ]
+        );
+        assert!(EscPos::new().qr("", 6).is_err());
+        assert!(EscPos::new().qr(&"x".repeat(MAX_QR_BYTES + 1), 6).is_err());
+    }
+}
@@ -0,0 +1,438 @@
+use crate::addr::HostAddr;
+use crate::settings;
+use crate::trust;
+use regex::Regex;
+use reqwest::blocking::Client;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use tracing::debug;
+
+/// Rules shipped with the app. Custom rules loaded at runtime are tried first.
+const BUILTIN_RULES: &str = include_str!("../data/fingerprint_rules.json");
+
+/// Page titles that say nothing about the device and must never be reported as a model.
+const GENERIC_TITLES: &[&str] = &[
+    "home",
+    "login",
+    "log in",
+    "index",
+    "welcome",
+    "top page",
+    "status",
+    "web image monitor",
+    "remote ui",
+    "embedded web server",
+    "device status",
+    "redirect",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    /// Base URL (scheme and host) the fingerprint was taken from.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FingerprintRule {
+    pub vendor: String,
+    #[serde(default)]
+    pub detect: Detect,
+    #[serde(default)]
+    pub probes: Vec<Probe>,
+}
+
+/// Signals that a landing page belongs to a vendor. Every match adds to the rule's score.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Detect {
+    /// Lower-case header name to regex.
+    #[serde(default)]
+    pub headers: HashMap<String, Pattern>,
+    /// Regexes matched against the raw landing page body.
+    #[serde(default)]
+    pub body: Vec<Pattern>,
+}
+
+/// A page to fetch and the fields to pull out of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Probe {
+    pub path: String,
+    /// Keyed by `model`, `serial` or `firmware`.
+    #[serde(default)]
+    pub fields: HashMap<String, Extractor>,
+}
+
+/// Where to read a value from. `header` reads a response header, `selector` narrows the
+/// body to the text (or `attr`) of the first matching element, and `regex` takes its first
+/// capture group from whatever was read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Extractor {
+    pub header: Option<String>,
+    pub selector: Option<CssSelector>,
+    pub attr: Option<String>,
+    pub regex: Option<Pattern>,
+}
+
+/// A regex compiled when the rule set is parsed. Serialized as its source text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        Regex::new(&source)
+            .map(Pattern)
+            .map_err(|e| format!("bad regex '{}': {}", source, e))
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> String {
+        pattern.0.as_str().to_string()
+    }
+}
+
+/// A CSS selector parsed when the rule set is parsed. Serialized as its source text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CssSelector {
+    source: String,
+    selector: Selector,
+}
+
+impl TryFrom<String> for CssSelector {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        let selector = Selector::parse(&source).map_err(|e| format!("bad selector '{}': {:?}", source, e))?;
+        Ok(CssSelector { source, selector })
+    }
+}
+
+impl From<CssSelector> for String {
+    fn from(selector: CssSelector) -> String {
+        selector.source
+    }
+}
+
+struct Page {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+fn rules() -> &'static RwLock<Vec<FingerprintRule>> {
+    static RULES: OnceLock<RwLock<Vec<FingerprintRule>>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        let builtin = parse_rules(BUILTIN_RULES).expect("built-in fingerprint rules are invalid");
+        RwLock::new(builtin)
+    })
+}
+
+/// Parses and validates a JSON rule set. Every regex and selector is compiled here, once, so
+/// a bad custom file is rejected up front instead of silently never matching.
+pub fn parse_rules(json: &str) -> Result<Vec<FingerprintRule>, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid fingerprint rules: {}", e))
+}
+
+/// Loads additional rules from a JSON file. They take precedence over the built-in set;
+/// loading a file again replaces rules for the same vendors.
+pub fn load_rules_file(path: &str) -> Result<usize, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let custom = parse_rules(&json)?;
+    let count = custom.len();
+
+    let mut active = rules().write().unwrap();
+    active.retain(|r| !custom.iter().any(|c| c.vendor.eq_ignore_ascii_case(&r.vendor)));
+    let mut merged = custom;
+    merged.extend(active.drain(..));
+    *active = merged;
+
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn load_fingerprint_rules(path: String) -> Result<usize, String> {
+    let path = crate::validate::input_file(&path, &["json"])?;
+    load_rules_file(&path.to_string_lossy())
+}
+
+/// Identifies the printer at `addr` from its embedded web server, trying plain HTTP
+/// first and then HTTPS. Self-signed certificates are pinned on first use by the trust store.
+pub fn fingerprint_host(addr: &HostAddr) -> Option<Fingerprint> {
+    // Certificates are only checked for HTTPS, which is tried on its default port
+    let client = trust::http_client(settings::current().discovery.http_timeout(), addr, 443).ok()?;
+    let rules = rules().read().unwrap().clone();
+
+    for scheme in ["http", "https"] {
+        let base = format!("{}://{}", scheme, addr.request_host());
+        let landing = match fetch(&client, &base, "/") {
+            Some(page) => page,
+            None => continue,
+        };
+        if let Some(mut fingerprint) = identify(|path| fetch(&client, &base, path), &landing, &rules) {
+            fingerprint.url = Some(format!("{}://{}", scheme, addr.url_host()));
+            return Some(fingerprint);
+        }
+    }
+
+    None
+}
+
+/// Picks the best-scoring rule for the landing page and runs its probes, fetching any page
+/// other than `/` through `fetch`.
+fn identify(fetch: impl Fn(&str) -> Option<Page>, landing: &Page, rules: &[FingerprintRule]) -> Option<Fingerprint> {
+    let mut candidates: Vec<(usize, &FingerprintRule)> = rules
+        .iter()
+        .map(|rule| (detection_score(rule, landing), rule))
+        .filter(|(score, _)| *score > 0)
+        .collect();
+    candidates.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    if let Some((_, rule)) = candidates.first() {
+        let mut fingerprint = Fingerprint {
+            vendor: Some(rule.vendor.clone()),
+            ..Default::default()
+        };
+        for probe in &rule.probes {
+            let fetched;
+            let page = if probe.path == "/" {
+                landing
+            } else {
+                fetched = match fetch(&probe.path) {
+                    Some(page) => page,
+                    None => continue,
+                };
+                &fetched
+            };
+            apply_probe(probe, page, &mut fingerprint);
+            if fingerprint.model.is_some() && fingerprint.serial.is_some() && fingerprint.firmware.is_some() {
+                break;
+            }
+        }
+        if fingerprint.model.is_none() {
+            fingerprint.model = generic_model(landing);
+        }
+        return Some(fingerprint);
+    }
+
+    generic_model(landing).map(|model| Fingerprint {
+        model: Some(model),
+        ..Default::default()
+    })
+}
+
+fn detection_score(rule: &FingerprintRule, page: &Page) -> usize {
+    let header_hits = rule.detect.headers.iter().filter(|(name, pattern)| {
+        page.headers
+            .get(&name.to_ascii_lowercase())
+            .is_some_and(|value| pattern.0.is_match(value))
+    });
+    let body_hits = rule.detect.body.iter().filter(|pattern| pattern.0.is_match(&page.body));
+    header_hits.count() * 2 + body_hits.count()
+}
+
+fn apply_probe(probe: &Probe, page: &Page, fingerprint: &mut Fingerprint) {
+    for (field, extractor) in &probe.fields {
+        let slot = match field.as_str() {
+            "model" => &mut fingerprint.model,
+            "serial" => &mut fingerprint.serial,
+            "firmware" => &mut fingerprint.firmware,
+            _ => continue,
+        };
+        if slot.is_none() {
+            *slot = extract(extractor, page);
+        }
+    }
+}
+
+fn extract(extractor: &Extractor, page: &Page) -> Option<String> {
+    let source = if let Some(header) = &extractor.header {
+        page.headers.get(&header.to_ascii_lowercase())?.clone()
+    } else if let Some(selector) = &extractor.selector {
+        let document = Html::parse_document(&page.body);
+        let element = document.select(&selector.selector).next()?;
+        match &extractor.attr {
+            Some(attr) => element.value().attr(attr)?.to_string(),
+            None => element.text().collect::<Vec<_>>().join(" "),
+        }
+    } else {
+        page.body.clone()
+    };
+
+    let value = match &extractor.regex {
+        Some(pattern) => {
+            let captures = pattern.0.captures(&source)?;
+            captures.get(1).or_else(|| captures.get(0))?.as_str().to_string()
+        }
+        None => source,
+    };
+
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Last resort for unknown vendors: the title, first heading or meta description, as long
+/// as it is not one of the generic landing page titles.
+fn generic_model(page: &Page) -> Option<String> {
+    let document = Html::parse_document(&page.body);
+    let candidates = [("title", None), ("h1", None), ("meta[name='description']", Some("content"))];
+
+    for (selector, attr) in candidates {
+        let selector = Selector::parse(selector).unwrap();
+        if let Some(element) = document.select(&selector).next() {
+            let text = match attr {
+                Some(attr) => element.value().attr(attr).unwrap_or_default().to_string(),
+                None => element.text().collect::<Vec<_>>().join(" "),
+            };
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() && !GENERIC_TITLES.contains(&text.to_ascii_lowercase().as_str()) {
+                return Some(text);
+            }
+        }
+    }
+
+    None
+}
+
+fn fetch(client: &Client, base: &str, path: &str) -> Option<Page> {
+    let url = format!("{}{}", base, path);
+    let response = match client.get(&url).send() {
+        Ok(response) => response,
+        Err(e) => {
+            debug!("HTTP query failed for {}: {}", url, e);
+            return None;
+        }
+    };
+    if !response.status().is_success() {
+        return None;
+    }
+
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str().to_ascii_lowercase(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = response.text().ok()?;
+
+    Some(Page { headers, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(headers: &[(&str, &str)], body: &str) -> Page {
+        Page {
+            headers: headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: body.to_string(),
+        }
+    }
+
+    fn identify_with(landing: Page, pages: &[(&str, &str)]) -> Fingerprint {
+        let rules = parse_rules(BUILTIN_RULES).unwrap();
+        let fetch = |path: &str| pages.iter().find(|(p, _)| *p == path).map(|(_, body)| page(&[], body));
+        identify(fetch, &landing, &rules).expect("no fingerprint")
+    }
+
+    #[test]
+    fn bundled_rules_parse() {
+        let rules = parse_rules(BUILTIN_RULES).unwrap();
+        assert!(rules.iter().any(|r| r.vendor == "HP"));
+        assert!(rules.iter().all(|r| !r.probes.is_empty()));
+    }
+
+    #[test]
+    fn bad_patterns_are_rejected() {
+        let bad_regex = r#"[{"vendor": "X", "detect": {"body": ["(unclosed"]}}]"#;
+        assert!(parse_rules(bad_regex).unwrap_err().contains("bad regex"));
+        let bad_selector = r#"[{"vendor": "X", "probes": [{"path": "/", "fields": {"model": {"selector": "[["}}}]}]"#;
+        assert!(parse_rules(bad_selector).unwrap_err().contains("bad selector"));
+    }
+
+    #[test]
+    fn hp_from_server_header() {
+        let landing = page(
+            &[("server", "HP HTTP Server; HP Color LaserJet MFP M479fdw - W1A80A; Serial Number: CNB1234567; Built: Tue Jan 10, 2023 {002_2302A}")],
+            "<html><title>HP Color LaserJet MFP M479fdw</title></html>",
+        );
+        let fingerprint = identify_with(landing, &[]);
+        assert_eq!(fingerprint.vendor.as_deref(), Some("HP"));
+        assert_eq!(fingerprint.model.as_deref(), Some("HP Color LaserJet MFP M479fdw"));
+        assert_eq!(fingerprint.serial.as_deref(), Some("CNB1234567"));
+        assert_eq!(fingerprint.firmware.as_deref(), Some("002_2302A"));
+    }
+
+    #[test]
+    fn hp_from_product_config() {
+        let landing = page(&[], "<html><title>Home</title><body>HP LaserJet Pro M404dn</body></html>");
+        let config = "<prdcfgdyn:ProductConfigDyn><dd:MakeAndModel>HP LaserJet Pro M404dn</dd:MakeAndModel>\
+            <dd:SerialNumber>PHBQ123456</dd:SerialNumber><dd:Revision>20230216</dd:Revision></prdcfgdyn:ProductConfigDyn>";
+        let fingerprint = identify_with(landing, &[("/DevMgmt/ProductConfigDyn.xml", config)]);
+        assert_eq!(fingerprint.vendor.as_deref(), Some("HP"));
+        assert_eq!(fingerprint.model.as_deref(), Some("HP LaserJet Pro M404dn"));
+        assert_eq!(fingerprint.serial.as_deref(), Some("PHBQ123456"));
+        assert_eq!(fingerprint.firmware.as_deref(), Some("20230216"));
+    }
+
+    #[test]
+    fn brother_from_information_page() {
+        let landing = page(
+            &[("server", "debut/1.30")],
+            "<html><head><title>Brother MFC-L2750DW series</title></head></html>",
+        );
+        let information = "<dl><dt>Model&#32;Name</dt>\n<dd>Brother MFC-L2750DW series</dd>\
+            <dt>Serial&#32;no.</dt><dd>E78123A9N456789</dd>\
+            <dt>Main&#32;Firmware&#32;Version</dt><dd>ZG</dd></dl>";
+        let fingerprint = identify_with(landing, &[("/general/information.html", information)]);
+        assert_eq!(fingerprint.vendor.as_deref(), Some("Brother"));
+        assert_eq!(fingerprint.model.as_deref(), Some("Brother MFC-L2750DW series"));
+        assert_eq!(fingerprint.serial.as_deref(), Some("E78123A9N456789"));
+        assert_eq!(fingerprint.firmware.as_deref(), Some("ZG"));
+    }
+
+    #[test]
+    fn brother_model_from_title_when_information_is_missing() {
+        let landing = page(&[], "<html><head><title>Brother HL-L2350DW series</title></head></html>");
+        let fingerprint = identify_with(landing, &[]);
+        assert_eq!(fingerprint.vendor.as_deref(), Some("Brother"));
+        assert_eq!(fingerprint.model.as_deref(), Some("Brother HL-L2350DW"));
+        assert_eq!(fingerprint.serial, None);
+    }
+
+    #[test]
+    fn canon_from_remote_ui() {
+        let landing = page(
+            &[("server", "CANON HTTP Server")],
+            "<html><head><title>Remote UI: imageCLASS MF743Cdw</title></head></html>",
+        );
+        let info = "<table><tr><th>Serial Number</th><td class=\"v\">2ZR01234</td></tr>\
+            <tr><th>Firmware Version</th><td>03.07</td></tr></table>";
+        let fingerprint = identify_with(landing, &[("/m_info.html", info)]);
+        assert_eq!(fingerprint.vendor.as_deref(), Some("Canon"));
+        assert_eq!(fingerprint.model.as_deref(), Some("imageCLASS MF743Cdw"));
+        assert_eq!(fingerprint.serial.as_deref(), Some("2ZR01234"));
+        assert_eq!(fingerprint.firmware.as_deref(), Some("03.07"));
+    }
+
+    #[test]
+    fn unknown_vendor_falls_back_to_title() {
+        let rules = parse_rules(BUILTIN_RULES).unwrap();
+        let landing = page(&[], "<html><title>Login</title><h1>Acme P-100</h1></html>");
+        let fingerprint = identify(|_| None, &landing, &rules).unwrap();
+        assert_eq!(fingerprint.vendor, None);
+        assert_eq!(fingerprint.model.as_deref(), Some("Acme P-100"));
+        let blank = page(&[], "<html><title>Home</title></html>");
+        assert!(identify(|_| None, &blank, &rules).is_none());
+    }
+}
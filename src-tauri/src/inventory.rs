@@ -0,0 +1,362 @@
+use crate::counters::CounterSample;
+use crate::pools::PrinterPool;
+use crate::printer::NetworkPrinter;
+use crate::supplies::SupplySample;
+use crate::validate;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+/// Oldest samples are dropped beyond this many per printer (about two years hourly).
+const MAX_SAMPLES: usize = 17_520;
+/// Source of entries created or updated by an inventory import.
+pub const IMPORTED: &str = "Imported";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub id: String,
+    pub printer: NetworkPrinter,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Page counter time series, oldest first.
+    #[serde(default)]
+    pub counters: Vec<CounterSample>,
+    /// Supply level time series, oldest first.
+    #[serde(default)]
+    pub supplies: Vec<SupplySample>,
+    /// How the printer was found: the status each discovery method or import gave it.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Ask for the page count over PJL on port 9100 when SNMP has none. Off unless turned on
+    /// for the printer, since devices that do not speak PJL print the query as text.
+    #[serde(default)]
+    pub pjl_counters: bool,
+}
+
+impl InventoryEntry {
+    /// Network address to poll, if the printer has one.
+    pub fn address(&self) -> Option<&str> {
+        match self.printer.ip_address.as_str() {
+            "local" | "" => None,
+            ip => Some(ip),
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.printer.name
+    }
+
+    pub fn push_counter(&mut self, sample: CounterSample) {
+        self.counters.push(sample);
+        if self.counters.len() > MAX_SAMPLES {
+            let excess = self.counters.len() - MAX_SAMPLES;
+            self.counters.drain(..excess);
+        }
+    }
+
+    pub fn push_supplies(&mut self, sample: SupplySample) {
+        self.supplies.push(sample);
+        if self.supplies.len() > MAX_SAMPLES {
+            let excess = self.supplies.len() - MAX_SAMPLES;
+            self.supplies.drain(..excess);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    #[serde(default)]
+    pub printers: Vec<InventoryEntry>,
+    /// Logical printers made of entries in `printers`.
+    #[serde(default)]
+    pub pools: Vec<PrinterPool>,
+}
+
+impl Inventory {
+    pub fn get(&self, id: &str) -> Option<&InventoryEntry> {
+        self.printers.iter().find(|e| e.id == id)
+    }
+
+    /// The entry a discovered printer belongs to: the one with its id, else one with the
+    /// same serial number, else one only known by this address (imported, or seen before
+    /// without a MAC or serial). Keeps a discovery that learns the MAC from duplicating
+    /// a printer that was imported under an `ip:` or `sn:` id.
+    pub fn find_printer(&self, printer: &NetworkPrinter) -> Option<&InventoryEntry> {
+        self.get(&printer_id(printer))
+            .or_else(|| {
+                let serial = printer.serial.as_ref()?;
+                self.printers.iter().find(|e| e.printer.serial.as_ref() == Some(serial))
+            })
+            .or_else(|| self.get(&format!("ip:{}", printer.ip_address)))
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut InventoryEntry> {
+        self.printers.iter_mut().find(|e| e.id == id)
+    }
+
+    pub fn pool(&self, id: &str) -> Option<&PrinterPool> {
+        self.pools.iter().find(|p| p.id == id)
+    }
+}
+
+/// Stable identity for a printer across IP changes: MAC address, then serial number, then
+/// address. Local queues are keyed by name.
+pub fn printer_id(printer: &NetworkPrinter) -> String {
+    if let Some(mac) = &printer.mac_address {
+        format!("mac:{}", mac)
+    } else if let Some(serial) = &printer.serial {
+        format!("sn:{}", serial)
+    } else if printer.ip_address == "local" {
+        format!("local:{}", printer.name)
+    } else {
+        format!("ip:{}", printer.ip_address)
+    }
+}
+
+/// Name of an imported printer whose row had none.
+pub fn imported_name(ip_address: &str) -> String {
+    format!("Printer at {}", ip_address)
+}
+
+/// The inventory plus the file it is persisted to. Managed as Tauri state.
+pub struct InventoryStore {
+    path: PathBuf,
+    inventory: Mutex<Inventory>,
+}
+
+impl InventoryStore {
+    /// Loads the inventory from `path`, starting empty when the file does not exist yet.
+    pub fn open(path: PathBuf) -> Result<InventoryStore, String> {
+        let inventory = if path.exists() {
+            let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            serde_json::from_str(&json).map_err(|e| format!("Invalid inventory file {}: {}", path.display(), e))?
+        } else {
+            Inventory::default()
+        };
+
+        Ok(InventoryStore {
+            path,
+            inventory: Mutex::new(inventory),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn snapshot(&self) -> Inventory {
+        self.inventory.lock().unwrap().clone()
+    }
+
+    /// Applies `change` and writes the result to disk.
+    pub fn update<T>(&self, change: impl FnOnce(&mut Inventory) -> T) -> Result<T, String> {
+        let mut inventory = self.inventory.lock().unwrap();
+        let result = change(&mut inventory);
+        save(&self.path, &inventory)?;
+        Ok(result)
+    }
+
+    /// Adds newly discovered printers and refreshes the details of known ones. Returns the
+    /// ids of printers that were not in the inventory before.
+    pub fn record_discovery(&self, printers: &[NetworkPrinter]) -> Result<Vec<String>, String> {
+        let now = Utc::now();
+        self.update(|inventory| {
+            let mut added = Vec::new();
+            for printer in printers {
+                let id = match inventory.find_printer(printer) {
+                    Some(entry) => entry.id.clone(),
+                    None => printer_id(printer),
+                };
+                match inventory.get_mut(&id) {
+                    Some(entry) => {
+                        let keep_name = entry.sources.iter().any(|s| s == IMPORTED)
+                            && entry.printer.name != imported_name(&entry.printer.ip_address);
+                        merge_discovered(&mut entry.printer, printer, keep_name);
+                        entry.last_seen = now;
+                        if !entry.sources.contains(&printer.status) {
+                            entry.sources.push(printer.status.clone());
+                        }
+                    }
+                    None => {
+                        added.push(id.clone());
+                        inventory.printers.push(InventoryEntry {
+                            id,
+                            printer: printer.clone(),
+                            location: None,
+                            tags: Vec::new(),
+                            first_seen: now,
+                            last_seen: now,
+                            counters: Vec::new(),
+                            supplies: Vec::new(),
+                            sources: vec![printer.status.clone()],
+                            pjl_counters: false,
+                        });
+                    }
+                }
+            }
+            added
+        })
+    }
+}
+
+/// Copies what discovery found into a known printer. Details discovery could not read
+/// keep their earlier (possibly imported) values, and an imported name beats the one
+/// discovery made up or read from the device.
+fn merge_discovered(known: &mut NetworkPrinter, found: &NetworkPrinter, keep_name: bool) {
+    if !keep_name {
+        known.name = found.name.clone();
+    }
+    known.ip_address = found.ip_address.clone();
+    if found.port != 0 {
+        known.port = found.port;
+    }
+    known.status = found.status.clone();
+    for (known, found) in [
+        (&mut known.model, &found.model),
+        (&mut known.vendor, &found.vendor),
+        (&mut known.serial, &found.serial),
+        (&mut known.firmware, &found.firmware),
+        (&mut known.hostname, &found.hostname),
+        (&mut known.mac_address, &found.mac_address),
+    ] {
+        if found.is_some() {
+            known.clone_from(found);
+        }
+    }
+}
+
+/// Writes through a temporary file so a crash never leaves a truncated inventory.
+fn save(path: &Path, inventory: &Inventory) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(inventory).map_err(|e| format!("Failed to serialize inventory: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, json).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+#[tauri::command]
+pub fn get_inventory(store: State<'_, InventoryStore>) -> Vec<InventoryEntry> {
+    store.snapshot().printers
+}
+
+#[tauri::command]
+pub fn update_inventory_entry(
+    store: State<'_, InventoryStore>,
+    id: String,
+    location: Option<String>,
+    tags: Option<Vec<String>>,
+    pjl_counters: Option<bool>,
+) -> Result<InventoryEntry, String> {
+    validate::id(&id)?;
+    if let Some(location) = &location {
+        validate::text("Location", location)?;
+    }
+    for tag in tags.iter().flatten() {
+        validate::text("Tag", tag)?;
+    }
+    store.update(|inventory| {
+        let entry = inventory.get_mut(&id).ok_or_else(|| format!("No printer with id {}", id))?;
+        entry.location = location.filter(|l| !l.trim().is_empty());
+        if let Some(tags) = tags {
+            entry.tags = tags;
+        }
+        if let Some(pjl_counters) = pjl_counters {
+            entry.pjl_counters = pjl_counters;
+        }
+        Ok(entry.clone())
+    })?
+}
+
+#[tauri::command]
+pub fn remove_inventory_entry(app: AppHandle, store: State<'_, InventoryStore>, id: String) -> Result<(), String> {
+    validate::id(&id)?;
+    store.update(|inventory| {
+        inventory.printers.retain(|e| e.id != id);
+        for pool in &mut inventory.pools {
+            pool.members.retain(|member| *member != id);
+        }
+    })?;
+    crate::tray::refresh(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery_keeps_imported_details() {
+        let dir = std::env::temp_dir().join(format!("inventory-merge-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = InventoryStore::open(dir.join("inventory.json")).unwrap();
+        let now = Utc::now();
+        store
+            .update(|inventory| {
+                inventory.printers.push(InventoryEntry {
+                    id: "ip:10.0.0.5".to_string(),
+                    printer: NetworkPrinter {
+                        name: "Front desk".to_string(),
+                        ip_address: "10.0.0.5".to_string(),
+                        model: Some("LaserJet M404".to_string()),
+                        serial: Some("PHB1234".to_string()),
+                        status: IMPORTED.to_string(),
+                        ..Default::default()
+                    },
+                    location: Some("Lobby".to_string()),
+                    tags: vec!["finance".to_string()],
+                    first_seen: now,
+                    last_seen: now,
+                    counters: Vec::new(),
+                    supplies: Vec::new(),
+                    sources: vec![IMPORTED.to_string()],
+                    pjl_counters: false,
+                })
+            })
+            .unwrap();
+
+        let found = NetworkPrinter {
+            name: "Network Printer at 10.0.0.5".to_string(),
+            ip_address: "10.0.0.5".to_string(),
+            port: 9100,
+            status: "Discovered".to_string(),
+            vendor: Some("HP".to_string()),
+            firmware: Some("2.1".to_string()),
+            ..Default::default()
+        };
+        assert!(store.record_discovery(&[found]).unwrap().is_empty());
+
+        let entry = store.snapshot().get("ip:10.0.0.5").cloned().unwrap();
+        assert_eq!(entry.printer.name, "Front desk");
+        assert_eq!(entry.printer.model.as_deref(), Some("LaserJet M404"));
+        assert_eq!(entry.printer.serial.as_deref(), Some("PHB1234"));
+        assert_eq!(entry.printer.vendor.as_deref(), Some("HP"));
+        assert_eq!(entry.printer.firmware.as_deref(), Some("2.1"));
+        assert_eq!(entry.printer.port, 9100);
+        assert_eq!(entry.location.as_deref(), Some("Lobby"));
+        assert_eq!(entry.tags, ["finance"]);
+        assert_eq!(entry.sources, [IMPORTED, "Discovered"]);
+
+        // What discovery does read replaces the imported value; a missing port does not
+        let found = NetworkPrinter {
+            name: "NPI5".to_string(),
+            ip_address: "10.0.0.5".to_string(),
+            model: Some("HP LaserJet Pro M404dn".to_string()),
+            status: "Discovered".to_string(),
+            ..Default::default()
+        };
+        store.record_discovery(&[found]).unwrap();
+        let entry = store.snapshot().get("ip:10.0.0.5").cloned().unwrap();
+        assert_eq!(entry.printer.name, "Front desk");
+        assert_eq!(entry.printer.model.as_deref(), Some("HP LaserJet Pro M404dn"));
+        assert_eq!(entry.printer.port, 9100);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,565 @@
+use crate::addr::HostAddr;
+use crate::inventory::{imported_name, printer_id, InventoryEntry, InventoryStore, IMPORTED};
+use crate::printer::NetworkPrinter;
+use crate::validate;
+use calamine::{open_workbook_auto, Reader};
+use chrono::Utc;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::State;
+
+/// Columns written by `export_inventory`, in order.
+const EXPORT_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "ip_address",
+    "port",
+    "location",
+    "model",
+    "vendor",
+    "serial",
+    "firmware",
+    "mac_address",
+    "hostname",
+    "tags",
+    "first_seen",
+    "last_seen",
+];
+
+/// File types `import_inventory` can read.
+const IMPORT_EXTENSIONS: &[&str] = &["csv", "json", "xlsx", "xlsm", "xls", "ods"];
+
+/// Header names recognised for each importable field when no mapping is given.
+const HEADER_ALIASES: &[(&str, &[&str])] = &[
+    ("name", &["name", "printer", "printer name", "device name"]),
+    ("ip_address", &["ip_address", "ip", "ip address", "address", "ipv4"]),
+    ("location", &["location", "site", "room", "office"]),
+    ("model", &["model", "printer model"]),
+    ("serial", &["serial", "serial number", "serial_number", "sn", "s/n"]),
+    ("tags", &["tags", "tag", "labels"]),
+];
+
+/// Source column to read for each field, overriding the header auto-detection.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ColumnMapping {
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub location: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowStatus {
+    New,
+    Update,
+    Invalid,
+}
+
+/// What importing one source row does (or would do, in a dry run).
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportRow {
+    /// 1-based data row number, not counting the header.
+    pub row: usize,
+    pub status: RowStatus,
+    pub printer_id: Option<String>,
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    pub rows: Vec<ImportRow>,
+    pub created: usize,
+    pub updated: usize,
+    pub invalid: usize,
+    /// False for a dry run: nothing was written.
+    pub applied: bool,
+}
+
+/// One validated row, ready to merge into the inventory.
+struct ImportRecord {
+    name: Option<String>,
+    ip_address: String,
+    location: Option<String>,
+    model: Option<String>,
+    serial: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+fn format_of(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// One exported row as a JSON object, with the keys in `EXPORT_COLUMNS` order like the
+/// CSV and XLSX exports.
+struct JsonRow<'a>(&'a [String]);
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(EXPORT_COLUMNS.len()))?;
+        for (column, value) in EXPORT_COLUMNS.iter().zip(self.0) {
+            map.serialize_entry(column, value)?;
+        }
+        map.end()
+    }
+}
+
+fn export_row(entry: &InventoryEntry) -> Vec<String> {
+    let printer = &entry.printer;
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    vec![
+        entry.id.clone(),
+        printer.name.clone(),
+        printer.ip_address.clone(),
+        printer.port.to_string(),
+        optional(&entry.location),
+        optional(&printer.model),
+        optional(&printer.vendor),
+        optional(&printer.serial),
+        optional(&printer.firmware),
+        optional(&printer.mac_address),
+        optional(&printer.hostname),
+        entry.tags.join(";"),
+        entry.first_seen.to_rfc3339(),
+        entry.last_seen.to_rfc3339(),
+    ]
+}
+
+/// Writes the inventory (without counter and supply history) as CSV, JSON or XLSX.
+pub fn export(entries: &[InventoryEntry], format: &str, path: &str) -> Result<(), String> {
+    let rows: Vec<Vec<String>> = entries.iter().map(export_row).collect();
+
+    match format.to_ascii_lowercase().as_str() {
+        "csv" => {
+            let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            writer.write_record(EXPORT_COLUMNS).map_err(|e| format!("Failed to write CSV: {}", e))?;
+            for row in &rows {
+                writer.write_record(row).map_err(|e| format!("Failed to write CSV: {}", e))?;
+            }
+            writer.flush().map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        "json" => {
+            let objects: Vec<JsonRow> = rows.iter().map(|row| JsonRow(row)).collect();
+            let json = serde_json::to_string_pretty(&objects).map_err(|e| format!("Failed to serialize inventory: {}", e))?;
+            std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        "xlsx" => {
+            let mut workbook = Workbook::new();
+            let sheet = workbook.add_worksheet();
+            sheet.set_name("Printers").map_err(|e| format!("Failed to write XLSX: {}", e))?;
+            let bold = Format::new().set_bold();
+            for (col, header) in EXPORT_COLUMNS.iter().enumerate() {
+                sheet
+                    .write_string_with_format(0, col as u16, *header, &bold)
+                    .map_err(|e| format!("Failed to write XLSX: {}", e))?;
+            }
+            for (row, values) in rows.iter().enumerate() {
+                for (col, value) in values.iter().enumerate() {
+                    sheet
+                        .write_string(row as u32 + 1, col as u16, value)
+                        .map_err(|e| format!("Failed to write XLSX: {}", e))?;
+                }
+            }
+            workbook.save(path).map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        other => Err(format!("Unsupported export format '{}' (expected csv, json or xlsx)", other)),
+    }
+}
+
+/// Reads a CSV, JSON (array of objects) or spreadsheet into header/value rows.
+fn read_table(path: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    match format_of(path).as_str() {
+        "csv" => {
+            let mut reader = csv::ReaderBuilder::new()
+                .flexible(true)
+                .from_path(path)
+                .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+            let headers = reader.headers().map_err(|e| format!("Failed to read CSV header: {}", e))?.clone();
+            reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|e| format!("Failed to read CSV: {}", e))?;
+                    Ok(headers.iter().map(String::from).zip(record.iter().map(String::from)).collect())
+                })
+                .collect()
+        }
+        "json" => {
+            let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let objects: Vec<serde_json::Map<String, serde_json::Value>> =
+                serde_json::from_str(&json).map_err(|e| format!("Expected a JSON array of objects: {}", e))?;
+            Ok(objects
+                .into_iter()
+                .map(|object| object.into_iter().map(|(key, value)| (key, json_text(&value))).collect())
+                .collect())
+        }
+        "xlsx" | "xlsm" | "xls" | "ods" => {
+            let mut workbook = open_workbook_auto(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+            let range = workbook
+                .worksheet_range_at(0)
+                .ok_or_else(|| format!("{} has no worksheets", path))?
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let mut rows = range.rows();
+            let headers: Vec<String> = match rows.next() {
+                Some(header) => header.iter().map(|cell| cell.to_string().trim().to_string()).collect(),
+                None => return Ok(Vec::new()),
+            };
+            Ok(rows
+                .map(|row| headers.iter().cloned().zip(row.iter().map(|cell| cell.to_string())).collect())
+                .collect())
+        }
+        other => Err(format!("Unsupported import format '{}' (expected csv, json or xlsx)", other)),
+    }
+}
+
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(items) => items.iter().map(json_text).collect::<Vec<_>>().join(";"),
+        other => other.to_string(),
+    }
+}
+
+/// Source header for each field: the explicit mapping, else the first matching alias.
+fn resolve_columns(headers: &[String], mapping: &ColumnMapping) -> HashMap<&'static str, String> {
+    let explicit = [
+        ("name", &mapping.name),
+        ("ip_address", &mapping.ip_address),
+        ("location", &mapping.location),
+        ("model", &mapping.model),
+        ("serial", &mapping.serial),
+        ("tags", &mapping.tags),
+    ];
+
+    HEADER_ALIASES
+        .iter()
+        .filter_map(|(field, aliases)| {
+            let explicit = explicit.iter().find(|(f, _)| f == field).and_then(|(_, column)| (*column).clone());
+            let column = explicit.or_else(|| {
+                headers
+                    .iter()
+                    .find(|header| aliases.iter().any(|alias| header.trim().eq_ignore_ascii_case(alias)))
+                    .cloned()
+            })?;
+            Some((*field, column))
+        })
+        .collect()
+}
+
+fn validate_row(row: &HashMap<String, String>, columns: &HashMap<&'static str, String>) -> Result<ImportRecord, Vec<String>> {
+    let field = |name: &str| {
+        columns
+            .get(name)
+            .and_then(|column| row.get(column))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let mut errors = Vec::new();
+
+    let ip_address = match field("ip_address") {
+        Some(ip) => match HostAddr::parse(&ip) {
+            Some(addr) => Some(addr.to_string()),
+            None => {
+                errors.push(format!("Invalid IP address '{}'", ip));
+                None
+            }
+        },
+        None => {
+            errors.push("Missing IP address".to_string());
+            None
+        }
+    };
+    let name = field("name");
+    if name.as_ref().is_some_and(|name| name.len() > 256) {
+        errors.push("Name is longer than 256 characters".to_string());
+    }
+
+    match ip_address {
+        Some(ip_address) if errors.is_empty() => Ok(ImportRecord {
+            name,
+            ip_address,
+            location: field("location"),
+            model: field("model"),
+            serial: field("serial"),
+            tags: field("tags").map(|tags| {
+                tags.split([';', ','])
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect()
+            }),
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Validates every row of `path` and, unless `dry_run`, merges the valid ones into the
+/// inventory. Existing printers are matched by serial number, then by IP address.
+pub fn import(store: &InventoryStore, path: &str, mapping: &ColumnMapping, dry_run: bool) -> Result<ImportResult, String> {
+    let table = read_table(path)?;
+    let mut headers: Vec<String> = table.iter().flat_map(|row| row.keys().cloned()).collect();
+    headers.sort();
+    headers.dedup();
+    let columns = resolve_columns(&headers, mapping);
+    if !columns.contains_key("ip_address") {
+        return Err("No IP address column found; map one explicitly".to_string());
+    }
+
+    let inventory = store.snapshot();
+    let mut rows = Vec::new();
+    let mut records = Vec::new();
+
+    for (index, row) in table.iter().enumerate() {
+        match validate_row(row, &columns) {
+            Ok(record) => {
+                let existing = inventory
+                    .printers
+                    .iter()
+                    .find(|e| record.serial.is_some() && e.printer.serial == record.serial)
+                    .or_else(|| inventory.printers.iter().find(|e| e.printer.ip_address == record.ip_address));
+                // A later row for a printer an earlier row creates updates that one
+                let earlier = records
+                    .iter()
+                    .find(|(_, r): &&(String, ImportRecord)| {
+                        (record.serial.is_some() && r.serial == record.serial) || r.ip_address == record.ip_address
+                    })
+                    .map(|(id, _)| id.clone());
+                let printer_id = match (existing, earlier) {
+                    (Some(entry), _) => entry.id.clone(),
+                    (None, Some(id)) => id,
+                    (None, None) => printer_id(&NetworkPrinter {
+                        ip_address: record.ip_address.clone(),
+                        serial: record.serial.clone(),
+                        ..Default::default()
+                    }),
+                };
+                let status = if existing.is_some() || records.iter().any(|(id, _)| id == &printer_id) {
+                    RowStatus::Update
+                } else {
+                    RowStatus::New
+                };
+                rows.push(ImportRow {
+                    row: index + 1,
+                    status,
+                    printer_id: Some(printer_id.clone()),
+                    name: record.name.clone(),
+                    ip_address: Some(record.ip_address.clone()),
+                    errors: Vec::new(),
+                });
+                records.push((printer_id, record));
+            }
+            Err(errors) => rows.push(ImportRow {
+                row: index + 1,
+                status: RowStatus::Invalid,
+                printer_id: None,
+                name: None,
+                ip_address: None,
+                errors,
+            }),
+        }
+    }
+
+    let count = |status| rows.iter().filter(|r| r.status == status).count();
+    let (created, updated, invalid) = (count(RowStatus::New), count(RowStatus::Update), count(RowStatus::Invalid));
+
+    if !dry_run && !records.is_empty() {
+        let now = Utc::now();
+        store.update(|inventory| {
+            for (id, record) in records {
+                if inventory.get(&id).is_none() {
+                    inventory.printers.push(InventoryEntry {
+                        id: id.clone(),
+                        printer: NetworkPrinter {
+                            name: imported_name(&record.ip_address),
+                            ip_address: record.ip_address.clone(),
+                            status: IMPORTED.to_string(),
+                            ..Default::default()
+                        },
+                        location: None,
+                        tags: Vec::new(),
+                        first_seen: now,
+                        last_seen: now,
+                        counters: Vec::new(),
+                        supplies: Vec::new(),
+                        sources: vec![IMPORTED.to_string()],
+                        pjl_counters: false,
+                    });
+                }
+                let entry = inventory.get_mut(&id).expect("entry was just ensured");
+                if !entry.sources.iter().any(|s| s == IMPORTED) {
+                    entry.sources.push(IMPORTED.to_string());
+                }
+                entry.printer.ip_address = record.ip_address;
+                if let Some(name) = record.name {
+                    entry.printer.name = name;
+                }
+                if record.model.is_some() {
+                    entry.printer.model = record.model;
+                }
+                if record.serial.is_some() {
+                    entry.printer.serial = record.serial;
+                }
+                if record.location.is_some() {
+                    entry.location = record.location;
+                }
+                if let Some(tags) = record.tags {
+                    entry.tags = tags;
+                }
+            }
+        })?;
+    }
+
+    Ok(ImportResult {
+        rows,
+        created,
+        updated,
+        invalid,
+        applied: !dry_run,
+    })
+}
+
+#[tauri::command]
+pub fn export_inventory(store: State<'_, InventoryStore>, format: String, path: String) -> Result<(), String> {
+    let path = validate::export_file(&path, &format)?;
+    export(&store.snapshot().printers, &format, &path.to_string_lossy())
+}
+
+/// Imports `path`; with `dry_run` set, only reports what each row would do.
+#[tauri::command]
+pub fn import_inventory(
+    store: State<'_, InventoryStore>,
+    path: String,
+    mapping: Option<ColumnMapping>,
+    dry_run: Option<bool>,
+) -> Result<ImportResult, String> {
+    let path = validate::input_file(&path, IMPORT_EXTENSIONS)?;
+    import(&store, &path.to_string_lossy(), &mapping.unwrap_or_default(), dry_run.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("inventory-io-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn import_file(
+        dir: &Path,
+        name: &str,
+        contents: &str,
+        mapping: &ColumnMapping,
+        dry_run: bool,
+    ) -> (InventoryStore, ImportResult) {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        let store = InventoryStore::open(dir.join("inventory.json")).unwrap();
+        let result = import(&store, &path.to_string_lossy(), mapping, dry_run).unwrap();
+        (store, result)
+    }
+
+    #[test]
+    fn csv_headers_are_matched_by_alias() {
+        let dir = temp_dir("alias");
+        let csv = "Printer Name,IP Address,Site,S/N,Labels\nFront desk,10.0.0.5,Lobby,PHB1234,finance; ground\n";
+        let (store, result) = import_file(&dir, "printers.csv", csv, &ColumnMapping::default(), false);
+        assert_eq!((result.created, result.updated, result.invalid), (1, 0, 0));
+
+        let entry = store.snapshot().get("sn:PHB1234").cloned().unwrap();
+        assert_eq!(entry.printer.name, "Front desk");
+        assert_eq!(entry.printer.ip_address, "10.0.0.5");
+        assert_eq!(entry.location.as_deref(), Some("Lobby"));
+        assert_eq!(entry.tags, ["finance", "ground"]);
+        assert_eq!(entry.sources, [IMPORTED]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn explicit_mapping_beats_aliases() {
+        let dir = temp_dir("mapping");
+        let csv = "name,host,where,ip\nFront desk,10.0.0.5,Lobby,not-an-address\n";
+        let mapping = ColumnMapping {
+            ip_address: Some("host".to_string()),
+            location: Some("where".to_string()),
+            ..Default::default()
+        };
+        let (store, result) = import_file(&dir, "printers.csv", csv, &mapping, false);
+        assert_eq!((result.created, result.invalid), (1, 0));
+        let entry = store.snapshot().get("ip:10.0.0.5").cloned().unwrap();
+        assert_eq!(entry.location.as_deref(), Some("Lobby"));
+
+        // Without the mapping the `ip` alias column is read instead
+        let result = import(&store, &dir.join("printers.csv").to_string_lossy(), &ColumnMapping::default(), true).unwrap();
+        assert_eq!(result.rows[0].errors, ["Invalid IP address 'not-an-address'"]);
+        std::fs::write(dir.join("none.csv"), "name,host\nA,10.0.0.6\n").unwrap();
+        let err = import(&store, &dir.join("none.csv").to_string_lossy(), &ColumnMapping::default(), true).unwrap_err();
+        assert!(err.contains("No IP address column"), "{}", err);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_rows_are_reported_per_row() {
+        let dir = temp_dir("errors");
+        let long = "x".repeat(257);
+        let csv = format!("name,ip\nGood,10.0.0.5\nNo address,\nBad,300.1.1.1\n{},10.0.0.7\nAgain,10.0.0.5\n", long);
+        let (store, result) = import_file(&dir, "printers.csv", &csv, &ColumnMapping::default(), false);
+        let statuses: Vec<RowStatus> = result.rows.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [RowStatus::New, RowStatus::Invalid, RowStatus::Invalid, RowStatus::Invalid, RowStatus::Update]
+        );
+        assert_eq!(result.rows[1].errors, ["Missing IP address"]);
+        assert_eq!(result.rows[2].errors, ["Invalid IP address '300.1.1.1'"]);
+        assert_eq!(result.rows[3].errors, ["Name is longer than 256 characters"]);
+        assert_eq!((result.created, result.updated, result.invalid), (1, 1, 3));
+
+        // The later row for the same address updates the printer the first one created
+        let inventory = store.snapshot();
+        assert_eq!(inventory.printers.len(), 1);
+        assert_eq!(inventory.printers[0].printer.name, "Again");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let dir = temp_dir("dry-run");
+        let csv = "name,ip\nFront desk,10.0.0.5\n";
+        let (store, result) = import_file(&dir, "printers.csv", csv, &ColumnMapping::default(), true);
+        assert_eq!(result.created, 1);
+        assert!(!result.applied);
+        assert!(store.snapshot().printers.is_empty());
+        assert!(!dir.join("inventory.json").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn xlsx_export_imports_again() {
+        let dir = temp_dir("xlsx");
+        let csv = "name,ip,model,tags\nFront desk,10.0.0.5,M404,a;b\n";
+        let (store, _) = import_file(&dir, "printers.csv", csv, &ColumnMapping::default(), false);
+        let xlsx = dir.join("printers.xlsx").to_string_lossy().to_string();
+        export(&store.snapshot().printers, "xlsx", &xlsx).unwrap();
+
+        let other = InventoryStore::open(dir.join("other.json")).unwrap();
+        let result = import(&other, &xlsx, &ColumnMapping::default(), false).unwrap();
+        assert_eq!((result.created, result.invalid), (1, 0));
+        let entry = other.snapshot().get("ip:10.0.0.5").cloned().unwrap();
+        assert_eq!(entry.printer.name, "Front desk");
+        assert_eq!(entry.printer.model.as_deref(), Some("M404"));
+        assert_eq!(entry.tags, ["a", "b"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use crate::addr::HostAddr;
+use crate::ipp_message::*;
+use crate::raster::Sides;
+use crate::settings;
+use crate::trust;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tracing::debug;
+
+/// Printer endpoints tried in order: IPPS on the IPP port and on 443 (guest VLANs often
+/// only allow 443), then plain IPP.
+const CANDIDATE_PORTS: &[(bool, u16)] = &[(true, 631), (true, 443), (false, 631)];
+/// Resource paths used by IPP Everywhere printers and older firmware.
+const CANDIDATE_PATHS: &[&str] = &["/ipp/print", "/ipp", "/"];
+/// Time allowed to upload a document and get the Print-Job response.
+const PRINT_TIMEOUT: Duration = Duration::from_secs(120);
+
+static REQUEST_ID: AtomicU32 = AtomicU32::new(1);
+
+fn next_request_id() -> u32 {
+    REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Where a printer's IPP service lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IppEndpoint {
+    pub tls: bool,
+    pub addr: HostAddr,
+    pub port: u16,
+    pub path: String,
+}
+
+impl IppEndpoint {
+    /// The HTTP URL requests are posted to.
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}:{}{}", scheme, self.addr.request_host(), self.port, self.path)
+    }
+
+    /// The `printer-uri` operation attribute.
+    pub fn uri(&self) -> String {
+        let scheme = if self.tls { "ipps" } else { "ipp" };
+        format!("{}://{}:{}{}", scheme, self.addr.url_host(), self.port, self.path)
+    }
+}
+
+/// HTTP basic credentials for printers that require authentication.
+pub struct IppAuth {
+    pub username: String,
+    pub password: String,
+    /// Basic auth sends the password in the clear, so over plain IPP it is only sent when
+    /// the credential opts in.
+    pub allow_cleartext: bool,
+}
+
+/// Why no IPP endpoint could be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IppError {
+    /// Nothing on the IPP ports answered as an IPP printer.
+    Unavailable(String),
+    /// A printer answered but turned us away: it wants other credentials, or its
+    /// certificate is not trusted.
+    Refused(String),
+}
+
+impl std::fmt::Display for IppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IppError::Unavailable(message) | IppError::Refused(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<IppError> for String {
+    fn from(error: IppError) -> String {
+        error.to_string()
+    }
+}
+
+/// Whether a failed request was stopped by the certificate check rather than the network.
+fn is_certificate_error(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        // io::Error hides the error it wraps from `source`
+        let inner = error.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref()).map(|e| e as &dyn std::error::Error);
+        if error.is::<rustls::Error>() || inner.is_some_and(|e| e.is::<rustls::Error>()) {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// What a printer reports about itself in Get-Printer-Attributes.
+#[derive(Debug, Clone, Default)]
+pub struct IppIdentity {
+    pub uri: String,
+    pub name: Option<String>,
+    pub model: Option<String>,
+    pub info: Option<String>,
+    pub uuid: Option<String>,
+    pub formats: Vec<String>,
+}
+
+/// Posts `request` to `endpoint` and parses the IPP response. Certificates are checked
+/// against the trust store, and `auth` is left out over plain IPP unless it allows that.
+pub fn send(endpoint: &IppEndpoint, request: &IppMessage, auth: Option<&IppAuth>, timeout: Duration) -> Result<IppMessage, String> {
+    post(endpoint, request, auth, timeout).map_err(String::from)
+}
+
+fn post(endpoint: &IppEndpoint, request: &IppMessage, auth: Option<&IppAuth>, timeout: Duration) -> Result<IppMessage, IppError> {
+    let client = trust::http_client(timeout, &endpoint.addr, endpoint.port).map_err(IppError::Unavailable)?;
+    let mut builder = client
+        .post(endpoint.url())
+        .header(reqwest::header::CONTENT_TYPE, "application/ipp")
+        .body(request.to_bytes());
+    if endpoint.addr.scope_id != 0 {
+        // The URL names a placeholder for the link-local address; the printer gets the real one
+        builder = builder.header(reqwest::header::HOST, format!("{}:{}", endpoint.addr.url_host(), endpoint.port));
+    }
+    let withheld = auth.is_some_and(|auth| !endpoint.tls && !auth.allow_cleartext);
+    if let Some(auth) = auth.filter(|_| !withheld) {
+        builder = builder.basic_auth(&auth.username, Some(&auth.password));
+    }
+    let response = builder.send().map_err(|e| {
+        let message = format!("IPP request to {} failed: {}", endpoint.uri(), e);
+        if is_certificate_error(&e) {
+            IppError::Refused(message)
+        } else {
+            IppError::Unavailable(message)
+        }
+    })?;
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        if withheld {
+            return Err(IppError::Refused(format!(
+                "{} requires authentication, but credentials are only sent over IPPS; set allow_cleartext on its IPP credential to send them anyway",
+                endpoint.uri()
+            )));
+        }
+        return Err(IppError::Refused(match auth {
+            Some(_) => format!("{} rejected the IPP credential", endpoint.uri()),
+            None => format!("{} requires authentication; add an IPP credential for it", endpoint.uri()),
+        }));
+    }
+    if status == reqwest::StatusCode::FORBIDDEN {
+        return Err(IppError::Refused(format!("{} does not allow this user to print (HTTP 403)", endpoint.uri())));
+    }
+    if !status.is_success() {
+        return Err(IppError::Unavailable(format!("{} answered HTTP {}", endpoint.uri(), status)));
+    }
+    let body = response
+        .bytes()
+        .map_err(|e| IppError::Unavailable(format!("Failed to read IPP response from {}: {}", endpoint.uri(), e)))?;
+    IppMessage::parse(&body).map_err(IppError::Unavailable)
+}
+
+pub fn get_printer_attributes(endpoint: &IppEndpoint, auth: Option<&IppAuth>, timeout: Duration) -> Result<IppMessage, String> {
+    printer_attributes(endpoint, auth, timeout).map_err(String::from)
+}
+
+fn printer_attributes(endpoint: &IppEndpoint, auth: Option<&IppAuth>, timeout: Duration) -> Result<IppMessage, IppError> {
+    let mut request = IppMessage::request(GET_PRINTER_ATTRIBUTES, next_request_id());
+    request.add(OPERATION_ATTRIBUTES, "printer-uri", IppValue::uri(&endpoint.uri()));
+    request.add_values(
+        OPERATION_ATTRIBUTES,
+        "requested-attributes",
+        ["printer-description", "printer-make-and-model", "document-format-supported"]
+            .iter()
+            .map(|name| IppValue::keyword(name))
+            .collect(),
+    );
+    let response = post(endpoint, &request, auth, timeout)?;
+    if !response.is_success() {
+        return Err(IppError::Unavailable(format!(
+            "{} refused Get-Printer-Attributes (status 0x{:04x})",
+            endpoint.uri(),
+            response.code
+        )));
+    }
+    Ok(response)
+}
+
+/// Finds the IPP endpoint of `addr`, preferring IPPS. Closed ports are skipped with a
+/// quick TCP connect before any request is sent. When no endpoint works but one turned
+/// us away, that refusal is the error, so callers do not mistake it for a printer
+/// without IPP.
+pub fn find_endpoint(addr: &HostAddr, auth: Option<&IppAuth>) -> Result<(IppEndpoint, IppMessage), IppError> {
+    search(addr, CANDIDATE_PORTS, auth)
+}
+
+fn search(addr: &HostAddr, candidates: &[(bool, u16)], auth: Option<&IppAuth>) -> Result<(IppEndpoint, IppMessage), IppError> {
+    let timeout = settings::current().discovery.http_timeout();
+    let mut refused = None;
+    for &(tls, port) in candidates {
+        if TcpStream::connect_timeout(&addr.socket_addr(port), timeout).is_err() {
+            continue;
+        }
+        for path in CANDIDATE_PATHS {
+            let endpoint = IppEndpoint {
+                tls,
+                addr: *addr,
+                port,
+                path: path.to_string(),
+            };
+            match printer_attributes(&endpoint, auth, timeout) {
+                Ok(attributes) => return Ok((endpoint, attributes)),
+                Err(e) => {
+                    debug!("{}", e);
+                    if let IppError::Refused(_) = e {
+                        refused.get_or_insert(e);
+                    }
+                }
+            }
+        }
+    }
+    Err(refused.unwrap_or_else(|| IppError::Unavailable(format!("No IPP service found on {}", addr))))
+}
+
+/// Query printer information over IPP, using IPPS when the printer offers it.
+pub fn query_printer_ipp(ip: &str) -> Option<IppIdentity> {
+    let addr = HostAddr::parse(ip)?;
+    let (endpoint, attributes) = match find_endpoint(&addr, None) {
+        Ok(found) => found,
+        Err(e) => {
+            debug!("{}", e);
+            return None;
+        }
+    };
+    let string = |name: &str| attributes.string(PRINTER_ATTRIBUTES, name).map(str::to_string);
+    Some(IppIdentity {
+        uri: endpoint.uri(),
+        name: string("printer-name"),
+        model: string("printer-make-and-model"),
+        info: string("printer-info"),
+        uuid: string("printer-uuid"),
+        formats: attributes.strings(PRINTER_ATTRIBUTES, "document-format-supported"),
+    })
+}
+
+/// Submits `data` as a single-document Print-Job and returns the printer's job id.
+#[allow(clippy::too_many_arguments)]
+pub fn print_job(
+    endpoint: &IppEndpoint,
+    auth: Option<&IppAuth>,
+    job_name: &str,
+    user: &str,
+    format: &str,
+    copies: u32,
+    sides: Sides,
+    data: Vec<u8>,
+) -> Result<i32, String> {
+    let mut request = IppMessage::request(PRINT_JOB, next_request_id());
+    request
+        .add(OPERATION_ATTRIBUTES, "printer-uri", IppValue::uri(&endpoint.uri()))
+        .add(OPERATION_ATTRIBUTES, "requesting-user-name", IppValue::name(user))
+        .add(OPERATION_ATTRIBUTES, "job-name", IppValue::name(job_name))
+        .add(OPERATION_ATTRIBUTES, "document-format", IppValue::mime(format));
+    if copies > 1 {
+        request.add(JOB_ATTRIBUTES, "copies", IppValue::Integer(copies as i32));
+    }
+    if sides != Sides::OneSided {
+        request.add(JOB_ATTRIBUTES, "sides", IppValue::keyword(sides.keyword()));
+    }
+    request.data = data;
+
+    let response = send(endpoint, &request, auth, PRINT_TIMEOUT)?;
+    if !response.is_success() {
+        let message = response.string(OPERATION_ATTRIBUTES, "status-message").unwrap_or("");
+        return Err(format!(
+            "{} rejected the job (status 0x{:04x}) {}",
+            endpoint.uri(),
+            response.code,
+            message
+        )
+        .trim_end()
+        .to_string());
+    }
+    response
+        .integer(JOB_ATTRIBUTES, "job-id")
+        .ok_or_else(|| format!("{} accepted the job but returned no job id", endpoint.uri()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Answers every request on a local port with `status` until `requests` were served.
+    fn http_server(status: u16, requests: usize) -> (u16, thread::JoinHandle<()>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let handle = thread::spawn(move || {
+            for request in server.incoming_requests().take(requests) {
+                let _ = request.respond(tiny_http::Response::empty(status));
+            }
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn refusals_are_not_a_missing_service() {
+        let addr = HostAddr::parse("127.0.0.1").unwrap();
+        let auth = IppAuth {
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+            allow_cleartext: true,
+        };
+
+        let (port, server) = http_server(401, CANDIDATE_PATHS.len());
+        let err = search(&addr, &[(false, port)], Some(&auth)).unwrap_err();
+        assert_eq!(err, IppError::Refused(format!("ipp://127.0.0.1:{}/ipp/print rejected the IPP credential", port)));
+        server.join().unwrap();
+
+        let (port, server) = http_server(403, CANDIDATE_PATHS.len());
+        assert!(matches!(search(&addr, &[(false, port)], None), Err(IppError::Refused(_))));
+        server.join().unwrap();
+
+        // A web server without IPP, or nothing listening at all, is a missing service
+        let (port, server) = http_server(404, CANDIDATE_PATHS.len());
+        assert!(matches!(search(&addr, &[(false, port)], None), Err(IppError::Unavailable(_))));
+        server.join().unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let err = search(&addr, &[(false, closed)], None).unwrap_err();
+        assert_eq!(err, IppError::Unavailable("No IPP service found on 127.0.0.1".to_string()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn scoped_addresses_are_reached_with_their_zone() {
+        let server = tiny_http::Server::http("[::1]:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let handle = thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let host = request.headers().iter().find(|h| h.field.equiv("Host")).map(|h| h.value.to_string());
+            let _ = request.respond(tiny_http::Response::empty(401));
+            host
+        });
+
+        let addr = HostAddr::parse("::1%lo").unwrap();
+        let endpoint = IppEndpoint {
+            tls: false,
+            addr,
+            port,
+            path: "/ipp/print".to_string(),
+        };
+        assert_eq!(endpoint.uri(), format!("ipp://[::1%25lo]:{}/ipp/print", port));
+        let err = printer_attributes(&endpoint, None, Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(err, IppError::Refused(_)), "{:?}", err);
+        assert_eq!(handle.join().unwrap(), Some(format!("[::1%25lo]:{}", port)));
+    }
+}
@@ -0,0 +1,521 @@
+use std::fmt;
+use std::io::Read;
+
+// Delimiter tags (RFC 8010 section 3.5.1)
+pub const OPERATION_ATTRIBUTES: u8 = 0x01;
+pub const JOB_ATTRIBUTES: u8 = 0x02;
+pub const END_OF_ATTRIBUTES: u8 = 0x03;
+pub const PRINTER_ATTRIBUTES: u8 = 0x04;
+pub const UNSUPPORTED_ATTRIBUTES: u8 = 0x05;
+
+// Value tags
+pub const TAG_UNSUPPORTED: u8 = 0x10;
+pub const TAG_UNKNOWN: u8 = 0x12;
+pub const TAG_NO_VALUE: u8 = 0x13;
+pub const TAG_INTEGER: u8 = 0x21;
+pub const TAG_BOOLEAN: u8 = 0x22;
+pub const TAG_ENUM: u8 = 0x23;
+pub const TAG_OCTET_STRING: u8 = 0x30;
+pub const TAG_DATE_TIME: u8 = 0x31;
+pub const TAG_RESOLUTION: u8 = 0x32;
+pub const TAG_RANGE: u8 = 0x33;
+pub const TAG_TEXT: u8 = 0x41;
+pub const TAG_NAME: u8 = 0x42;
+pub const TAG_KEYWORD: u8 = 0x44;
+pub const TAG_URI: u8 = 0x45;
+pub const TAG_URI_SCHEME: u8 = 0x46;
+pub const TAG_CHARSET: u8 = 0x47;
+pub const TAG_NATURAL_LANGUAGE: u8 = 0x48;
+pub const TAG_MIME_MEDIA_TYPE: u8 = 0x49;
+
+// Operations (RFC 8011 section 5.4.15)
+pub const PRINT_JOB: u16 = 0x0002;
+pub const VALIDATE_JOB: u16 = 0x0004;
+pub const CREATE_JOB: u16 = 0x0005;
+pub const SEND_DOCUMENT: u16 = 0x0006;
+pub const CANCEL_JOB: u16 = 0x0008;
+pub const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+pub const GET_JOBS: u16 = 0x000A;
+pub const GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+
+// Status codes
+pub const SUCCESSFUL_OK: u16 = 0x0000;
+pub const CLIENT_ERROR_BAD_REQUEST: u16 = 0x0400;
+pub const CLIENT_ERROR_NOT_POSSIBLE: u16 = 0x0404;
+pub const CLIENT_ERROR_NOT_FOUND: u16 = 0x0406;
+pub const CLIENT_ERROR_REQUEST_ENTITY_TOO_LARGE: u16 = 0x0409;
+pub const CLIENT_ERROR_DOCUMENT_FORMAT_NOT_SUPPORTED: u16 = 0x040A;
+pub const SERVER_ERROR_INTERNAL_ERROR: u16 = 0x0500;
+pub const SERVER_ERROR_OPERATION_NOT_SUPPORTED: u16 = 0x0501;
+pub const SERVER_ERROR_VERSION_NOT_SUPPORTED: u16 = 0x0503;
+
+// job-state values (RFC 8011 section 5.3.7)
+pub const JOB_PENDING: i32 = 3;
+pub const JOB_PROCESSING: i32 = 5;
+pub const JOB_CANCELED: i32 = 7;
+pub const JOB_ABORTED: i32 = 8;
+pub const JOB_COMPLETED: i32 = 9;
+
+// printer-state values (RFC 8011 section 5.4.11)
+pub const PRINTER_IDLE: i32 = 3;
+pub const PRINTER_PROCESSING: i32 = 4;
+pub const PRINTER_STOPPED: i32 = 5;
+
+/// One attribute value. Strings keep their value tag so `keyword`, `uri`, `name` and so on
+/// round-trip unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IppValue {
+    Integer(i32),
+    Enum(i32),
+    Boolean(bool),
+    String { tag: u8, value: String },
+    Range(i32, i32),
+    Resolution { x: i32, y: i32, units: u8 },
+    /// Out-of-band values such as `no-value` and `unknown`.
+    OutOfBand(u8),
+    /// Anything else (dates, collections, octet strings), as raw bytes.
+    Other { tag: u8, bytes: Vec<u8> },
+}
+
+impl IppValue {
+    pub fn keyword(value: &str) -> Self {
+        IppValue::String { tag: TAG_KEYWORD, value: value.to_string() }
+    }
+
+    pub fn text(value: &str) -> Self {
+        IppValue::String { tag: TAG_TEXT, value: value.to_string() }
+    }
+
+    pub fn name(value: &str) -> Self {
+        IppValue::String { tag: TAG_NAME, value: value.to_string() }
+    }
+
+    pub fn uri(value: &str) -> Self {
+        IppValue::String { tag: TAG_URI, value: value.to_string() }
+    }
+
+    pub fn mime(value: &str) -> Self {
+        IppValue::String { tag: TAG_MIME_MEDIA_TYPE, value: value.to_string() }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            IppValue::Integer(_) => TAG_INTEGER,
+            IppValue::Enum(_) => TAG_ENUM,
+            IppValue::Boolean(_) => TAG_BOOLEAN,
+            IppValue::String { tag, .. } => *tag,
+            IppValue::Range(..) => TAG_RANGE,
+            IppValue::Resolution { .. } => TAG_RESOLUTION,
+            IppValue::OutOfBand(tag) => *tag,
+            IppValue::Other { tag, .. } => *tag,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            IppValue::String { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            IppValue::Integer(n) | IppValue::Enum(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let bytes: Vec<u8> = match self {
+            IppValue::Integer(n) | IppValue::Enum(n) => n.to_be_bytes().to_vec(),
+            IppValue::Boolean(b) => vec![u8::from(*b)],
+            IppValue::String { value, .. } => value.as_bytes().to_vec(),
+            IppValue::Range(low, high) => [low.to_be_bytes(), high.to_be_bytes()].concat(),
+            IppValue::Resolution { x, y, units } => {
+                let mut bytes = [x.to_be_bytes(), y.to_be_bytes()].concat();
+                bytes.push(*units);
+                bytes
+            }
+            IppValue::OutOfBand(_) => Vec::new(),
+            IppValue::Other { bytes, .. } => bytes.clone(),
+        };
+        out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+        out.extend_from_slice(&bytes);
+    }
+
+    fn decode(tag: u8, bytes: &[u8]) -> Result<Self, String> {
+        let int = |offset: usize| -> Result<i32, String> {
+            bytes
+                .get(offset..offset + 4)
+                .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| format!("Truncated value for tag 0x{:02x}", tag))
+        };
+        Ok(match tag {
+            0x10..=0x1f => IppValue::OutOfBand(tag),
+            TAG_INTEGER => IppValue::Integer(int(0)?),
+            TAG_ENUM => IppValue::Enum(int(0)?),
+            TAG_BOOLEAN => IppValue::Boolean(bytes.first().is_some_and(|b| *b != 0)),
+            TAG_RANGE => IppValue::Range(int(0)?, int(4)?),
+            TAG_RESOLUTION => IppValue::Resolution {
+                x: int(0)?,
+                y: int(4)?,
+                units: *bytes.get(8).ok_or("Truncated resolution")?,
+            },
+            0x41..=0x49 => IppValue::String {
+                tag,
+                value: String::from_utf8_lossy(bytes).into_owned(),
+            },
+            _ => IppValue::Other { tag, bytes: bytes.to_vec() },
+        })
+    }
+}
+
+impl fmt::Display for IppValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IppValue::Integer(n) | IppValue::Enum(n) => write!(f, "{}", n),
+            IppValue::Boolean(b) => write!(f, "{}", b),
+            IppValue::String { value, .. } => write!(f, "{}", value),
+            IppValue::Range(low, high) => write!(f, "{}-{}", low, high),
+            IppValue::Resolution { x, y, units } => {
+                write!(f, "{}x{}{}", x, y, if *units == 3 { "dpi" } else { "dpcm" })
+            }
+            IppValue::OutOfBand(tag) => write!(f, "(out-of-band 0x{:02x})", tag),
+            IppValue::Other { tag, bytes } => write!(f, "(0x{:02x}, {} bytes)", tag, bytes.len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IppAttribute {
+    pub name: String,
+    pub values: Vec<IppValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IppGroup {
+    pub tag: u8,
+    pub attributes: Vec<IppAttribute>,
+}
+
+/// An IPP request or response: the binary header and attribute groups, followed by the
+/// document data, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IppMessage {
+    pub version: (u8, u8),
+    /// Operation id in requests, status code in responses.
+    pub code: u16,
+    pub request_id: u32,
+    pub groups: Vec<IppGroup>,
+    pub data: Vec<u8>,
+}
+
+impl IppMessage {
+    /// A request with the mandatory `attributes-charset` and
+    /// `attributes-natural-language` operation attributes already in place.
+    pub fn request(operation: u16, request_id: u32) -> Self {
+        Self::with_code(operation, request_id)
+    }
+
+    /// A response to `request`, echoing its version and request id.
+    pub fn response(request: &IppMessage, status: u16) -> Self {
+        let mut response = Self::with_code(status, request.request_id);
+        response.version = request.version;
+        response
+    }
+
+    fn with_code(code: u16, request_id: u32) -> Self {
+        let mut message = IppMessage {
+            version: (2, 0),
+            code,
+            request_id,
+            groups: Vec::new(),
+            data: Vec::new(),
+        };
+        message.add(OPERATION_ATTRIBUTES, "attributes-charset", IppValue::String {
+            tag: TAG_CHARSET,
+            value: "utf-8".to_string(),
+        });
+        message.add(OPERATION_ATTRIBUTES, "attributes-natural-language", IppValue::String {
+            tag: TAG_NATURAL_LANGUAGE,
+            value: "en".to_string(),
+        });
+        message
+    }
+
+    /// Appends a single-valued attribute to the last group with `group` as its tag,
+    /// starting a new group if needed.
+    pub fn add(&mut self, group: u8, name: &str, value: IppValue) -> &mut Self {
+        self.add_values(group, name, vec![value])
+    }
+
+    pub fn add_values(&mut self, group: u8, name: &str, values: Vec<IppValue>) -> &mut Self {
+        if self.groups.last().is_none_or(|g| g.tag != group) {
+            self.groups.push(IppGroup { tag: group, attributes: Vec::new() });
+        }
+        let attributes = &mut self.groups.last_mut().unwrap().attributes;
+        attributes.push(IppAttribute { name: name.to_string(), values });
+        self
+    }
+
+    /// Starts a new group even if the previous one has the same tag, as needed for one
+    /// job-attributes group per job in a Get-Jobs response.
+    pub fn start_group(&mut self, group: u8) -> &mut Self {
+        self.groups.push(IppGroup { tag: group, attributes: Vec::new() });
+        self
+    }
+
+    /// First attribute called `name` in any group with tag `group`.
+    pub fn attribute(&self, group: u8, name: &str) -> Option<&IppAttribute> {
+        self.groups
+            .iter()
+            .filter(|g| g.tag == group)
+            .flat_map(|g| g.attributes.iter())
+            .find(|a| a.name == name)
+    }
+
+    /// First value of `name` as a string.
+    pub fn string(&self, group: u8, name: &str) -> Option<&str> {
+        self.attribute(group, name)?.values.first()?.as_str()
+    }
+
+    pub fn integer(&self, group: u8, name: &str) -> Option<i32> {
+        self.attribute(group, name)?.values.first()?.as_i32()
+    }
+
+    pub fn boolean(&self, group: u8, name: &str) -> Option<bool> {
+        match self.attribute(group, name)?.values.first()? {
+            IppValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Every string value of `name`, e.g. the entries of `document-format-supported`.
+    pub fn strings(&self, group: u8, name: &str) -> Vec<String> {
+        self.attribute(group, name)
+            .map(|a| a.values.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code < 0x0100
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.version.0, self.version.1];
+        out.extend_from_slice(&self.code.to_be_bytes());
+        out.extend_from_slice(&self.request_id.to_be_bytes());
+        for group in &self.groups {
+            out.push(group.tag);
+            for attribute in &group.attributes {
+                for (i, value) in attribute.values.iter().enumerate() {
+                    out.push(value.tag());
+                    // Additional values of the same attribute carry an empty name
+                    let name = if i == 0 { attribute.name.as_bytes() } else { &[] };
+                    out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+                    out.extend_from_slice(name);
+                    value.encode(&mut out);
+                }
+            }
+        }
+        out.push(END_OF_ATTRIBUTES);
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<IppMessage, String> {
+        if bytes.len() < 9 {
+            return Err("IPP message is too short".to_string());
+        }
+        let mut message = IppMessage {
+            version: (bytes[0], bytes[1]),
+            code: u16::from_be_bytes([bytes[2], bytes[3]]),
+            request_id: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            groups: Vec::new(),
+            data: Vec::new(),
+        };
+
+        let mut pos = 8;
+        let read_u16 = |pos: usize| -> Result<usize, String> {
+            bytes
+                .get(pos..pos + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                .ok_or_else(|| "Truncated IPP message".to_string())
+        };
+        loop {
+            let tag = *bytes.get(pos).ok_or("IPP message has no end-of-attributes tag")?;
+            pos += 1;
+            match tag {
+                END_OF_ATTRIBUTES => break,
+                0x00..=0x0f => {
+                    message.groups.push(IppGroup { tag, attributes: Vec::new() });
+                }
+                value_tag => {
+                    let group = message.groups.last_mut().ok_or("IPP attribute outside of a group")?;
+                    let name_len = read_u16(pos)?;
+                    let name = bytes.get(pos + 2..pos + 2 + name_len).ok_or("Truncated IPP attribute name")?;
+                    pos += 2 + name_len;
+                    let value_len = read_u16(pos)?;
+                    let value = bytes.get(pos + 2..pos + 2 + value_len).ok_or("Truncated IPP attribute value")?;
+                    pos += 2 + value_len;
+
+                    let value = IppValue::decode(value_tag, value)?;
+                    if name.is_empty() {
+                        let attribute = group.attributes.last_mut().ok_or("IPP value without an attribute")?;
+                        attribute.values.push(value);
+                    } else {
+                        group.attributes.push(IppAttribute {
+                            name: String::from_utf8_lossy(name).into_owned(),
+                            values: vec![value],
+                        });
+                    }
+                }
+            }
+        }
+
+        message.data = bytes[pos..].to_vec();
+        Ok(message)
+    }
+
+    /// Reads the header and attribute groups from `reader` and stops at the
+    /// end-of-attributes tag, leaving the document data in `reader` so it can be streamed.
+    /// Fails once the attributes grow past `limit` bytes.
+    pub fn read_attributes(reader: &mut impl Read, limit: usize) -> Result<IppMessage, String> {
+        let mut bytes = Vec::new();
+        read_into(reader, &mut bytes, 8)?;
+        loop {
+            if bytes.len() > limit {
+                return Err(format!("IPP attributes are larger than {} bytes", limit));
+            }
+            read_into(reader, &mut bytes, 1)?;
+            match bytes[bytes.len() - 1] {
+                END_OF_ATTRIBUTES => break,
+                0x00..=0x0f => {}
+                _ => {
+                    // Name, then value, each with a two-byte length
+                    for _ in 0..2 {
+                        read_into(reader, &mut bytes, 2)?;
+                        let length = u16::from_be_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+                        read_into(reader, &mut bytes, length as usize)?;
+                    }
+                }
+            }
+        }
+        Self::parse(&bytes)
+    }
+}
+
+fn read_into(reader: &mut impl Read, bytes: &mut Vec<u8>, count: usize) -> Result<(), String> {
+    let read = reader
+        .take(count as u64)
+        .read_to_end(bytes)
+        .map_err(|e| format!("Failed to read IPP message: {}", e))?;
+    if read < count {
+        return Err("Truncated IPP message".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> IppMessage {
+        let mut message = IppMessage::request(PRINT_JOB, 42);
+        message
+            .add(OPERATION_ATTRIBUTES, "printer-uri", IppValue::uri("ipp://printer.local:631/ipp/print"))
+            .add(OPERATION_ATTRIBUTES, "job-name", IppValue::name("Zoë's report"))
+            .add(JOB_ATTRIBUTES, "copies", IppValue::Integer(2))
+            .add(JOB_ATTRIBUTES, "print-color-mode-default", IppValue::Enum(3))
+            .add(JOB_ATTRIBUTES, "page-ranges", IppValue::Range(1, 5))
+            .add(JOB_ATTRIBUTES, "printer-resolution", IppValue::Resolution { x: 600, y: 600, units: 3 })
+            .add(JOB_ATTRIBUTES, "color-supported", IppValue::Boolean(true))
+            .add(JOB_ATTRIBUTES, "printer-state-message", IppValue::OutOfBand(TAG_NO_VALUE))
+            .add(JOB_ATTRIBUTES, "printer-current-time", IppValue::Other {
+                tag: TAG_DATE_TIME,
+                bytes: vec![7, 234, 10, 18, 9, 30, 0, 0, b'+', 0, 0],
+            })
+            .add_values(
+                PRINTER_ATTRIBUTES,
+                "document-format-supported",
+                vec![IppValue::mime("application/pdf"), IppValue::mime("image/urf")],
+            );
+        message.data = b"%PDF-1.7 document".to_vec();
+        message
+    }
+
+    #[test]
+    fn encode_parse_round_trip() {
+        let message = sample();
+        let bytes = message.to_bytes();
+        assert_eq!(&bytes[..8], [2, 0, 0, 2, 0, 0, 0, 42]);
+        assert_eq!(IppMessage::parse(&bytes).unwrap(), message);
+
+        let parsed = IppMessage::parse(&bytes).unwrap();
+        assert_eq!(parsed.strings(PRINTER_ATTRIBUTES, "document-format-supported"), ["application/pdf", "image/urf"]);
+        assert_eq!(parsed.integer(JOB_ATTRIBUTES, "copies"), Some(2));
+        assert_eq!(parsed.boolean(JOB_ATTRIBUTES, "color-supported"), Some(true));
+        assert_eq!(parsed.data, b"%PDF-1.7 document");
+    }
+
+    #[test]
+    fn read_attributes_leaves_the_document() {
+        let message = sample();
+        let bytes = message.to_bytes();
+        let mut reader = &bytes[..];
+        let parsed = IppMessage::read_attributes(&mut reader, 4096).unwrap();
+        assert!(parsed.data.is_empty());
+        assert_eq!(parsed.groups, message.groups);
+        assert_eq!(reader, b"%PDF-1.7 document");
+    }
+
+    #[test]
+    fn truncated_messages_are_errors() {
+        let bytes = sample().to_bytes();
+        let end = bytes.len() - b"%PDF-1.7 document".len();
+        // Every cut inside the attributes must fail cleanly, never panic
+        for cut in 0..end {
+            assert!(IppMessage::parse(&bytes[..cut]).is_err(), "cut at {}", cut);
+            assert!(IppMessage::read_attributes(&mut &bytes[..cut], 4096).is_err(), "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn oversized_lengths_are_errors() {
+        let header = [2, 0, 0, 2, 0, 0, 0, 1, OPERATION_ATTRIBUTES];
+        // Name length far past the end of the message
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&[TAG_KEYWORD, 0xff, 0xff, b'a']);
+        assert_eq!(IppMessage::parse(&bytes).unwrap_err(), "Truncated IPP attribute name");
+        assert!(IppMessage::read_attributes(&mut &bytes[..], 1024 * 1024).is_err());
+
+        // Value length past the end
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&[TAG_KEYWORD, 0, 1, b'a', 0xff, 0xff, b'b', END_OF_ATTRIBUTES]);
+        assert_eq!(IppMessage::parse(&bytes).unwrap_err(), "Truncated IPP attribute value");
+
+        // Integers and resolutions shorter than their fixed size
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&[TAG_INTEGER, 0, 1, b'a', 0, 2, 0, 1, END_OF_ATTRIBUTES]);
+        assert!(IppMessage::parse(&bytes).unwrap_err().contains("Truncated value"));
+        let mut bytes = header.to_vec();
+        bytes.extend_from_slice(&[TAG_RESOLUTION, 0, 1, b'a', 0, 8, 0, 0, 1, 44, 0, 0, 1, 44, END_OF_ATTRIBUTES]);
+        assert_eq!(IppMessage::parse(&bytes).unwrap_err(), "Truncated resolution");
+
+        // Attributes that never end are cut off at the limit instead of filling memory
+        let mut bytes = header.to_vec();
+        for _ in 0..1000 {
+            bytes.extend_from_slice(&[TAG_KEYWORD, 0, 1, b'a', 0, 4, b'b', b'b', b'b', b'b']);
+        }
+        let err = IppMessage::read_attributes(&mut &bytes[..], 4096).unwrap_err();
+        assert!(err.contains("larger than 4096 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn values_outside_a_group_are_errors() {
+        let bytes = [2, 0, 0, 2, 0, 0, 0, 1, TAG_KEYWORD, 0, 1, b'a', 0, 0, END_OF_ATTRIBUTES];
+        assert_eq!(IppMessage::parse(&bytes).unwrap_err(), "IPP attribute outside of a group");
+        let bytes = [2, 0, 0, 2, 0, 0, 0, 1, OPERATION_ATTRIBUTES, TAG_KEYWORD, 0, 0, 0, 0, END_OF_ATTRIBUTES];
+        assert_eq!(IppMessage::parse(&bytes).unwrap_err(), "IPP value without an attribute");
+    }
+}
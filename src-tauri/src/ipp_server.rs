@@ -0,0 +1,876 @@
+use crate::inventory::InventoryStore;
+use crate::ipp_message::*;
+use crate::printing::JobHistory;
+use crate::relay;
+use crate::settings::VirtualPrinterSettings;
+use crate::vault::CredentialVault;
+use chrono::{DateTime, Utc};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+use tiny_http::{Header, Method, Response, Server};
+use tracing::{debug, info, info_span, warn};
+
+/// Resource path of the printer, as used by IPP Everywhere printers.
+pub const RESOURCE: &str = "/ipp/print";
+/// Largest document accepted. Documents are streamed to disk, never held in memory.
+const MAX_DOCUMENT_BYTES: u64 = 512 * 1024 * 1024;
+/// Largest attribute section accepted; only this part of a request is buffered.
+const MAX_ATTRIBUTE_BYTES: usize = 1024 * 1024;
+/// Requests handled at once. Further connections wait in the listener until a worker is
+/// free.
+const WORKERS: usize = 8;
+/// Jobs kept in memory for Get-Jobs; older ones stay on disk.
+const MAX_RETAINED_JOBS: usize = 500;
+
+/// Formats accepted by the virtual printer and the extension documents are saved with.
+pub const DOCUMENT_FORMATS: &[(&str, &str)] = &[
+    ("application/pdf", "pdf"),
+    ("application/postscript", "ps"),
+    ("image/pwg-raster", "pwg"),
+    ("image/urf", "urf"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("text/plain", "txt"),
+    ("application/octet-stream", "bin"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapturedJobState {
+    /// Created with Create-Job and waiting for its last document.
+    Pending,
+    /// Being forwarded by the relay.
+    Processing,
+    Completed,
+    Canceled,
+    /// Still pending when the server stopped, or the relay could not print it.
+    Aborted,
+}
+
+impl CapturedJobState {
+    fn ipp(self) -> i32 {
+        match self {
+            CapturedJobState::Pending => JOB_PENDING,
+            CapturedJobState::Processing => JOB_PROCESSING,
+            CapturedJobState::Completed => JOB_COMPLETED,
+            CapturedJobState::Canceled => JOB_CANCELED,
+            CapturedJobState::Aborted => JOB_ABORTED,
+        }
+    }
+
+    fn reason(self) -> &'static str {
+        match self {
+            CapturedJobState::Pending => "job-incoming",
+            CapturedJobState::Processing => "job-transforming",
+            CapturedJobState::Completed => "job-completed-successfully",
+            CapturedJobState::Canceled => "job-canceled-by-user",
+            CapturedJobState::Aborted => "aborted-by-system",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedDocument {
+    /// File name inside the capture folder.
+    pub file: String,
+    pub format: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub bytes: usize,
+}
+
+/// A job received by the virtual printer. Saved next to its documents as
+/// `job-<id>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedJob {
+    pub job_id: i32,
+    pub name: String,
+    pub user: String,
+    pub state: CapturedJobState,
+    pub created: DateTime<Utc>,
+    #[serde(default)]
+    pub completed: Option<DateTime<Utc>>,
+    /// Address of the client that sent the job.
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub documents: Vec<CapturedDocument>,
+    /// Job template attributes from the request (copies, media, sides...), as text.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Vec<String>>,
+    /// Printer the relay forwarded the job to.
+    #[serde(default)]
+    pub forwarded_to: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VirtualPrinterStatus {
+    pub running: bool,
+    pub uri: Option<String>,
+    pub folder: Option<String>,
+    pub advertised: bool,
+    /// Why the printer is not running although it is enabled.
+    pub error: Option<String>,
+}
+
+struct VirtualPrinter {
+    config: VirtualPrinterSettings,
+    /// Source of the inventory, vault and job history for the relay; without it jobs are
+    /// only captured.
+    app: Option<AppHandle>,
+    folder: PathBuf,
+    uuid: String,
+    started: Instant,
+    jobs: Mutex<Vec<CapturedJob>>,
+    next_job_id: AtomicI32,
+}
+
+fn extension(format: &str) -> &'static str {
+    DOCUMENT_FORMATS
+        .iter()
+        .find(|(f, _)| *f == format)
+        .map(|(_, ext)| *ext)
+        .unwrap_or("bin")
+}
+
+/// Stable `urn:uuid:` for a printer name and port, so clients recognise the printer
+/// across restarts.
+fn printer_uuid(name: &str, port: u16) -> String {
+    let hash = Sha256::digest(format!("{}:{}", name, port).as_bytes());
+    let hex: String = hash.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Jobs saved by earlier runs, oldest first. Jobs that never got their last document, or
+/// were still being relayed, are marked aborted.
+fn load_jobs(folder: &Path) -> Vec<CapturedJob> {
+    let Ok(entries) = std::fs::read_dir(folder) else { return Vec::new() };
+    let mut jobs: Vec<CapturedJob> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .filter_map(|json| serde_json::from_str(&json).ok())
+        .collect();
+    jobs.sort_by_key(|job: &CapturedJob| job.job_id);
+    for job in &mut jobs {
+        if matches!(job.state, CapturedJobState::Pending | CapturedJobState::Processing) {
+            job.state = CapturedJobState::Aborted;
+        }
+    }
+    let excess = jobs.len().saturating_sub(MAX_RETAINED_JOBS);
+    jobs.drain(..excess);
+    jobs
+}
+
+/// Request details that are not part of the IPP message.
+struct Origin {
+    /// `ipp://host:port/ipp/print` as the client addressed us.
+    printer_uri: String,
+    client: Option<String>,
+}
+
+impl VirtualPrinter {
+    fn save_job(&self, job: &CapturedJob) -> Result<(), String> {
+        let path = self.folder.join(format!("job-{:05}.json", job.job_id));
+        let json = serde_json::to_string_pretty(job).map_err(|e| format!("Failed to serialize job: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Copies the document from `data` into the capture folder.
+    fn save_document(
+        &self,
+        job: &mut CapturedJob,
+        format: &str,
+        name: Option<&str>,
+        data: &mut dyn Read,
+    ) -> Result<(), (u16, String)> {
+        let file = format!("job-{:05}-{}.{}", job.job_id, job.documents.len() + 1, extension(format));
+        let path = self.folder.join(&file);
+        let internal = |e: std::io::Error| {
+            warn!("failed to save {}: {}", path.display(), e);
+            (SERVER_ERROR_INTERNAL_ERROR, format!("Failed to write {}: {}", path.display(), e))
+        };
+        let mut out = std::fs::File::create(&path).map_err(internal)?;
+        let bytes = std::io::copy(&mut data.take(MAX_DOCUMENT_BYTES + 1), &mut out).map_err(internal)?;
+        if bytes > MAX_DOCUMENT_BYTES {
+            drop(out);
+            let _ = std::fs::remove_file(&path);
+            return Err((
+                CLIENT_ERROR_REQUEST_ENTITY_TOO_LARGE,
+                format!("Documents larger than {} MB are not accepted", MAX_DOCUMENT_BYTES / (1024 * 1024)),
+            ));
+        }
+        job.documents.push(CapturedDocument {
+            file,
+            format: format.to_string(),
+            name: name.map(str::to_string),
+            bytes: bytes as usize,
+        });
+        Ok(())
+    }
+
+    /// Answers `request`; `document` is the data that follows its attributes.
+    fn process(self: &Arc<Self>, request: &IppMessage, origin: &Origin, document: &mut dyn Read) -> IppMessage {
+        if !(1..=2).contains(&request.version.0) {
+            return IppMessage::response(request, SERVER_ERROR_VERSION_NOT_SUPPORTED);
+        }
+        if request.attribute(OPERATION_ATTRIBUTES, "attributes-charset").is_none() {
+            return IppMessage::response(request, CLIENT_ERROR_BAD_REQUEST);
+        }
+        let result = match request.code {
+            PRINT_JOB => self.print_job(request, origin, document),
+            VALIDATE_JOB => self.check_format(request).map(|_| IppMessage::response(request, SUCCESSFUL_OK)),
+            CREATE_JOB => self.create_job(request, origin),
+            SEND_DOCUMENT => self.send_document(request, origin, document),
+            CANCEL_JOB => self.cancel_job(request),
+            GET_JOB_ATTRIBUTES => self.get_job_attributes(request, origin),
+            GET_JOBS => Ok(self.get_jobs(request, origin)),
+            GET_PRINTER_ATTRIBUTES => Ok(self.get_printer_attributes(request, origin)),
+            _ => Err((SERVER_ERROR_OPERATION_NOT_SUPPORTED, "Operation not supported".to_string())),
+        };
+        result.unwrap_or_else(|(status, message)| {
+            let mut response = IppMessage::response(request, status);
+            response.add(OPERATION_ATTRIBUTES, "status-message", IppValue::text(&message));
+            response
+        })
+    }
+
+    fn check_format(&self, request: &IppMessage) -> Result<String, (u16, String)> {
+        let format = request
+            .string(OPERATION_ATTRIBUTES, "document-format")
+            .unwrap_or("application/octet-stream");
+        if DOCUMENT_FORMATS.iter().any(|(f, _)| *f == format) {
+            Ok(format.to_string())
+        } else {
+            Err((CLIENT_ERROR_DOCUMENT_FORMAT_NOT_SUPPORTED, format!("{} is not supported", format)))
+        }
+    }
+
+    fn new_job(&self, request: &IppMessage, origin: &Origin) -> CapturedJob {
+        let attributes = request
+            .groups
+            .iter()
+            .filter(|g| g.tag == JOB_ATTRIBUTES)
+            .flat_map(|g| g.attributes.iter())
+            .map(|a| (a.name.clone(), a.values.iter().map(|v| v.to_string()).collect()))
+            .collect();
+        CapturedJob {
+            job_id: self.next_job_id.fetch_add(1, Ordering::Relaxed),
+            name: request.string(OPERATION_ATTRIBUTES, "job-name").unwrap_or("Untitled").to_string(),
+            user: request
+                .string(OPERATION_ATTRIBUTES, "requesting-user-name")
+                .unwrap_or("anonymous")
+                .to_string(),
+            state: CapturedJobState::Pending,
+            created: Utc::now(),
+            completed: None,
+            client: origin.client.clone(),
+            documents: Vec::new(),
+            attributes,
+            forwarded_to: None,
+            error: None,
+        }
+    }
+
+    /// Marks a job whose last document arrived: completed, or processing until the relay
+    /// has forwarded it.
+    fn finish(&self, job: &mut CapturedJob) {
+        if self.config.relay.enabled && self.app.is_some() {
+            job.state = CapturedJobState::Processing;
+        } else {
+            job.state = CapturedJobState::Completed;
+            job.completed = Some(Utc::now());
+        }
+        info!(job_id = job.job_id, user = %job.user, documents = job.documents.len(), "captured print job");
+    }
+
+    /// Forwards a processing job in the background and records the outcome in the job
+    /// history and the job's metadata.
+    fn relay(self: &Arc<Self>, mut job: CapturedJob) {
+        if job.state != CapturedJobState::Processing {
+            return;
+        }
+        let Some(app) = self.app.clone() else { return };
+        let printer = self.clone();
+        let span = tracing::Span::current();
+        thread::spawn(move || {
+            let _span = span.enter();
+            let inventory = app.state::<InventoryStore>().snapshot();
+            let vault = app.state::<CredentialVault>();
+            let (outcome, records) = relay::forward_job(&printer.config.relay, &inventory, &vault, &job, &printer.folder);
+            let history = app.state::<JobHistory>();
+            for record in &records {
+                if let Err(e) = history.append(record) {
+                    warn!("{}", e);
+                }
+            }
+
+            job.completed = Some(Utc::now());
+            match outcome {
+                Ok(target) => {
+                    job.state = CapturedJobState::Completed;
+                    job.forwarded_to = Some(target);
+                    if printer.config.relay.delete_forwarded {
+                        for document in &job.documents {
+                            let _ = std::fs::remove_file(printer.folder.join(&document.file));
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(job_id = job.job_id, user = %job.user, "relay gave up: {}", e);
+                    job.state = CapturedJobState::Aborted;
+                    job.error = Some(e);
+                }
+            }
+            let _ = printer.record(&job);
+        });
+    }
+
+    /// Saves the job's metadata and adds it to the in-memory list.
+    fn record(&self, job: &CapturedJob) -> Result<(), (u16, String)> {
+        self.save_job(job).map_err(|e| {
+            warn!("{}", e);
+            (SERVER_ERROR_INTERNAL_ERROR, e)
+        })?;
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.iter_mut().find(|j| j.job_id == job.job_id) {
+            Some(existing) => *existing = job.clone(),
+            None => {
+                jobs.push(job.clone());
+                let excess = jobs.len().saturating_sub(MAX_RETAINED_JOBS);
+                jobs.drain(..excess);
+            }
+        }
+        Ok(())
+    }
+
+    fn job_response(&self, request: &IppMessage, job: &CapturedJob, origin: &Origin) -> IppMessage {
+        let mut response = IppMessage::response(request, SUCCESSFUL_OK);
+        add_job_attributes(&mut response, job, &origin.printer_uri);
+        response
+    }
+
+    fn print_job(
+        self: &Arc<Self>,
+        request: &IppMessage,
+        origin: &Origin,
+        document: &mut dyn Read,
+    ) -> Result<IppMessage, (u16, String)> {
+        let format = self.check_format(request)?;
+        let mut job = self.new_job(request, origin);
+        let name = request.string(OPERATION_ATTRIBUTES, "document-name");
+        self.save_document(&mut job, &format, name, document)?;
+        self.finish(&mut job);
+        self.record(&job)?;
+        let response = self.job_response(request, &job, origin);
+        self.relay(job);
+        Ok(response)
+    }
+
+    fn create_job(&self, request: &IppMessage, origin: &Origin) -> Result<IppMessage, (u16, String)> {
+        let job = self.new_job(request, origin);
+        self.record(&job)?;
+        debug!(job_id = job.job_id, "created job");
+        Ok(self.job_response(request, &job, origin))
+    }
+
+    fn find_job(&self, request: &IppMessage) -> Result<CapturedJob, (u16, String)> {
+        let job_id = request
+            .integer(OPERATION_ATTRIBUTES, "job-id")
+            .ok_or((CLIENT_ERROR_BAD_REQUEST, "job-id is required".to_string()))?;
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|job| job.job_id == job_id)
+            .cloned()
+            .ok_or((CLIENT_ERROR_NOT_FOUND, format!("Job {} not found", job_id)))
+    }
+
+    fn send_document(
+        self: &Arc<Self>,
+        request: &IppMessage,
+        origin: &Origin,
+        document: &mut dyn Read,
+    ) -> Result<IppMessage, (u16, String)> {
+        let mut job = self.find_job(request)?;
+        if job.state != CapturedJobState::Pending {
+            return Err((CLIENT_ERROR_NOT_POSSIBLE, format!("Job {} is no longer accepting documents", job.job_id)));
+        }
+        let format = self.check_format(request)?;
+        // A Send-Document without data only closes the job
+        let mut document = BufReader::new(document);
+        let has_data = document.fill_buf().map(|buffer| !buffer.is_empty()).unwrap_or(false);
+        if has_data {
+            let name = request.string(OPERATION_ATTRIBUTES, "document-name");
+            self.save_document(&mut job, &format, name, &mut document)?;
+        }
+        if request.boolean(OPERATION_ATTRIBUTES, "last-document") == Some(true) {
+            self.finish(&mut job);
+        }
+        self.record(&job)?;
+        let response = self.job_response(request, &job, origin);
+        self.relay(job);
+        Ok(response)
+    }
+
+    fn cancel_job(&self, request: &IppMessage) -> Result<IppMessage, (u16, String)> {
+        let mut job = self.find_job(request)?;
+        if job.state != CapturedJobState::Pending {
+            return Err((CLIENT_ERROR_NOT_POSSIBLE, format!("Job {} is already finished", job.job_id)));
+        }
+        job.state = CapturedJobState::Canceled;
+        job.completed = Some(Utc::now());
+        self.record(&job)?;
+        Ok(IppMessage::response(request, SUCCESSFUL_OK))
+    }
+
+    fn get_job_attributes(&self, request: &IppMessage, origin: &Origin) -> Result<IppMessage, (u16, String)> {
+        let job = self.find_job(request)?;
+        Ok(self.job_response(request, &job, origin))
+    }
+
+    fn get_jobs(&self, request: &IppMessage, origin: &Origin) -> IppMessage {
+        let which = request.string(OPERATION_ATTRIBUTES, "which-jobs").unwrap_or("not-completed");
+        let limit = request
+            .integer(OPERATION_ATTRIBUTES, "limit")
+            .filter(|&limit| limit > 0)
+            .map(|limit| limit as usize)
+            .unwrap_or(usize::MAX);
+        let jobs = self.jobs.lock().unwrap();
+        let mut response = IppMessage::response(request, SUCCESSFUL_OK);
+        let matching = jobs
+            .iter()
+            .rev()
+            .filter(|job| {
+                let active = matches!(job.state, CapturedJobState::Pending | CapturedJobState::Processing);
+                match which {
+                    "completed" => !active,
+                    "all" => true,
+                    _ => active,
+                }
+            })
+            .take(limit);
+        for job in matching {
+            response.start_group(JOB_ATTRIBUTES);
+            add_job_attributes(&mut response, job, &origin.printer_uri);
+        }
+        response
+    }
+
+    fn get_printer_attributes(&self, request: &IppMessage, origin: &Origin) -> IppMessage {
+        let requested = request.strings(OPERATION_ATTRIBUTES, "requested-attributes");
+        let wanted = |name: &str| {
+            requested.is_empty()
+                || requested
+                    .iter()
+                    .any(|r| r == name || r == "all" || r == "printer-description")
+        };
+        let queued = self
+            .jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|job| matches!(job.state, CapturedJobState::Pending | CapturedJobState::Processing))
+            .count();
+
+        let mut response = IppMessage::response(request, SUCCESSFUL_OK);
+        for (name, values) in self.printer_attributes(&origin.printer_uri, queued) {
+            if wanted(name) {
+                response.add_values(PRINTER_ATTRIBUTES, name, values);
+            }
+        }
+        response
+    }
+
+    fn printer_attributes(&self, printer_uri: &str, queued: usize) -> Vec<(&'static str, Vec<IppValue>)> {
+        let keywords = |values: &[&str]| values.iter().map(|v| IppValue::keyword(v)).collect::<Vec<_>>();
+        let operations = [
+            PRINT_JOB,
+            VALIDATE_JOB,
+            CREATE_JOB,
+            SEND_DOCUMENT,
+            CANCEL_JOB,
+            GET_JOB_ATTRIBUTES,
+            GET_JOBS,
+            GET_PRINTER_ATTRIBUTES,
+        ];
+        let charset = |value: &str| IppValue::String { tag: TAG_CHARSET, value: value.to_string() };
+        let language = |value: &str| IppValue::String { tag: TAG_NATURAL_LANGUAGE, value: value.to_string() };
+        vec![
+            ("printer-uri-supported", vec![IppValue::uri(printer_uri)]),
+            ("uri-security-supported", keywords(&["none"])),
+            ("uri-authentication-supported", keywords(&["none"])),
+            ("printer-name", vec![IppValue::name(&self.config.name)]),
+            ("printer-info", vec![IppValue::text(&self.config.name)]),
+            ("printer-make-and-model", vec![IppValue::text("Printer Manager Virtual Printer")]),
+            ("printer-uuid", vec![IppValue::uri(&self.uuid)]),
+            ("printer-state", vec![IppValue::Enum(PRINTER_IDLE)]),
+            ("printer-state-reasons", keywords(&["none"])),
+            ("printer-is-accepting-jobs", vec![IppValue::Boolean(true)]),
+            ("queued-job-count", vec![IppValue::Integer(queued as i32)]),
+            ("printer-up-time", vec![IppValue::Integer(self.started.elapsed().as_secs().max(1) as i32)]),
+            ("ipp-versions-supported", keywords(&["1.1", "2.0"])),
+            ("ipp-features-supported", keywords(&["ipp-everywhere"])),
+            ("operations-supported", operations.iter().map(|&op| IppValue::Enum(i32::from(op))).collect()),
+            ("multiple-document-jobs-supported", vec![IppValue::Boolean(true)]),
+            ("charset-configured", vec![charset("utf-8")]),
+            ("charset-supported", vec![charset("utf-8")]),
+            ("natural-language-configured", vec![language("en")]),
+            ("generated-natural-language-supported", vec![language("en")]),
+            ("document-format-default", vec![IppValue::mime("application/octet-stream")]),
+            ("document-format-supported", DOCUMENT_FORMATS.iter().map(|(f, _)| IppValue::mime(f)).collect()),
+            ("pdl-override-supported", keywords(&["attempted"])),
+            ("compression-supported", keywords(&["none"])),
+            ("color-supported", vec![IppValue::Boolean(true)]),
+            ("copies-default", vec![IppValue::Integer(1)]),
+            ("copies-supported", vec![IppValue::Range(1, 999)]),
+            ("sides-default", keywords(&["one-sided"])),
+            ("sides-supported", keywords(&["one-sided", "two-sided-long-edge", "two-sided-short-edge"])),
+            ("media-default", keywords(&["iso_a4_210x297mm"])),
+            ("media-supported", keywords(&["iso_a4_210x297mm", "na_letter_8.5x11in", "na_legal_8.5x14in"])),
+            ("print-color-mode-default", keywords(&["color"])),
+            ("print-color-mode-supported", keywords(&["auto", "color", "monochrome"])),
+            (
+                "pwg-raster-document-resolution-supported",
+                vec![IppValue::Resolution { x: 300, y: 300, units: 3 }, IppValue::Resolution { x: 600, y: 600, units: 3 }],
+            ),
+            ("pwg-raster-document-type-supported", keywords(&["black_1", "sgray_8", "srgb_8"])),
+            ("pwg-raster-document-sheet-back", keywords(&["normal"])),
+            ("urf-supported", keywords(&["V1.4", "CP1", "W8", "SRGB24", "RS300-600", "DM1", "IS1"])),
+        ]
+    }
+}
+
+fn add_job_attributes(message: &mut IppMessage, job: &CapturedJob, printer_uri: &str) {
+    message
+        .add(JOB_ATTRIBUTES, "job-id", IppValue::Integer(job.job_id))
+        .add(JOB_ATTRIBUTES, "job-uri", IppValue::uri(&format!("{}/{}", printer_uri, job.job_id)))
+        .add(JOB_ATTRIBUTES, "job-printer-uri", IppValue::uri(printer_uri))
+        .add(JOB_ATTRIBUTES, "job-name", IppValue::name(&job.name))
+        .add(JOB_ATTRIBUTES, "job-originating-user-name", IppValue::name(&job.user))
+        .add(JOB_ATTRIBUTES, "job-state", IppValue::Enum(job.state.ipp()))
+        .add(JOB_ATTRIBUTES, "job-state-reasons", IppValue::keyword(job.state.reason()));
+}
+
+fn handle(printer: &Arc<VirtualPrinter>, mut request: tiny_http::Request) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    if path != RESOURCE && path != "/" {
+        let _ = request.respond(Response::from_string("Not found").with_status_code(404));
+        return;
+    }
+    if *request.method() != Method::Post {
+        let page = format!("{} is an IPP printer. Print to ipp://<this host>:{}{}", printer.config.name, printer.config.port, RESOURCE);
+        let _ = request.respond(Response::from_string(page));
+        return;
+    }
+
+    let host = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Host"))
+        .map(|h| h.value.as_str().to_string())
+        .unwrap_or_else(|| format!("localhost:{}", printer.config.port));
+    let origin = Origin {
+        printer_uri: format!("ipp://{}{}", host, RESOURCE),
+        client: request.remote_addr().map(|addr| addr.ip().to_string()),
+    };
+
+    // Only the attributes are read here; the document is streamed to disk while processing
+    let mut reader = request.as_reader();
+    let message = match IppMessage::read_attributes(&mut reader, MAX_ATTRIBUTE_BYTES) {
+        Ok(message) => message,
+        Err(e) => {
+            debug!("invalid IPP request: {}", e);
+            let _ = request.respond(Response::from_string(e).with_status_code(400));
+            return;
+        }
+    };
+    let response = printer.process(&message, &origin, reader);
+
+    let content_type = Header::from_bytes("Content-Type", "application/ipp").unwrap();
+    if let Err(e) = request.respond(Response::from_data(response.to_bytes()).with_header(content_type)) {
+        debug!("failed to send IPP response: {}", e);
+    }
+}
+
+/// Announces the printer as an IPP Everywhere service.
+fn advertise(printer: &VirtualPrinter) -> Result<ServiceDaemon, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| format!("DNS-SD failed to start: {}", e))?;
+    let host = dns_lookup::get_hostname().unwrap_or_else(|_| "printer-manager".to_string());
+    let formats: Vec<&str> = DOCUMENT_FORMATS.iter().map(|(f, _)| *f).collect();
+    let pdl = formats.join(",");
+    let uuid = printer.uuid.trim_start_matches("urn:uuid:");
+    let properties = [
+        ("txtvers", "1"),
+        ("qtotal", "1"),
+        ("rp", RESOURCE.trim_start_matches('/')),
+        ("ty", "Printer Manager Virtual Printer"),
+        ("note", printer.config.name.as_str()),
+        ("pdl", pdl.as_str()),
+        ("UUID", uuid),
+        ("URF", "V1.4,CP1,W8,SRGB24,RS300-600,DM1,IS1"),
+        ("Color", "T"),
+        ("Duplex", "T"),
+        ("kind", "document"),
+    ];
+    let service = ServiceInfo::new(
+        "_ipp._tcp.local.",
+        &printer.config.name,
+        &format!("{}.local.", host),
+        (),
+        printer.config.port,
+        &properties[..],
+    )
+    .map_err(|e| format!("Invalid DNS-SD record: {}", e))?
+    .enable_addr_auto();
+    daemon.register(service).map_err(|e| format!("DNS-SD registration failed: {}", e))?;
+    Ok(daemon)
+}
+
+struct Running {
+    printer: Arc<VirtualPrinter>,
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+    dns_sd: Option<ServiceDaemon>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    app: Option<AppHandle>,
+    default_folder: Option<PathBuf>,
+    running: Option<Running>,
+    error: Option<String>,
+}
+
+fn state() -> &'static Mutex<ServerState> {
+    static STATE: OnceLock<Mutex<ServerState>> = OnceLock::new();
+    STATE.get_or_init(|| Mutex::new(ServerState::default()))
+}
+
+fn start(config: &VirtualPrinterSettings, folder: PathBuf, app: Option<AppHandle>) -> Result<Running, String> {
+    std::fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+    let address = if config.listen_on_network { "0.0.0.0" } else { "127.0.0.1" };
+    let server = Server::http((address, config.port))
+        .map_err(|e| format!("Failed to listen on {}:{}: {}", address, config.port, e))?;
+
+    let jobs = load_jobs(&folder);
+    let next_job_id = jobs.iter().map(|job| job.job_id).max().unwrap_or(0) + 1;
+    let printer = Arc::new(VirtualPrinter {
+        config: config.clone(),
+        app,
+        uuid: printer_uuid(&config.name, config.port),
+        folder,
+        started: Instant::now(),
+        jobs: Mutex::new(jobs),
+        next_job_id: AtomicI32::new(next_job_id),
+    });
+    // Advertising a printer other machines cannot reach only confuses them
+    let dns_sd = if config.advertise && !config.listen_on_network {
+        info!("virtual printer is not advertised because it only accepts local jobs");
+        None
+    } else if config.advertise {
+        match advertise(&printer) {
+            Ok(daemon) => Some(daemon),
+            Err(e) => {
+                warn!("virtual printer will not be advertised: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let server = Arc::new(server);
+    let stopping = Arc::new(AtomicBool::new(false));
+    let span = info_span!("virtual_printer", port = config.port);
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            let printer = printer.clone();
+            let stopping = stopping.clone();
+            let span = span.clone();
+            thread::spawn(move || {
+                let _span = span.enter();
+                loop {
+                    match server.recv() {
+                        Ok(request) => handle(&printer, request),
+                        Err(_) if stopping.load(Ordering::Relaxed) => break,
+                        Err(e) => debug!("virtual printer connection failed: {}", e),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    info!(port = config.port, folder = %printer.folder.display(), "virtual printer started");
+    Ok(Running {
+        printer,
+        server,
+        stopping,
+        workers,
+        dns_sd,
+    })
+}
+
+fn stop(running: Running) {
+    if let Some(daemon) = running.dns_sd {
+        let _ = daemon.unregister(&format!("{}._ipp._tcp.local.", running.printer.config.name));
+        let _ = daemon.shutdown();
+    }
+    running.stopping.store(true, Ordering::Relaxed);
+    // Each call wakes one worker blocked in `recv`
+    for _ in &running.workers {
+        running.server.unblock();
+    }
+    for worker in running.workers {
+        let _ = worker.join();
+    }
+    info!(port = running.printer.config.port, "virtual printer stopped");
+}
+
+/// Sets the folder used when the settings name none, then starts the printer if it is
+/// enabled.
+pub fn init(app: AppHandle, default_folder: PathBuf) {
+    {
+        let mut state = state().lock().unwrap();
+        state.app = Some(app);
+        state.default_folder = Some(default_folder);
+    }
+    apply(&crate::settings::current().virtual_printer);
+}
+
+/// Stops the running printer, if any, and starts it again with `config` when enabled.
+pub fn apply(config: &VirtualPrinterSettings) {
+    let mut state = state().lock().unwrap();
+    if let Some(running) = state.running.take() {
+        stop(running);
+    }
+    state.error = None;
+    if !config.enabled {
+        return;
+    }
+
+    let folder = match (&config.folder, &state.default_folder) {
+        (Some(folder), _) => PathBuf::from(folder),
+        (None, Some(default)) => default.clone(),
+        (None, None) => return,
+    };
+    match start(config, folder, state.app.clone()) {
+        Ok(running) => state.running = Some(running),
+        Err(e) => {
+            warn!("virtual printer not started: {}", e);
+            state.error = Some(e);
+        }
+    }
+}
+
+pub fn status() -> VirtualPrinterStatus {
+    let state = state().lock().unwrap();
+    match &state.running {
+        Some(running) => VirtualPrinterStatus {
+            running: true,
+            uri: Some(format!("ipp://localhost:{}{}", running.printer.config.port, RESOURCE)),
+            folder: Some(running.printer.folder.display().to_string()),
+            advertised: running.dns_sd.is_some(),
+            error: None,
+        },
+        None => VirtualPrinterStatus {
+            running: false,
+            uri: None,
+            folder: None,
+            advertised: false,
+            error: state.error.clone(),
+        },
+    }
+}
+
+/// Jobs received by the running printer, newest first.
+pub fn captured_jobs(limit: usize) -> Vec<CapturedJob> {
+    let state = state().lock().unwrap();
+    match &state.running {
+        Some(running) => running.printer.jobs.lock().unwrap().iter().rev().take(limit).cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+#[tauri::command]
+pub fn get_virtual_printer_status() -> VirtualPrinterStatus {
+    status()
+}
+
+#[tauri::command]
+pub fn get_captured_jobs(limit: Option<usize>) -> Vec<CapturedJob> {
+    captured_jobs(limit.unwrap_or(200))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::HostAddr;
+    use crate::ipp_client::{self, IppEndpoint};
+    use crate::raster::Sides;
+
+    #[test]
+    fn print_job_is_spooled_to_disk() {
+        let folder = std::env::temp_dir().join(format!("ipp-server-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        let config = VirtualPrinterSettings {
+            port: 0,
+            ..Default::default()
+        };
+        let running = start(&config, folder.clone(), None).unwrap();
+        let port = running.server.server_addr().to_ip().unwrap().port();
+        let endpoint = IppEndpoint {
+            tls: false,
+            addr: HostAddr::parse("127.0.0.1").unwrap(),
+            port,
+            path: RESOURCE.to_string(),
+        };
+
+        let data = b"%PDF-1.7\nloopback test\n%%EOF\n".to_vec();
+        let job_id =
+            ipp_client::print_job(&endpoint, None, "Loopback", "tester", "application/pdf", 2, Sides::OneSided, data.clone())
+                .unwrap();
+        assert_eq!(job_id, 1);
+        assert_eq!(std::fs::read(folder.join("job-00001-1.pdf")).unwrap(), data);
+
+        let json = std::fs::read_to_string(folder.join("job-00001.json")).unwrap();
+        let job: CapturedJob = serde_json::from_str(&json).unwrap();
+        assert_eq!((job.name.as_str(), job.user.as_str()), ("Loopback", "tester"));
+        assert_eq!(job.state, CapturedJobState::Completed);
+        assert_eq!(job.client.as_deref(), Some("127.0.0.1"));
+        assert_eq!(job.attributes.get("copies").map(Vec::as_slice), Some(&["2".to_string()][..]));
+        assert_eq!(job.documents.len(), 1);
+        assert_eq!(job.documents[0].bytes, data.len());
+
+        // Formats the printer does not take are refused without a job
+        let err = ipp_client::print_job(&endpoint, None, "Odd", "tester", "application/x-odd", 1, Sides::OneSided, Vec::new())
+            .unwrap_err();
+        assert!(err.contains("0x040a"), "{}", err);
+        assert!(!folder.join("job-00002.json").exists());
+
+        stop(running);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
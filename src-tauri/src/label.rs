@@ -0,0 +1,630 @@
+use crate::addr::HostAddr;
+use crate::inventory::InventoryStore;
+use crate::printing::{self, JobHistory, JobRecord};
+use crate::raw;
+use crate::validate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::State;
+use tracing::{info, warn};
+
+/// MIME type labels are logged under in the job history.
+pub const ZPL_FORMAT: &str = "application/vnd.zebra-zpl";
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_COPIES: u32 = 9999;
+/// Labels in one CSV batch; each is a separate format in the printer's buffer.
+const MAX_BATCH: usize = 5000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    #[default]
+    Normal,
+    /// 90 degrees clockwise.
+    Rotated,
+    Inverted,
+    /// 270 degrees clockwise.
+    Bottom,
+}
+
+impl Rotation {
+    fn code(self) -> char {
+        match self {
+            Rotation::Normal => 'N',
+            Rotation::Rotated => 'R',
+            Rotation::Inverted => 'I',
+            Rotation::Bottom => 'B',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+    Justified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelBarcode {
+    Code128,
+    Code39,
+    Ean13,
+    UpcA,
+}
+
+fn default_font_height() -> u32 {
+    30
+}
+
+fn default_barcode_height() -> u32 {
+    100
+}
+
+fn default_module_width() -> u32 {
+    2
+}
+
+fn default_magnification() -> u32 {
+    4
+}
+
+fn default_max_lines() -> u32 {
+    1
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// One element of a label. Positions and sizes are in printer dots (8 per mm at 203 dpi,
+/// 12 at 300 dpi). Text and data may contain `{{field}}` placeholders filled from the
+/// label data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LabelElement {
+    /// One line of text in the scalable font.
+    Text {
+        x: u32,
+        y: u32,
+        text: String,
+        #[serde(default = "default_font_height")]
+        height: u32,
+        #[serde(default)]
+        rotation: Rotation,
+    },
+    /// Text wrapped inside `width` dots over at most `max_lines` lines.
+    TextBlock {
+        x: u32,
+        y: u32,
+        width: u32,
+        text: String,
+        #[serde(default = "default_font_height")]
+        height: u32,
+        #[serde(default = "default_max_lines")]
+        max_lines: u32,
+        #[serde(default)]
+        align: TextAlign,
+    },
+    Barcode {
+        x: u32,
+        y: u32,
+        kind: LabelBarcode,
+        data: String,
+        #[serde(default = "default_barcode_height")]
+        height: u32,
+        /// Width of the narrowest bar.
+        #[serde(default = "default_module_width")]
+        module_width: u32,
+        /// Print the data as text under the bars.
+        #[serde(default = "default_true")]
+        readable: bool,
+        #[serde(default)]
+        rotation: Rotation,
+    },
+    Qr {
+        x: u32,
+        y: u32,
+        data: String,
+        /// Dots per module, 1 to 10.
+        #[serde(default = "default_magnification")]
+        magnification: u32,
+    },
+    /// A rectangle outline, or a line when `width` or `height` equals `thickness`.
+    Box {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        #[serde(default = "default_module_width")]
+        thickness: u32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelTemplate {
+    pub name: String,
+    /// Label size in dots.
+    pub width: u32,
+    pub height: u32,
+    pub elements: Vec<LabelElement>,
+}
+
+/// `text` with every `{{field}}` replaced by its value from `data`. Unknown fields are an
+/// error so a bad CSV column does not print half-empty labels.
+pub fn fill(text: &str, data: &HashMap<String, String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| format!("Unclosed placeholder in '{}'", text))?;
+        let field = after[..end].trim();
+        let value = data.get(field).ok_or_else(|| format!("Missing field '{}'", field))?;
+        out.push_str(value);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Field data for `^FH`: the characters ZPL treats as commands are sent as `_` hex
+/// escapes.
+fn field_data(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '^' => out.push_str("_5E"),
+            '~' => out.push_str("_7E"),
+            '_' => out.push_str("_5F"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn check_barcode(kind: LabelBarcode, data: &str) -> Result<(), String> {
+    let digits = data.bytes().all(|b| b.is_ascii_digit());
+    let valid = !data.is_empty()
+        && match kind {
+            LabelBarcode::Code128 => data.bytes().all(|b| (32..127).contains(&b)),
+            LabelBarcode::Code39 => data
+                .bytes()
+                .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%+-./".contains(&b)),
+            LabelBarcode::Ean13 => digits && (12..=13).contains(&data.len()),
+            LabelBarcode::UpcA => digits && (11..=12).contains(&data.len()),
+        };
+    if !valid {
+        return Err(format!("'{}' cannot be encoded as a {:?} barcode", data, kind));
+    }
+    Ok(())
+}
+
+fn render_element(element: &LabelElement, data: &HashMap<String, String>, zpl: &mut String) -> Result<(), String> {
+    match element {
+        LabelElement::Text { x, y, text, height, rotation } => {
+            zpl.push_str(&format!(
+                "^FO{},{}^A0{},{},{}^FH^FD{}^FS\n",
+                x,
+                y,
+                rotation.code(),
+                height,
+                height,
+                field_data(&fill(text, data)?)
+            ));
+        }
+        LabelElement::TextBlock { x, y, width, text, height, max_lines, align } => {
+            let align = match align {
+                TextAlign::Left => 'L',
+                TextAlign::Center => 'C',
+                TextAlign::Right => 'R',
+                TextAlign::Justified => 'J',
+            };
+            // \& is a line break inside a field block
+            let text = field_data(&fill(text, data)?).replace('\n', "\\&");
+            zpl.push_str(&format!(
+                "^FO{},{}^A0N,{},{}^FB{},{},0,{}^FH^FD{}^FS\n",
+                x, y, height, height, width, max_lines, align, text
+            ));
+        }
+        LabelElement::Barcode { x, y, kind, data: value, height, module_width, readable, rotation } => {
+            let value = fill(value, data)?;
+            check_barcode(*kind, &value)?;
+            let readable = if *readable { 'Y' } else { 'N' };
+            let r = rotation.code();
+            let command = match kind {
+                LabelBarcode::Code128 => format!("^BC{},{},{},N,N", r, height, readable),
+                LabelBarcode::Code39 => format!("^B3{},N,{},{},N", r, height, readable),
+                LabelBarcode::Ean13 => format!("^BE{},{},{},N", r, height, readable),
+                LabelBarcode::UpcA => format!("^BU{},{},{},N,Y", r, height, readable),
+            };
+            zpl.push_str(&format!(
+                "^FO{},{}^BY{}{}^FH^FD{}^FS\n",
+                x,
+                y,
+                module_width.clamp(&1, &10),
+                command,
+                field_data(&value)
+            ));
+        }
+        LabelElement::Qr { x, y, data: value, magnification } => {
+            let value = fill(value, data)?;
+            if value.is_empty() {
+                return Err("QR code data is empty".to_string());
+            }
+            // Error correction level M, automatic input mode
+            zpl.push_str(&format!(
+                "^FO{},{}^BQN,2,{}^FH^FDMA,{}^FS\n",
+                x,
+                y,
+                magnification.clamp(&1, &10),
+                field_data(&value)
+            ));
+        }
+        LabelElement::Box { x, y, width, height, thickness } => {
+            zpl.push_str(&format!("^FO{},{}^GB{},{},{}^FS\n", x, y, width, height, thickness));
+        }
+    }
+    Ok(())
+}
+
+/// One ZPL format for `template` filled with `data`, printed `copies` times.
+pub fn render_label(template: &LabelTemplate, data: &HashMap<String, String>, copies: u32) -> Result<String, String> {
+    if template.width == 0 || template.height == 0 {
+        return Err(format!("Label template '{}' has no size", template.name));
+    }
+    // UTF-8 field data, label size, then the fields
+    let mut zpl = format!("^XA\n^CI28\n^PW{}\n^LL{}\n", template.width, template.height);
+    for element in &template.elements {
+        render_element(element, data, &mut zpl)?;
+    }
+    zpl.push_str(&format!("^PQ{}\n^XZ\n", copies.max(1)));
+    Ok(zpl)
+}
+
+/// Label data for each row of a CSV file, keyed by the header names.
+pub fn read_batch(path: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let path = validate::input_file(path, &["csv"])?;
+    let mut reader = csv::Reader::from_path(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let headers = reader.headers().map_err(|e| format!("Failed to read CSV header: {}", e))?.clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Failed to read CSV: {}", e))?;
+        let row: HashMap<String, String> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, value)| (header.trim().to_string(), value.to_string()))
+            .collect();
+        rows.push(row);
+    }
+    if rows.len() > MAX_BATCH {
+        return Err(format!("{} has more than {} labels", path.display(), MAX_BATCH));
+    }
+    Ok(rows)
+}
+
+/// State from the three `~HS` host status strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelPrinterStatus {
+    pub paper_out: bool,
+    pub paused: bool,
+    pub head_open: bool,
+    pub ribbon_out: bool,
+    pub buffer_full: bool,
+    pub under_temperature: bool,
+    pub over_temperature: bool,
+    /// Label length in dots as configured or calibrated.
+    pub label_length: Option<u32>,
+    pub formats_in_buffer: Option<u32>,
+    pub labels_remaining: Option<u32>,
+}
+
+impl LabelPrinterStatus {
+    /// Why the printer cannot print right now, if anything stops it.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.head_open {
+            Some("the print head is open")
+        } else if self.paper_out {
+            Some("it is out of labels")
+        } else if self.ribbon_out {
+            Some("it is out of ribbon")
+        } else if self.paused {
+            Some("it is paused")
+        } else if self.over_temperature {
+            Some("the print head is too hot")
+        } else {
+            None
+        }
+    }
+}
+
+/// The STX ... ETX framed strings of a host reply.
+fn framed(reply: &[u8]) -> Vec<String> {
+    reply
+        .split(|&b| b == 0x02)
+        .skip(1)
+        .filter_map(|part| part.iter().position(|&b| b == 0x03).map(|end| &part[..end]))
+        .map(|part| String::from_utf8_lossy(part).trim().to_string())
+        .collect()
+}
+
+pub fn parse_host_status(reply: &[u8]) -> Result<LabelPrinterStatus, String> {
+    let strings = framed(reply);
+    let fields = |i: usize| -> Vec<&str> {
+        strings
+            .get(i)
+            .map(|s| s.split(',').map(str::trim).collect())
+            .unwrap_or_default()
+    };
+    let (first, second) = (fields(0), fields(1));
+    if first.len() < 12 || second.len() < 9 {
+        return Err(format!("Unexpected host status reply '{}'", String::from_utf8_lossy(reply)));
+    }
+    let flag = |fields: &[&str], i: usize| fields.get(i).is_some_and(|f| *f == "1");
+    let number = |fields: &[&str], i: usize| fields.get(i).and_then(|f| f.parse().ok());
+    Ok(LabelPrinterStatus {
+        paper_out: flag(&first, 1),
+        paused: flag(&first, 2),
+        label_length: number(&first, 3),
+        formats_in_buffer: number(&first, 4),
+        buffer_full: flag(&first, 5),
+        under_temperature: flag(&first, 10),
+        over_temperature: flag(&first, 11),
+        head_open: flag(&second, 2),
+        ribbon_out: flag(&second, 3),
+        labels_remaining: number(&second, 8),
+    })
+}
+
+/// One line of the `^HH` configuration dump, e.g. `DARKNESS` = `+10.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigSetting {
+    pub name: String,
+    pub value: String,
+}
+
+/// Parses the `^HH` reply: each line has the value, a run of spaces, then the setting
+/// name.
+pub fn parse_configuration(reply: &[u8]) -> Vec<ConfigSetting> {
+    let text = String::from_utf8_lossy(reply);
+    text.lines()
+        .map(|line| line.trim_matches(|c: char| c.is_whitespace() || c == '\u{2}' || c == '\u{3}'))
+        .filter_map(|line| {
+            let split = line.find("  ")?;
+            let (value, name) = (line[..split].trim(), line[split..].trim());
+            (!name.is_empty()).then(|| ConfigSetting {
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn query_status(addr: &HostAddr) -> Result<LabelPrinterStatus, String> {
+    let reply = raw::query(addr, raw::RAW_PORT, b"~HS", STATUS_TIMEOUT)?;
+    parse_host_status(&reply)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelPrinterInfo {
+    pub status: LabelPrinterStatus,
+    pub configuration: Vec<ConfigSetting>,
+}
+
+#[tauri::command]
+pub fn get_label_printer_status(
+    inventory: State<'_, InventoryStore>,
+    printer_id: String,
+) -> Result<LabelPrinterInfo, String> {
+    let (_, addr) = printing::network_printer(&inventory.snapshot(), &printer_id)?;
+    let status = query_status(&addr)?;
+    let configuration = match raw::query(&addr, raw::RAW_PORT, b"^XA^HH^XZ", STATUS_TIMEOUT) {
+        Ok(reply) => parse_configuration(&reply),
+        Err(e) => {
+            warn!(printer = %printer_id, "no configuration from label printer: {}", e);
+            Vec::new()
+        }
+    };
+    Ok(LabelPrinterInfo { status, configuration })
+}
+
+/// Sends `zpl` after checking the printer can print, and logs the job.
+fn send_labels(
+    inventory: &InventoryStore,
+    history: &JobHistory,
+    printer_id: &str,
+    document: &str,
+    copies: u32,
+    zpl: String,
+) -> Result<JobRecord, String> {
+    let (entry, addr) = printing::network_printer(&inventory.snapshot(), printer_id)?;
+    let user = printing::current_user();
+    let mut record = JobRecord::new(&entry, &user, document, ZPL_FORMAT, copies, zpl.len());
+    record.uri = Some(format!("socket://{}:{}", addr.url_host(), raw::RAW_PORT));
+
+    let result = match query_status(&addr) {
+        Ok(status) => match status.problem() {
+            Some(problem) => Err(format!("{} cannot print: {}", entry.display_name(), problem)),
+            None => raw::send(&addr, raw::RAW_PORT, zpl.as_bytes()),
+        },
+        Err(e) => {
+            warn!(printer = %entry.id, "no status from label printer, printing anyway: {}", e);
+            raw::send(&addr, raw::RAW_PORT, zpl.as_bytes())
+        }
+    };
+    if let Err(e) = &result {
+        warn!(printer = %entry.id, document, "label job failed: {}", e);
+        record.error = Some(e.clone());
+    } else {
+        info!(printer = %entry.id, document, copies, "labels printed");
+    }
+    if let Err(e) = history.append(&record) {
+        warn!("{}", e);
+    }
+    result.map(|_| record)
+}
+
+/// Prints `template` filled with `data` on the ZPL printer `printer_id`.
+#[tauri::command]
+pub fn print_label(
+    inventory: State<'_, InventoryStore>,
+    history: State<'_, JobHistory>,
+    printer_id: String,
+    template: LabelTemplate,
+    data: HashMap<String, String>,
+    copies: Option<u32>,
+) -> Result<JobRecord, String> {
+    let copies = copies.unwrap_or(1);
+    if !(1..=MAX_COPIES).contains(&copies) {
+        return Err(format!("Copies must be between 1 and {}", MAX_COPIES));
+    }
+    let zpl = render_label(&template, &data, copies)?;
+    send_labels(&inventory, &history, &printer_id, &template.name, copies, zpl)
+}
+
+/// Prints one label per row of the CSV file at `path`, whose header names the template
+/// fields. Every row is rendered before anything is sent, so a bad row stops the batch
+/// instead of leaving it half printed.
+#[tauri::command]
+pub fn print_label_batch(
+    inventory: State<'_, InventoryStore>,
+    history: State<'_, JobHistory>,
+    printer_id: String,
+    template: LabelTemplate,
+    path: String,
+) -> Result<JobRecord, String> {
+    let rows = read_batch(&path)?;
+    if rows.is_empty() {
+        return Err(format!("{} has no rows", path));
+    }
+    let mut zpl = String::new();
+    for (i, row) in rows.iter().enumerate() {
+        // Row 1 is the header
+        let label = render_label(&template, row, 1).map_err(|e| format!("Row {}: {}", i + 2, e))?;
+        zpl.push_str(&label);
+    }
+    let document = format!("{} ({} labels)", template.name, rows.len());
+    send_labels(&inventory, &history, &printer_id, &document, rows.len() as u32, zpl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> LabelTemplate {
+        serde_json::from_str(
+            r#"{
+                "name": "Shipping",
+                "width": 812,
+                "height": 1218,
+                "elements": [
+                    { "type": "box", "x": 20, "y": 20, "width": 772, "height": 1178, "thickness": 4 },
+                    { "type": "text", "x": 40, "y": 40, "text": "Ship to: {{name}}", "height": 50 },
+                    { "type": "text_block", "x": 40, "y": 110, "width": 730, "text": "{{street}}\n{{city}}", "max_lines": 3 },
+                    { "type": "barcode", "x": 40, "y": 300, "kind": "code128", "data": "{{tracking}}", "height": 150, "module_width": 3 },
+                    { "type": "qr", "x": 500, "y": 700, "data": "https://track.example/{{ tracking }}", "magnification": 6 },
+                    { "type": "text", "x": 780, "y": 900, "text": "Ref {{ref}}", "rotation": "rotated" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn data(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn renders_shipping_label() {
+        let data = data(&[
+            ("name", "Zoë Müller"),
+            ("street", "1 Main St"),
+            ("city", "Springfield"),
+            ("tracking", "1Z999AA10123456784"),
+            ("ref", "A^B~C_D"),
+        ]);
+        assert_eq!(
+            render_label(&template(), &data, 2).unwrap(),
+            "^XA\n\
+             ^CI28\n\
+             ^PW812\n\
+             ^LL1218\n\
+             ^FO20,20^GB772,1178,4^FS\n\
+             ^FO40,40^A0N,50,50^FH^FDShip to: Zoë Müller^FS\n\
+             ^FO40,110^A0N,30,30^FB730,3,0,L^FH^FD1 Main St\\&Springfield^FS\n\
+             ^FO40,300^BY3^BCN,150,Y,N,N^FH^FD1Z999AA10123456784^FS\n\
+             ^FO500,700^BQN,2,6^FH^FDMA,https://track.example/1Z999AA10123456784^FS\n\
+             ^FO780,900^A0R,30,30^FH^FDRef A_5EB_7EC_5FD^FS\n\
+             ^PQ2\n\
+             ^XZ\n"
+        );
+    }
+
+    #[test]
+    fn renders_retail_barcodes() {
+        let template: LabelTemplate = serde_json::from_str(
+            r#"{
+                "name": "Shelf",
+                "width": 400,
+                "height": 200,
+                "elements": [
+                    { "type": "barcode", "x": 10, "y": 10, "kind": "ean13", "data": "{{ean}}", "height": 80 },
+                    { "type": "barcode", "x": 10, "y": 110, "kind": "code39", "data": "SKU-{{sku}}", "height": 60, "readable": false }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            render_label(&template, &data(&[("ean", "400638133393"), ("sku", "42")]), 1).unwrap(),
+            "^XA\n^CI28\n^PW400\n^LL200\n\
+             ^FO10,10^BY2^BEN,80,Y,N^FH^FD400638133393^FS\n\
+             ^FO10,110^BY2^B3N,N,60,N,N^FH^FDSKU-42^FS\n\
+             ^PQ1\n^XZ\n"
+        );
+        assert!(render_label(&template, &data(&[("ean", "12ab"), ("sku", "42")]), 1).is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        let values = data(&[("a", "1")]);
+        assert_eq!(fill("x{{a}}y{{ a }}", &values).unwrap(), "x1y1");
+        assert_eq!(fill("no fields", &values).unwrap(), "no fields");
+        assert_eq!(fill("{{b}}", &values).unwrap_err(), "Missing field 'b'");
+        assert!(fill("{{a", &values).is_err());
+        assert!(render_label(&template(), &values, 1).is_err());
+    }
+
+    #[test]
+    fn host_status() {
+        let reply = b"\x02030,0,0,1218,000,0,0,0,000,0,0,0\x03\r\n\x02001,0,0,0,1,2,6,0,00000000,1,000\x03\r\n\x021234,0\x03\r\n";
+        let status = parse_host_status(reply).unwrap();
+        assert_eq!(status.label_length, Some(1218));
+        assert_eq!(status.labels_remaining, Some(0));
+        assert!(status.problem().is_none());
+
+        let reply = b"\x02030,1,1,1218,002,0,0,0,000,0,0,0\x03\x02001,0,1,0,1,2,6,0,00000003,1,000\x03\x021234,0\x03";
+        let status = parse_host_status(reply).unwrap();
+        assert!(status.paper_out && status.paused && status.head_open);
+        assert_eq!((status.formats_in_buffer, status.labels_remaining), (Some(2), Some(3)));
+        assert_eq!(status.problem(), Some("the print head is open"));
+
+        assert!(parse_host_status(b"").is_err());
+        assert!(parse_host_status(b"\x021,2\x03").is_err());
+    }
+
+    #[test]
+    fn configuration_dump() {
+        let reply = b"\x02\r\n  +10.0               DARKNESS\r\n  4.0 IPS             PRINT SPEED\r\n  832                 PRINT WIDTH\r\n  V60.19.15Z <-       FIRMWARE\r\n\x03";
+        let settings = parse_configuration(reply);
+        assert_eq!(settings.len(), 4);
+        assert_eq!(settings[0], ConfigSetting { name: "DARKNESS".into(), value: "+10.0".into() });
+        assert_eq!(settings[1].value, "4.0 IPS");
+        assert_eq!(settings[3].value, "V60.19.15Z <-");
+    }
+}
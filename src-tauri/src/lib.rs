@@ -1 +1,36 @@
-pub mod printer;
\ No newline at end of file
+pub mod addr;
+pub mod alerts;
+pub mod convert;
+pub mod counters;
+pub mod escpos;
+pub mod fingerprint;
+pub mod inventory;
+pub mod inventory_io;
+pub mod ipp_client;
+pub mod ipp_message;
+pub mod ipp_server;
+pub mod label;
+pub mod logging;
+pub mod lpd;
+pub mod mib;
+pub mod monitor;
+pub mod names;
+pub mod neighbors;
+pub mod oui;
+pub mod pools;
+pub mod printer;
+pub mod printing;
+pub mod raster;
+pub mod raw;
+pub mod relay;
+pub mod schedule;
+pub mod settings;
+pub mod snmp_broadcast;
+pub mod snmp_tools;
+pub mod supplies;
+pub mod testpage;
+pub mod text_pdf;
+pub mod tray;
+pub mod trust;
+pub mod validate;
+pub mod vault;
@@ -0,0 +1,126 @@
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+const LOG_PREFIX: &str = "app";
+const LOG_SUFFIX: &str = "log";
+/// Daily files kept before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+const DEFAULT_LINES: usize = 500;
+
+pub const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+struct Logging {
+    dir: PathBuf,
+    filter: reload::Handle<EnvFilter, Registry>,
+    /// Flushes buffered lines when the app exits.
+    _guard: WorkerGuard,
+}
+
+static LOGGING: OnceLock<Logging> = OnceLock::new();
+
+/// Filter for `level` that keeps chatty dependencies at warnings.
+fn filter_for(level: &str) -> EnvFilter {
+    EnvFilter::new(format!("warn,app={level}"))
+}
+
+/// Sends `tracing` output to daily-rotated files in `dir` (and to stderr in debug builds,
+/// where there is a console to see it).
+pub fn init(dir: &Path, level: &str) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix(LOG_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(dir)
+        .map_err(|e| format!("Failed to open log files in {}: {}", dir.display(), e))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let (filter, handle) = reload::Layer::new(filter_for(level));
+    let file_layer = fmt::layer().with_writer(writer).with_ansi(false);
+    let console_layer = cfg!(debug_assertions).then(|| fmt::layer().with_writer(std::io::stderr));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .map_err(|e| format!("Failed to install logger: {}", e))?;
+
+    LOGGING
+        .set(Logging {
+            dir: dir.to_path_buf(),
+            filter: handle,
+            _guard: guard,
+        })
+        .map_err(|_| "Logging is already initialised".to_string())
+}
+
+/// Changes the log level of a running app.
+pub fn set_level(level: &str) -> Result<(), String> {
+    let Some(logging) = LOGGING.get() else { return Ok(()) };
+    logging
+        .filter
+        .reload(filter_for(level))
+        .map_err(|e| format!("Failed to change log level: {}", e))
+}
+
+fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            name.starts_with(LOG_PREFIX) && name.ends_with(LOG_SUFFIX)
+        })
+        .collect();
+    // Rotated names carry the date, so name order is age order
+    files.sort();
+    files
+}
+
+/// The last `lines` log lines across the rotated files, oldest first, optionally only
+/// those at `level` or more severe.
+pub fn tail(lines: usize, level: Option<&str>) -> Vec<String> {
+    let Some(logging) = LOGGING.get() else { return Vec::new() };
+    let wanted: Vec<String> = match level {
+        Some(level) => {
+            let index = LEVELS.iter().position(|l| l.eq_ignore_ascii_case(level)).unwrap_or(0);
+            LEVELS[index..].iter().map(|l| l.to_ascii_uppercase()).collect()
+        }
+        None => Vec::new(),
+    };
+
+    let mut collected: Vec<String> = Vec::new();
+    for file in log_files(&logging.dir).iter().rev() {
+        let Ok(handle) = std::fs::File::open(file) else { continue };
+        let mut matching: Vec<String> = BufReader::new(handle)
+            .lines()
+            .map_while(Result::ok)
+            // Lines read "<timestamp> <LEVEL> <target>: <message>"
+            .filter(|line| {
+                wanted.is_empty() || line.split_whitespace().nth(1).is_some_and(|l| wanted.iter().any(|w| w == l))
+            })
+            .collect();
+        matching.append(&mut collected);
+        collected = matching;
+        if collected.len() >= lines {
+            break;
+        }
+    }
+
+    let skip = collected.len().saturating_sub(lines);
+    collected.split_off(skip)
+}
+
+/// Recent log lines for the viewer and for attaching to bug reports.
+#[tauri::command]
+pub fn get_logs(lines: Option<usize>, level: Option<String>) -> Vec<String> {
+    tail(lines.unwrap_or(DEFAULT_LINES), level.as_deref())
+}
@@ -0,0 +1,83 @@
+use crate::addr::HostAddr;
+use crate::raw;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+pub const LPD_PORT: u16 = 515;
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+static JOB_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+/// Control file fields are single lines; names are cut at 131 bytes, the RFC 1179 limit
+/// for `J` and `N` lines, and stripped of anything that would break the line.
+fn field(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(131)
+        .collect()
+}
+
+fn expect_ack(stream: &mut TcpStream, step: &str) -> Result<(), String> {
+    let mut ack = [0u8; 1];
+    stream
+        .read_exact(&mut ack)
+        .map_err(|e| format!("LPD server did not answer {}: {}", step, e))?;
+    if ack[0] != 0 {
+        return Err(format!("LPD server refused {} (code {})", step, ack[0]));
+    }
+    Ok(())
+}
+
+fn send_file(stream: &mut TcpStream, code: u8, name: &str, contents: &[u8], step: &str) -> Result<(), String> {
+    let header = format!("{}{} {}\n", code as char, contents.len(), name);
+    stream
+        .write_all(header.as_bytes())
+        .map_err(|e| format!("Failed to send {}: {}", step, e))?;
+    expect_ack(stream, step)?;
+    stream
+        .write_all(contents)
+        .and_then(|_| stream.write_all(&[0]))
+        .map_err(|e| format!("Failed to send {}: {}", step, e))?;
+    expect_ack(stream, step)
+}
+
+/// Submits `data` to `queue` with the RFC 1179 "receive job" command. The data file is
+/// printed with the `l` (leave control characters) filter so PDLs pass through untouched.
+pub fn send(addr: &HostAddr, queue: &str, user: &str, job_name: &str, copies: u32, data: &[u8]) -> Result<(), String> {
+    let mut stream = raw::connect(addr, LPD_PORT)?;
+    stream
+        .set_read_timeout(Some(ACK_TIMEOUT))
+        .map_err(|e| format!("Socket setup failed: {}", e))?;
+
+    let host: String = dns_lookup::get_hostname()
+        .unwrap_or_else(|_| "localhost".to_string())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .take(31)
+        .collect();
+    let number = JOB_NUMBER.fetch_add(1, Ordering::Relaxed) % 1000;
+    let data_file = format!("dfA{:03}{}", number, host);
+    let control_file = format!("cfA{:03}{}", number, host);
+
+    let mut control = format!(
+        "H{}\nP{}\nJ{}\nN{}\n",
+        host,
+        field(user),
+        field(job_name),
+        field(job_name)
+    );
+    for _ in 0..copies.max(1) {
+        control.push_str(&format!("l{}\n", data_file));
+    }
+    control.push_str(&format!("U{}\n", data_file));
+
+    stream
+        .write_all(format!("\x02{}\n", field(queue)).as_bytes())
+        .map_err(|e| format!("Failed to start LPD job: {}", e))?;
+    expect_ack(&mut stream, &format!("queue '{}'", queue))?;
+    send_file(&mut stream, 0x02, &control_file, control.as_bytes(), "the control file")?;
+    send_file(&mut stream, 0x03, &data_file, data, "the document")
+}
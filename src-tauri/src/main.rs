@@ -3,7 +3,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use serde::{Deserialize};
+use tauri::Manager;
+use app::alerts::AlertEngine;
+use app::fingerprint::load_rules_file;
+use app::inventory::InventoryStore;
+use app::schedule::Scheduler;
+use app::settings::open_or_reset;
+use app::vault::CredentialVault;
+use app::oui::load_installed_registry;
 use app::printer::*;
+use app::printing::JobHistory;
+use tracing::{debug, error, info, warn};
 
 #[derive(Deserialize)]
 struct Printer {
@@ -11,18 +21,19 @@ struct Printer {
 }
 
 #[tauri::command]
-fn process_text(text: String) -> String {
+fn process_text(text: String) -> Result<String, String> {
+    app::validate::text("Text", &text)?;
     if text.is_empty() {
-        String::new()
+        Ok(String::new())
     } else {
-        format!("Hello, {}!", text)
+        Ok(format!("Hello, {}!", text))
     }
 }
 
 #[tauri::command]
 fn list_printers() -> Result<Vec<String>, String> {
     // Try PowerShell first
-    eprintln!("Attempting PowerShell Get-Printer command");
+    debug!("listing printers with PowerShell Get-Printer");
     let output = std::process::Command::new("powershell")
         .arg("-Command")
         .arg("Get-Printer | Select-Object -Property Name | ConvertTo-Json -Compress")
@@ -31,9 +42,8 @@ fn list_printers() -> Result<Vec<String>, String> {
     let output = match output {
         Ok(output) => output,
         Err(e) => {
-            eprintln!("PowerShell Get-Printer failed: {}", e);
             // Fallback to wmic
-            eprintln!("Attempting wmic printer command");
+            warn!("PowerShell Get-Printer failed, trying wmic: {}", e);
             let wmic_output = std::process::Command::new("wmic")
                 .arg("printer")
                 .arg("get")
@@ -46,7 +56,6 @@ fn list_printers() -> Result<Vec<String>, String> {
             }
 
             let raw_output = String::from_utf8_lossy(&wmic_output.stdout);
-            eprintln!("Raw wmic output: {}", raw_output);
 
             let printer_names: Vec<String> = raw_output
                 .lines()
@@ -55,7 +64,7 @@ fn list_printers() -> Result<Vec<String>, String> {
                 .map(|line| line.trim().to_string())
                 .collect();
 
-            eprintln!("Parsed printer names (wmic): {:?}", printer_names);
+            debug!(count = printer_names.len(), "printers listed by wmic");
             return Ok(printer_names);
         }
     };
@@ -65,10 +74,8 @@ fn list_printers() -> Result<Vec<String>, String> {
     }
 
     let raw_output = String::from_utf8_lossy(&output.stdout);
-    eprintln!("Raw PowerShell output: {}", raw_output);
-
     if raw_output.trim().is_empty() {
-        eprintln!("No printers detected by PowerShell");
+        debug!("no printers listed by PowerShell");
         return Ok(vec![]);
     }
 
@@ -80,17 +87,139 @@ fn list_printers() -> Result<Vec<String>, String> {
         },
     };
 
-    eprintln!("Parsed printer names: {:?}", printer_names);
+    debug!(count = printer_names.len(), "printers listed by PowerShell");
     Ok(printer_names)
 }
 
 fn main() {
+    // `app snmp get|walk|bulkwalk|set ...` runs a one-off SNMP command instead of the UI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = app::snmp_tools::run_cli(&args) {
+        std::process::exit(code);
+    }
+
     tauri::Builder::default()
+        .plugin(tauri_plugin_notification::init())
+        .setup(|app| {
+            // Settings come first: everything started below reads them
+            let config_dir = app.path().app_config_dir()?;
+            // The legacy schedules file first: an error naming it is its fault, not settings.json's
+            let settings_files = [config_dir.join(app::settings::LEGACY_SCHEDULES_FILE), config_dir.join(app::settings::SETTINGS_FILE)];
+            let (settings, settings_errors) =
+                open_or_reset(&settings_files, || app::settings::load(&config_dir))?;
+            app::logging::init(&app.path().app_log_dir()?, &settings.logging.level)?;
+            info!(version = env!("CARGO_PKG_VERSION"), "starting");
+            // Logging only works from here on, so settings problems are reported late
+            for e in settings_errors {
+                error!("{}", e);
+            }
+
+            // Pinned printer certificates and the admin's CA bundle, used by every HTTPS/IPPS request
+            let trust_files = [config_dir.join(app::trust::TRUST_FILE), config_dir.join(app::trust::CA_BUNDLE_FILE)];
+            for e in open_or_reset(&trust_files, || app::trust::load(&config_dir))?.1 {
+                error!("{}", e);
+            }
+
+            // Custom fingerprint rules dropped into the config dir extend the built-in set
+            let rules = config_dir.join("fingerprint_rules.json");
+            if rules.exists() {
+                if let Err(e) = load_rules_file(&rules.to_string_lossy()) {
+                    warn!("failed to load custom fingerprint rules: {}", e);
+                }
+            }
+            // A full IEEE OUI registry installed by the user replaces the bundled subset
+            if let Ok(data_dir) = app.path().app_data_dir() {
+                load_installed_registry(&data_dir);
+            }
+            // Printers seen by discovery are kept in an inventory that the monitor polls hourly
+            let data_dir = app.path().app_data_dir()?;
+            let inventory = InventoryStore::open(data_dir.join("inventory.json"))?;
+            app.manage(inventory);
+            let alert_files = [config_dir.join("alert_rules.json"), config_dir.join(app::alerts::ALERT_COMMANDS_FILE)];
+            let (alerts, alert_errors) = open_or_reset(&alert_files, || {
+                AlertEngine::open(alert_files[0].clone(), &alert_files[1], data_dir.join("alert_history.jsonl"))
+            })?;
+            for e in alert_errors {
+                error!("{}", e);
+            }
+            app.manage(alerts);
+            // Without an OS keyring (headless Linux) the vault waits for a passphrase instead
+            let vault = CredentialVault::open(data_dir.join("credentials.json"))?;
+            if !vault.status().initialized {
+                if let Err(e) = vault.create_with_keyring() {
+                    info!("credential vault will use a passphrase: {}", e);
+                }
+            }
+            if let Err(e) = app.state::<AlertEngine>().migrate_logins(&vault) {
+                error!("failed to move alert SMTP passwords into the vault: {}", e);
+            }
+            app.manage(vault);
+            app.manage(JobHistory::new(data_dir.join("print_jobs.jsonl")));
+            app.manage(Scheduler::new(data_dir.join("reports")));
+            app::ipp_server::init(app.handle().clone(), data_dir.join("captured_jobs"));
+            app::monitor::start(app.handle().clone());
+            app::schedule::start(app.handle().clone());
+            app::tray::init(app.handle())?;
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             process_text, 
             list_printers, 
             list_all_printers, 
-
+            app::fingerprint::load_fingerprint_rules,
+            app::oui::update_oui_registry,
+            app::snmp_tools::snmp_get,
+            app::snmp_tools::snmp_walk,
+            app::snmp_tools::snmp_bulkwalk,
+            app::snmp_tools::snmp_set,
+            app::inventory::get_inventory,
+            app::inventory::update_inventory_entry,
+            app::inventory::remove_inventory_entry,
+            app::pools::get_pools,
+            app::pools::save_pool,
+            app::pools::remove_pool,
+            app::pools::rank_pool,
+            app::inventory_io::export_inventory,
+            app::inventory_io::import_inventory,
+            app::counters::collect_page_counters,
+            app::counters::get_usage_report,
+            app::counters::export_usage_report,
+            app::supplies::get_supply_forecast,
+            app::alerts::get_alert_rules,
+            app::alerts::save_alert_rules,
+            app::alerts::get_alert_history,
+            app::alerts::test_alert_action,
+            app::schedule::get_schedules,
+            app::schedule::save_schedules,
+            app::schedule::run_discovery_now,
+            app::schedule::get_change_reports,
+            app::schedule::get_change_report,
+            app::schedule::export_change_report,
+            app::settings::get_settings,
+            app::settings::update_settings,
+            app::logging::get_logs,
+            app::vault::get_vault_status,
+            app::vault::unlock_vault,
+            app::vault::lock_vault,
+            app::vault::store_credential,
+            app::vault::list_credentials,
+            app::vault::remove_credential,
+            app::vault::find_credential,
+            app::printing::print_file,
+            app::printing::get_print_jobs,
+            app::escpos::print_receipt,
+            app::escpos::get_receipt_printer_status,
+            app::label::print_label,
+            app::label::print_label_batch,
+            app::label::get_label_printer_status,
+            app::testpage::print_test_page,
+            app::ipp_server::get_virtual_printer_status,
+            app::ipp_server::get_captured_jobs,
+            app::trust::get_trust_store,
+            app::trust::trust_certificate,
+            app::trust::forget_certificate,
+            app::trust::import_trusted_ca,
+            app::trust::remove_trusted_ca,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
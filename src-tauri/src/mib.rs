@@ -0,0 +1,134 @@
+/// Object names from SNMPv2-MIB, IF-MIB, HOST-RESOURCES-MIB and Printer-MIB (RFC 3805)
+/// that admins ask for most, so OIDs can be given as `sysLocation.0` instead of digits.
+const MIB_NAMES: &[(&str, &str)] = &[
+    // SNMPv2-MIB
+    ("system", "1.3.6.1.2.1.1"),
+    ("sysDescr", "1.3.6.1.2.1.1.1"),
+    ("sysObjectID", "1.3.6.1.2.1.1.2"),
+    ("sysUpTime", "1.3.6.1.2.1.1.3"),
+    ("sysContact", "1.3.6.1.2.1.1.4"),
+    ("sysName", "1.3.6.1.2.1.1.5"),
+    ("sysLocation", "1.3.6.1.2.1.1.6"),
+    ("sysServices", "1.3.6.1.2.1.1.7"),
+    // IF-MIB
+    ("ifTable", "1.3.6.1.2.1.2.2"),
+    ("ifDescr", "1.3.6.1.2.1.2.2.1.2"),
+    ("ifPhysAddress", "1.3.6.1.2.1.2.2.1.6"),
+    ("ifOperStatus", "1.3.6.1.2.1.2.2.1.8"),
+    // HOST-RESOURCES-MIB
+    ("hrSystemUptime", "1.3.6.1.2.1.25.1.1"),
+    ("hrDevicePrinter", "1.3.6.1.2.1.25.3.1.5"),
+    ("hrDeviceTable", "1.3.6.1.2.1.25.3.2"),
+    ("hrDeviceIndex", "1.3.6.1.2.1.25.3.2.1.1"),
+    ("hrDeviceType", "1.3.6.1.2.1.25.3.2.1.2"),
+    ("hrDeviceDescr", "1.3.6.1.2.1.25.3.2.1.3"),
+    ("hrDeviceID", "1.3.6.1.2.1.25.3.2.1.4"),
+    ("hrDeviceStatus", "1.3.6.1.2.1.25.3.2.1.5"),
+    ("hrDeviceErrors", "1.3.6.1.2.1.25.3.2.1.6"),
+    ("hrPrinterTable", "1.3.6.1.2.1.25.3.5"),
+    ("hrPrinterStatus", "1.3.6.1.2.1.25.3.5.1.1"),
+    ("hrPrinterDetectedErrorState", "1.3.6.1.2.1.25.3.5.1.2"),
+    // Printer-MIB
+    ("printmib", "1.3.6.1.2.1.43"),
+    ("prtGeneralTable", "1.3.6.1.2.1.43.5.1"),
+    ("prtGeneralConfigChanges", "1.3.6.1.2.1.43.5.1.1.1"),
+    ("prtGeneralCurrentLocalization", "1.3.6.1.2.1.43.5.1.1.2"),
+    ("prtGeneralReset", "1.3.6.1.2.1.43.5.1.1.3"),
+    ("prtGeneralPrinterName", "1.3.6.1.2.1.43.5.1.1.16"),
+    ("prtGeneralSerialNumber", "1.3.6.1.2.1.43.5.1.1.17"),
+    ("prtAlertCriticalEvents", "1.3.6.1.2.1.43.5.1.1.18"),
+    ("prtAlertAllEvents", "1.3.6.1.2.1.43.5.1.1.19"),
+    ("prtInputTable", "1.3.6.1.2.1.43.8.2"),
+    ("prtInputType", "1.3.6.1.2.1.43.8.2.1.2"),
+    ("prtInputMaxCapacity", "1.3.6.1.2.1.43.8.2.1.9"),
+    ("prtInputCurrentLevel", "1.3.6.1.2.1.43.8.2.1.10"),
+    ("prtInputStatus", "1.3.6.1.2.1.43.8.2.1.11"),
+    ("prtInputMediaName", "1.3.6.1.2.1.43.8.2.1.12"),
+    ("prtInputName", "1.3.6.1.2.1.43.8.2.1.13"),
+    ("prtInputDescription", "1.3.6.1.2.1.43.8.2.1.18"),
+    ("prtOutputTable", "1.3.6.1.2.1.43.9.2"),
+    ("prtMarkerTable", "1.3.6.1.2.1.43.10.2"),
+    ("prtMarkerCounterUnit", "1.3.6.1.2.1.43.10.2.1.3"),
+    ("prtMarkerLifeCount", "1.3.6.1.2.1.43.10.2.1.4"),
+    ("prtMarkerPowerOnCount", "1.3.6.1.2.1.43.10.2.1.5"),
+    ("prtMarkerProcessColorants", "1.3.6.1.2.1.43.10.2.1.6"),
+    ("prtMarkerSuppliesTable", "1.3.6.1.2.1.43.11.1"),
+    ("prtMarkerSuppliesColorantIndex", "1.3.6.1.2.1.43.11.1.1.3"),
+    ("prtMarkerSuppliesClass", "1.3.6.1.2.1.43.11.1.1.4"),
+    ("prtMarkerSuppliesType", "1.3.6.1.2.1.43.11.1.1.5"),
+    ("prtMarkerSuppliesDescription", "1.3.6.1.2.1.43.11.1.1.6"),
+    ("prtMarkerSuppliesSupplyUnit", "1.3.6.1.2.1.43.11.1.1.7"),
+    ("prtMarkerSuppliesMaxCapacity", "1.3.6.1.2.1.43.11.1.1.8"),
+    ("prtMarkerSuppliesLevel", "1.3.6.1.2.1.43.11.1.1.9"),
+    ("prtMarkerColorantTable", "1.3.6.1.2.1.43.12.1"),
+    ("prtMarkerColorantValue", "1.3.6.1.2.1.43.12.1.1.4"),
+    ("prtConsoleDisplayBufferText", "1.3.6.1.2.1.43.16.5.1.2"),
+    ("prtAlertTable", "1.3.6.1.2.1.43.18.1"),
+    ("prtAlertSeverityLevel", "1.3.6.1.2.1.43.18.1.1.2"),
+    ("prtAlertTrainingLevel", "1.3.6.1.2.1.43.18.1.1.3"),
+    ("prtAlertGroup", "1.3.6.1.2.1.43.18.1.1.4"),
+    ("prtAlertCode", "1.3.6.1.2.1.43.18.1.1.7"),
+    ("prtAlertDescription", "1.3.6.1.2.1.43.18.1.1.8"),
+    ("prtAlertTime", "1.3.6.1.2.1.43.18.1.1.9"),
+];
+
+/// Parses a numeric OID (`1.3.6.1.2.1.1.5.0`, leading dot allowed) or a symbolic one with
+/// an optional module prefix and instance suffix (`SNMPv2-MIB::sysName.0`).
+pub fn resolve_oid(input: &str) -> Result<Vec<u32>, String> {
+    let trimmed = input.trim();
+    let symbolic = trimmed.rsplit("::").next().unwrap_or(trimmed);
+    let symbolic = symbolic.strip_prefix('.').unwrap_or(symbolic);
+
+    let (head, tail) = match symbolic.split_once('.') {
+        Some((head, tail)) => (head, Some(tail)),
+        None => (symbolic, None),
+    };
+
+    let mut oid = if head.chars().all(|c| c.is_ascii_digit()) {
+        parse_numeric(head)?
+    } else {
+        let (_, numeric) = MIB_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(head))
+            .ok_or_else(|| format!("Unknown MIB object '{}'", head))?;
+        parse_numeric(numeric)?
+    };
+    if let Some(tail) = tail {
+        oid.extend(parse_numeric(tail)?);
+    }
+
+    if oid.len() < 2 {
+        return Err(format!("OID '{}' is too short", input));
+    }
+    Ok(oid)
+}
+
+/// Symbolic form of `oid` using the longest known prefix, e.g. `prtMarkerLifeCount.1.1`.
+pub fn name_for_oid(oid: &[u32]) -> Option<String> {
+    MIB_NAMES
+        .iter()
+        .filter_map(|(name, numeric)| {
+            let prefix = parse_numeric(numeric).ok()?;
+            oid.starts_with(&prefix).then_some((name, prefix.len()))
+        })
+        .max_by_key(|(_, length)| *length)
+        .map(|(name, length)| {
+            let suffix: Vec<String> = oid[length..].iter().map(|arc| arc.to_string()).collect();
+            if suffix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", name, suffix.join("."))
+            }
+        })
+}
+
+pub fn format_oid(oid: &[u32]) -> String {
+    oid.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join(".")
+}
+
+fn parse_numeric(text: &str) -> Result<Vec<u32>, String> {
+    text.split('.')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u32>().map_err(|_| format!("Invalid OID component '{}'", part)))
+        .collect()
+}
@@ -0,0 +1,174 @@
+use crate::addr::HostAddr;
+use crate::alerts::AlertEngine;
+use crate::counters::{collect_counters, CounterSample};
+use crate::inventory::InventoryStore;
+use crate::settings;
+use crate::snmp_tools::{self, SnmpValue};
+use crate::supplies::{collect_supplies, SupplyLevel, SupplySample};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::{OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::warn;
+
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `hrPrinterDetectedErrorState` bits, most significant bit of the first octet first.
+const ERROR_STATE_BITS: &[&str] = &[
+    "lowPaper",
+    "noPaper",
+    "lowToner",
+    "noToner",
+    "doorOpen",
+    "jammed",
+    "offline",
+    "serviceRequested",
+    "inputTrayMissing",
+    "outputTrayMissing",
+    "markerSupplyMissing",
+    "outputNearFull",
+    "outputFull",
+    "inputTrayEmpty",
+    "overduePreventMaint",
+];
+
+/// What one poll learned about one printer.
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorSample {
+    pub printer_id: String,
+    pub at: DateTime<Utc>,
+    pub online: bool,
+    /// Active `hrPrinterDetectedErrorState` conditions, e.g. `jammed`.
+    pub errors: Vec<String>,
+    pub counters: Option<CounterSample>,
+    pub supplies: Option<Vec<SupplyLevel>>,
+}
+
+/// Starts the background poller. Samples are emitted to the UI as `monitor-samples` and
+/// fed to the alert engine; counters and supplies are stored once per record interval.
+/// Intervals are re-read from the settings on every pass.
+pub fn start(app: AppHandle) {
+    thread::spawn(move || {
+        let mut last_stored: Option<Instant> = None;
+        loop {
+            settings::reload_if_changed(&app);
+            let intervals = settings::current().monitor.clone();
+            let store = app.state::<InventoryStore>();
+            let record = last_stored.is_none_or(|at| at.elapsed() >= intervals.record_interval());
+            let samples = poll_once(&store, record);
+            if record {
+                last_stored = Some(Instant::now());
+            }
+
+            if let Err(e) = app.emit("monitor-samples", &samples) {
+                warn!("failed to emit monitor samples: {}", e);
+            }
+            if let Some(engine) = app.try_state::<AlertEngine>() {
+                engine.evaluate(&app, &store.snapshot(), &samples);
+            }
+            thread::sleep(intervals.status_interval());
+        }
+    });
+}
+
+fn latest_samples() -> &'static RwLock<HashMap<String, MonitorSample>> {
+    static LATEST: OnceLock<RwLock<HashMap<String, MonitorSample>>> = OnceLock::new();
+    LATEST.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// The most recent poll of `printer_id`, if the monitor has reached it since startup.
+pub fn latest(printer_id: &str) -> Option<MonitorSample> {
+    latest_samples().read().unwrap().get(printer_id).cloned()
+}
+
+/// Polls every networked printer in parallel. With `record` set, counters and supply
+/// levels are appended to the inventory time series.
+pub fn poll_once(store: &InventoryStore, record: bool) -> Vec<MonitorSample> {
+    let handles: Vec<_> = store
+        .snapshot()
+        .printers
+        .into_iter()
+        .filter_map(|entry| {
+            let ip = entry.address()?.to_string();
+            let port = entry.printer.port;
+            let printer_id = entry.id;
+            Some(thread::spawn(move || poll_printer(printer_id, &ip, port, record)))
+        })
+        .collect();
+    let samples: Vec<MonitorSample> = handles.into_iter().filter_map(|h| h.join().ok()).collect();
+    {
+        let mut latest = latest_samples().write().unwrap();
+        for sample in &samples {
+            latest.insert(sample.printer_id.clone(), sample.clone());
+        }
+    }
+
+    if record {
+        let stored = store.update(|inventory| {
+            for sample in &samples {
+                let Some(entry) = inventory.get_mut(&sample.printer_id) else { continue };
+                if let Some(counters) = &sample.counters {
+                    entry.push_counter(counters.clone());
+                }
+                if let Some(supplies) = &sample.supplies {
+                    entry.push_supplies(SupplySample {
+                        at: sample.at,
+                        supplies: supplies.clone(),
+                    });
+                }
+            }
+        });
+        if let Err(e) = stored {
+            warn!("failed to store monitor samples: {}", e);
+        }
+    }
+
+    samples
+}
+
+fn poll_printer(printer_id: String, ip: &str, port: u16, with_counters: bool) -> MonitorSample {
+    let community = settings::current().discovery.snmp_community.clone();
+    let errors = printer_errors(ip, &community);
+    let supplies = collect_supplies(ip, &community).ok();
+    // Never PJL from here: a background poll must not make a non-PJL device print
+    let counters = if with_counters { collect_counters(ip, &community, false).ok() } else { None };
+    let online = errors.is_some()
+        || supplies.is_some()
+        || counters.is_some()
+        || is_reachable(ip, if port == 0 { 9100 } else { port });
+
+    MonitorSample {
+        printer_id,
+        at: Utc::now(),
+        online,
+        errors: errors.unwrap_or_default(),
+        counters,
+        supplies,
+    }
+}
+
+/// Decodes `hrPrinterDetectedErrorState` for the first printer device.
+pub fn printer_errors(ip: &str, community: &str) -> Option<Vec<String>> {
+    let varbinds = snmp_tools::get(ip, &["hrPrinterDetectedErrorState.1".to_string()], community).ok()?;
+    let SnmpValue::OctetString { hex, .. } = &varbinds.first()?.value else { return None };
+    let bytes: Vec<u8> = (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+        .collect();
+
+    Some(
+        ERROR_STATE_BITS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bytes.get(bit / 8).is_some_and(|byte| byte & (0x80 >> (bit % 8)) != 0))
+            .map(|(_, name)| name.to_string())
+            .collect(),
+    )
+}
+
+fn is_reachable(ip: &str, port: u16) -> bool {
+    HostAddr::parse(ip).is_some_and(|addr| TcpStream::connect_timeout(&addr.socket_addr(port), REACHABILITY_TIMEOUT).is_ok())
+}
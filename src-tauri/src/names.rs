@@ -0,0 +1,452 @@
+use crate::addr::HostAddr;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The system resolver does not expose record TTLs, so its answers are kept this long.
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+/// Failed lookups are retried sooner than successful ones expire.
+const NEGATIVE_TTL: Duration = Duration::from_secs(60);
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+const NETBIOS_PORT: u16 = 137;
+const LLMNR_PORT: u16 = 5355;
+const LLMNR_GROUP_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 252);
+const LLMNR_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 3);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_NBSTAT: u16 = 0x21;
+const CLASS_IN: u16 = 1;
+
+/// Header flag bits shared by DNS, LLMNR and NetBIOS name service messages.
+const FLAG_RESPONSE: u16 = 0x8000;
+const RCODE_MASK: u16 = 0x000f;
+
+struct CacheEntry {
+    value: Option<String>,
+    expires: Instant,
+}
+
+fn cache() -> &'static Mutex<HashMap<String, CacheEntry>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, CacheEntry>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Returns the cached answer for `key`, or runs `lookup` and caches its result. The lookup
+/// reports its own TTL when the protocol provides one.
+fn cached<F>(key: String, lookup: F) -> Option<String>
+where
+    F: FnOnce() -> Option<(String, Duration)>,
+{
+    if let Some(entry) = cache().lock().unwrap().get(&key) {
+        if entry.expires > Instant::now() {
+            return entry.value.clone();
+        }
+    }
+
+    let (value, ttl) = match lookup() {
+        Some((value, ttl)) => (Some(value), ttl),
+        None => (None, NEGATIVE_TTL),
+    };
+    cache().lock().unwrap().insert(
+        key,
+        CacheEntry {
+            value: value.clone(),
+            expires: Instant::now() + ttl,
+        },
+    );
+    value
+}
+
+/// Drops every cached name, e.g. after the network changed.
+pub fn clear_cache() {
+    cache().lock().unwrap().clear();
+}
+
+/// Resolves a host name to an address using the system resolver, falling back to LLMNR
+/// for single-label names that only exist on the local link. IPv4 answers are preferred.
+pub fn resolve_name(hostname: &str) -> Option<String> {
+    let hostname = hostname.trim().trim_end_matches('.');
+    if hostname.is_empty() {
+        return None;
+    }
+
+    cached(format!("fwd:{}", hostname.to_ascii_lowercase()), || {
+        let addresses: Vec<SocketAddr> = (hostname, 0)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect())
+            .unwrap_or_default();
+        let best = addresses
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or_else(|| addresses.first())
+            .map(|addr| HostAddr::from(*addr).to_string());
+
+        best.map(|address| (address, DEFAULT_TTL))
+            .or_else(|| llmnr_resolve(hostname))
+    })
+}
+
+/// Finds a friendly name for an address: reverse DNS first, then NetBIOS node status and
+/// finally an LLMNR reverse query sent straight to the host.
+pub fn reverse_lookup(addr: &HostAddr) -> Option<String> {
+    cached(format!("rev:{}", addr), || {
+        reverse_dns(addr)
+            .or_else(|| netbios_name(addr))
+            .or_else(|| llmnr_reverse(addr))
+    })
+}
+
+fn reverse_dns(addr: &HostAddr) -> Option<(String, Duration)> {
+    let name = dns_lookup::lookup_addr(&addr.ip).ok()?;
+    // getnameinfo hands back the numeric address when there is no PTR record
+    if name.parse::<IpAddr>().is_ok() || HostAddr::parse(&name).is_some() {
+        return None;
+    }
+    Some((name.trim_end_matches('.').to_string(), DEFAULT_TTL))
+}
+
+/// Sends a NetBIOS Node Status (NBSTAT) request for the wildcard name and returns the
+/// machine's unique workstation name. NetBIOS only exists on IPv4.
+fn netbios_name(addr: &HostAddr) -> Option<(String, Duration)> {
+    let IpAddr::V4(ip) = addr.ip else { return None };
+
+    let id = transaction_id();
+    let mut query = dns_header(id, 0x0000);
+    query.extend_from_slice(&netbios_wildcard());
+    query.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    let response = udp_exchange(SocketAddr::from((ip, NETBIOS_PORT)), id, &query)?;
+    parse_node_status(&response, id).map(|name| (name, DEFAULT_TTL))
+}
+
+fn parse_node_status(response: &[u8], id: u16) -> Option<String> {
+    if !is_answer_to(response, id) || u16::from_be_bytes([response[6], response[7]]) == 0 {
+        return None;
+    }
+    // Answer name, then type, class, TTL and rdlength
+    let mut offset = skip_name(response, 12)? + 10;
+    let count = *response.get(offset)? as usize;
+    offset += 1;
+
+    for _ in 0..count {
+        let entry = response.get(offset..offset + 18)?;
+        let suffix = entry[15];
+        let flags = u16::from_be_bytes([entry[16], entry[17]]);
+        let is_group = flags & 0x8000 != 0;
+        if suffix == 0x00 && !is_group {
+            let name = String::from_utf8_lossy(&entry[..15]).trim_end().to_string();
+            if !name.is_empty() {
+                return Some(name);
+            }
+        }
+        offset += 18;
+    }
+
+    None
+}
+
+/// The wildcard name `*` in first-level NetBIOS encoding: padded to 16 bytes with NULs and
+/// every byte split into two nibbles offset from 'A'.
+fn netbios_wildcard() -> Vec<u8> {
+    let mut padded = [0u8; 16];
+    padded[0] = b'*';
+
+    let mut encoded = vec![32u8];
+    for byte in padded {
+        encoded.push(b'A' + (byte >> 4));
+        encoded.push(b'A' + (byte & 0x0f));
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// Resolves a single-label name over LLMNR multicast (RFC 4795).
+fn llmnr_resolve(hostname: &str) -> Option<(String, Duration)> {
+    if hostname.contains('.') {
+        return None;
+    }
+
+    let v4 = llmnr_query(SocketAddr::from((LLMNR_GROUP_V4, LLMNR_PORT)), hostname, TYPE_A);
+    v4.or_else(|| llmnr_query(SocketAddr::from((LLMNR_GROUP_V6, LLMNR_PORT)), hostname, TYPE_AAAA))
+}
+
+/// Asks the host itself for its name with a unicast LLMNR PTR query.
+fn llmnr_reverse(addr: &HostAddr) -> Option<(String, Duration)> {
+    llmnr_query(addr.socket_addr(LLMNR_PORT), &reverse_name(&addr.ip), TYPE_PTR)
+}
+
+fn llmnr_query(target: SocketAddr, name: &str, qtype: u16) -> Option<(String, Duration)> {
+    let id = transaction_id();
+    let mut query = dns_header(id, 0x0000);
+    query.extend_from_slice(&encode_dns_name(name));
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    let response = udp_exchange(target, id, &query)?;
+    parse_answer(&response, id, qtype)
+}
+
+/// Extracts the first answer of `qtype` from a DNS-format response to query `id`, with its TTL.
+fn parse_answer(response: &[u8], id: u16, qtype: u16) -> Option<(String, Duration)> {
+    if !is_answer_to(response, id) {
+        return None;
+    }
+    let questions = u16::from_be_bytes([response[4], response[5]]);
+    let answers = u16::from_be_bytes([response[6], response[7]]);
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(response, offset)? + 4;
+    }
+    for _ in 0..answers {
+        offset = skip_name(response, offset)?;
+        let header = response.get(offset..offset + 10)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let ttl = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data_start = offset + 10;
+        let data = response.get(data_start..data_start + length)?;
+        offset = data_start + length;
+
+        if rtype != qtype {
+            continue;
+        }
+        let value = match rtype {
+            TYPE_A if length == 4 => Ipv4Addr::new(data[0], data[1], data[2], data[3]).to_string(),
+            TYPE_AAAA if length == 16 => {
+                let octets: [u8; 16] = data.try_into().ok()?;
+                Ipv6Addr::from(octets).to_string()
+            }
+            TYPE_PTR => read_name(response, data_start)?,
+            _ => continue,
+        };
+        let ttl = Duration::from_secs(u64::from(ttl)).max(Duration::from_secs(1));
+        return Some((value, ttl));
+    }
+
+    None
+}
+
+/// A fresh transaction id per query, so a late or forged datagram for an earlier query is
+/// not taken as the answer to this one.
+fn transaction_id() -> u16 {
+    static NEXT: OnceLock<AtomicU16> = OnceLock::new();
+    NEXT.get_or_init(|| {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        AtomicU16::new((nanos ^ (nanos >> 16)) as u16)
+    })
+    .fetch_add(1, Ordering::Relaxed)
+}
+
+/// True when `response` is a successful reply (QR set, RCODE 0) carrying transaction `id`.
+fn is_answer_to(response: &[u8], id: u16) -> bool {
+    if response.len() < 12 {
+        return false;
+    }
+    let flags = u16::from_be_bytes([response[2], response[3]]);
+    u16::from_be_bytes([response[0], response[1]]) == id && flags & FLAG_RESPONSE != 0 && flags & RCODE_MASK == 0
+}
+
+fn dns_header(id: u16, flags: u16) -> Vec<u8> {
+    let mut header = Vec::with_capacity(64);
+    header.extend_from_slice(&id.to_be_bytes());
+    header.extend_from_slice(&flags.to_be_bytes());
+    header.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    header.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // ANCOUNT, NSCOUNT, ARCOUNT
+    header
+}
+
+fn encode_dns_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.trim_end_matches('.').split('.') {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// `in-addr.arpa` / `ip6.arpa` name for a PTR query.
+fn reverse_name(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ip) => {
+            let nibbles: Vec<String> = ip
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|byte| [byte & 0x0f, byte >> 4])
+                .map(|nibble| format!("{:x}", nibble))
+                .collect();
+            format!("{}.ip6.arpa", nibbles.join("."))
+        }
+    }
+}
+
+/// Returns the offset just past the (possibly compressed) name starting at `offset`.
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(offset)?;
+        if length & 0xc0 == 0xc0 {
+            return Some(offset + 2);
+        }
+        if length == 0 {
+            return Some(offset + 1);
+        }
+        offset += 1 + length as usize;
+    }
+}
+
+/// Decodes a (possibly compressed) name into dotted form.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<String> {
+    let mut labels = Vec::new();
+    // Bound pointer chasing so a malicious packet cannot loop forever
+    for _ in 0..32 {
+        let length = *packet.get(offset)?;
+        if length & 0xc0 == 0xc0 {
+            let low = *packet.get(offset + 1)?;
+            offset = (usize::from(length & 0x3f) << 8) | usize::from(low);
+            continue;
+        }
+        if length == 0 {
+            return Some(labels.join("."));
+        }
+        let label = packet.get(offset + 1..offset + 1 + length as usize)?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        offset += 1 + length as usize;
+    }
+    None
+}
+
+/// Sends `query` and waits for the first datagram carrying transaction `id`, ignoring
+/// anything else that arrives on the socket in the meantime.
+fn udp_exchange(target: SocketAddr, id: u16, query: &[u8]) -> Option<Vec<u8>> {
+    let bind: SocketAddr = if target.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.send_to(query, target).ok()?;
+
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut buffer = [0u8; 1500];
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())?;
+        socket.set_read_timeout(Some(remaining)).ok()?;
+        let (length, _) = socket.recv_from(&mut buffer).ok()?;
+        if length >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+            return Some(buffer[..length].to_vec());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reply to a PTR query (id 0x1234) for 10.0.0.5: printer.example.com, TTL 3600.
+    const PTR_REPLY: &[u8] = b"\x12\x34\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00\
+        \x015\x010\x010\x0210\x07in-addr\x04arpa\x00\x00\x0c\x00\x01\
+        \xc0\x0c\x00\x0c\x00\x01\x00\x00\x0e\x10\x00\x15\x07printer\x07example\x03com\x00";
+
+    /// LLMNR reply (id 0xbeef) for `office` carrying an A record (TTL 30) and an AAAA record.
+    const LLMNR_REPLY: &[u8] = b"\xbe\xef\x80\x00\x00\x01\x00\x02\x00\x00\x00\x00\
+        \x06office\x00\x00\x01\x00\x01\
+        \xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x1e\x00\x04\x0a\x00\x00\x07\
+        \xc0\x0c\x00\x1c\x00\x01\x00\x00\x00\x1e\x00\x10\xfe\x80\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x07";
+
+    /// NBSTAT reply (id 0x4242) listing a group name before the unique workstation name.
+    fn nbstat_reply() -> Vec<u8> {
+        let mut reply = b"\x42\x42\x84\x00\x00\x00\x00\x01\x00\x00\x00\x00".to_vec();
+        reply.extend_from_slice(&netbios_wildcard());
+        reply.extend_from_slice(b"\x00\x21\x00\x01\x00\x00\x00\x00\x00\x53\x02");
+        reply.extend_from_slice(b"WORKGROUP      \x00\x84\x00");
+        reply.extend_from_slice(b"OFFICE-PC      \x00\x04\x00");
+        reply.extend_from_slice(&[0x00, 0x1b, 0x21, 0x0a, 0x0b, 0x0c]);
+        reply.extend_from_slice(&[0; 40]);
+        reply
+    }
+
+    #[test]
+    fn queries_are_encoded() {
+        assert_eq!(dns_header(0xabcd, 0), [0xab, 0xcd, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encode_dns_name("printer.local."), b"\x07printer\x05local\x00");
+
+        let wildcard = netbios_wildcard();
+        assert_eq!(wildcard.len(), 34);
+        assert_eq!(&wildcard[..3], b"\x20CK");
+        assert!(wildcard[3..33].iter().all(|&b| b == b'A'));
+        assert_eq!(wildcard[33], 0);
+
+        assert_eq!(reverse_name(&"10.0.0.5".parse().unwrap()), "5.0.0.10.in-addr.arpa");
+        let v6 = reverse_name(&"2001:db8::1".parse().unwrap());
+        assert!(v6.starts_with("1.0.0.0.0.0.0.0."));
+        assert!(v6.ends_with(".8.b.d.0.1.0.0.2.ip6.arpa"));
+    }
+
+    #[test]
+    fn answers_are_parsed() {
+        assert_eq!(
+            parse_answer(PTR_REPLY, 0x1234, TYPE_PTR),
+            Some(("printer.example.com".to_string(), Duration::from_secs(3600)))
+        );
+        assert_eq!(parse_answer(LLMNR_REPLY, 0xbeef, TYPE_A), Some(("10.0.0.7".to_string(), Duration::from_secs(30))));
+        assert_eq!(parse_answer(LLMNR_REPLY, 0xbeef, TYPE_AAAA).map(|(ip, _)| ip).as_deref(), Some("fe80::7"));
+        assert_eq!(parse_node_status(&nbstat_reply(), 0x4242).as_deref(), Some("OFFICE-PC"));
+    }
+
+    #[test]
+    fn replies_to_other_queries_are_ignored() {
+        assert_eq!(parse_answer(PTR_REPLY, 0x1235, TYPE_PTR), None);
+        assert_eq!(parse_answer(PTR_REPLY, 0x1234, TYPE_A), None);
+        assert_eq!(parse_node_status(&nbstat_reply(), 0x4243), None);
+
+        let mut query = PTR_REPLY.to_vec();
+        query[2] &= 0x7f;
+        assert_eq!(parse_answer(&query, 0x1234, TYPE_PTR), None);
+        let mut failure = PTR_REPLY.to_vec();
+        failure[3] |= 0x03; // NXDOMAIN
+        assert_eq!(parse_answer(&failure, 0x1234, TYPE_PTR), None);
+    }
+
+    #[test]
+    fn truncated_replies_are_rejected() {
+        for length in 0..PTR_REPLY.len() {
+            assert_eq!(parse_answer(&PTR_REPLY[..length], 0x1234, TYPE_PTR), None, "{} bytes", length);
+        }
+        for length in 0..LLMNR_REPLY.len() {
+            assert!(parse_answer(&LLMNR_REPLY[..length], 0xbeef, TYPE_AAAA).is_none(), "{} bytes", length);
+        }
+        // Everything up to the end of the name table is needed
+        let reply = nbstat_reply();
+        for length in 0..reply.len() - 46 {
+            assert_eq!(parse_node_status(&reply[..length], 0x4242), None, "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn compression_loops_end() {
+        // A PTR answer whose name points at itself
+        let mut reply = PTR_REPLY[..PTR_REPLY.len() - 23].to_vec();
+        let rdata = reply.len() + 2;
+        reply.extend_from_slice(&[0x00, 0x02, 0xc0, rdata as u8]);
+        assert_eq!(parse_answer(&reply, 0x1234, TYPE_PTR), None);
+
+        // Two pointers pointing at each other, and a label running past the end
+        assert_eq!(read_name(&[0xc0, 0x02, 0xc0, 0x00], 0), None);
+        assert_eq!(read_name(b"\x07printer\x3fabc", 0), None);
+        assert_eq!(read_name(b"\x07printer\x00", 0).as_deref(), Some("printer"));
+        assert_eq!(skip_name(&[0xc0, 0x00], 0), Some(2));
+        assert_eq!(skip_name(b"\x07printer", 0), None);
+    }
+}
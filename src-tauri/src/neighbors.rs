@@ -0,0 +1,409 @@
+use crate::addr::HostAddr;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long to let ARP replies arrive after the priming sweep.
+const PRIME_SETTLE: Duration = Duration::from_millis(750);
+
+/// An entry from the operating system's neighbour (ARP/NDP) table.
+#[derive(Debug, Clone)]
+pub struct Neighbor {
+    pub ip: HostAddr,
+    pub mac: String,
+    pub interface: Option<String>,
+}
+
+/// Reads the neighbour table: `/proc/net/arp` and `ip neigh` on Linux, `GetIpNetTable` and
+/// `netsh` for IPv6 on Windows and `arp -an` elsewhere. Incomplete and failed entries are
+/// skipped.
+pub fn read_neighbor_table() -> Vec<Neighbor> {
+    let mut neighbors = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(table) = std::fs::read_to_string("/proc/net/arp") {
+            neighbors.extend(parse_proc_net_arp(&table));
+        }
+        if let Ok(output) = Command::new("ip").arg("neigh").arg("show").output() {
+            neighbors.extend(parse_ip_neigh(&String::from_utf8_lossy(&output.stdout)));
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        match windows_ip_net_table() {
+            Some(table) => neighbors.extend(table),
+            None => {
+                if let Ok(output) = Command::new("arp").arg("-a").output() {
+                    neighbors.extend(parse_arp_output(&String::from_utf8_lossy(&output.stdout)));
+                }
+            }
+        }
+        if let Ok(output) = Command::new("netsh").args(["interface", "ipv6", "show", "neighbors"]).output() {
+            neighbors.extend(parse_netsh_neighbors(&String::from_utf8_lossy(&output.stdout)));
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        if let Ok(output) = Command::new("arp").arg("-an").output() {
+            neighbors.extend(parse_arp_output(&String::from_utf8_lossy(&output.stdout)));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    neighbors.retain(|n| seen.insert(n.ip));
+    neighbors
+}
+
+/// Sends one throwaway UDP datagram to the discard port of every address so the OS
+/// resolves them over ARP. Hosts that are up end up in the neighbour table even if they
+/// drop the datagram; no raw sockets or elevated privileges are needed.
+pub fn prime_neighbor_table(range: &[Ipv4Addr]) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("failed to open UDP socket for neighbour sweep: {}", e);
+            return;
+        }
+    };
+    for ip in range {
+        let _ = socket.send_to(&[0], (*ip, 9));
+    }
+    thread::sleep(PRIME_SETTLE);
+}
+
+/// The addresses from `range` that the neighbour table says are alive, after optionally
+/// priming it. Returns `None` when the table is unavailable or has no entry in `range`
+/// (another interface's subnet, or an unprimed table) so callers can fall back to probing
+/// the whole range.
+pub fn live_hosts(range: &[Ipv4Addr], prime: bool) -> Option<Vec<Neighbor>> {
+    if prime {
+        prime_neighbor_table(range);
+    }
+
+    let live: Vec<Neighbor> = read_neighbor_table()
+        .into_iter()
+        .filter(|n| match n.ip.ip {
+            IpAddr::V4(ip) => range.contains(&ip),
+            IpAddr::V6(_) => false,
+        })
+        .collect();
+    if live.is_empty() {
+        None
+    } else {
+        Some(live)
+    }
+}
+
+/// Normalises `0:11:22:aa:bb:cc`, `00-11-22-AA-BB-CC` and friends to `00:11:22:AA:BB:CC`.
+/// All-zero and broadcast addresses (incomplete entries) are rejected.
+pub fn normalize_mac(raw: &str) -> Option<String> {
+    let octets: Vec<u8> = raw
+        .split([':', '-'])
+        .map(|part| u8::from_str_radix(part, 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if octets.len() != 6 || octets.iter().all(|&b| b == 0) || octets.iter().all(|&b| b == 0xff) {
+        return None;
+    }
+
+    Some(
+        octets
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+/// `IP address  HW type  Flags  HW address  Mask  Device`; flags 0x0 mean incomplete.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_net_arp(table: &str) -> Vec<Neighbor> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || fields[2] == "0x0" {
+                return None;
+            }
+            Some(Neighbor {
+                ip: HostAddr::from(Ipv4Addr::from_str(fields[0]).ok()?),
+                mac: normalize_mac(fields[3])?,
+                interface: Some(fields[5].to_string()),
+            })
+        })
+        .collect()
+}
+
+/// `192.168.1.1 dev eth0 lladdr 00:11:22:33:44:55 REACHABLE`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_ip_neigh(output: &str) -> Vec<Neighbor> {
+    output
+        .lines()
+        .filter(|line| !line.contains("FAILED") && !line.contains("INCOMPLETE"))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let value_after = |key: &str| fields.iter().position(|f| *f == key).and_then(|i| fields.get(i + 1));
+            let interface = value_after("dev").map(|s| s.to_string());
+            let address = match (&interface, fields.first()) {
+                (Some(dev), Some(ip)) if ip.starts_with("fe80") => format!("{}%{}", ip, dev),
+                (_, Some(ip)) => ip.to_string(),
+                _ => return None,
+            };
+            Some(Neighbor {
+                ip: HostAddr::parse(&address)?,
+                mac: normalize_mac(value_after("lladdr")?)?,
+                interface,
+            })
+        })
+        .collect()
+}
+
+/// Parses both the Windows (`192.168.1.1   00-11-22-33-44-55   dynamic`) and BSD
+/// (`? (192.168.1.1) at 0:11:22:33:44:55 on en0`) flavours of `arp -a`, without multicast
+/// entries.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_arp_output(output: &str) -> Vec<Neighbor> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields
+                .iter()
+                .find_map(|f| Ipv4Addr::from_str(f.trim_matches(|c| c == '(' || c == ')')).ok())
+                .filter(|ip| !ip.is_multicast())?;
+            let mac = fields.iter().find_map(|f| normalize_mac(f))?;
+            let interface = fields
+                .iter()
+                .position(|f| *f == "on")
+                .and_then(|i| fields.get(i + 1))
+                .map(|s| s.to_string());
+            Some(Neighbor {
+                ip: HostAddr::from(ip),
+                mac,
+                interface,
+            })
+        })
+        .collect()
+}
+
+/// `netsh interface ipv6 show neighbors`: one table per `Interface 12: Ethernet` heading
+/// (localised, so only the index before the colon is relied on). Link-local addresses get
+/// that index as their zone; multicast and unreachable entries are skipped.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_netsh_neighbors(output: &str) -> Vec<Neighbor> {
+    let mut interface: Option<String> = None;
+    let mut neighbors = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some(ip) = fields.first().and_then(|f| Ipv6Addr::from_str(f).ok()) else {
+            if let Some((heading, _)) = line.split_once(':') {
+                if let Some(index) = heading.split_whitespace().last().filter(|i| i.parse::<u32>().is_ok()) {
+                    interface = Some(index.to_string());
+                }
+            }
+            continue;
+        };
+        if ip.is_multicast() || fields.iter().any(|f| f.starts_with("Unreachable") || *f == "Incomplete") {
+            continue;
+        }
+        let address = match &interface {
+            Some(index) if ip.segments()[0] & 0xffc0 == 0xfe80 => format!("{}%{}", ip, index),
+            _ => ip.to_string(),
+        };
+        let (Some(ip), Some(mac)) = (HostAddr::parse(&address), fields.get(1).and_then(|f| normalize_mac(f))) else {
+            continue;
+        };
+        neighbors.push(Neighbor {
+            ip,
+            mac,
+            interface: interface.clone(),
+        });
+    }
+    neighbors
+}
+
+#[cfg(windows)]
+fn windows_ip_net_table() -> Option<Vec<Neighbor>> {
+    use windows_sys::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{GetIpNetTable, MIB_IPNETTABLE};
+
+    const MIB_IPNET_TYPE_INVALID: u32 = 2;
+
+    let mut size = 0u32;
+    if unsafe { GetIpNetTable(std::ptr::null_mut(), &mut size, 0) } != ERROR_INSUFFICIENT_BUFFER {
+        return None;
+    }
+    // u32 elements keep the buffer aligned for the table header
+    let mut buffer = vec![0u32; (size as usize + 3) / 4];
+    let table = buffer.as_mut_ptr() as *mut MIB_IPNETTABLE;
+    if unsafe { GetIpNetTable(table, &mut size, 1) } != NO_ERROR {
+        return None;
+    }
+
+    let rows = unsafe { std::slice::from_raw_parts((*table).table.as_ptr(), (*table).dwNumEntries as usize) };
+    let neighbors = rows
+        .iter()
+        .filter(|row| unsafe { row.Anonymous.dwType } != MIB_IPNET_TYPE_INVALID)
+        .filter_map(|row| {
+            let length = (row.dwPhysAddrLen as usize).min(row.bPhysAddr.len());
+            let mac = row.bPhysAddr[..length]
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(":");
+            Some(Neighbor {
+                // dwAddr holds the address in network byte order
+                ip: HostAddr::from(Ipv4Addr::from(row.dwAddr.to_ne_bytes())),
+                mac: normalize_mac(&mac)?,
+                interface: Some(row.dwIndex.to_string()),
+            })
+        })
+        .collect();
+
+    Some(neighbors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(neighbors: &[Neighbor]) -> Vec<(String, String, Option<String>)> {
+        neighbors
+            .iter()
+            .map(|n| (n.ip.to_string(), n.mac.clone(), n.interface.clone()))
+            .collect()
+    }
+
+    fn entry(ip: &str, mac: &str, interface: Option<&str>) -> (String, String, Option<String>) {
+        (ip.to_string(), mac.to_string(), interface.map(str::to_string))
+    }
+
+    #[test]
+    fn proc_net_arp_skips_incomplete_entries() {
+        let table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         00:11:22:33:44:55     *        eth0
+192.168.1.20     0x1         0x0         00:00:00:00:00:00     *        eth0
+192.168.1.31     0x1         0x2         3c:2a:f4:0a:0b:0c     *        wlan0
+";
+        assert_eq!(
+            summary(&parse_proc_net_arp(table)),
+            [
+                entry("192.168.1.1", "00:11:22:33:44:55", Some("eth0")),
+                entry("192.168.1.31", "3C:2A:F4:0A:0B:0C", Some("wlan0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn ip_neigh_skips_failed_and_incomplete_entries() {
+        let output = "\
+192.168.1.1 dev eth0 lladdr 00:11:22:33:44:55 REACHABLE
+192.168.1.20 dev eth0  FAILED
+192.168.1.21 dev eth0 lladdr 00:11:22:33:44:66 INCOMPLETE
+192.168.1.31 dev eth0 lladdr 3c:2a:f4:0a:0b:0c STALE
+2001:db8::31 dev eth0 lladdr 3c:2a:f4:0a:0b:0c router DELAY
+192.168.1.40 dev eth0 PROBE
+";
+        assert_eq!(
+            summary(&parse_ip_neigh(output)),
+            [
+                entry("192.168.1.1", "00:11:22:33:44:55", Some("eth0")),
+                entry("192.168.1.31", "3C:2A:F4:0A:0B:0C", Some("eth0")),
+                entry("2001:db8::31", "3C:2A:F4:0A:0B:0C", Some("eth0")),
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ip_neigh_scopes_link_local_addresses() {
+        let neighbors = parse_ip_neigh("fe80::3e2a:f4ff:fe0a:b0c dev lo lladdr 3c:2a:f4:0a:0b:0c STALE\n");
+        assert_eq!(summary(&neighbors), [entry("fe80::3e2a:f4ff:fe0a:b0c%lo", "3C:2A:F4:0A:0B:0C", Some("lo"))]);
+    }
+
+    #[test]
+    fn arp_output_is_read_in_windows_and_bsd_form() {
+        let windows = "\
+Interface: 192.168.1.50 --- 0xb
+  Internet Address      Physical Address      Type
+  192.168.1.1           00-11-22-33-44-55     dynamic
+  192.168.1.31          3c-2a-f4-0a-0b-0c     dynamic
+  192.168.1.255         ff-ff-ff-ff-ff-ff     static
+  224.0.0.22            01-00-5e-00-00-16     static
+";
+        assert_eq!(
+            summary(&parse_arp_output(windows)),
+            [
+                entry("192.168.1.1", "00:11:22:33:44:55", None),
+                entry("192.168.1.31", "3C:2A:F4:0A:0B:0C", None),
+            ]
+        );
+
+        let bsd = "\
+? (192.168.1.1) at 0:11:22:33:44:55 on en0 ifscope [ethernet]
+? (192.168.1.20) at (incomplete) on en0 ifscope [ethernet]
+printer.lan (192.168.1.31) at 3c:2a:f4:a:b:c on en0 ifscope [ethernet]
+";
+        assert_eq!(
+            summary(&parse_arp_output(bsd)),
+            [
+                entry("192.168.1.1", "00:11:22:33:44:55", Some("en0")),
+                entry("192.168.1.31", "3C:2A:F4:0A:0B:0C", Some("en0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn netsh_neighbors_take_the_interface_index_as_zone() {
+        let output = "\
+Interface 1: Loopback Pseudo-Interface 1
+
+
+Internet Address                              Physical Address   Type
+--------------------------------------------  -----------------  -----------
+ff02::c                                                          Permanent
+
+Interface 12: Ethernet
+
+
+Internet Address                              Physical Address   Type
+--------------------------------------------  -----------------  -----------
+2001:db8::31                                  3c-2a-f4-0a-0b-0c  Reachable
+fe80::3e2a:f4ff:fe0a:b0c                      3c-2a-f4-0a-0b-0c  Stale
+fe80::1                                       00-11-22-33-44-55  Reachable (Router)
+fe80::99                                      00-00-00-00-00-00  Unreachable
+ff02::1                                       33-33-00-00-00-01  Permanent
+";
+        let neighbors = parse_netsh_neighbors(output);
+        let found: Vec<(IpAddr, u32, &str, Option<&str>)> = neighbors
+            .iter()
+            .map(|n| (n.ip.ip, n.ip.scope_id, n.mac.as_str(), n.interface.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("2001:db8::31".parse().unwrap(), 0, "3C:2A:F4:0A:0B:0C", Some("12")),
+                ("fe80::3e2a:f4ff:fe0a:b0c".parse().unwrap(), 12, "3C:2A:F4:0A:0B:0C", Some("12")),
+                ("fe80::1".parse().unwrap(), 12, "00:11:22:33:44:55", Some("12")),
+            ]
+        );
+    }
+
+    #[test]
+    fn macs_are_normalised() {
+        assert_eq!(normalize_mac("0:11:22:aa:bb:cc").as_deref(), Some("00:11:22:AA:BB:CC"));
+        assert_eq!(normalize_mac("00-11-22-AA-BB-CC").as_deref(), Some("00:11:22:AA:BB:CC"));
+        for invalid in ["00:00:00:00:00:00", "ff:ff:ff:ff:ff:ff", "00:11:22:33:44", "(incomplete)", "00:11:22:33:44:zz"] {
+            assert_eq!(normalize_mac(invalid), None, "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+use tracing::{error, warn};
+
+/// The MA-L registry in its IEEE `oui.csv` format, gzip-compressed. `build.rs` bundles the
+/// file `OUI_REGISTRY` points at or downloads the current one, and only falls back to the
+/// printer-vendor snapshot in `data/oui.csv.gz` when building offline; either way
+/// `update_oui_registry` can add newer files later.
+const BUNDLED_REGISTRY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/oui.csv.gz"));
+
+/// Installed registry files, one per IEEE block size, loaded in this order on startup.
+const INSTALLED_NAMES: &[&str] = &["oui", "mam", "oui36"];
+
+/// Organisation name fragments (lower case) that identify printer manufacturers, with the
+/// short vendor name used elsewhere in the app.
+const PRINTER_VENDORS: &[(&str, &str)] = &[
+    ("hewlett packard", "HP"),
+    ("hp inc", "HP"),
+    ("brother", "Brother"),
+    ("canon", "Canon"),
+    ("seiko epson", "Epson"),
+    ("ricoh", "Ricoh"),
+    ("xerox", "Xerox"),
+    ("kyocera", "Kyocera"),
+    ("lexmark", "Lexmark"),
+    ("konica minolta", "Konica Minolta"),
+    ("zebra technologies", "Zebra"),
+    ("oki electric", "OKI"),
+    ("sharp", "Sharp"),
+    ("toshiba tec", "Toshiba"),
+    ("pantum", "Pantum"),
+    ("star micronics", "Star Micronics"),
+    ("bixolon", "Bixolon"),
+];
+
+/// Assignment prefix (upper-case hex, 6/7/9 digits for MA-L/MA-M/MA-S) to organisation.
+type Registry = HashMap<String, String>;
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let registry = parse_registry(GzDecoder::new(BUNDLED_REGISTRY)).unwrap_or_else(|e| {
+            error!("failed to load bundled OUI registry: {}", e);
+            Registry::new()
+        });
+        RwLock::new(registry)
+    })
+}
+
+/// Merges a local IEEE `oui.csv`/`mam.csv`/`oui36.csv` or `oui.txt` file, optionally
+/// gzip-compressed, into the active registry. The file replaces the assignments of the block
+/// sizes it contains, so an MA-M or MA-S file adds to the MA-L registry instead of replacing
+/// it. Returns the number of assignments loaded.
+pub fn load_registry_file(path: &Path) -> Result<usize, String> {
+    let parsed = read_registry_file(path)?;
+    let count = parsed.len();
+    merge(&mut registry().write().unwrap(), parsed);
+    Ok(count)
+}
+
+fn read_registry_file(path: &Path) -> Result<Registry, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let parsed = if path.extension().is_some_and(|ext| ext == "gz") {
+        parse_registry(GzDecoder::new(file))?
+    } else {
+        parse_registry(file)?
+    };
+    if parsed.is_empty() {
+        return Err(format!("No OUI assignments found in {}", path.display()));
+    }
+    Ok(parsed)
+}
+
+fn merge(active: &mut Registry, parsed: Registry) {
+    let lengths: HashSet<usize> = parsed.keys().map(String::len).collect();
+    active.retain(|assignment, _| !lengths.contains(&assignment.len()));
+    active.extend(parsed);
+}
+
+/// Installs a registry file into `data_dir` so it survives restarts, then loads it. Each
+/// block size is kept in its own file, so installing `mam.csv` leaves `oui.csv` in place.
+pub fn install_registry_file(source: &Path, data_dir: &Path) -> Result<usize, String> {
+    let parsed = read_registry_file(source)?;
+    let name = match parsed.keys().map(String::len).max() {
+        Some(9) => "oui36",
+        Some(7) => "mam",
+        _ => "oui",
+    };
+    let gz = source.extension().is_some_and(|ext| ext == "gz");
+    let target = data_dir.join(format!("{}.csv{}", name, if gz { ".gz" } else { "" }));
+    let stale = data_dir.join(format!("{}.csv{}", name, if gz { "" } else { ".gz" }));
+
+    std::fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir.display(), e))?;
+    std::fs::copy(source, &target).map_err(|e| format!("Failed to copy registry: {}", e))?;
+    let _ = std::fs::remove_file(stale);
+
+    let count = parsed.len();
+    merge(&mut registry().write().unwrap(), parsed);
+    Ok(count)
+}
+
+/// Loads the registry files previously installed in `data_dir`, if there are any.
+pub fn load_installed_registry(data_dir: &Path) {
+    for name in INSTALLED_NAMES {
+        for extension in ["csv", "csv.gz"] {
+            let path = data_dir.join(format!("{}.{}", name, extension));
+            if path.exists() {
+                if let Err(e) = load_registry_file(&path) {
+                    warn!("failed to load installed OUI registry: {}", e);
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn update_oui_registry(app: tauri::AppHandle, path: String) -> Result<usize, String> {
+    use tauri::Manager;
+
+    let path = crate::validate::input_file(&path, &["csv", "txt", "gz"])?;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    install_registry_file(&path, &data_dir)
+}
+
+/// Organisation that owns the MAC address, preferring the most specific assignment.
+pub fn lookup_organization(mac: &str) -> Option<String> {
+    let hex: String = mac.chars().filter(|c| c.is_ascii_hexdigit()).collect::<String>().to_ascii_uppercase();
+    if hex.len() < 6 {
+        return None;
+    }
+
+    let registry = registry().read().unwrap();
+    [9, 7, 6]
+        .iter()
+        .filter(|&&len| hex.len() >= len)
+        .find_map(|&len| registry.get(&hex[..len]).cloned())
+}
+
+/// Short vendor name for a MAC address ("HP", "Brother", ...) if it belongs to a printer
+/// manufacturer.
+pub fn printer_vendor(mac: &str) -> Option<String> {
+    let organization = lookup_organization(mac)?.to_ascii_lowercase();
+    PRINTER_VENDORS
+        .iter()
+        .find(|(fragment, _)| organization.contains(fragment))
+        .map(|(_, vendor)| vendor.to_string())
+}
+
+/// Vendor for display: the short printer vendor name when known, otherwise the registry's
+/// organisation name.
+pub fn vendor_for_mac(mac: &str) -> Option<String> {
+    printer_vendor(mac).or_else(|| lookup_organization(mac))
+}
+
+fn parse_registry<R: Read>(reader: R) -> Result<Registry, String> {
+    let mut text = String::new();
+    BufReader::new(reader)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read OUI registry: {}", e))?;
+
+    if text.starts_with("Registry,") {
+        parse_csv(&text)
+    } else {
+        Ok(parse_txt(text.as_bytes()))
+    }
+}
+
+/// `Registry,Assignment,Organization Name,Organization Address`
+fn parse_csv(text: &str) -> Result<Registry, String> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let mut registry = Registry::new();
+
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid OUI CSV: {}", e))?;
+        if let (Some(assignment), Some(organization)) = (record.get(1), record.get(2)) {
+            let assignment = assignment.trim().to_ascii_uppercase();
+            if matches!(assignment.len(), 6 | 7 | 9) {
+                registry.insert(assignment, organization.trim().to_string());
+            }
+        }
+    }
+
+    Ok(registry)
+}
+
+/// `00-00-0C   (hex)\t\tCisco Systems, Inc` lines from the IEEE text export.
+fn parse_txt(bytes: &[u8]) -> Registry {
+    bytes
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let (prefix, organization) = line.split_once("(hex)")?;
+            let prefix: String = prefix.trim().split('-').collect();
+            if prefix.len() != 6 {
+                return None;
+            }
+            Some((prefix.to_ascii_uppercase(), organization.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_and_text_exports_are_parsed() {
+        let csv = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-L,08000e,\"Ricoh Company, Ltd.\",Tokyo\n\
+                   MA-M,70B3D51,Example Labs,\n\
+                   MA-L,12,Too short,\n";
+        let registry = parse_registry(csv.as_bytes()).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry["08000E"], "Ricoh Company, Ltd.");
+        assert_eq!(registry["70B3D51"], "Example Labs");
+
+        let txt = "OUI/MA-L\t\t\tOrganization\n\
+                   00-00-48   (hex)\t\tSeiko Epson Corporation\n\
+                   000048     (base 16)\t\tSeiko Epson Corporation\n\
+                   3c-2a-f4   (hex)\t\tBrother Industries, LTD.\n";
+        let registry = parse_registry(txt.as_bytes()).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry["3C2AF4"], "Brother Industries, LTD.");
+    }
+
+    #[test]
+    fn lookups_accept_any_mac_notation() {
+        // Locally administered prefixes, so no real assignment collides with them
+        registry().write().unwrap().insert("02A0B1".to_string(), "Hewlett Packard".to_string());
+        for mac in ["02:a0:b1:12:34:56", "02-A0-B1-12-34-56", "02a0.b112.3456", "02A0B1123456", "02a0b1"] {
+            assert_eq!(lookup_organization(mac).as_deref(), Some("Hewlett Packard"), "{}", mac);
+        }
+        assert_eq!(lookup_organization("02:a0"), None);
+        assert_eq!(lookup_organization("not a mac"), None);
+        assert_eq!(printer_vendor("02:a0:b1:00:00:01").as_deref(), Some("HP"));
+    }
+
+    #[test]
+    fn the_most_specific_assignment_wins() {
+        {
+            let mut registry = registry().write().unwrap();
+            registry.insert("02C0D1".to_string(), "Block owner".to_string());
+            registry.insert("02C0D1E".to_string(), "MA-M owner".to_string());
+            registry.insert("02C0D1EF0".to_string(), "MA-S owner".to_string());
+        }
+        assert_eq!(lookup_organization("02:c0:d1:ef:01:00").as_deref(), Some("MA-S owner"));
+        assert_eq!(lookup_organization("02:c0:d1:e0:00:00").as_deref(), Some("MA-M owner"));
+        assert_eq!(lookup_organization("02:c0:d1:00:00:00").as_deref(), Some("Block owner"));
+        assert_eq!(vendor_for_mac("02:c0:d1:00:00:00").as_deref(), Some("Block owner"));
+    }
+
+    #[test]
+    fn merging_replaces_only_the_same_block_size() {
+        let mut active = Registry::from([
+            ("0000AA".to_string(), "Old MA-L".to_string()),
+            ("70B3D51".to_string(), "Old MA-M".to_string()),
+        ]);
+        merge(&mut active, Registry::from([("70B3D52".to_string(), "New MA-M".to_string())]));
+        assert_eq!(active.len(), 2);
+        assert_eq!(active["0000AA"], "Old MA-L");
+        assert_eq!(active["70B3D52"], "New MA-M");
+    }
+}
@@ -0,0 +1,452 @@
+use crate::addr::HostAddr;
+use crate::inventory::{Inventory, InventoryEntry, InventoryStore};
+use crate::ipp_client;
+use crate::ipp_message::*;
+use crate::monitor::{self, MonitorSample};
+use crate::printing::choose_format;
+use crate::validate;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use tauri::{AppHandle, State};
+use tracing::warn;
+
+/// Pool ids share the inventory's id space; this prefix keeps them apart from printers.
+pub const POOL_PREFIX: &str = "pool:";
+
+/// Printer conditions that stop a member from taking jobs.
+const BLOCKING_ERRORS: &[&str] = &[
+    "noPaper",
+    "noToner",
+    "doorOpen",
+    "jammed",
+    "offline",
+    "inputTrayMissing",
+    "outputTrayMissing",
+    "markerSupplyMissing",
+    "outputFull",
+];
+/// Conditions that still print but make a member less attractive.
+const WARNING_ERRORS: &[&str] = &["lowPaper", "lowToner", "outputNearFull", "serviceRequested"];
+/// Supply kinds whose level means "what is left" (waste containers count up instead).
+const CONSUMABLE_KINDS: &[&str] = &["toner", "tonerCartridge", "ink", "inkCartridge", "inkRibbon"];
+
+/// A logical printer made of several physical ones. Jobs go to whichever member is best
+/// placed to print them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrinterPool {
+    pub id: String,
+    pub name: String,
+    /// Member printer ids, most preferred first.
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+}
+
+/// What a job needs from the printer that prints it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobRequirements {
+    /// MIME type of the document.
+    pub format: Option<String>,
+    pub color: bool,
+    pub duplex: bool,
+}
+
+/// Live IPP state and capabilities of a member.
+#[derive(Debug, Clone, Default)]
+pub struct IppStatus {
+    pub state: i32,
+    pub accepting: bool,
+    pub queued: i32,
+    pub formats: Vec<String>,
+    pub color: bool,
+    pub duplex: bool,
+}
+
+impl IppStatus {
+    pub fn from_attributes(attributes: &IppMessage) -> IppStatus {
+        IppStatus {
+            state: attributes.integer(PRINTER_ATTRIBUTES, "printer-state").unwrap_or(PRINTER_IDLE),
+            accepting: attributes.boolean(PRINTER_ATTRIBUTES, "printer-is-accepting-jobs").unwrap_or(true),
+            queued: attributes.integer(PRINTER_ATTRIBUTES, "queued-job-count").unwrap_or(0),
+            formats: attributes.strings(PRINTER_ATTRIBUTES, "document-format-supported"),
+            color: attributes.boolean(PRINTER_ATTRIBUTES, "color-supported").unwrap_or(false),
+            duplex: attributes
+                .strings(PRINTER_ATTRIBUTES, "sides-supported")
+                .iter()
+                .any(|side| side.starts_with("two-sided")),
+        }
+    }
+}
+
+/// Everything known about a member right now.
+#[derive(Debug, Clone, Default)]
+pub struct MemberStatus {
+    pub online: bool,
+    /// Active `hrPrinterDetectedErrorState` conditions from the monitor.
+    pub errors: Vec<String>,
+    /// Lowest toner or ink level in percent.
+    pub lowest_supply: Option<f64>,
+    /// `None` when the printer has no reachable IPP service.
+    pub ipp: Option<IppStatus>,
+}
+
+/// How a member fared when choosing a printer for a job.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolCandidate {
+    pub printer_id: String,
+    pub printer_name: String,
+    pub online: bool,
+    pub queued_jobs: Option<i32>,
+    pub lowest_supply: Option<f64>,
+    /// Lower is better; `None` when the member cannot take the job.
+    pub score: Option<u32>,
+    /// Why the member cannot take the job.
+    pub excluded: Option<String>,
+}
+
+pub fn is_pool_id(id: &str) -> bool {
+    id.starts_with(POOL_PREFIX)
+}
+
+/// Rates a member for a job: its position in the pool, plus penalties for queued jobs,
+/// being busy, warnings and low supplies. Members that are offline, stopped, in an error
+/// state or lack something the job needs are refused with the reason.
+pub fn score(preference: usize, status: &MemberStatus, requirements: &JobRequirements) -> Result<u32, String> {
+    if !status.online {
+        return Err("offline".to_string());
+    }
+    if let Some(error) = status.errors.iter().find(|e| BLOCKING_ERRORS.contains(&e.as_str())) {
+        return Err(format!("reports {}", error));
+    }
+
+    let mut score = u32::try_from(preference).unwrap_or(u32::MAX);
+    match &status.ipp {
+        Some(ipp) => {
+            if !ipp.accepting {
+                return Err("not accepting jobs".to_string());
+            }
+            if ipp.state == PRINTER_STOPPED {
+                return Err("stopped".to_string());
+            }
+            if let Some(format) = &requirements.format {
+                if choose_format(format, &ipp.formats, true).is_none() {
+                    return Err(format!("cannot print {}", format));
+                }
+            }
+            if requirements.color && !ipp.color {
+                return Err("no color".to_string());
+            }
+            if requirements.duplex && !ipp.duplex {
+                return Err("no duplex".to_string());
+            }
+            // A misbehaving printer can report any queue length; keep the sum from wrapping
+            score = score.saturating_add((ipp.queued.max(0) as u32).saturating_mul(10));
+            if ipp.state == PRINTER_PROCESSING {
+                score = score.saturating_add(5);
+            }
+        }
+        None if requirements.color || requirements.duplex => {
+            return Err("capabilities unknown (no IPP)".to_string());
+        }
+        // Queue length unknown: assume it is busier than an idle IPP printer
+        None => score = score.saturating_add(20),
+    }
+
+    let warnings = status.errors.iter().filter(|e| WARNING_ERRORS.contains(&e.as_str())).count() as u32;
+    score = score.saturating_add(warnings.saturating_mul(5));
+    score = score.saturating_add(match status.lowest_supply {
+        Some(percent) if percent < 5.0 => 30,
+        Some(percent) if percent < 20.0 => 10,
+        _ => 0,
+    });
+    Ok(score)
+}
+
+fn lowest_supply(entry: &InventoryEntry, sample: Option<&MonitorSample>) -> Option<f64> {
+    let supplies = match sample.and_then(|s| s.supplies.as_ref()) {
+        Some(supplies) => supplies,
+        None => &entry.supplies.last()?.supplies,
+    };
+    supplies
+        .iter()
+        .filter(|s| CONSUMABLE_KINDS.contains(&s.kind.as_str()))
+        .filter_map(|s| s.percent)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// Gathers the member's state from the monitor's last poll and a live IPP query.
+fn member_status(entry: &InventoryEntry) -> MemberStatus {
+    let sample = monitor::latest(&entry.id);
+    let ipp = entry
+        .address()
+        .and_then(HostAddr::parse)
+        .and_then(|addr| ipp_client::find_endpoint(&addr, None).ok())
+        .map(|(_, attributes)| IppStatus::from_attributes(&attributes));
+    MemberStatus {
+        // An answering IPP service beats a stale "offline" from the last poll, and a
+        // printer the monitor has not polled yet gets the benefit of the doubt
+        online: ipp.is_some() || sample.as_ref().is_none_or(|s| s.online),
+        errors: sample.as_ref().map(|s| s.errors.clone()).unwrap_or_default(),
+        lowest_supply: lowest_supply(entry, sample.as_ref()),
+        ipp,
+    }
+}
+
+/// Rates every member of `pool` for a job, querying them in parallel. Members that can
+/// take the job come first, best first.
+pub fn rank(pool: &PrinterPool, inventory: &Inventory, requirements: &JobRequirements) -> Vec<PoolCandidate> {
+    let handles: Vec<_> = pool
+        .members
+        .iter()
+        .enumerate()
+        .map(|(preference, id)| {
+            let entry = inventory.get(id).cloned();
+            let id = id.clone();
+            let requirements = requirements.clone();
+            thread::spawn(move || {
+                let Some(entry) = entry else {
+                    return PoolCandidate {
+                        printer_name: id.clone(),
+                        printer_id: id,
+                        online: false,
+                        queued_jobs: None,
+                        lowest_supply: None,
+                        score: None,
+                        excluded: Some("not in the inventory".to_string()),
+                    };
+                };
+                let status = member_status(&entry);
+                let result = score(preference, &status, &requirements);
+                PoolCandidate {
+                    printer_id: entry.id.clone(),
+                    printer_name: entry.display_name().to_string(),
+                    online: status.online,
+                    queued_jobs: status.ipp.as_ref().map(|ipp| ipp.queued),
+                    lowest_supply: status.lowest_supply,
+                    score: result.as_ref().ok().copied(),
+                    excluded: result.err(),
+                }
+            })
+        })
+        .collect();
+
+    let mut candidates: Vec<PoolCandidate> = handles
+        .into_iter()
+        .zip(&pool.members)
+        .filter_map(|(handle, id)| match handle.join() {
+            Ok(candidate) => Some(candidate),
+            Err(_) => {
+                warn!("Rating pool member {} of {} panicked", id, pool.id);
+                None
+            }
+        })
+        .collect();
+    candidates.sort_by_key(|c| c.score.unwrap_or(u32::MAX));
+    candidates
+}
+
+/// The printers a job for `id` should be tried on, in order: the printer itself, or the
+/// pool members able to take the job, best first.
+pub fn resolve_targets(inventory: &Inventory, id: &str, requirements: &JobRequirements) -> Result<Vec<InventoryEntry>, String> {
+    if !is_pool_id(id) {
+        let entry = inventory.get(id).ok_or_else(|| format!("Unknown printer '{}'", id))?;
+        return Ok(vec![entry.clone()]);
+    }
+
+    let pool = inventory.pool(id).ok_or_else(|| format!("Unknown printer pool '{}'", id))?;
+    let candidates = rank(pool, inventory, requirements);
+    let targets: Vec<InventoryEntry> = candidates
+        .iter()
+        .filter(|c| c.score.is_some())
+        .filter_map(|c| inventory.get(&c.printer_id).cloned())
+        .collect();
+    if targets.is_empty() {
+        let reasons: Vec<String> = candidates
+            .iter()
+            .map(|c| format!("{}: {}", c.printer_name, c.excluded.as_deref().unwrap_or("unavailable")))
+            .collect();
+        return Err(format!("No printer in {} can take the job ({})", pool.name, reasons.join("; ")));
+    }
+    Ok(targets)
+}
+
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+fn validate_pool(pool: &PrinterPool, inventory: &Inventory) -> Result<(), String> {
+    if pool.name.trim().is_empty() {
+        return Err("A pool needs a name".to_string());
+    }
+    validate::text("Pool name", &pool.name)?;
+    if let Some(location) = &pool.location {
+        validate::text("Location", location)?;
+    }
+    if pool.members.is_empty() {
+        return Err(format!("Pool '{}' has no printers", pool.name));
+    }
+    for (i, member) in pool.members.iter().enumerate() {
+        if is_pool_id(member) {
+            return Err("Pools cannot contain other pools".to_string());
+        }
+        if pool.members[..i].contains(member) {
+            return Err(format!("{} is in the pool twice", member));
+        }
+        let entry = inventory.get(member).ok_or_else(|| format!("Unknown printer '{}'", member))?;
+        if entry.address().is_none() {
+            return Err(format!("{} is a local queue and cannot be pooled", entry.display_name()));
+        }
+    }
+    Ok(())
+}
+
+/// Adds `pool`, or replaces the pool with the same id. New pools get an id from their name.
+pub fn save(store: &InventoryStore, mut pool: PrinterPool) -> Result<PrinterPool, String> {
+    if pool.id.is_empty() {
+        pool.id = format!("{}{}", POOL_PREFIX, slug(&pool.name));
+    }
+    validate::id(&pool.id)?;
+    if !is_pool_id(&pool.id) || pool.id.len() == POOL_PREFIX.len() {
+        return Err(format!("Invalid pool id '{}'", pool.id));
+    }
+    pool.location = pool.location.filter(|l| !l.trim().is_empty());
+
+    store.update(|inventory| {
+        validate_pool(&pool, inventory)?;
+        match inventory.pools.iter_mut().find(|p| p.id == pool.id) {
+            Some(existing) => *existing = pool.clone(),
+            None => inventory.pools.push(pool.clone()),
+        }
+        Ok(pool)
+    })?
+}
+
+#[tauri::command]
+pub fn get_pools(store: State<'_, InventoryStore>) -> Vec<PrinterPool> {
+    store.snapshot().pools
+}
+
+#[tauri::command]
+pub fn save_pool(app: AppHandle, store: State<'_, InventoryStore>, pool: PrinterPool) -> Result<PrinterPool, String> {
+    let pool = save(&store, pool)?;
+    crate::tray::refresh(&app);
+    Ok(pool)
+}
+
+#[tauri::command]
+pub fn remove_pool(app: AppHandle, store: State<'_, InventoryStore>, id: String) -> Result<(), String> {
+    validate::id(&id)?;
+    store.update(|inventory| inventory.pools.retain(|p| p.id != id))?;
+    crate::tray::refresh(&app);
+    Ok(())
+}
+
+/// Shows which member a job would go to and why the others would not.
+#[tauri::command]
+pub fn rank_pool(
+    store: State<'_, InventoryStore>,
+    id: String,
+    requirements: Option<JobRequirements>,
+) -> Result<Vec<PoolCandidate>, String> {
+    validate::id(&id)?;
+    let inventory = store.snapshot();
+    let pool = inventory.pool(&id).ok_or_else(|| format!("Unknown printer pool '{}'", id))?;
+    Ok(rank(pool, &inventory, &requirements.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle(queued: i32) -> MemberStatus {
+        MemberStatus {
+            online: true,
+            ipp: Some(IppStatus {
+                state: PRINTER_IDLE,
+                accepting: true,
+                queued,
+                formats: vec!["application/pdf".to_string()],
+                color: false,
+                duplex: true,
+            }),
+            ..MemberStatus::default()
+        }
+    }
+
+    #[test]
+    fn busier_and_less_preferred_members_score_worse() {
+        let any = JobRequirements::default();
+        let first = score(0, &idle(0), &any).unwrap();
+        let second = score(1, &idle(0), &any).unwrap();
+        let queued = score(0, &idle(2), &any).unwrap();
+        assert!(first < second);
+        assert!(second < queued);
+
+        let unknown = MemberStatus { online: true, ..MemberStatus::default() };
+        assert!(score(0, &unknown, &any).unwrap() > first);
+
+        let mut warned = idle(0);
+        warned.errors = vec!["lowToner".to_string()];
+        warned.lowest_supply = Some(3.0);
+        assert_eq!(score(0, &warned, &any).unwrap(), first + 5 + 30);
+    }
+
+    #[test]
+    fn members_that_cannot_take_the_job_are_refused() {
+        let any = JobRequirements::default();
+        assert_eq!(score(0, &MemberStatus::default(), &any).unwrap_err(), "offline");
+
+        let mut jammed = idle(0);
+        jammed.errors = vec!["jammed".to_string()];
+        assert_eq!(score(0, &jammed, &any).unwrap_err(), "reports jammed");
+
+        let color = JobRequirements { color: true, ..JobRequirements::default() };
+        assert_eq!(score(0, &idle(0), &color).unwrap_err(), "no color");
+        let duplex = JobRequirements { duplex: true, ..JobRequirements::default() };
+        assert!(score(0, &idle(0), &duplex).is_ok());
+        let unknown = MemberStatus { online: true, ..MemberStatus::default() };
+        assert_eq!(score(0, &unknown, &duplex).unwrap_err(), "capabilities unknown (no IPP)");
+
+        let word = JobRequirements {
+            format: Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document".to_string()),
+            ..JobRequirements::default()
+        };
+        assert!(score(0, &idle(0), &word).unwrap_err().starts_with("cannot print"));
+
+        let mut stopped = idle(0);
+        stopped.ipp.as_mut().unwrap().state = PRINTER_STOPPED;
+        assert_eq!(score(0, &stopped, &any).unwrap_err(), "stopped");
+    }
+
+    #[test]
+    fn huge_queues_saturate_instead_of_wrapping() {
+        let mut flooded = idle(i32::MAX);
+        flooded.ipp.as_mut().unwrap().state = PRINTER_PROCESSING;
+        flooded.lowest_supply = Some(1.0);
+        assert_eq!(score(usize::MAX, &flooded, &JobRequirements::default()).unwrap(), u32::MAX);
+        assert_eq!(score(0, &idle(-4), &JobRequirements::default()).unwrap(), 0);
+    }
+
+    #[test]
+    fn members_missing_from_the_inventory_are_ranked_last() {
+        let pool = PrinterPool {
+            id: "pool:office".to_string(),
+            name: "Office".to_string(),
+            members: vec!["gone-a".to_string(), "gone-b".to_string()],
+            location: None,
+        };
+        let ranked = rank(&pool, &Inventory::default(), &JobRequirements::default());
+        assert_eq!(ranked.len(), 2);
+        assert!(ranked.iter().all(|c| c.score.is_none() && c.excluded.as_deref() == Some("not in the inventory")));
+        assert_eq!(ranked[0].printer_id, "gone-a");
+    }
+}
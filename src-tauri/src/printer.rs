@@ -3,17 +3,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::net::{Ipv4Addr, TcpStream};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
 use std::process::Command;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
 use snmp::{SyncSession, Value};
-use reqwest::blocking::Client;
-use scraper::Html;
-use scraper::Selector;
+use tauri::Manager;
+use tracing::{debug, info, info_span, warn};
+use crate::addr::HostAddr;
+use crate::alerts::AlertEngine;
+use crate::fingerprint::{fingerprint_host, Fingerprint};
+use crate::ipp_client::query_printer_ipp;
+use crate::inventory::InventoryStore;
+use crate::names;
+use crate::neighbors;
+use crate::oui;
+use crate::settings;
+use crate::snmp_broadcast;
 #[derive(Deserialize)]
 struct Printer {
     Name: String,
@@ -24,18 +32,33 @@ struct Device {
     Name: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NetworkPrinter {
     pub name: String,
     pub ip_address: String,
     pub port: u16,
     pub model: Option<String>,
     pub status: String,
+    pub vendor: Option<String>,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    pub hostname: Option<String>,
+    pub mac_address: Option<String>,
+}
+
+/// Everything we could learn about a host from SNMP and its web interface.
+#[derive(Debug, Clone, Default)]
+pub struct PrinterIdentity {
+    pub name: Option<String>,
+    pub model: Option<String>,
+    pub vendor: Option<String>,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
 }
 
 
 #[tauri::command]
-pub fn list_all_printers() -> Result<Vec<NetworkPrinter>, String> {
+pub fn list_all_printers(app: tauri::AppHandle) -> Result<Vec<NetworkPrinter>, String> {
     let mut all_printers = Vec::new();
     
     // Get locally installed printers first
@@ -48,39 +71,53 @@ pub fn list_all_printers() -> Result<Vec<NetworkPrinter>, String> {
                     port: 0,
                     model: None,
                     status: "Local".to_string(),
+                    ..Default::default()
                 });
             }
         }
-        Err(e) => eprintln!("Failed to get local printers: {}", e),
+        Err(e) => warn!("failed to list local printers: {}", e),
     }
     
     // Discover network printers
     match discover_network_printers() {
-        Ok(network_printers) => {
-            for v in &network_printers {
-                println!("new printer {}", v.name);
+        Ok(network_printers) => all_printers.extend(network_printers),
+        Err(e) => warn!("network discovery failed: {}", e),
+    }
+
+    // Every discovery run refreshes the persistent inventory
+    if let Some(store) = app.try_state::<InventoryStore>() {
+        match store.record_discovery(&all_printers) {
+            Ok(added) if !added.is_empty() => {
+                if let Some(engine) = app.try_state::<AlertEngine>() {
+                    engine.on_new_printers(&app, &store.snapshot(), &added);
+                }
+                crate::tray::refresh(&app);
             }
-            all_printers.extend(network_printers);
+            Ok(_) => {}
+            Err(e) => warn!("failed to update inventory: {}", e),
         }
-        Err(e) => eprintln!("Failed to discover network printers: {}", e),
     }
     
     Ok(all_printers)
 }
 
 pub fn query_printer_snmp(ip: &str) -> Option<(String, Option<String>)> {
-    // Validate IP address
-    if Ipv4Addr::from_str(ip).is_err() {
-        eprintln!("Invalid IP address: {}", ip);
-        return None;
-    }
+    // Validate IP address (IPv4 or IPv6, optionally with a zone)
+    let addr = match HostAddr::parse(ip) {
+        Some(addr) => addr,
+        None => {
+            debug!("invalid IP address: {}", ip);
+            return None;
+        }
+    };
 
-    let community = b"public";
-    let timeout = std::time::Duration::from_secs(1);
-    let target = format!("{}:161", ip);
+    let settings = settings::current();
+    let community = settings.discovery.snmp_community.as_bytes();
+    let timeout = settings.discovery.snmp_timeout();
+    let target = addr.socket_addr(161);
 
     // Attempt SNMP query
-    match SyncSession::new(&target, community, Some(timeout), 0) {
+    match SyncSession::new(target, community, Some(timeout), 0) {
         Ok(mut session) => {
             // OIDs for printer name and model
             let name_oid = &[1, 3, 6, 1, 2, 1, 1, 5, 0]; // sysName
@@ -117,67 +154,63 @@ pub fn query_printer_snmp(ip: &str) -> Option<(String, Option<String>)> {
                 }
             }
 
-            // Fallback to HTTP if model is not found
-            let model = model.or_else(|| query_printer_http(ip));
-
             if let Some(name) = name {
                 return Some((name, model));
             } else {
-                eprintln!("Failed to retrieve printer name via SNMP for IP: {}", ip);
+                debug!("no sysName via SNMP from {}", ip);
             }
         }
         Err(e) => {
-            eprintln!("SNMP session failed for IP {}: {}", ip, e);
+            debug!("SNMP session to {} failed: {}", ip, e);
         }
     }
 
-    // Final fallback to HTTP if SNMP fails entirely
-    query_printer_http(ip).map(|model| (model.clone(), Some(model)))
+    None
 }
 
-/// Fallback function to query printer information via HTTP.
+/// Query printer information via the printer's web interface.
 ///
-/// Attempts to scrape the printer's web interface for model information.
-fn query_printer_http(ip: &str) -> Option<String> {
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(2))
-        .build()
-        .ok()?;
-    
-    let url = format!("http://{}", ip);
-    match client.get(&url).send() {
-        Ok(response) => {
-            if response.status().is_success() {
-                let body = response.text().ok()?;
-                let document = Html::parse_document(&body);
-                
-                // Try common selectors for printer model
-                let selectors = vec![
-                    Selector::parse("title").unwrap(),
-                    Selector::parse("h1").unwrap(),
-                    Selector::parse("meta[name='description']").unwrap(),
-                ];
-
-                for selector in selectors {
-                    if let Some(element) = document.select(&selector).next() {
-                        let text = element.text().collect::<Vec<_>>().join(" ");
-                        if !text.is_empty() {
-                            return Some(text);
-                        }
-                    }
-                }
-            }
-            None
-        }
-        Err(e) => {
-            eprintln!("HTTP query failed for IP {}: {}", ip, e);
-            None
-        }
+/// Runs the vendor fingerprint rules against the embedded web server over HTTP and HTTPS.
+pub fn query_printer_http(ip: &str) -> Option<Fingerprint> {
+    fingerprint_host(&HostAddr::parse(ip)?)
+}
+
+/// Identifies a printer by combining SNMP, IPP and an HTTP fingerprint.
+///
+/// SNMP wins for the name and model when it answers; IPP(S) is asked when SNMP gives no
+/// model. The web interface fills in the vendor, serial and firmware, and the model when
+/// neither protocol has it.
+pub fn identify_printer(ip: &str) -> Option<PrinterIdentity> {
+    let snmp = query_printer_snmp(ip);
+    let http = query_printer_http(ip).unwrap_or_default();
+
+    let (name, snmp_model) = match snmp {
+        Some((name, model)) => (Some(name), model),
+        None => (None, None),
+    };
+    let ipp = match snmp_model {
+        Some(_) => None,
+        None => query_printer_ipp(ip),
+    }
+    .unwrap_or_default();
+    let identity = PrinterIdentity {
+        name: name.or(ipp.name).or_else(|| http.model.clone()),
+        model: snmp_model.or(ipp.model).or(http.model),
+        vendor: http.vendor,
+        serial: http.serial,
+        firmware: http.firmware,
+    };
+
+    if identity.name.is_none() && identity.model.is_none() && identity.vendor.is_none() {
+        None
+    } else {
+        Some(identity)
     }
 }
+
 pub fn List_local_printers() -> Result<Vec<String>, String> {
     // Try PowerShell first
-    eprintln!("Attempting PowerShell Get-Printer command");
+    debug!("listing printers with PowerShell Get-Printer");
     let output = Command::new("powershell")
         .arg("-Command")
         .arg("Get-Printer | Select-Object -Property Name | ConvertTo-Json -Compress")
@@ -186,9 +219,8 @@ pub fn List_local_printers() -> Result<Vec<String>, String> {
     let output = match output {
         Ok(output) => output,
         Err(e) => {
-            eprintln!("PowerShell Get-Printer failed: {}", e);
             // Fallback to wmic
-            eprintln!("Attempting wmic printer command");
+            debug!("PowerShell Get-Printer failed, trying wmic: {}", e);
             let wmic_output = Command::new("wmic")
                 .arg("printer")
                 .arg("get")
@@ -201,7 +233,6 @@ pub fn List_local_printers() -> Result<Vec<String>, String> {
             }
 
             let raw_output = String::from_utf8_lossy(&wmic_output.stdout);
-            eprintln!("Raw wmic output: {}", raw_output);
 
             let printer_names: Vec<String> = raw_output
                 .lines()
@@ -210,7 +241,7 @@ pub fn List_local_printers() -> Result<Vec<String>, String> {
                 .map(|line| line.trim().to_string())
                 .collect();
 
-            eprintln!("Parsed printer names (wmic): {:?}", printer_names);
+            debug!(count = printer_names.len(), "printers listed by wmic");
             return Ok(printer_names);
         }
     };
@@ -220,10 +251,8 @@ pub fn List_local_printers() -> Result<Vec<String>, String> {
     }
 
     let raw_output = String::from_utf8_lossy(&output.stdout);
-    eprintln!("Raw PowerShell output: {}", raw_output);
-
     if raw_output.trim().is_empty() {
-        eprintln!("No printers detected by PowerShell");
+        debug!("no printers listed by PowerShell");
         return Ok(vec![]);
     }
 
@@ -235,34 +264,47 @@ pub fn List_local_printers() -> Result<Vec<String>, String> {
         },
     };
 
-    eprintln!("Parsed printer names: {:?}", printer_names);
+    debug!(count = printer_names.len(), "printers listed by PowerShell");
     Ok(printer_names)
 }
 
 pub fn discover_network_printers() -> Result<Vec<NetworkPrinter>, String> {
+    discover_network_printers_in(None)
+}
+
+/// Runs discovery with the port scan limited to `ranges` instead of the local subnet.
+/// The subnet broadcast is skipped then, since it cannot reach other networks.
+pub fn discover_network_printers_in(ranges: Option<&[Ipv4Addr]>) -> Result<Vec<NetworkPrinter>, String> {
     let mut network_printers = Vec::new();
-    println!("Start discrovering..");
+    info!(ranges = ranges.map_or(0, |r| r.len()), "starting network discovery");
     // Method 1: Use Windows NET VIEW command
-    if let Ok(net_view_printers) = discover_printers_net_view() {
-        network_printers.extend(net_view_printers);
-    } else {
-        println!("discover_printers_net_view not Ok");
-    }
+    run_method("net_view", &mut network_printers, discover_printers_net_view);
     
     // Method 2: Use PowerShell WMI to find network printers
-    if let Ok(wmi_printers) = discover_printers_wmi() {
-        network_printers.extend(wmi_printers);
-    }else {
-        println!("discover_printers_wmi not Ok");
-    }
+    run_method("wmi", &mut network_printers, discover_printers_wmi);
     
-    // Method 3: Port scan common printer ports on local network
-    if let Ok(scanned_printers) = discover_printers_port_scan() {
-        network_printers.extend(scanned_printers);
-    }else {
-        println!("discover_printers_port_scan not Ok");
+    // Method 3: One SNMP GetRequest to the subnet broadcast address
+    let mut snmp_hosts = HashSet::new();
+    if ranges.is_none() {
+        let before = network_printers.len();
+        run_method("snmp_broadcast", &mut network_printers, discover_printers_snmp_broadcast);
+        snmp_hosts.extend(network_printers[before..].iter().filter_map(|p| HostAddr::parse(&p.ip_address)));
     }
     
+    // Method 4: Port scan common printer ports. Hosts that answered the broadcast are
+    // already identified; the rest still need probing, since receipt and label printers,
+    // IPv6-only neighbours and hosts without SNMP never answer it.
+    run_method("port_scan", &mut network_printers, || {
+        let ipv4_range = match ranges {
+            Some(ranges) => ranges.to_vec(),
+            None => get_local_network_range().unwrap_or_else(|e| {
+                warn!("IPv4 range unavailable: {}", e);
+                Vec::new()
+            }),
+        };
+        port_scan(ipv4_range, ranges.is_none(), &snmp_hosts)
+    });
+    
     // Remove duplicates based on IP address
     let mut unique_printers = Vec::new();
     let mut seen_ips = HashSet::new();
@@ -273,9 +315,26 @@ pub fn discover_network_printers() -> Result<Vec<NetworkPrinter>, String> {
         }
     }
     
+    info!(found = unique_printers.len(), "network discovery finished");
     Ok(unique_printers)
 }
 
+/// Runs one discovery method inside its own span so its log lines can be told apart.
+fn run_method(
+    method: &str,
+    printers: &mut Vec<NetworkPrinter>,
+    discover: impl FnOnce() -> Result<Vec<NetworkPrinter>, String>,
+) {
+    let _span = info_span!("discovery", method).entered();
+    match discover() {
+        Ok(found) => {
+            debug!(found = found.len(), "method finished");
+            printers.extend(found);
+        }
+        Err(e) => warn!("method failed: {}", e),
+    }
+}
+
 pub fn discover_printers_net_view() -> Result<Vec<NetworkPrinter>, String> {
     let output = Command::new("net")
         .arg("view")
@@ -283,23 +342,24 @@ pub fn discover_printers_net_view() -> Result<Vec<NetworkPrinter>, String> {
         .map_err(|e| format!("Failed to execute net view: {}", e))?;
     
     if !output.status.success() {
-        println!("Printing scaner not sucess returning empty");
+        debug!("net view failed, no printers from this method");
         return Ok(Vec::new());
     }
     
     let raw_output = String::from_utf8_lossy(&output.stdout);
     let mut printers = Vec::new();
-    println!("discover_printers_net_view{}", raw_output);
     for line in raw_output.lines() {
         if line.contains("Print") || line.contains("Printer") {
             if let Some(name) = extract_computer_name(line) {
                 if let Ok(ip) = resolve_hostname(&name) {
                     printers.push(NetworkPrinter {
                         name: name.clone(),
+                        hostname: Some(name.clone()),
                         ip_address: ip,
                         port: 515, // Default LPR port
                         model: None,
                         status: "Network".to_string(),
+                        ..Default::default()
                     });
                 }
             }
@@ -310,7 +370,6 @@ pub fn discover_printers_net_view() -> Result<Vec<NetworkPrinter>, String> {
 }
 
 pub fn discover_printers_wmi() -> Result<Vec<NetworkPrinter>, String> {
-    println!("call discover_printers_wmi");
     let output = Command::new("powershell")
         .arg("-Command")
         .arg("Get-WmiObject -Class Win32_Printer | Where-Object {$_.Network -eq $true} | Select-Object Name, PortName, DriverName | ConvertTo-Json -Compress")
@@ -318,18 +377,17 @@ pub fn discover_printers_wmi() -> Result<Vec<NetworkPrinter>, String> {
         .map_err(|e| format!("Failed to execute PowerShell WMI: {}", e))?;
     
     if !output.status.success() {
-        println!("discover_printers_wmi not success return empty");
+        debug!("WMI query failed, no printers from this method");
         return Ok(Vec::new());
     }
     
     let raw_output = String::from_utf8_lossy(&output.stdout);
     if raw_output.trim().is_empty() {
-        println!("Raw output is empty");
+        debug!("WMI returned no network printers");
         return Ok(Vec::new());
     }
     
     let mut printers = Vec::new();
-    println!("discover_printers_wmi {}", raw_output);
     // Try to parse as array first, then as single object
     if let Ok(wmi_printers) = serde_json::from_str::<Vec<serde_json::Value>>(&raw_output) {
         for printer in wmi_printers {
@@ -343,6 +401,7 @@ pub fn discover_printers_wmi() -> Result<Vec<NetworkPrinter>, String> {
                     port: 9100, // Default IPP port
                     model: printer["DriverName"].as_str().map(|s| s.to_string()),
                     status: "Network (WMI)".to_string(),
+                    ..Default::default()
                 });
             }
         }
@@ -357,6 +416,7 @@ pub fn discover_printers_wmi() -> Result<Vec<NetworkPrinter>, String> {
                 port: 9100,
                 model: printer["DriverName"].as_str().map(|s| s.to_string()),
                 status: "Network (WMI)".to_string(),
+                ..Default::default()
             });
         }
     }
@@ -364,35 +424,149 @@ pub fn discover_printers_wmi() -> Result<Vec<NetworkPrinter>, String> {
     Ok(printers)
 }
 
+/// Finds printers with a single SNMP GetRequest sent to the subnet and limited broadcast
+/// addresses, then runs the usual identification on every host with the Printer-MIB.
+pub fn discover_printers_snmp_broadcast() -> Result<Vec<NetworkPrinter>, String> {
+    let mut targets = vec![SocketAddr::from((Ipv4Addr::BROADCAST, 161))];
+    if let Some(ip) = get_local_network_range().ok().and_then(|range| range.first().copied()) {
+        let octets = ip.octets();
+        // Same /24 assumption as get_local_network_range
+        targets.push(SocketAddr::from((Ipv4Addr::new(octets[0], octets[1], octets[2], 255), 161)));
+    }
+
+    let settings = settings::current();
+    let responders = snmp_broadcast::discover_printers(
+        &targets,
+        settings.discovery.snmp_community.as_bytes(),
+        settings.discovery.snmp_timeout(),
+    );
+    let mac_addresses: HashMap<HostAddr, String> = neighbors::read_neighbor_table()
+        .into_iter()
+        .map(|n| (n.ip, n.mac))
+        .collect();
+
+    let handles: Vec<_> = responders
+        .into_iter()
+        .map(|responder| {
+            let mac_address = mac_addresses.get(&responder.addr).cloned();
+            let ports = settings.discovery.ports.clone();
+            let span = info_span!("host", ip = %responder.addr);
+            thread::spawn(move || {
+                let _span = span.entered();
+                let ip_str = responder.addr.to_string();
+                let port = ports
+                    .into_iter()
+                    .find(|&port| is_printer_port_open(responder.addr, port))
+                    .unwrap_or(0);
+                let identity = identify_printer(&ip_str).unwrap_or_default();
+                let hostname = names::reverse_lookup(&responder.addr);
+                let vendor = identity
+                    .vendor
+                    .or_else(|| mac_address.as_deref().and_then(oui::vendor_for_mac));
+
+                NetworkPrinter {
+                    name: identity
+                        .name
+                        .or_else(|| responder.printer_name.clone())
+                        .or_else(|| hostname.clone())
+                        .unwrap_or_else(|| format!("Network Printer at {}", ip_str)),
+                    ip_address: ip_str,
+                    port,
+                    model: identity.model.or(responder.printer_name),
+                    status: "Discovered (SNMP broadcast)".to_string(),
+                    vendor,
+                    serial: identity.serial,
+                    firmware: identity.firmware,
+                    hostname,
+                    mac_address,
+                }
+            })
+        })
+        .collect();
+
+    Ok(handles.into_iter().filter_map(|handle| handle.join().ok()).collect())
+}
+
 pub fn discover_printers_port_scan() -> Result<Vec<NetworkPrinter>, String> {
-    println!("discover_printers_port_scan call....");
-    let network_range = get_local_network_range()?;
-    let common_ports = vec![9100, 515, 631]; // IPP, LPR, CUPS
+    let ipv4_range = get_local_network_range().unwrap_or_else(|e| {
+        warn!("IPv4 range unavailable: {}", e);
+        Vec::new()
+    });
+    port_scan(ipv4_range, true, &HashSet::new())
+}
+
+/// Scans `ipv4_range`. For the `local` subnet the neighbour table narrows the scan to live
+/// hosts and IPv6 neighbours are added; routed ranges have no neighbour entries, so every
+/// address in them is probed. Hosts in `skip` were found by another method and are left out.
+fn port_scan(ipv4_range: Vec<Ipv4Addr>, local: bool, skip: &HashSet<HostAddr>) -> Result<Vec<NetworkPrinter>, String> {
+    // Only probe hosts the OS already knows are alive, unless the neighbour table is unavailable
+    let prime = settings::current().discovery.prime_neighbors;
+    let live = if local { neighbors::live_hosts(&ipv4_range, prime) } else { None };
+    let mut network_range: Vec<HostAddr> = match live {
+        Some(live) => live.iter().map(|n| n.ip).collect(),
+        None => ipv4_range.iter().map(|&ip| HostAddr::from(ip)).collect(),
+    };
+    // IPv6 subnets are far too large to sweep, so only probe neighbours that answered NDP
+    if local {
+        network_range.extend(discover_ipv6_neighbors());
+    }
+    let scanned = network_range.len();
+    network_range.retain(|ip| !skip.contains(ip));
+    if network_range.is_empty() {
+        if scanned > 0 {
+            return Ok(Vec::new());
+        }
+        return Err("Could not determine any address to scan".to_string());
+    }
+    let mac_addresses: Arc<HashMap<HostAddr, String>> = Arc::new(
+        neighbors::read_neighbor_table()
+            .into_iter()
+            .map(|n| (n.ip, n.mac))
+            .collect(),
+    );
+    let settings = settings::current();
+    let common_ports = settings.discovery.ports.clone();
 
     let found_printers = Arc::new(Mutex::new(Vec::new()));
     let mut handles = Vec::new();
 
     // Limit concurrent threads to avoid overwhelming the network
-    let chunk_size = 10;
+    let chunk_size = settings.discovery.chunk_size;
     for chunk in network_range.chunks(chunk_size) {
         for &ip in chunk {
             for &port in &common_ports {
                 let found_printers = Arc::clone(&found_printers);
-                let ip_clone = ip.clone();
+                let mac_addresses = Arc::clone(&mac_addresses);
+                let ip_clone = ip;
+                let span = info_span!("host", ip = %ip, port);
                 let handle = thread::spawn(move || {
+                    let _span = span.entered();
                     if is_printer_port_open(ip_clone, port) {
                         let ip_str = ip_clone.to_string();
-                        println!("Port is open: IP: {} PORT: {}", ip_str, port);
+                        debug!("port open");
 
-                        let (name, model) = query_printer_snmp(&ip_str)
-                            .unwrap_or_else(|| (format!("Network Printer at {}", ip_str), None));
+                        let identity = identify_printer(&ip_str).unwrap_or_default();
+                        let hostname = names::reverse_lookup(&ip_clone);
+                        let mac_address = mac_addresses.get(&ip_clone).cloned();
+                        // The MAC's registered vendor is the last resort when SNMP and HTTP say nothing
+                        let vendor = identity
+                            .vendor
+                            .or_else(|| mac_address.as_deref().and_then(oui::vendor_for_mac));
 
                         let printer = NetworkPrinter {
-                            name,
+                            name: identity
+                                .name
+                                .or_else(|| hostname.clone())
+                                .unwrap_or_else(|| format!("Network Printer at {}", ip_str)),
                             ip_address: ip_str,
                             port,
-                            model,
+                            model: identity.model,
                             status: "Discovered (SNMP)".to_string(),
+                            vendor,
+                            serial: identity.serial,
+                            firmware: identity.firmware,
+                            hostname,
+                            mac_address,
                         };
                         found_printers.lock().unwrap().push(printer);
                     }
@@ -407,7 +581,29 @@ pub fn discover_printers_port_scan() -> Result<Vec<NetworkPrinter>, String> {
         }
     }
 
-    let printers = found_printers.lock().unwrap().clone();
+    let mut printers = found_printers.lock().unwrap().clone();
+
+    // Flag live hosts whose MAC belongs to a printer manufacturer even if no print port answered
+    for ip in &network_range {
+        let ip_str = ip.to_string();
+        if printers.iter().any(|p| p.ip_address == ip_str) {
+            continue;
+        }
+        let Some(mac) = mac_addresses.get(ip) else { continue };
+        if let Some(vendor) = oui::printer_vendor(mac) {
+            printers.push(NetworkPrinter {
+                name: format!("{} device at {}", vendor, ip_str),
+                ip_address: ip_str,
+                port: 0,
+                model: None,
+                status: "Printer vendor (OUI)".to_string(),
+                vendor: Some(vendor),
+                mac_address: Some(mac.clone()),
+                ..Default::default()
+            });
+        }
+    }
+
     Ok(printers)
 }
 
@@ -424,42 +620,134 @@ pub fn extract_computer_name(line: &str) -> Option<String> {
 }
 
 pub fn resolve_hostname(hostname: &str) -> Result<String, String> {
-    let output = Command::new("nslookup")
-        .arg(hostname)
-        .output()
-        .map_err(|e| format!("Failed to resolve hostname: {}", e))?;
-    
-    let raw_output = String::from_utf8_lossy(&output.stdout);
-    
-    for line in raw_output.lines() {
-        if line.contains("Address:") && !line.contains("#") {
-            if let Some(ip_start) = line.find("Address:") {
-                let ip_part = line[ip_start + 8..].trim();
-                if Ipv4Addr::from_str(ip_part).is_ok() {
-                    return Ok(ip_part.to_string());
-                }
-            }
-        }
-    }
-    
-    Err("Could not resolve hostname".to_string())
+    names::resolve_name(hostname).ok_or_else(|| format!("Could not resolve hostname {}", hostname))
 }
 
 pub fn extract_ip_from_port(port_name: &str) -> Option<String> {
-    // Extract IP address from port names like "IP_192.168.1.100" or "192.168.1.100"
+    // Extract IP address from port names like "IP_192.168.1.100", "IP_fe80::1%12" or "[2001:db8::5]"
     if let Some(ip_start) = port_name.find("IP_") {
         let ip_part = &port_name[ip_start + 3..];
-        if Ipv4Addr::from_str(ip_part).is_ok() {
-            return Some(ip_part.to_string());
+        if let Some(addr) = HostAddr::parse(ip_part) {
+            return Some(addr.to_string());
         }
     }
     
     // Try to parse the port name directly as an IP
-    if Ipv4Addr::from_str(port_name).is_ok() {
-        return Some(port_name.to_string());
+    HostAddr::parse(port_name).map(|addr| addr.to_string())
+}
+
+/// Finds IPv6 hosts on the local links by pinging the all-nodes multicast group
+/// (`ff02::1`) on every interface and then reading the neighbour cache that NDP filled in.
+pub fn discover_ipv6_neighbors() -> Vec<HostAddr> {
+    for interface in ipv6_interfaces() {
+        let target = format!("ff02::1%{}", interface);
+        let mut ping = Command::new("ping");
+        if cfg!(windows) {
+            ping.args(["-6", "-n", "2", "-w", "500", &target]);
+        } else {
+            ping.args(["-6", "-c", "2", "-W", "1", &target]);
+        }
+        if let Err(e) = ping.output() {
+            debug!("all-nodes ping failed on {}: {}", interface, e);
+        }
+    }
+
+    let output = if cfg!(windows) {
+        Command::new("netsh").args(["interface", "ipv6", "show", "neighbors"]).output()
+    } else {
+        Command::new("ip").args(["-6", "neigh", "show"]).output()
+    };
+    let raw_output = match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => {
+            warn!("failed to read IPv6 neighbour cache: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut neighbors = Vec::new();
+    let mut current_interface = None;
+    for line in raw_output.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(first) = fields.next() else { continue };
+
+        if cfg!(windows) {
+            // "Interface 12: Ethernet" headers precede each block of neighbours
+            if first == "Interface" {
+                current_interface = line
+                    .split([' ', ':'])
+                    .nth(1)
+                    .map(|index| index.to_string());
+                continue;
+            }
+            if line.contains("Unreachable") || line.contains("Permanent") {
+                continue;
+            }
+            let scoped = match &current_interface {
+                Some(index) if first.starts_with("fe80") => format!("{}%{}", first, index),
+                _ => first.to_string(),
+            };
+            if let Some(addr) = HostAddr::parse(&scoped) {
+                push_ipv6_neighbor(&mut neighbors, addr);
+            }
+        } else {
+            // "fe80::1 dev eth0 lladdr 00:11:22:33:44:55 REACHABLE"
+            if line.contains("FAILED") || line.contains("INCOMPLETE") {
+                continue;
+            }
+            let device = line.split_whitespace().skip_while(|f| *f != "dev").nth(1);
+            let scoped = match device {
+                Some(device) if first.starts_with("fe80") => format!("{}%{}", first, device),
+                _ => first.to_string(),
+            };
+            if let Some(addr) = HostAddr::parse(&scoped) {
+                push_ipv6_neighbor(&mut neighbors, addr);
+            }
+        }
+    }
+
+    neighbors
+}
+
+fn push_ipv6_neighbor(neighbors: &mut Vec<HostAddr>, addr: HostAddr) {
+    let is_usable = match addr.ip {
+        IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_multicast() && !ip.is_unspecified(),
+        IpAddr::V4(_) => false,
+    };
+    if is_usable && !neighbors.contains(&addr) {
+        neighbors.push(addr);
+    }
+}
+
+/// Interfaces that have IPv6 enabled, as names on Unix and indexes on Windows.
+fn ipv6_interfaces() -> Vec<String> {
+    if cfg!(windows) {
+        let output = match Command::new("netsh").args(["interface", "ipv6", "show", "interfaces"]).output() {
+            Ok(output) => output,
+            Err(_) => return Vec::new(),
+        };
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.contains("connected") && !line.contains("Loopback"))
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|index| index.parse::<u32>().is_ok())
+            .map(|index| index.to_string())
+            .collect()
+    } else {
+        std::fs::read_to_string("/proc/net/if_inet6")
+            .map(|table| {
+                let mut names: Vec<String> = table
+                    .lines()
+                    .filter_map(|line| line.split_whitespace().last())
+                    .filter(|name| *name != "lo")
+                    .map(|name| name.to_string())
+                    .collect();
+                names.sort();
+                names.dedup();
+                names
+            })
+            .unwrap_or_default()
     }
-    
-    None
 }
 
 pub fn get_local_network_range() -> Result<Vec<Ipv4Addr>, String> {
@@ -468,8 +756,6 @@ pub fn get_local_network_range() -> Result<Vec<Ipv4Addr>, String> {
         .map_err(|e| format!("Failed to get IP config: {}", e))?;
     
     let raw_output = String::from_utf8_lossy(&output.stdout);
-
-    println!("Network range output {}", raw_output);
     
     let mut local_ip = None;
     for line in raw_output.lines() {
@@ -488,6 +774,7 @@ pub fn get_local_network_range() -> Result<Vec<Ipv4Addr>, String> {
     }
     
     if let Some(ip) = local_ip {
+        debug!(local_ip = %ip, "scanning the local /24");
         let octets = ip.octets();
         let mut range = Vec::new();
         
@@ -502,8 +789,8 @@ pub fn get_local_network_range() -> Result<Vec<Ipv4Addr>, String> {
     }
 }
 
-fn is_printer_port_open(ip: Ipv4Addr, port: u16) -> bool {
-    match TcpStream::connect_timeout(&(ip, port).into(), Duration::from_millis(100)) {
+fn is_printer_port_open(ip: HostAddr, port: u16) -> bool {
+    match TcpStream::connect_timeout(&ip.socket_addr(port), settings::current().discovery.connect_timeout()) {
         Ok(_) => true,
         Err(_) => false,
     }
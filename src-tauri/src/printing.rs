@@ -0,0 +1,315 @@
+use crate::addr::HostAddr;
+use crate::convert;
+use crate::inventory::{Inventory, InventoryEntry, InventoryStore};
+use crate::ipp_client::{self, IppAuth};
+use crate::ipp_message::PRINTER_ATTRIBUTES;
+use crate::pools::{self, JobRequirements};
+use crate::raster::Sides;
+use crate::settings;
+use crate::validate;
+use crate::vault::{CredentialKind, CredentialVault};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+use tracing::{info, warn};
+
+/// Largest document `print_file` sends in one request.
+const MAX_DOCUMENT_BYTES: u64 = 256 * 1024 * 1024;
+pub const MAX_COPIES: u32 = 999;
+
+/// File extensions `print_file` accepts and the MIME type they are sent as.
+pub const DOCUMENT_FORMATS: &[(&str, &str)] = &[
+    ("pdf", "application/pdf"),
+    ("ps", "application/postscript"),
+    ("txt", "text/plain"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("pwg", "image/pwg-raster"),
+    ("urf", "image/urf"),
+];
+
+/// One submitted (or failed) job, as logged in the job history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub at: DateTime<Utc>,
+    pub printer_id: String,
+    pub printer_name: String,
+    /// IPP URI the job was sent to, when one was found.
+    #[serde(default)]
+    pub uri: Option<String>,
+    /// Who printed it: the local user, or the requesting user of a relayed job.
+    #[serde(default)]
+    pub user: Option<String>,
+    pub document: String,
+    pub format: String,
+    pub copies: u32,
+    pub bytes: usize,
+    /// Job id assigned by the printer.
+    #[serde(default)]
+    pub job_id: Option<i32>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl JobRecord {
+    pub fn new(entry: &InventoryEntry, user: &str, document: &str, format: &str, copies: u32, bytes: usize) -> JobRecord {
+        JobRecord {
+            at: Utc::now(),
+            printer_id: entry.id.clone(),
+            printer_name: entry.display_name().to_string(),
+            uri: None,
+            user: Some(user.to_string()),
+            document: document.to_string(),
+            format: format.to_string(),
+            copies,
+            bytes,
+            job_id: None,
+            error: None,
+        }
+    }
+}
+
+/// Append-only job history. Managed as Tauri state.
+pub struct JobHistory {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JobHistory {
+    pub fn new(path: PathBuf) -> JobHistory {
+        JobHistory { path, lock: Mutex::new(()) }
+    }
+
+    pub fn append(&self, record: &JobRecord) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+        let line = serde_json::to_string(record).map_err(|e| format!("Failed to serialize job: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// The most recent `limit` jobs, newest first, optionally only those of `user`.
+    pub fn recent(&self, limit: usize, user: Option<&str>) -> Result<Vec<JobRecord>, String> {
+        read_history(&self.path, limit, user)
+    }
+}
+
+fn read_history(path: &Path, limit: usize, user: Option<&str>) -> Result<Vec<JobRecord>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut jobs: Vec<JobRecord> = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<JobRecord>(&line).ok())
+        .filter(|job| user.is_none() || job.user.as_deref() == user)
+        .collect();
+    jobs.reverse();
+    jobs.truncate(limit);
+    Ok(jobs)
+}
+
+pub fn document_format(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    DOCUMENT_FORMATS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, format)| *format)
+}
+
+/// Name sent as `requesting-user-name`.
+pub fn current_user() -> String {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "printer-manager".to_string())
+}
+
+/// The inventory entry `printer_id` and its network address, for jobs sent straight to
+/// the device.
+pub fn network_printer(inventory: &Inventory, printer_id: &str) -> Result<(InventoryEntry, HostAddr), String> {
+    validate::id(printer_id)?;
+    let entry = inventory
+        .get(printer_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown printer '{}'", printer_id))?;
+    let address = entry
+        .address()
+        .ok_or_else(|| format!("{} is a local queue, not a network printer", entry.display_name()))?;
+    let addr = HostAddr::parse(address).ok_or_else(|| format!("Invalid printer address {}", address))?;
+    Ok((entry, addr))
+}
+
+/// IPP login for `entry` from the vault. A locked vault is not an error here: the job is
+/// tried without credentials and the printer says whether it needs them.
+pub fn ipp_auth(vault: &CredentialVault, entry: &InventoryEntry, addr: &HostAddr) -> Option<IppAuth> {
+    match vault.lookup(CredentialKind::Ipp, Some(&entry.id), Some(addr.ip)) {
+        Ok(Some(credential)) => Some(IppAuth {
+            username: credential.summary.username.clone().unwrap_or_default(),
+            password: credential.secret("password").unwrap_or_default().to_string(),
+            allow_cleartext: credential.summary.attributes.get("allow_cleartext").is_some_and(|v| v == "true"),
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            warn!(printer = %entry.id, "printing without IPP credentials: {}", e);
+            None
+        }
+    }
+}
+
+/// The format to send a `format` document as, given what the printer lists in
+/// `document-format-supported` (an empty list means the printer did not say). With
+/// `convert`, documents the printer does not take are sent in a format they can be
+/// converted to instead of being refused.
+pub fn choose_format(format: &str, supported: &[String], convert: bool) -> Option<String> {
+    if convert {
+        return convert::target_format(format, supported);
+    }
+    if supported.is_empty() || supported.iter().any(|f| f == format) {
+        return Some(format.to_string());
+    }
+    None
+}
+
+/// Sends `data` to the printer behind `entry` over IPP(S), checking that it accepts
+/// `format` or, with `convert`, converting it to a format it does. The outcome is
+/// returned as a job record either way.
+#[allow(clippy::too_many_arguments)]
+pub fn submit(
+    vault: &CredentialVault,
+    entry: &InventoryEntry,
+    user: &str,
+    document: &str,
+    format: &str,
+    copies: u32,
+    sides: Sides,
+    convert: bool,
+    data: Vec<u8>,
+) -> JobRecord {
+    let mut record = JobRecord::new(entry, user, document, format, copies, data.len());
+
+    let result = (|| {
+        let address = entry
+            .address()
+            .ok_or_else(|| format!("{} is a local queue, not a network printer", entry.display_name()))?;
+        let addr = HostAddr::parse(address).ok_or_else(|| format!("Invalid printer address {}", address))?;
+        let auth = ipp_auth(vault, entry, &addr);
+        let (endpoint, attributes) = ipp_client::find_endpoint(&addr, auth.as_ref())?;
+        record.uri = Some(endpoint.uri());
+
+        let supported = attributes.strings(PRINTER_ATTRIBUTES, "document-format-supported");
+        let send_as = choose_format(format, &supported, convert).ok_or_else(|| {
+            format!(
+                "{} does not accept {} (supported: {})",
+                entry.display_name(),
+                format,
+                supported.join(", ")
+            )
+        })?;
+        let data = if convert {
+            convert::convert(data, format, &send_as, sides, &attributes, &settings::current().printing)?
+        } else {
+            data
+        };
+        ipp_client::print_job(&endpoint, auth.as_ref(), document, user, &send_as, copies, sides, data)
+    })();
+
+    match result {
+        Ok(job_id) => {
+            info!(printer = %entry.id, job_id, user, document, "print job submitted");
+            record.job_id = Some(job_id);
+        }
+        Err(e) => {
+            warn!(printer = %entry.id, user, document, "print job failed: {}", e);
+            record.error = Some(e);
+        }
+    }
+    record
+}
+
+/// Prints a file on a printer or pool; without `printer_id` the default target from the
+/// settings is used. A pool tries its members best first until one takes the job, and
+/// every attempt is logged.
+#[tauri::command]
+pub fn print_file(
+    inventory: State<'_, InventoryStore>,
+    vault: State<'_, CredentialVault>,
+    history: State<'_, JobHistory>,
+    printer_id: Option<String>,
+    path: String,
+    copies: Option<u32>,
+    sides: Option<String>,
+) -> Result<JobRecord, String> {
+    let target = match printer_id {
+        Some(id) => id,
+        None => settings::current()
+            .printing
+            .default_target
+            .clone()
+            .ok_or("No printer was chosen and no default printer is set")?,
+    };
+    validate::id(&target)?;
+    let extensions: Vec<&str> = DOCUMENT_FORMATS.iter().map(|(ext, _)| *ext).collect();
+    let path = validate::input_file(&path, &extensions)?;
+    let copies = copies.unwrap_or(1);
+    if !(1..=MAX_COPIES).contains(&copies) {
+        return Err(format!("Copies must be between 1 and {}", MAX_COPIES));
+    }
+    let size = std::fs::metadata(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?.len();
+    if size > MAX_DOCUMENT_BYTES {
+        return Err(format!("{} is larger than {} MB", path.display(), MAX_DOCUMENT_BYTES / (1024 * 1024)));
+    }
+
+    let sides = match sides.as_deref() {
+        Some(keyword) => Sides::parse(keyword).ok_or_else(|| format!("Unknown sides '{}'", keyword))?,
+        None => Sides::OneSided,
+    };
+
+    let format = document_format(&path).unwrap_or("application/octet-stream");
+    let requirements = JobRequirements {
+        format: Some(format.to_string()),
+        duplex: sides.duplex(),
+        ..Default::default()
+    };
+    let targets = pools::resolve_targets(&inventory.snapshot(), &target, &requirements)?;
+    let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let document = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let user = current_user();
+    let mut errors = Vec::new();
+    for entry in &targets {
+        let record = submit(&vault, entry, &user, &document, format, copies, sides, true, data.clone());
+        if let Err(e) = history.append(&record) {
+            warn!("{}", e);
+        }
+        match record.error {
+            Some(error) if targets.len() > 1 => errors.push(format!("{}: {}", entry.display_name(), error)),
+            Some(error) => errors.push(error),
+            None => return Ok(record),
+        }
+    }
+    Err(errors.join("; "))
+}
+
+#[tauri::command]
+pub fn get_print_jobs(
+    history: State<'_, JobHistory>,
+    limit: Option<usize>,
+    user: Option<String>,
+) -> Result<Vec<JobRecord>, String> {
+    history.recent(limit.unwrap_or(200), user.as_deref())
+}
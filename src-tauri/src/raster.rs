@@ -0,0 +1,825 @@
+use std::io::Write;
+
+/// PWG `cups_page_header2_t` size.
+const PWG_HEADER_LEN: usize = 1796;
+const URF_HEADER_LEN: usize = 32;
+const PWG_SYNC: &[u8] = b"RaS2";
+const URF_MAGIC: &[u8] = b"UNIRAST\0";
+/// Most pixels one run or literal block can cover.
+const MAX_RUN: usize = 128;
+/// Most times one line can be repeated by a single line-repeat byte.
+const MAX_LINE_REPEAT: usize = 256;
+/// URF control byte that fills the rest of the line with white.
+const URF_FILL_WHITE: u8 = 0x80;
+/// Largest page a decoded stream may allocate; more than a 600 dpi A3 color page.
+const MAX_PAGE_BYTES: usize = 512 * 1024 * 1024;
+
+// Offsets of the `cups_page_header2_t` fields PWG 5102.4 uses
+const PWG_MEDIA_CLASS: usize = 0;
+const PWG_DUPLEX: usize = 272;
+const PWG_RESOLUTION: usize = 276;
+const PWG_NUM_COPIES: usize = 340;
+const PWG_PAGE_SIZE: usize = 352;
+const PWG_TUMBLE: usize = 368;
+const PWG_WIDTH: usize = 372;
+const PWG_HEIGHT: usize = 376;
+const PWG_BITS_PER_COLOR: usize = 384;
+const PWG_BITS_PER_PIXEL: usize = 388;
+const PWG_BYTES_PER_LINE: usize = 392;
+const PWG_COLOR_SPACE: usize = 400;
+const PWG_NUM_COLORS: usize = 420;
+const PWG_TOTAL_PAGE_COUNT: usize = 452;
+const PWG_CROSS_FEED_TRANSFORM: usize = 456;
+const PWG_FEED_TRANSFORM: usize = 460;
+const PWG_PAGE_SIZE_NAME: usize = 1732;
+
+/// Pixel layout of a raster page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// 1 bit per pixel, 1 is black (PWG `black_1`).
+    Black1,
+    /// 8 bit gray, 255 is white (PWG `sgray_8`, URF `W8`).
+    Gray8,
+    /// 8 bit per channel sRGB (PWG `srgb_8`, URF `SRGB24`).
+    Rgb8,
+}
+
+impl ColorMode {
+    pub fn bits_per_pixel(self) -> u32 {
+        match self {
+            ColorMode::Black1 => 1,
+            ColorMode::Gray8 => 8,
+            ColorMode::Rgb8 => 24,
+        }
+    }
+
+    /// Bytes compressed as one unit: a whole pixel, or eight pixels for bilevel pages.
+    fn unit(self) -> usize {
+        match self {
+            ColorMode::Rgb8 => 3,
+            _ => 1,
+        }
+    }
+
+    fn colors(self) -> u32 {
+        match self {
+            ColorMode::Rgb8 => 3,
+            _ => 1,
+        }
+    }
+
+    /// `cupsColorSpace`: 3 black, 18 sGray, 19 sRGB.
+    fn pwg_color_space(self) -> u32 {
+        match self {
+            ColorMode::Black1 => 3,
+            ColorMode::Gray8 => 18,
+            ColorMode::Rgb8 => 19,
+        }
+    }
+
+    fn from_pwg(color_space: u32, bits_per_pixel: u32) -> Option<ColorMode> {
+        match (color_space, bits_per_pixel) {
+            (3, 1) => Some(ColorMode::Black1),
+            (18, 8) => Some(ColorMode::Gray8),
+            (19, 24) => Some(ColorMode::Rgb8),
+            _ => None,
+        }
+    }
+
+    /// Blank paper in this layout.
+    fn white(self) -> u8 {
+        match self {
+            ColorMode::Black1 => 0x00,
+            _ => 0xff,
+        }
+    }
+}
+
+/// The IPP `sides` of a job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sides {
+    #[default]
+    OneSided,
+    /// Duplex bound on the long edge, like a book.
+    TwoSidedLongEdge,
+    /// Duplex bound on the short edge, like a notepad ("tumble").
+    TwoSidedShortEdge,
+}
+
+impl Sides {
+    pub fn parse(keyword: &str) -> Option<Sides> {
+        match keyword {
+            "one-sided" => Some(Sides::OneSided),
+            "two-sided-long-edge" => Some(Sides::TwoSidedLongEdge),
+            "two-sided-short-edge" => Some(Sides::TwoSidedShortEdge),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Sides::OneSided => "one-sided",
+            Sides::TwoSidedLongEdge => "two-sided-long-edge",
+            Sides::TwoSidedShortEdge => "two-sided-short-edge",
+        }
+    }
+
+    pub fn duplex(self) -> bool {
+        self != Sides::OneSided
+    }
+
+    fn tumble(self) -> bool {
+        self == Sides::TwoSidedShortEdge
+    }
+}
+
+/// How a duplex printer expects back sides, from `pwg-raster-document-sheet-back`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SheetBack {
+    #[default]
+    Normal,
+    Flipped,
+    Rotated,
+    ManualTumble,
+}
+
+impl SheetBack {
+    pub fn parse(keyword: &str) -> SheetBack {
+        match keyword {
+            "flipped" => SheetBack::Flipped,
+            "rotated" => SheetBack::Rotated,
+            "manual-tumble" => SheetBack::ManualTumble,
+            _ => SheetBack::Normal,
+        }
+    }
+
+    /// Whether back sides are mirrored across (`CrossFeedTransform`) and along
+    /// (`FeedTransform`) the paper path, as CUPS' rastertopwg does it.
+    fn transforms(self, tumble: bool) -> (bool, bool) {
+        match (self, tumble) {
+            (SheetBack::Flipped, true) => (true, false),
+            (SheetBack::Flipped, false) => (false, true),
+            (SheetBack::ManualTumble, true) | (SheetBack::Rotated, false) => (true, true),
+            _ => (false, false),
+        }
+    }
+}
+
+/// Job-wide settings written into every page header.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RasterOptions {
+    pub sides: Sides,
+    /// Only used for PWG; URF printers turn back sides themselves.
+    pub sheet_back: SheetBack,
+    /// PWG media name such as `iso_a4_210x297mm`, written as `cupsPageSizeName`.
+    pub media: Option<String>,
+}
+
+/// One page of pixels, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterPage {
+    pub width: u32,
+    pub height: u32,
+    pub dpi: u32,
+    pub color: ColorMode,
+    pub pixels: Vec<u8>,
+}
+
+impl RasterPage {
+    /// A white page.
+    pub fn blank(width: u32, height: u32, dpi: u32, color: ColorMode) -> RasterPage {
+        let mut page = RasterPage {
+            width,
+            height,
+            dpi,
+            color,
+            pixels: Vec::new(),
+        };
+        page.pixels = vec![color.white(); page.bytes_per_line() * height as usize];
+        page
+    }
+
+    /// A white page with dimensions read from a stream, refusing sizes that would
+    /// overflow or exhaust memory.
+    fn checked_blank(width: u32, height: u32, dpi: u32, color: ColorMode) -> Result<RasterPage, String> {
+        if width == 0 || height == 0 || dpi == 0 {
+            return Err(format!("Raster page of {}x{} pixels at {} dpi is empty", width, height, dpi));
+        }
+        let bytes = (width as usize)
+            .checked_mul(color.bits_per_pixel() as usize)
+            .map(|bits| bits.div_ceil(8))
+            .and_then(|line| line.checked_mul(height as usize))
+            .filter(|&bytes| bytes <= MAX_PAGE_BYTES);
+        if bytes.is_none() {
+            return Err(format!("Raster page of {}x{} pixels is too large", width, height));
+        }
+        Ok(RasterPage::blank(width, height, dpi, color))
+    }
+
+    pub fn bytes_per_line(&self) -> usize {
+        (self.width as usize * self.color.bits_per_pixel() as usize).div_ceil(8)
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let len = self.bytes_per_line();
+        &self.pixels[y as usize * len..(y as usize + 1) * len]
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let len = self.bytes_per_line();
+        &mut self.pixels[y as usize * len..(y as usize + 1) * len]
+    }
+
+    /// Page size in points, as PWG headers give it; zero for a page without a resolution.
+    fn size_points(&self) -> (u32, u32) {
+        if self.dpi == 0 {
+            return (0, 0);
+        }
+        let points = |pixels: u32| (u64::from(pixels) * 72 / u64::from(self.dpi)) as u32;
+        (points(self.width), points(self.height))
+    }
+
+    /// The page mirrored left to right and/or top to bottom.
+    fn transformed(&self, cross_feed: bool, feed: bool) -> RasterPage {
+        let mut out = RasterPage::blank(self.width, self.height, self.dpi, self.color);
+        for y in 0..self.height {
+            let source = self.row(if feed { self.height - 1 - y } else { y });
+            let target = out.row_mut(y);
+            if !cross_feed {
+                target.copy_from_slice(source);
+                continue;
+            }
+            for x in 0..self.width as usize {
+                let mirrored = self.width as usize - 1 - x;
+                match self.color {
+                    ColorMode::Black1 => {
+                        if source[mirrored / 8] & (0x80 >> (mirrored % 8)) != 0 {
+                            target[x / 8] |= 0x80 >> (x % 8);
+                        }
+                    }
+                    color => {
+                        let unit = color.unit();
+                        target[x * unit..(x + 1) * unit].copy_from_slice(&source[mirrored * unit..(mirrored + 1) * unit]);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Run-length encodes one line: a control byte of 0..=127 repeats the next unit that many
+/// times plus one, 129..=255 is followed by 257 minus that many literal units. URF can
+/// end a line early with a fill-with-white marker.
+fn compress_line(line: &[u8], unit: usize, white: Option<u8>, out: &mut Vec<u8>) {
+    let units: Vec<&[u8]> = line.chunks(unit).collect();
+    let content_end = match white {
+        Some(white) => units
+            .iter()
+            .rposition(|u| u.iter().any(|&b| b != white))
+            .map_or(0, |last| last + 1),
+        None => units.len(),
+    };
+    let mut i = 0;
+    while i < units.len() {
+        if i >= content_end {
+            out.push(URF_FILL_WHITE);
+            return;
+        }
+
+        let mut run = 1;
+        while i + run < units.len() && run < MAX_RUN && units[i + run] == units[i] {
+            run += 1;
+        }
+        if run > 1 || i + 1 == units.len() {
+            out.push((run - 1) as u8);
+            out.extend_from_slice(units[i]);
+            i += run;
+            continue;
+        }
+
+        // Literal block up to the next pair of equal units
+        let start = i;
+        while i < units.len() && i - start < MAX_RUN && (i + 1 == units.len() || units[i] != units[i + 1]) {
+            i += 1;
+        }
+        let count = i - start;
+        if count == 1 {
+            out.push(0);
+        } else {
+            out.push((257 - count) as u8);
+        }
+        for unit in &units[start..i] {
+            out.extend_from_slice(unit);
+        }
+    }
+}
+
+/// Encodes the lines of `page`, folding identical consecutive lines into a repeat count.
+fn compress_page(page: &RasterPage, urf: bool, out: &mut Vec<u8>) {
+    let white = urf.then(|| page.color.white());
+    let mut y = 0;
+    while y < page.height {
+        let line = page.row(y);
+        let mut repeat = 1;
+        while y + repeat < page.height && (repeat as usize) < MAX_LINE_REPEAT && page.row(y + repeat) == line {
+            repeat += 1;
+        }
+        out.push((repeat - 1) as u8);
+        compress_line(line, page.color.unit(), white, out);
+        y += repeat;
+    }
+}
+
+fn put_u32(header: &mut [u8], offset: usize, value: u32) {
+    header[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn put_str(header: &mut [u8], offset: usize, value: &str) {
+    let bytes = value.as_bytes();
+    let len = bytes.len().min(63);
+    header[offset..offset + len].copy_from_slice(&bytes[..len]);
+}
+
+fn get_u32(header: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]])
+}
+
+fn get_str(header: &[u8], offset: usize) -> String {
+    let field = &header[offset..offset + 64];
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).to_string()
+}
+
+/// Which raster stream a `RasterWriter` produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    Pwg,
+    Urf,
+}
+
+impl RasterFormat {
+    pub fn from_mime(format: &str) -> Option<RasterFormat> {
+        match format {
+            "image/pwg-raster" => Some(RasterFormat::Pwg),
+            "image/urf" => Some(RasterFormat::Urf),
+            _ => None,
+        }
+    }
+}
+
+/// Writes a multi-page raster stream one page at a time, so a long job never has to be
+/// held in memory as pixels.
+pub struct RasterWriter<W: Write> {
+    out: W,
+    format: RasterFormat,
+    options: RasterOptions,
+    total_pages: u32,
+    written: u32,
+}
+
+impl<W: Write> RasterWriter<W> {
+    /// Starts a stream of `total_pages` pages. URF needs the count up front; PWG records
+    /// it in every header.
+    pub fn new(mut out: W, format: RasterFormat, options: RasterOptions, total_pages: u32) -> Result<Self, String> {
+        let result = match format {
+            RasterFormat::Pwg => out.write_all(PWG_SYNC),
+            RasterFormat::Urf => out
+                .write_all(URF_MAGIC)
+                .and_then(|_| out.write_all(&total_pages.to_be_bytes())),
+        };
+        result.map_err(|e| format!("Failed to write raster stream: {}", e))?;
+        Ok(RasterWriter {
+            out,
+            format,
+            options,
+            total_pages,
+            written: 0,
+        })
+    }
+
+    pub fn write_page(&mut self, page: &RasterPage) -> Result<(), String> {
+        if self.written == self.total_pages {
+            return Err(format!("Raster stream was announced with {} pages", self.total_pages));
+        }
+        let back_side = self.options.sides.duplex() && self.written % 2 == 1;
+        let mut data = Vec::new();
+        match self.format {
+            RasterFormat::Pwg => {
+                let (cross_feed, feed) = if back_side {
+                    self.options.sheet_back.transforms(self.options.sides.tumble())
+                } else {
+                    (false, false)
+                };
+                data.extend_from_slice(&self.pwg_header(page, cross_feed, feed));
+                if cross_feed || feed {
+                    compress_page(&page.transformed(cross_feed, feed), false, &mut data);
+                } else {
+                    compress_page(page, false, &mut data);
+                }
+            }
+            RasterFormat::Urf => {
+                data.extend_from_slice(&self.urf_header(page)?);
+                compress_page(page, true, &mut data);
+            }
+        }
+        self.out
+            .write_all(&data)
+            .map_err(|e| format!("Failed to write raster page: {}", e))?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Returns the output once every announced page was written.
+    pub fn finish(mut self) -> Result<W, String> {
+        if self.written != self.total_pages {
+            return Err(format!(
+                "Raster stream has {} of {} pages",
+                self.written, self.total_pages
+            ));
+        }
+        self.out.flush().map_err(|e| format!("Failed to write raster stream: {}", e))?;
+        Ok(self.out)
+    }
+
+    fn pwg_header(&self, page: &RasterPage, cross_feed: bool, feed: bool) -> Vec<u8> {
+        let mut header = vec![0u8; PWG_HEADER_LEN];
+        let (width_pt, height_pt) = page.size_points();
+        // Transforms are 1 for as-is and -1 for mirrored
+        let transform = |mirrored: bool| if mirrored { u32::MAX } else { 1 };
+        put_str(&mut header, PWG_MEDIA_CLASS, "PwgRaster");
+        put_u32(&mut header, PWG_DUPLEX, self.options.sides.duplex() as u32);
+        put_u32(&mut header, PWG_RESOLUTION, page.dpi);
+        put_u32(&mut header, PWG_RESOLUTION + 4, page.dpi);
+        put_u32(&mut header, PWG_NUM_COPIES, 1);
+        put_u32(&mut header, PWG_PAGE_SIZE, width_pt);
+        put_u32(&mut header, PWG_PAGE_SIZE + 4, height_pt);
+        put_u32(&mut header, PWG_TUMBLE, self.options.sides.tumble() as u32);
+        put_u32(&mut header, PWG_WIDTH, page.width);
+        put_u32(&mut header, PWG_HEIGHT, page.height);
+        put_u32(&mut header, PWG_BITS_PER_COLOR, if page.color == ColorMode::Black1 { 1 } else { 8 });
+        put_u32(&mut header, PWG_BITS_PER_PIXEL, page.color.bits_per_pixel());
+        put_u32(&mut header, PWG_BYTES_PER_LINE, page.bytes_per_line() as u32);
+        put_u32(&mut header, PWG_COLOR_SPACE, page.color.pwg_color_space());
+        put_u32(&mut header, PWG_NUM_COLORS, page.color.colors());
+        put_u32(&mut header, PWG_TOTAL_PAGE_COUNT, self.total_pages);
+        put_u32(&mut header, PWG_CROSS_FEED_TRANSFORM, transform(cross_feed));
+        put_u32(&mut header, PWG_FEED_TRANSFORM, transform(feed));
+        if let Some(media) = &self.options.media {
+            put_str(&mut header, PWG_PAGE_SIZE_NAME, media);
+        }
+        header
+    }
+
+    fn urf_header(&self, page: &RasterPage) -> Result<[u8; URF_HEADER_LEN], String> {
+        let color_space = match page.color {
+            ColorMode::Gray8 => 0,
+            ColorMode::Rgb8 => 1,
+            ColorMode::Black1 => return Err("URF does not support 1-bit pages".to_string()),
+        };
+        let mut header = [0u8; URF_HEADER_LEN];
+        header[0] = page.color.bits_per_pixel() as u8;
+        header[1] = color_space;
+        header[2] = match self.options.sides {
+            Sides::OneSided => 1,
+            Sides::TwoSidedShortEdge => 2,
+            Sides::TwoSidedLongEdge => 3,
+        };
+        // Normal quality
+        header[3] = 4;
+        put_u32(&mut header, 12, page.width);
+        put_u32(&mut header, 16, page.height);
+        put_u32(&mut header, 20, page.dpi);
+        Ok(header)
+    }
+}
+
+/// A PWG Raster (`image/pwg-raster`, PWG 5102.4) stream of `pages`, the format every
+/// IPP Everywhere printer accepts.
+pub fn encode_pwg(pages: &[RasterPage], options: &RasterOptions) -> Result<Vec<u8>, String> {
+    encode(RasterFormat::Pwg, pages, options)
+}
+
+/// An Apple URF (`image/urf`) stream of `pages`, the AirPrint counterpart of PWG Raster.
+/// URF has no bilevel mode, so `Black1` pages are refused.
+pub fn encode_urf(pages: &[RasterPage], options: &RasterOptions) -> Result<Vec<u8>, String> {
+    encode(RasterFormat::Urf, pages, options)
+}
+
+pub fn encode(format: RasterFormat, pages: &[RasterPage], options: &RasterOptions) -> Result<Vec<u8>, String> {
+    let mut writer = RasterWriter::new(Vec::new(), format, options.clone(), pages.len() as u32)?;
+    for page in pages {
+        writer.write_page(page)?;
+    }
+    writer.finish()
+}
+
+/// A page read back from a raster stream, with the header fields that describe it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPage {
+    pub page: RasterPage,
+    pub sides: Sides,
+    /// PWG only: total pages, back side transforms and media name from the header.
+    pub total_pages: Option<u32>,
+    pub cross_feed_mirrored: bool,
+    pub feed_mirrored: bool,
+    pub media: Option<String>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.at.checked_add(len).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| format!("Raster stream is truncated at byte {}", self.at))?;
+        let bytes = &self.data[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+/// Reverses `compress_page`.
+fn decompress_page(reader: &mut Reader, page: &mut RasterPage, urf: bool) -> Result<(), String> {
+    let unit = page.color.unit();
+    let line_len = page.bytes_per_line();
+    let white = page.color.white();
+    let mut y = 0;
+    while y < page.height {
+        let repeat = reader.byte()? as u32 + 1;
+        let mut line = Vec::with_capacity(line_len);
+        while line.len() < line_len {
+            match reader.byte()? {
+                URF_FILL_WHITE if urf => line.resize(line_len, white),
+                control @ 0..=127 => {
+                    let pixel = reader.take(unit)?;
+                    for _ in 0..=control {
+                        line.extend_from_slice(pixel);
+                    }
+                }
+                control => line.extend_from_slice(reader.take((257 - control as usize) * unit)?),
+            }
+        }
+        if line.len() != line_len {
+            return Err(format!("Raster line {} overruns the page width", y));
+        }
+        for _ in 0..repeat.min(page.height - y) {
+            page.row_mut(y).copy_from_slice(&line);
+            y += 1;
+        }
+    }
+    Ok(())
+}
+
+fn decode_pwg(reader: &mut Reader) -> Result<Vec<DecodedPage>, String> {
+    let mut pages = Vec::new();
+    while reader.at < reader.data.len() {
+        let header = reader.take(PWG_HEADER_LEN)?;
+        let color = ColorMode::from_pwg(get_u32(header, PWG_COLOR_SPACE), get_u32(header, PWG_BITS_PER_PIXEL))
+            .ok_or("Unsupported PWG color space")?;
+        let mut page = RasterPage::checked_blank(
+            get_u32(header, PWG_WIDTH),
+            get_u32(header, PWG_HEIGHT),
+            get_u32(header, PWG_RESOLUTION),
+            color,
+        )?;
+        if page.bytes_per_line() as u32 != get_u32(header, PWG_BYTES_PER_LINE) {
+            return Err("PWG header has an inconsistent line length".to_string());
+        }
+        decompress_page(reader, &mut page, false)?;
+        let sides = match (get_u32(header, PWG_DUPLEX), get_u32(header, PWG_TUMBLE)) {
+            (0, _) => Sides::OneSided,
+            (_, 0) => Sides::TwoSidedLongEdge,
+            _ => Sides::TwoSidedShortEdge,
+        };
+        let media = get_str(header, PWG_PAGE_SIZE_NAME);
+        pages.push(DecodedPage {
+            page,
+            sides,
+            total_pages: Some(get_u32(header, PWG_TOTAL_PAGE_COUNT)),
+            cross_feed_mirrored: get_u32(header, PWG_CROSS_FEED_TRANSFORM) == u32::MAX,
+            feed_mirrored: get_u32(header, PWG_FEED_TRANSFORM) == u32::MAX,
+            media: (!media.is_empty()).then_some(media),
+        });
+    }
+    Ok(pages)
+}
+
+fn decode_urf(reader: &mut Reader) -> Result<Vec<DecodedPage>, String> {
+    let count = get_u32(reader.take(4)?, 0);
+    let mut pages = Vec::new();
+    for _ in 0..count {
+        let header = reader.take(URF_HEADER_LEN)?;
+        let color = match (header[0], header[1]) {
+            (8, 0) => ColorMode::Gray8,
+            (24, 1) => ColorMode::Rgb8,
+            (bits, space) => return Err(format!("Unsupported URF page ({} bits, color space {})", bits, space)),
+        };
+        let mut page = RasterPage::checked_blank(get_u32(header, 12), get_u32(header, 16), get_u32(header, 20), color)?;
+        decompress_page(reader, &mut page, true)?;
+        pages.push(DecodedPage {
+            page,
+            sides: match header[2] {
+                2 => Sides::TwoSidedShortEdge,
+                3 => Sides::TwoSidedLongEdge,
+                _ => Sides::OneSided,
+            },
+            total_pages: None,
+            cross_feed_mirrored: false,
+            feed_mirrored: false,
+            media: None,
+        });
+    }
+    Ok(pages)
+}
+
+/// Reads a PWG Raster or URF stream back into pages.
+pub fn decode(data: &[u8]) -> Result<Vec<DecodedPage>, String> {
+    let mut reader = Reader { data, at: 0 };
+    if data.starts_with(PWG_SYNC) {
+        reader.at = PWG_SYNC.len();
+        decode_pwg(&mut reader)
+    } else if data.starts_with(URF_MAGIC) {
+        reader.at = URF_MAGIC.len();
+        decode_urf(&mut reader)
+    } else {
+        Err("Not a PWG Raster or URF stream".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page whose bytes follow a pattern with both runs and literals.
+    fn pattern(width: u32, height: u32, color: ColorMode, seed: usize) -> RasterPage {
+        let mut page = RasterPage::blank(width, height, 300, color);
+        for (i, byte) in page.pixels.iter_mut().enumerate() {
+            // Every fourth stretch is a run of white
+            *byte = if (i / 16) % 4 == 0 { color.white() } else { ((i + seed) * 37 % 251) as u8 };
+        }
+        page
+    }
+
+    fn one_page(page: &RasterPage, format: RasterFormat) -> DecodedPage {
+        let data = encode(format, std::slice::from_ref(page), &RasterOptions::default()).unwrap();
+        let mut pages = decode(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        pages.remove(0)
+    }
+
+    #[test]
+    fn pages_round_trip_in_every_color_mode() {
+        for color in [ColorMode::Black1, ColorMode::Gray8, ColorMode::Rgb8] {
+            let page = pattern(37, 9, color, 0);
+            assert_eq!(one_page(&page, RasterFormat::Pwg).page, page, "{:?}", color);
+            if color == ColorMode::Black1 {
+                assert!(encode_urf(&[page], &RasterOptions::default()).is_err());
+            } else {
+                assert_eq!(one_page(&page, RasterFormat::Urf).page, page, "{:?}", color);
+            }
+        }
+    }
+
+    #[test]
+    fn multi_page_streams_keep_every_page() {
+        let pages: Vec<RasterPage> = (0..3).map(|seed| pattern(20, 5, ColorMode::Rgb8, seed)).collect();
+        let options = RasterOptions {
+            media: Some("iso_a4_210x297mm".to_string()),
+            ..Default::default()
+        };
+        for format in [RasterFormat::Pwg, RasterFormat::Urf] {
+            let decoded = decode(&encode(format, &pages, &options).unwrap()).unwrap();
+            let decoded_pages: Vec<RasterPage> = decoded.iter().map(|d| d.page.clone()).collect();
+            assert_eq!(decoded_pages, pages);
+            if format == RasterFormat::Pwg {
+                assert!(decoded.iter().all(|d| d.total_pages == Some(3)));
+                assert!(decoded.iter().all(|d| d.media.as_deref() == Some("iso_a4_210x297mm")));
+            }
+        }
+
+        let mut writer = RasterWriter::new(Vec::new(), RasterFormat::Pwg, RasterOptions::default(), 2).unwrap();
+        writer.write_page(&pages[0]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn urf_fill_marker_whitens_the_rest_of_the_line() {
+        // One gray page, 10x2 pixels: a black pixel, then fill, repeated for both lines
+        let mut data = URF_MAGIC.to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        let mut header = [0u8; URF_HEADER_LEN];
+        header[0] = 8;
+        put_u32(&mut header, 12, 10);
+        put_u32(&mut header, 16, 2);
+        put_u32(&mut header, 20, 300);
+        data.extend_from_slice(&header);
+        data.extend_from_slice(&[0x01, 0x00, 0x00, URF_FILL_WHITE]);
+
+        let page = decode(&data).unwrap().remove(0).page;
+        let mut line = vec![0xff; 10];
+        line[0] = 0x00;
+        assert_eq!(page.row(0), line.as_slice());
+        assert_eq!(page.row(1), line.as_slice());
+
+        // The encoder uses the marker too, and PWG never does
+        let urf = encode_urf(std::slice::from_ref(&page), &RasterOptions::default()).unwrap();
+        assert!(urf.ends_with(&[0x01, 0x00, 0x00, URF_FILL_WHITE]));
+        assert_eq!(one_page(&page, RasterFormat::Pwg).page, page);
+    }
+
+    #[test]
+    fn long_runs_of_equal_lines_are_split() {
+        let mut page = RasterPage::blank(8, 600, 300, ColorMode::Gray8);
+        page.row_mut(599)[3] = 0;
+        for format in [RasterFormat::Pwg, RasterFormat::Urf] {
+            assert_eq!(one_page(&page, format).page, page, "{:?}", format);
+        }
+
+        // 599 white lines need three repeat bytes: 256 + 256 + 87
+        let mut out = Vec::new();
+        compress_page(&page, false, &mut out);
+        let white_line = [7u8, 0xff];
+        let mut expected = Vec::new();
+        for repeat in [256usize, 256, 87] {
+            expected.push((repeat - 1) as u8);
+            expected.extend_from_slice(&white_line);
+        }
+        assert!(out.starts_with(&expected), "{:?}", out);
+    }
+
+    #[test]
+    fn back_sides_follow_sheet_back_and_tumble() {
+        // A dark top left pixel shows how the back side was turned
+        let mut page = RasterPage::blank(4, 3, 300, ColorMode::Gray8);
+        page.row_mut(0)[0] = 0;
+        let cases = [
+            (SheetBack::Normal, false, (false, false)),
+            (SheetBack::Normal, true, (false, false)),
+            (SheetBack::Flipped, false, (false, true)),
+            (SheetBack::Flipped, true, (true, false)),
+            (SheetBack::Rotated, false, (true, true)),
+            (SheetBack::Rotated, true, (false, false)),
+            (SheetBack::ManualTumble, false, (false, false)),
+            (SheetBack::ManualTumble, true, (true, true)),
+        ];
+        for (sheet_back, tumble, (cross_feed, feed)) in cases {
+            let sides = if tumble { Sides::TwoSidedShortEdge } else { Sides::TwoSidedLongEdge };
+            let options = RasterOptions {
+                sides,
+                sheet_back,
+                media: None,
+            };
+            let decoded = decode(&encode_pwg(&[page.clone(), page.clone()], &options).unwrap()).unwrap();
+            let case = format!("{:?} tumble={}", sheet_back, tumble);
+
+            let front = &decoded[0];
+            assert_eq!(front.sides, sides, "{}", case);
+            assert_eq!((front.cross_feed_mirrored, front.feed_mirrored), (false, false), "{}", case);
+            assert_eq!(front.page, page, "{}", case);
+
+            let back = &decoded[1];
+            assert_eq!((back.cross_feed_mirrored, back.feed_mirrored), (cross_feed, feed), "{}", case);
+            let x = if cross_feed { 3 } else { 0 };
+            let y = if feed { 2 } else { 0 };
+            assert_eq!(back.page.row(y)[x], 0, "{}", case);
+            assert_eq!(back.page.pixels.iter().filter(|&&p| p == 0).count(), 1, "{}", case);
+
+            // URF leaves turning the back side to the printer
+            let urf = decode(&encode_urf(&[page.clone(), page.clone()], &options).unwrap()).unwrap();
+            assert_eq!(urf[1].page, page, "{}", case);
+            assert_eq!(urf[1].sides, sides, "{}", case);
+        }
+    }
+
+    #[test]
+    fn oversized_and_empty_pages_are_refused() {
+        let stream = |width: u32, height: u32, dpi: u32| {
+            let mut header = vec![0u8; PWG_HEADER_LEN];
+            put_u32(&mut header, PWG_WIDTH, width);
+            put_u32(&mut header, PWG_HEIGHT, height);
+            put_u32(&mut header, PWG_RESOLUTION, dpi);
+            put_u32(&mut header, PWG_BITS_PER_PIXEL, 24);
+            put_u32(&mut header, PWG_COLOR_SPACE, 19);
+            put_u32(&mut header, PWG_BYTES_PER_LINE, width.wrapping_mul(3));
+            let mut data = PWG_SYNC.to_vec();
+            data.extend_from_slice(&header);
+            data
+        };
+        assert!(decode(&stream(u32::MAX, u32::MAX, 300)).unwrap_err().contains("too large"));
+        assert!(decode(&stream(100_000, 100_000, 300)).unwrap_err().contains("too large"));
+        assert!(decode(&stream(10, 10, 0)).unwrap_err().contains("empty"));
+        assert!(decode(&stream(0, 10, 300)).unwrap_err().contains("empty"));
+
+        // Encoding a page without a resolution writes a zero size instead of dividing by zero
+        let page = RasterPage::blank(4, 4, 0, ColorMode::Gray8);
+        assert_eq!(page.size_points(), (0, 0));
+        assert!(encode_pwg(&[page], &RasterOptions::default()).is_ok());
+    }
+}
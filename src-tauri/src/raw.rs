@@ -0,0 +1,101 @@
+use crate::addr::HostAddr;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+/// Port of the raw (AppSocket/JetDirect) protocol.
+pub const RAW_PORT: u16 = 9100;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn connect(addr: &HostAddr, port: u16) -> Result<TcpStream, String> {
+    let stream = TcpStream::connect_timeout(&addr.socket_addr(port), CONNECT_TIMEOUT)
+        .map_err(|e| format!("Cannot connect to {}:{}: {}", addr, port, e))?;
+    stream
+        .set_write_timeout(Some(WRITE_TIMEOUT))
+        .map_err(|e| format!("Socket setup failed: {}", e))?;
+    Ok(stream)
+}
+
+/// Sends `data` as-is to port 9100. The printer interprets whatever language it is
+/// in, so nothing is checked beyond the connection.
+pub fn send(addr: &HostAddr, port: u16, data: &[u8]) -> Result<(), String> {
+    let mut stream = connect(addr, port)?;
+    stream
+        .write_all(data)
+        .and_then(|_| stream.flush())
+        .map_err(|e| format!("Failed to send to {}:{}: {}", addr, port, e))?;
+    // Closing our side tells the printer the job is complete
+    let _ = stream.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+/// Sends `request` and reads the reply until the printer goes quiet for `timeout`, for
+/// status queries on the raw port.
+pub fn query(addr: &HostAddr, port: u16, request: &[u8], timeout: Duration) -> Result<Vec<u8>, String> {
+    query_until(addr, port, request, timeout, |_| false)
+}
+
+/// Like [`query`], but stops reading as soon as `complete` accepts the reply so far, for
+/// replies of known length.
+pub fn query_until(
+    addr: &HostAddr,
+    port: u16,
+    request: &[u8],
+    timeout: Duration,
+    complete: impl Fn(&[u8]) -> bool,
+) -> Result<Vec<u8>, String> {
+    let mut stream = connect(addr, port)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| format!("Socket setup failed: {}", e))?;
+    stream
+        .write_all(request)
+        .map_err(|e| format!("Failed to send to {}:{}: {}", addr, port, e))?;
+
+    let mut reply = Vec::new();
+    let mut buffer = [0u8; 1024];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                reply.extend_from_slice(&buffer[..n]);
+                if complete(&reply) {
+                    break;
+                }
+            }
+            Err(_) if !reply.is_empty() => break,
+            Err(e) => return Err(format!("No reply from {}:{}: {}", addr, port, e)),
+        }
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    #[test]
+    fn a_complete_reply_ends_the_query_early() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 3];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(b"ok!").unwrap();
+            // Keep the connection open like a printer would until the client hangs up
+            let _ = stream.read(&mut request);
+            request
+        });
+
+        let addr = HostAddr::parse("127.0.0.1").unwrap();
+        let started = Instant::now();
+        let reply = query_until(&addr, port, b"ask", Duration::from_secs(10), |reply| reply.len() >= 3).unwrap();
+        assert_eq!(reply, b"ok!");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(&server.join().unwrap(), b"ask");
+    }
+}
@@ -0,0 +1,326 @@
+use crate::addr::HostAddr;
+use crate::inventory::{Inventory, InventoryEntry};
+use crate::ipp_server::CapturedJob;
+use crate::pools::{self, JobRequirements};
+use crate::printing::{self, JobRecord};
+use crate::raster::Sides;
+use crate::vault::CredentialVault;
+use crate::{lpd, raw};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// How jobs reach a relay target. Only IPP targets report the formats they take and get
+/// converted documents; LPD and raw targets receive the captured bytes unchanged, so they
+/// must understand whatever format the client sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayProtocol {
+    #[default]
+    Ipp,
+    Lpd,
+    Raw,
+}
+
+/// A printer jobs are forwarded to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelayTarget {
+    /// A printer, or a pool whose members are tried best first.
+    pub printer_id: String,
+    #[serde(default)]
+    pub protocol: RelayProtocol,
+    /// LPD queue name; printers usually accept any, `lp` is the common default.
+    #[serde(default)]
+    pub queue: Option<String>,
+}
+
+/// Forwarding of jobs received by the virtual printer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelaySettings {
+    pub enabled: bool,
+    /// Tried in order: the first is the primary, the rest take over when it is offline.
+    pub targets: Vec<RelayTarget>,
+    /// Convert documents an IPP target does not take, e.g. text to PDF or images to raster.
+    /// Has no effect on LPD and raw targets.
+    pub convert: bool,
+    /// Delete a job's documents from the capture folder once they were forwarded.
+    pub delete_forwarded: bool,
+}
+
+impl RelaySettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.targets.is_empty() {
+            return Err("Relay needs at least one target printer".to_string());
+        }
+        for target in &self.targets {
+            crate::validate::id(&target.printer_id)?;
+            if let Some(queue) = &target.queue {
+                crate::validate::text("LPD queue", queue)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies requested in the captured job's `copies` attribute, capped like `print_file`.
+fn copies(job: &CapturedJob) -> u32 {
+    job.attributes
+        .get("copies")
+        .and_then(|values| values.first())
+        .and_then(|value| value.parse().ok())
+        .filter(|&copies| copies >= 1)
+        .map_or(1, |copies: u32| copies.min(printing::MAX_COPIES))
+}
+
+/// Duplex mode requested in the captured job's `sides` attribute.
+fn sides(job: &CapturedJob) -> Sides {
+    job.attributes
+        .get("sides")
+        .and_then(|values| values.first())
+        .and_then(|value| Sides::parse(value))
+        .unwrap_or_default()
+}
+
+/// Sends `copies` copies one at a time, stopping at the first failure. Returns how many
+/// went through alongside the outcome.
+fn send_copies(copies: u32, mut send: impl FnMut() -> Result<(), String>) -> (u32, Result<(), String>) {
+    for printed in 0..copies {
+        if let Err(e) = send() {
+            return (printed, Err(e));
+        }
+    }
+    (copies, Ok(()))
+}
+
+/// Sends one document to `target`. Alongside the record, returns how many copies a raw
+/// target printed before failing; IPP and LPD take the copy count as part of the job.
+#[allow(clippy::too_many_arguments)]
+fn send_document(
+    vault: &CredentialVault,
+    target: &RelayTarget,
+    entry: &InventoryEntry,
+    job: &CapturedJob,
+    document: &str,
+    format: &str,
+    data: Vec<u8>,
+    convert: bool,
+) -> (JobRecord, u32) {
+    let copies = copies(job);
+    if target.protocol == RelayProtocol::Ipp {
+        return (printing::submit(vault, entry, &job.user, document, format, copies, sides(job), convert, data), 0);
+    }
+
+    let mut printed = 0;
+    let mut record = JobRecord::new(entry, &job.user, document, format, copies, data.len());
+    let result = entry
+        .address()
+        .and_then(HostAddr::parse)
+        .ok_or_else(|| format!("{} has no network address", entry.display_name()))
+        .and_then(|addr| match target.protocol {
+            RelayProtocol::Lpd => {
+                let queue = target.queue.as_deref().unwrap_or("lp");
+                record.uri = Some(format!("lpd://{}/{}", addr.url_host(), queue));
+                lpd::send(&addr, queue, &job.user, &job.name, copies, &data)
+            }
+            _ => {
+                record.uri = Some(format!("socket://{}:{}", addr.url_host(), raw::RAW_PORT));
+                let (sent, result) = send_copies(copies, || raw::send(&addr, raw::RAW_PORT, &data));
+                printed = sent;
+                result.map_err(|e| if sent > 0 { format!("{} after printing {} of {} copies", e, sent, copies) } else { e })
+            }
+        });
+    match result {
+        Ok(()) => info!(printer = %entry.id, user = %job.user, document, "relayed print job"),
+        Err(e) => {
+            warn!(printer = %entry.id, user = %job.user, document, "relay failed: {}", e);
+            record.error = Some(e);
+        }
+    }
+    (record, printed)
+}
+
+/// Forwards every document of `job` to the first target that takes them all, moving on
+/// to the next target when one fails before taking any document. Once a target has
+/// printed part of the job, a document or some copies of one, it is not retried
+/// elsewhere, as that would print those pages twice. Returns the printer that printed the job and one history record per
+/// attempt.
+pub fn forward_job(
+    settings: &RelaySettings,
+    inventory: &Inventory,
+    vault: &CredentialVault,
+    job: &CapturedJob,
+    folder: &Path,
+) -> (Result<String, String>, Vec<JobRecord>) {
+    let mut records = Vec::new();
+    let mut last_error = "No relay target is configured".to_string();
+
+    let requirements = JobRequirements {
+        format: job.documents.first().map(|d| d.format.clone()),
+        duplex: sides(job).duplex(),
+        ..Default::default()
+    };
+    let mut printers = Vec::new();
+    for target in &settings.targets {
+        match pools::resolve_targets(inventory, &target.printer_id, &requirements) {
+            Ok(entries) => printers.extend(entries.into_iter().map(|entry| (target, entry))),
+            Err(e) => {
+                last_error = format!("Relay target '{}': {}", target.printer_id, e);
+                warn!("{}", last_error);
+            }
+        }
+    }
+
+    for (target, entry) in &printers {
+        let mut failed = None;
+        let mut accepted = 0;
+        for document in &job.documents {
+            let name = document.name.clone().unwrap_or_else(|| job.name.clone());
+            let data = match std::fs::read(folder.join(&document.file)) {
+                Ok(data) => data,
+                Err(e) => return (Err(format!("Failed to read {}: {}", document.file, e)), records),
+            };
+            let (record, printed) = send_document(vault, target, entry, job, &name, &document.format, data, settings.convert);
+            let error = record.error.clone();
+            records.push(record);
+            if let Some(error) = error {
+                failed = Some((error, printed > 0));
+                break;
+            }
+            accepted += 1;
+        }
+
+        match failed {
+            None => return (Ok(entry.display_name().to_string()), records),
+            Some((error, partial)) if accepted > 0 || partial => {
+                let error = if accepted > 0 {
+                    format!("{} after printing {} of {} documents", error, accepted, job.documents.len())
+                } else {
+                    error
+                };
+                let error = format!("{}: {}; not retried on another printer", entry.display_name(), error);
+                return (Err(error), records);
+            }
+            Some((error, _)) => {
+                last_error = format!("{}: {}", entry.display_name(), error);
+                if printers.len() > 1 {
+                    info!(job_id = job.job_id, "relay target {} failed, trying the next one", entry.display_name());
+                }
+            }
+        }
+    }
+
+    (Err(last_error), records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::NetworkPrinter;
+    use crate::ipp_server::{CapturedDocument, CapturedJobState};
+    use chrono::Utc;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn job(copies: &str) -> CapturedJob {
+        CapturedJob {
+            job_id: 7,
+            name: "report".to_string(),
+            user: "alice".to_string(),
+            state: CapturedJobState::Processing,
+            created: Utc::now(),
+            completed: None,
+            client: None,
+            documents: vec![CapturedDocument {
+                file: "job-7-1.prn".to_string(),
+                format: "application/vnd.hp-pcl".to_string(),
+                name: None,
+                bytes: 5,
+            }],
+            attributes: BTreeMap::from([("copies".to_string(), vec![copies.to_string()])]),
+            forwarded_to: None,
+            error: None,
+        }
+    }
+
+    fn entry(id: &str, ip: &str) -> InventoryEntry {
+        InventoryEntry {
+            id: id.to_string(),
+            printer: NetworkPrinter { name: id.to_string(), ip_address: ip.to_string(), ..Default::default() },
+            location: None,
+            tags: Vec::new(),
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+            counters: Vec::new(),
+            supplies: Vec::new(),
+            sources: Vec::new(),
+            pjl_counters: false,
+        }
+    }
+
+    fn raw_target(printer_id: &str) -> RelayTarget {
+        RelayTarget { printer_id: printer_id.to_string(), protocol: RelayProtocol::Raw, queue: None }
+    }
+
+    #[test]
+    fn copies_are_capped() {
+        assert_eq!(copies(&job("3")), 3);
+        assert_eq!(copies(&job("0")), 1);
+        assert_eq!(copies(&job("many")), 1);
+        assert_eq!(copies(&job("100000")), printing::MAX_COPIES);
+    }
+
+    #[test]
+    fn copies_stop_at_the_first_failure() {
+        let mut sent = 0;
+        let (printed, result) = send_copies(5, || {
+            sent += 1;
+            if sent == 3 { Err("connection reset".to_string()) } else { Ok(()) }
+        });
+        assert_eq!(printed, 2);
+        assert_eq!(result.unwrap_err(), "connection reset");
+        assert_eq!(sent, 3);
+        assert_eq!(send_copies(2, || Ok(())), (2, Ok(())));
+    }
+
+    #[test]
+    fn an_unreachable_target_fails_over_to_the_next() {
+        let dir = std::env::temp_dir().join(format!("relay-failover-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("job-7-1.prn"), b"hello").unwrap();
+
+        // Nothing listens on the first address, so its connection is refused
+        let listener = TcpListener::bind(("127.0.44.3", raw::RAW_PORT)).unwrap();
+        let server = std::thread::spawn(move || {
+            (0..2)
+                .map(|_| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut data = Vec::new();
+                    stream.read_to_end(&mut data).unwrap();
+                    data
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let inventory = Inventory {
+            printers: vec![entry("primary", "127.0.44.2"), entry("backup", "127.0.44.3")],
+            ..Default::default()
+        };
+        let settings = RelaySettings {
+            enabled: true,
+            targets: vec![raw_target("primary"), raw_target("backup")],
+            ..Default::default()
+        };
+        let vault = CredentialVault::open(dir.join("vault.json")).unwrap();
+        let (result, records) = forward_job(&settings, &inventory, &vault, &job("2"), &dir);
+
+        assert_eq!(result.unwrap(), "backup");
+        assert_eq!(records.len(), 2);
+        assert!(records[0].error.is_some());
+        assert_eq!(records[1].uri.as_deref(), Some("socket://127.0.44.3:9100"));
+        assert_eq!(server.join().unwrap(), vec![b"hello".to_vec(), b"hello".to_vec()]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,503 @@
+use crate::addr::{parse_ipv4_range, MAX_RANGE_HOSTS};
+use crate::alerts::AlertEngine;
+use crate::inventory::{printer_id, Inventory, InventoryStore};
+use crate::printer::{discover_network_printers, discover_network_printers_in, NetworkPrinter};
+use crate::settings;
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tracing::{info, warn};
+
+/// How often the scheduler checks whether a scan is due.
+const TICK: Duration = Duration::from_secs(30);
+
+/// A recurring discovery run, stored in the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    /// Five-field cron expression in local time, e.g. `0 2 * * *` for 02:00 nightly.
+    pub cron: String,
+    /// IPv4 ranges to scan (see `parse_ipv4_range`); empty scans the local network.
+    #[serde(default)]
+    pub ranges: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    New,
+    Disappeared,
+    IpChanged,
+    ModelChanged,
+    FirmwareChanged,
+}
+
+/// One difference between a scan and the inventory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub printer_id: String,
+    pub name: String,
+    pub ip_address: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeReport {
+    pub id: String,
+    pub schedule_id: Option<String>,
+    pub schedule_name: Option<String>,
+    pub ranges: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Printers found by the scan.
+    pub found: usize,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportSummary {
+    pub id: String,
+    pub schedule_name: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub found: usize,
+    pub changes: usize,
+}
+
+/// Next time `cron` fires after `after`.
+pub fn next_occurrence(cron: &str, after: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    Cron::new(cron)
+        .parse()
+        .map_err(|e| format!("Invalid cron expression '{}': {}", cron, e))?
+        .find_next_occurrence(&after, false)
+        .map_err(|e| format!("No next run for '{}': {}", cron, e))
+}
+
+fn expand_ranges(ranges: &[String]) -> Result<Vec<Ipv4Addr>, String> {
+    let mut seen = HashSet::new();
+    let mut addresses = Vec::new();
+    for range in ranges {
+        for ip in parse_ipv4_range(range)? {
+            if seen.insert(ip) {
+                addresses.push(ip);
+            }
+        }
+        if addresses.len() as u32 > MAX_RANGE_HOSTS {
+            return Err(format!("Ranges cover more than {} addresses", MAX_RANGE_HOSTS));
+        }
+    }
+    Ok(addresses)
+}
+
+fn changed(old: &Option<String>, new: &Option<String>) -> bool {
+    matches!((old, new), (Some(old), Some(new)) if old != new)
+}
+
+/// Addresses a scan of the stored `ranges` looked at. A local network scan has no stored
+/// range, so it covers the /24 networks (the subnet size the local scan assumes) in which
+/// it found printers.
+fn scanned_addresses(ranges: &[String], found: &[NetworkPrinter]) -> Result<HashSet<Ipv4Addr>, String> {
+    if !ranges.is_empty() {
+        return Ok(expand_ranges(ranges)?.into_iter().collect());
+    }
+    let networks: HashSet<[u8; 3]> = found
+        .iter()
+        .filter_map(|printer| printer.ip_address.parse::<Ipv4Addr>().ok())
+        .map(|ip| {
+            let [a, b, c, _] = ip.octets();
+            [a, b, c]
+        })
+        .collect();
+    Ok(networks.into_iter().flat_map(|[a, b, c]| (1..255).map(move |d| Ipv4Addr::new(a, b, c, d))).collect())
+}
+
+/// Compares a scan of the schedule's stored `ranges` with the inventory as it was before the
+/// scan. Only printers inside the scanned ranges can be reported as gone; IPv6, imported and
+/// other-range printers were never looked for.
+pub fn diff(before: &Inventory, found: &[NetworkPrinter], ranges: &[String]) -> Result<Vec<Change>, String> {
+    let scanned = scanned_addresses(ranges, found)?;
+    let mut changes = Vec::new();
+    let mut seen = HashSet::new();
+
+    for printer in found {
+        let known = before.find_printer(printer);
+        let id = known.map_or_else(|| printer_id(printer), |entry| entry.id.clone());
+        let change = |kind, old: Option<String>, new: Option<String>| Change {
+            kind,
+            printer_id: id.clone(),
+            name: printer.name.clone(),
+            ip_address: printer.ip_address.clone(),
+            old,
+            new,
+        };
+
+        match known {
+            None => changes.push(change(ChangeKind::New, None, None)),
+            Some(entry) => {
+                let known = &entry.printer;
+                if known.ip_address != printer.ip_address {
+                    changes.push(change(
+                        ChangeKind::IpChanged,
+                        Some(known.ip_address.clone()),
+                        Some(printer.ip_address.clone()),
+                    ));
+                }
+                if changed(&known.model, &printer.model) {
+                    changes.push(change(ChangeKind::ModelChanged, known.model.clone(), printer.model.clone()));
+                }
+                if changed(&known.firmware, &printer.firmware) {
+                    changes.push(change(ChangeKind::FirmwareChanged, known.firmware.clone(), printer.firmware.clone()));
+                }
+            }
+        }
+        seen.insert(id);
+    }
+
+    for entry in &before.printers {
+        let Some(address) = entry.address() else { continue };
+        let in_scope = address.parse::<Ipv4Addr>().is_ok_and(|ip| scanned.contains(&ip));
+        if in_scope && !seen.contains(&entry.id) {
+            changes.push(Change {
+                kind: ChangeKind::Disappeared,
+                printer_id: entry.id.clone(),
+                name: entry.display_name().to_string(),
+                ip_address: address.to_string(),
+                old: None,
+                new: None,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Runs discoveries and keeps the change reports they produce. Managed as Tauri state;
+/// the schedules themselves are part of the settings.
+pub struct Scheduler {
+    reports_dir: PathBuf,
+    /// Held for the duration of a scan so runs never overlap.
+    running: Mutex<()>,
+}
+
+impl Scheduler {
+    pub fn new(reports_dir: PathBuf) -> Scheduler {
+        Scheduler {
+            reports_dir,
+            running: Mutex::new(()),
+        }
+    }
+
+    pub fn schedules(&self) -> Vec<Schedule> {
+        settings::current().schedules.clone()
+    }
+
+    pub fn set_schedules(&self, app: &AppHandle, schedules: Vec<Schedule>) -> Result<(), String> {
+        settings::update(Some(app), |settings| settings.schedules = schedules).map(|_| ())
+    }
+
+    /// Scans, diffs against the inventory, updates the inventory and saves the report.
+    pub fn run_discovery(&self, app: &AppHandle, schedule: Option<&Schedule>, ranges: &[String]) -> Result<ChangeReport, String> {
+        let _running = self.running.lock().unwrap();
+        let started_at = Utc::now();
+
+        let addresses = expand_ranges(ranges)?;
+        let found = if addresses.is_empty() {
+            discover_network_printers()?
+        } else {
+            discover_network_printers_in(Some(&addresses))?
+        };
+
+        let store = app.state::<InventoryStore>();
+        let changes = diff(&store.snapshot(), &found, ranges)?;
+        let added = store.record_discovery(&found)?;
+        if !added.is_empty() {
+            if let Some(engine) = app.try_state::<AlertEngine>() {
+                engine.on_new_printers(app, &store.snapshot(), &added);
+            }
+            crate::tray::refresh(app);
+        }
+
+        let report = ChangeReport {
+            // Milliseconds keep ids of back-to-back runs apart
+            id: started_at.format("%Y%m%dT%H%M%S%3fZ").to_string(),
+            schedule_id: schedule.map(|s| s.id.clone()),
+            schedule_name: schedule.map(|s| s.name.clone()),
+            ranges: ranges.to_vec(),
+            started_at,
+            finished_at: Utc::now(),
+            found: found.len(),
+            changes,
+        };
+        self.save_report(&report)?;
+        if let Err(e) = app.emit("change-report", &report) {
+            warn!("failed to emit change report: {}", e);
+        }
+        Ok(report)
+    }
+
+    fn save_report(&self, report: &ChangeReport) -> Result<(), String> {
+        std::fs::create_dir_all(&self.reports_dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.reports_dir.display(), e))?;
+        let path = self.reports_dir.join(format!("{}.json", report.id));
+        let json = serde_json::to_string_pretty(report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn load_report(&self, id: &str) -> Result<ChangeReport, String> {
+        // Ids are timestamps; refuse anything that could step outside the reports directory
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("Invalid report id '{}'", id));
+        }
+        let path = self.reports_dir.join(format!("{}.json", id));
+        let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid report {}: {}", path.display(), e))
+    }
+
+    /// Every saved report, newest first.
+    pub fn list_reports(&self) -> Vec<ReportSummary> {
+        let Ok(entries) = std::fs::read_dir(&self.reports_dir) else { return Vec::new() };
+        let mut reports: Vec<ReportSummary> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+            .filter_map(|json| serde_json::from_str::<ChangeReport>(&json).ok())
+            .map(|report| ReportSummary {
+                id: report.id,
+                schedule_name: report.schedule_name,
+                started_at: report.started_at,
+                found: report.found,
+                changes: report.changes.len(),
+            })
+            .collect();
+        reports.sort_by_key(|report| std::cmp::Reverse(report.started_at));
+        reports
+    }
+}
+
+/// Writes a report as CSV (one line per change) or as JSON.
+pub fn export_report(report: &ChangeReport, format: &str, path: &str) -> Result<(), String> {
+    match format.to_ascii_lowercase().as_str() {
+        "csv" => {
+            let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            for change in &report.changes {
+                writer.serialize(change).map_err(|e| format!("Failed to write CSV: {}", e))?;
+            }
+            writer.flush().map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        "json" => {
+            let json = serde_json::to_string_pretty(report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+            std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+        other => Err(format!("Unsupported export format '{}' (expected csv or json)", other)),
+    }
+}
+
+/// Starts the scheduler thread. Each enabled schedule runs at its next cron occurrence;
+/// editing a schedule's expression recomputes its next run.
+pub fn start(app: AppHandle) {
+    thread::spawn(move || {
+        let mut next_runs: HashMap<(String, String), DateTime<Local>> = HashMap::new();
+        loop {
+            let scheduler = app.state::<Scheduler>();
+            let schedules: Vec<Schedule> = scheduler.schedules().into_iter().filter(|s| s.enabled).collect();
+            next_runs.retain(|(id, cron), _| schedules.iter().any(|s| &s.id == id && &s.cron == cron));
+
+            for schedule in &schedules {
+                let key = (schedule.id.clone(), schedule.cron.clone());
+                let next = match next_runs.get(&key) {
+                    Some(next) => *next,
+                    None => match next_occurrence(&schedule.cron, Local::now()) {
+                        Ok(next) => *next_runs.entry(key.clone()).or_insert(next),
+                        Err(e) => {
+                            warn!(schedule = %schedule.name, "{}", e);
+                            continue;
+                        }
+                    },
+                };
+                if next > Local::now() {
+                    continue;
+                }
+
+                info!(schedule = %schedule.name, "running scheduled discovery");
+                if let Err(e) = scheduler.run_discovery(&app, Some(schedule), &schedule.ranges) {
+                    warn!(schedule = %schedule.name, "scheduled discovery failed: {}", e);
+                }
+                match next_occurrence(&schedule.cron, Local::now()) {
+                    Ok(next) => {
+                        next_runs.insert(key, next);
+                    }
+                    Err(_) => {
+                        next_runs.remove(&key);
+                    }
+                }
+            }
+            thread::sleep(TICK);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_schedules(scheduler: State<'_, Scheduler>) -> Vec<Schedule> {
+    scheduler.schedules()
+}
+
+#[tauri::command]
+pub fn save_schedules(app: AppHandle, scheduler: State<'_, Scheduler>, schedules: Vec<Schedule>) -> Result<(), String> {
+    scheduler.set_schedules(&app, schedules)
+}
+
+/// Runs a discovery with change detection right away.
+#[tauri::command]
+pub fn run_discovery_now(app: AppHandle, scheduler: State<'_, Scheduler>, ranges: Option<Vec<String>>) -> Result<ChangeReport, String> {
+    scheduler.run_discovery(&app, None, &ranges.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_change_reports(scheduler: State<'_, Scheduler>) -> Vec<ReportSummary> {
+    scheduler.list_reports()
+}
+
+#[tauri::command]
+pub fn get_change_report(scheduler: State<'_, Scheduler>, id: String) -> Result<ChangeReport, String> {
+    scheduler.load_report(&id)
+}
+
+#[tauri::command]
+pub fn export_change_report(scheduler: State<'_, Scheduler>, id: String, format: String, path: String) -> Result<(), String> {
+    let path = crate::validate::export_file(&path, &format)?;
+    export_report(&scheduler.load_report(&id)?, &format, &path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::InventoryEntry;
+
+    fn printer(ip: &str, mac: Option<&str>, model: Option<&str>, firmware: Option<&str>) -> NetworkPrinter {
+        NetworkPrinter {
+            name: format!("Printer {}", ip),
+            ip_address: ip.to_string(),
+            port: 9100,
+            model: model.map(str::to_string),
+            firmware: firmware.map(str::to_string),
+            mac_address: mac.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn inventory(printers: Vec<NetworkPrinter>) -> Inventory {
+        let printers = printers
+            .into_iter()
+            .map(|printer| InventoryEntry {
+                id: printer_id(&printer),
+                printer,
+                location: None,
+                tags: Vec::new(),
+                first_seen: Utc::now(),
+                last_seen: Utc::now(),
+                counters: Vec::new(),
+                supplies: Vec::new(),
+                sources: Vec::new(),
+                pjl_counters: false,
+            })
+            .collect();
+        Inventory { printers, ..Default::default() }
+    }
+
+    fn ranges(list: &[&str]) -> Vec<String> {
+        list.iter().map(|range| range.to_string()).collect()
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, &str, Option<&str>, Option<&str>)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.printer_id.as_str(), c.old.as_deref(), c.new.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn scans_report_new_moved_and_updated_printers() {
+        let before = inventory(vec![
+            printer("10.0.0.5", Some("00:11:22:33:44:55"), Some("LaserJet M404"), Some("2.1")),
+            printer("10.0.0.6", Some("00:11:22:33:44:66"), Some("Color 3000"), Some("1.0")),
+            printer("10.0.0.7", None, None, None),
+        ]);
+        let found = vec![
+            printer("10.0.0.15", Some("00:11:22:33:44:55"), Some("LaserJet M404"), Some("2.4")),
+            // An unknown model or firmware is not a change
+            printer("10.0.0.6", Some("00:11:22:33:44:66"), None, None),
+            // Learning the MAC of a printer known by its address keeps its id
+            printer("10.0.0.7", Some("00:11:22:33:44:77"), Some("TM-T88"), None),
+            printer("10.0.0.8", Some("00:11:22:33:44:88"), None, None),
+        ];
+        let changes = diff(&before, &found, &ranges(&["10.0.0.0/24"])).unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (ChangeKind::IpChanged, "mac:00:11:22:33:44:55", Some("10.0.0.5"), Some("10.0.0.15")),
+                (ChangeKind::FirmwareChanged, "mac:00:11:22:33:44:55", Some("2.1"), Some("2.4")),
+                (ChangeKind::New, "mac:00:11:22:33:44:88", None, None),
+            ]
+        );
+        assert_eq!(changes[2].ip_address, "10.0.0.8");
+
+        let found = vec![printer("10.0.0.6", Some("00:11:22:33:44:66"), Some("Color 3500"), Some("1.0"))];
+        let changes = diff(&before, &found, &ranges(&["10.0.0.6"])).unwrap();
+        assert_eq!(summary(&changes), vec![(ChangeKind::ModelChanged, "mac:00:11:22:33:44:66", Some("Color 3000"), Some("Color 3500"))]);
+    }
+
+    #[test]
+    fn only_printers_in_the_stored_ranges_disappear() {
+        let before = inventory(vec![
+            printer("10.0.0.5", Some("00:11:22:33:44:55"), None, None),
+            printer("10.0.0.6", Some("00:11:22:33:44:66"), None, None),
+            printer("10.0.1.5", Some("00:11:22:33:44:77"), None, None),
+            printer("fe80::1%2", Some("00:11:22:33:44:88"), None, None),
+            printer("local", None, None, None),
+        ]);
+        let found = vec![printer("10.0.0.5", Some("00:11:22:33:44:55"), None, None)];
+
+        let changes = diff(&before, &found, &ranges(&["10.0.0.0/24"])).unwrap();
+        assert_eq!(summary(&changes), vec![(ChangeKind::Disappeared, "mac:00:11:22:33:44:66", None, None)]);
+        assert_eq!(changes[0].ip_address, "10.0.0.6");
+        assert_eq!(changes[0].name, "Printer 10.0.0.6");
+
+        let changes = diff(&before, &found, &ranges(&["10.0.0.5", "10.0.1.0/24"])).unwrap();
+        assert_eq!(summary(&changes), vec![(ChangeKind::Disappeared, "mac:00:11:22:33:44:77", None, None)]);
+    }
+
+    #[test]
+    fn local_scans_cover_the_networks_they_found_printers_in() {
+        let before = inventory(vec![
+            printer("192.168.1.20", Some("00:11:22:33:44:55"), None, None),
+            printer("192.168.1.21", Some("00:11:22:33:44:66"), None, None),
+            printer("192.168.2.20", Some("00:11:22:33:44:77"), None, None),
+        ]);
+        let found = vec![printer("192.168.1.20", Some("00:11:22:33:44:55"), None, None)];
+        let changes = diff(&before, &found, &[]).unwrap();
+        assert_eq!(summary(&changes), vec![(ChangeKind::Disappeared, "mac:00:11:22:33:44:66", None, None)]);
+
+        // Nothing found, nothing known to have been looked at
+        assert!(diff(&before, &[], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_stored_ranges_are_refused() {
+        assert!(diff(&Inventory::default(), &[], &ranges(&["10.0.0.0/33"])).is_err());
+        assert!(diff(&Inventory::default(), &[], &ranges(&["10.0.0.0/8"])).is_err());
+    }
+}
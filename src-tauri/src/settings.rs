@@ -0,0 +1,595 @@
+use crate::relay::RelaySettings;
+use crate::schedule::{next_occurrence, Schedule};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+
+/// Version written by this build. Older files are migrated step by step on load.
+pub const SETTINGS_VERSION: u32 = 1;
+pub const SETTINGS_FILE: &str = "settings.json";
+/// Where discovery schedules lived before settings version 1.
+pub const LEGACY_SCHEDULES_FILE: &str = "schedules.json";
+
+/// Everything the user can tune. Missing fields take their defaults, so a partial file or
+/// one written by an older build still loads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub discovery: DiscoverySettings,
+    pub monitor: MonitorSettings,
+    pub logging: LoggingSettings,
+    pub virtual_printer: VirtualPrinterSettings,
+    pub printing: PrintingSettings,
+    pub schedules: Vec<Schedule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoverySettings {
+    /// Ports probed by the port scan (raw/JetDirect, LPD, IPP).
+    pub ports: Vec<u16>,
+    pub connect_timeout_ms: u64,
+    /// Hosts scanned concurrently.
+    pub chunk_size: usize,
+    pub snmp_community: String,
+    pub snmp_timeout_ms: u64,
+    /// Timeout for embedded web server fingerprinting.
+    pub http_timeout_ms: u64,
+    /// Send a UDP datagram to every address of the local subnet before reading the
+    /// neighbour table, so hosts that have not talked to this machine yet show up too.
+    pub prime_neighbors: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSettings {
+    /// How often printer status is checked and alert rules evaluated.
+    pub status_interval_secs: u64,
+    /// How often counters and supply levels are stored.
+    pub record_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    /// One of `trace`, `debug`, `info`, `warn`, `error`.
+    pub level: String,
+}
+
+/// The app's own IPP printer, which saves every job it receives to a folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualPrinterSettings {
+    pub enabled: bool,
+    /// Shown to clients as `printer-name` and used as the DNS-SD instance name.
+    pub name: String,
+    pub port: u16,
+    /// Accept jobs from other machines; otherwise only from this one.
+    pub listen_on_network: bool,
+    /// Announce the printer as `_ipp._tcp` so clients find it without a URL. Only takes
+    /// effect with `listen_on_network`.
+    pub advertise: bool,
+    /// Where jobs are saved. Defaults to `captured_jobs` in the app data folder.
+    pub folder: Option<String>,
+    /// Forward received jobs to real printers instead of only keeping them.
+    pub relay: RelaySettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintingSettings {
+    /// Printer or pool id used when a print request does not name one.
+    pub default_target: Option<String>,
+    /// Font text documents are set in when converted to PDF, one of `text_pdf::FONTS`.
+    pub text_font: String,
+    /// Text size in points.
+    pub text_size: f32,
+    /// Margin around converted text and images.
+    pub margin_mm: f32,
+    /// PWG media name of the paper to lay out for when the printer does not report its
+    /// `media-default`.
+    pub paper: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            discovery: DiscoverySettings::default(),
+            monitor: MonitorSettings::default(),
+            logging: LoggingSettings::default(),
+            virtual_printer: VirtualPrinterSettings::default(),
+            printing: PrintingSettings::default(),
+            schedules: Vec::new(),
+        }
+    }
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        DiscoverySettings {
+            ports: vec![9100, 515, 631],
+            connect_timeout_ms: 100,
+            chunk_size: 10,
+            snmp_community: "public".to_string(),
+            snmp_timeout_ms: 1000,
+            http_timeout_ms: 2000,
+            prime_neighbors: true,
+        }
+    }
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        MonitorSettings {
+            status_interval_secs: 60,
+            record_interval_secs: 60 * 60,
+        }
+    }
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        LoggingSettings {
+            level: "info".to_string(),
+        }
+    }
+}
+
+impl Default for VirtualPrinterSettings {
+    fn default() -> Self {
+        VirtualPrinterSettings {
+            enabled: false,
+            name: "Printer Manager Capture".to_string(),
+            port: 8631,
+            listen_on_network: false,
+            advertise: false,
+            folder: None,
+            relay: RelaySettings::default(),
+        }
+    }
+}
+
+impl Default for PrintingSettings {
+    fn default() -> Self {
+        PrintingSettings {
+            default_target: None,
+            text_font: "Courier".to_string(),
+            text_size: 10.0,
+            margin_mm: 12.7,
+            paper: "iso_a4_210x297mm".to_string(),
+        }
+    }
+}
+
+impl DiscoverySettings {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn snmp_timeout(&self) -> Duration {
+        Duration::from_millis(self.snmp_timeout_ms)
+    }
+
+    pub fn http_timeout(&self) -> Duration {
+        Duration::from_millis(self.http_timeout_ms)
+    }
+}
+
+impl MonitorSettings {
+    pub fn status_interval(&self) -> Duration {
+        Duration::from_secs(self.status_interval_secs)
+    }
+
+    pub fn record_interval(&self) -> Duration {
+        Duration::from_secs(self.record_interval_secs)
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        let discovery = &self.discovery;
+        if discovery.ports.is_empty() {
+            return Err("At least one discovery port is required".to_string());
+        }
+        if discovery.ports.contains(&0) {
+            return Err("Port 0 is not a valid discovery port".to_string());
+        }
+        if !(10..=10_000).contains(&discovery.connect_timeout_ms) {
+            return Err("Connect timeout must be between 10 and 10000 ms".to_string());
+        }
+        if !(1..=256).contains(&discovery.chunk_size) {
+            return Err("Chunk size must be between 1 and 256".to_string());
+        }
+        if discovery.snmp_community.is_empty() || discovery.snmp_community.len() > 64 {
+            return Err("SNMP community must be 1 to 64 characters".to_string());
+        }
+        if !(100..=30_000).contains(&discovery.snmp_timeout_ms) {
+            return Err("SNMP timeout must be between 100 and 30000 ms".to_string());
+        }
+        if !(100..=60_000).contains(&discovery.http_timeout_ms) {
+            return Err("HTTP timeout must be between 100 and 60000 ms".to_string());
+        }
+
+        let monitor = &self.monitor;
+        if !(10..=86_400).contains(&monitor.status_interval_secs) {
+            return Err("Status interval must be between 10 seconds and a day".to_string());
+        }
+        if !(60..=7 * 86_400).contains(&monitor.record_interval_secs) {
+            return Err("Record interval must be between a minute and a week".to_string());
+        }
+
+        if !crate::logging::LEVELS.contains(&self.logging.level.as_str()) {
+            return Err(format!(
+                "Log level must be one of {}",
+                crate::logging::LEVELS.join(", ")
+            ));
+        }
+
+        let virtual_printer = &self.virtual_printer;
+        let name = virtual_printer.name.trim();
+        // DNS-SD instance names are a single label of at most 63 bytes
+        if name.is_empty() || name.len() > 63 || name.contains('.') {
+            return Err("Virtual printer name must be 1 to 63 characters without dots".to_string());
+        }
+        crate::validate::text("Virtual printer name", name)?;
+        if virtual_printer.port == 0 {
+            return Err("Port 0 is not a valid virtual printer port".to_string());
+        }
+        if let Some(folder) = &virtual_printer.folder {
+            crate::validate::text("Virtual printer folder", folder)?;
+            if !Path::new(folder).is_absolute() {
+                return Err("Virtual printer folder must be an absolute path".to_string());
+            }
+        }
+        virtual_printer.relay.validate()?;
+        let printing = &self.printing;
+        if let Some(target) = &printing.default_target {
+            crate::validate::id(target)?;
+        }
+        if !crate::text_pdf::FONTS.contains(&printing.text_font.as_str()) {
+            return Err(format!("Text font must be one of {}", crate::text_pdf::FONTS.join(", ")));
+        }
+        if !(4.0..=72.0).contains(&printing.text_size) {
+            return Err("Text size must be between 4 and 72 points".to_string());
+        }
+        if !(0.0..=50.0).contains(&printing.margin_mm) {
+            return Err("Margins must be between 0 and 50 mm".to_string());
+        }
+        if crate::convert::media_size_mm(&printing.paper).is_none() {
+            return Err(format!("'{}' is not a PWG media name like iso_a4_210x297mm", printing.paper));
+        }
+
+        for schedule in &self.schedules {
+            if self.schedules.iter().filter(|s| s.id == schedule.id).count() > 1 {
+                return Err(format!("Duplicate schedule id '{}'", schedule.id));
+            }
+            next_occurrence(&schedule.cron, chrono::Local::now())?;
+            for range in &schedule.ranges {
+                crate::addr::parse_ipv4_range(range)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Loaded {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    settings: Arc<Settings>,
+}
+
+fn state() -> &'static RwLock<Loaded> {
+    static STATE: OnceLock<RwLock<Loaded>> = OnceLock::new();
+    STATE.get_or_init(|| {
+        RwLock::new(Loaded {
+            path: None,
+            modified: None,
+            settings: Arc::new(Settings::default()),
+        })
+    })
+}
+
+/// The settings in effect. Cheap to call; long-running loops should call it on every
+/// iteration so edits take effect without a restart.
+pub fn current() -> Arc<Settings> {
+    state().read().unwrap().settings.clone()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Upgrades a settings document one version at a time.
+fn migrate(mut value: Value, config_dir: &Path) -> Result<Value, String> {
+    let mut version = value.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > SETTINGS_VERSION {
+        return Err(format!(
+            "Settings version {} is newer than this build supports ({})",
+            version, SETTINGS_VERSION
+        ));
+    }
+
+    while version < SETTINGS_VERSION {
+        match version {
+            // 0 -> 1: discovery schedules used to live in their own file
+            0 => {
+                let legacy = config_dir.join(LEGACY_SCHEDULES_FILE);
+                if value.get("schedules").is_none() && legacy.exists() {
+                    let json = std::fs::read_to_string(&legacy)
+                        .map_err(|e| format!("Failed to read {}: {}", legacy.display(), e))?;
+                    let schedules: Value = serde_json::from_str(&json)
+                        .map_err(|e| format!("Invalid schedules {}: {}", legacy.display(), e))?;
+                    value["schedules"] = schedules;
+                }
+            }
+            _ => unreachable!("no migration from settings version {}", version),
+        }
+        version += 1;
+        value["version"] = Value::from(version);
+    }
+
+    Ok(value)
+}
+
+fn read_file(path: &Path) -> Result<Settings, String> {
+    let config_dir = path.parent().unwrap_or(Path::new("."));
+    let value = if path.exists() {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid settings file {}: {}", path.display(), e))?
+    } else {
+        Value::Object(Default::default())
+    };
+
+    let invalid = |e| format!("Invalid settings file {}: {}", path.display(), e);
+    let value = migrate(value, config_dir).map_err(invalid)?;
+    let settings: Settings = serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?;
+    settings.validate().map_err(invalid)?;
+    Ok(settings)
+}
+
+fn write_file(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(settings).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, json).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// Runs `open` and, while it fails on one of `files`, moves that file aside as
+/// `<name>.invalid` and tries again, so a corrupt file costs its contents instead of keeping
+/// the app from starting. Returns the value and the errors that were recovered from.
+pub fn open_or_reset<T>(files: &[PathBuf], open: impl Fn() -> Result<T, String>) -> Result<(T, Vec<String>), String> {
+    let mut errors = Vec::new();
+    loop {
+        match open() {
+            Ok(value) => return Ok((value, errors)),
+            Err(e) => {
+                // Only a file the error names is to blame
+                let culprit = files.iter().find(|path| path.exists() && e.contains(&path.display().to_string()));
+                let Some(path) = culprit else { return Err(e) };
+                let aside = move_aside(path).map_err(|move_error| format!("{} ({})", e, move_error))?;
+                errors.push(format!("{}; moved to {} and started with defaults", e, aside.display()));
+            }
+        }
+    }
+}
+
+fn move_aside(path: &Path) -> Result<PathBuf, String> {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".invalid");
+    let aside = PathBuf::from(aside);
+    std::fs::rename(path, &aside).map_err(|e| format!("failed to move {} aside: {}", path.display(), e))?;
+    Ok(aside)
+}
+
+/// Loads (and migrates) `settings.json` from `config_dir`. A migrated file is written
+/// back right away so the old layout is only ever read once.
+pub fn load(config_dir: &Path) -> Result<Arc<Settings>, String> {
+    let path = config_dir.join(SETTINGS_FILE);
+    let settings = read_file(&path)?;
+    let on_disk_version = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        .and_then(|value| value.get("version").and_then(Value::as_u64));
+    if on_disk_version != Some(u64::from(SETTINGS_VERSION)) {
+        write_file(&path, &settings)?;
+    }
+
+    let settings = Arc::new(settings);
+    let mut state = state().write().unwrap();
+    state.modified = modified(&path);
+    state.path = Some(path);
+    state.settings = settings.clone();
+    Ok(settings)
+}
+
+/// Re-reads the settings file if it was edited outside the app. Invalid edits are
+/// reported and ignored; the previous settings stay in effect.
+pub fn reload_if_changed(app: &AppHandle) {
+    let (path, known) = {
+        let state = state().read().unwrap();
+        match &state.path {
+            Some(path) => (path.clone(), state.modified),
+            None => return,
+        }
+    };
+    let on_disk = modified(&path);
+    if on_disk == known {
+        return;
+    }
+
+    match read_file(&path) {
+        Ok(settings) => {
+            let settings = Arc::new(settings);
+            let previous = {
+                let mut state = state().write().unwrap();
+                state.modified = on_disk;
+                std::mem::replace(&mut state.settings, settings.clone())
+            };
+            info!(path = %path.display(), "reloaded settings");
+            apply(&previous, &settings);
+            if let Err(e) = app.emit("settings-changed", settings.as_ref()) {
+                warn!("failed to emit settings change: {}", e);
+            }
+        }
+        Err(e) => {
+            warn!("ignoring invalid settings file: {}", e);
+            state().write().unwrap().modified = on_disk;
+        }
+    }
+}
+
+/// Pushes changes that are held outside the settings state, like the log filter and the
+/// virtual printer.
+fn apply(previous: &Settings, settings: &Settings) {
+    if previous.logging.level != settings.logging.level {
+        if let Err(e) = crate::logging::set_level(&settings.logging.level) {
+            warn!("{}", e);
+        }
+    }
+    if previous.virtual_printer != settings.virtual_printer {
+        crate::ipp_server::apply(&settings.virtual_printer);
+    }
+}
+
+/// Validates and saves `settings`, then makes them current.
+pub fn replace(app: Option<&AppHandle>, mut settings: Settings) -> Result<Arc<Settings>, String> {
+    settings.version = SETTINGS_VERSION;
+    settings.validate()?;
+
+    let path = state().read().unwrap().path.clone();
+    if let Some(path) = &path {
+        write_file(path, &settings)?;
+    }
+
+    let settings = Arc::new(settings);
+    let previous = {
+        let mut state = state().write().unwrap();
+        state.modified = path.as_deref().and_then(modified);
+        std::mem::replace(&mut state.settings, settings.clone())
+    };
+    apply(&previous, &settings);
+    if let Some(app) = app {
+        if let Err(e) = app.emit("settings-changed", settings.as_ref()) {
+            warn!("failed to emit settings change: {}", e);
+        }
+    }
+    Ok(settings)
+}
+
+/// Applies `change` to a copy of the current settings and saves the result.
+pub fn update(app: Option<&AppHandle>, change: impl FnOnce(&mut Settings)) -> Result<Arc<Settings>, String> {
+    let mut settings = current().as_ref().clone();
+    change(&mut settings);
+    replace(app, settings)
+}
+
+#[tauri::command]
+pub fn get_settings() -> Settings {
+    current().as_ref().clone()
+}
+
+#[tauri::command]
+pub fn update_settings(app: AppHandle, settings: Settings) -> Result<Settings, String> {
+    replace(Some(&app), settings).map(|settings| settings.as_ref().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("settings-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn version_0_imports_the_schedules_file() {
+        let dir = temp_dir("migrate");
+        let schedules = r#"[{"id": "nightly", "name": "Nightly", "cron": "0 2 * * *", "ranges": ["10.0.0.0/24"]}]"#;
+        std::fs::write(dir.join(LEGACY_SCHEDULES_FILE), schedules).unwrap();
+
+        let value = migrate(serde_json::json!({"logging": {"level": "debug"}}), &dir).unwrap();
+        assert_eq!(value["version"], SETTINGS_VERSION);
+        assert_eq!(value["schedules"][0]["id"], "nightly");
+        assert_eq!(value["logging"]["level"], "debug");
+
+        // Schedules already in the settings win over the old file
+        let value = migrate(serde_json::json!({"schedules": []}), &dir).unwrap();
+        assert_eq!(value["schedules"], serde_json::json!([]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let err = migrate(serde_json::json!({"version": SETTINGS_VERSION + 1}), Path::new(".")).unwrap_err();
+        assert!(err.contains("newer than this build"), "{}", err);
+    }
+
+    #[test]
+    fn validate_checks_bounds() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+        let cases: [fn(&mut Settings); 8] = [
+            |s| s.discovery.ports.clear(),
+            |s| s.discovery.ports.push(0),
+            |s| s.discovery.connect_timeout_ms = 9,
+            |s| s.discovery.chunk_size = 257,
+            |s| s.discovery.snmp_community = "c".repeat(65),
+            |s| s.monitor.status_interval_secs = 86_401,
+            |s| s.logging.level = "loud".to_string(),
+            |s| s.printing.margin_mm = 50.5,
+        ];
+        for (i, change) in cases.iter().enumerate() {
+            let mut settings = Settings::default();
+            change(&mut settings);
+            assert!(settings.validate().is_err(), "case {} passed validation", i);
+        }
+        let mut settings = Settings::default();
+        settings.discovery.connect_timeout_ms = 10_000;
+        settings.discovery.chunk_size = 1;
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn bad_files_are_moved_aside_on_open() {
+        let dir = temp_dir("reset");
+        let path = dir.join(SETTINGS_FILE);
+        let files = [dir.join(LEGACY_SCHEDULES_FILE), path.clone()];
+        for bad in [
+            format!(r#"{{"version": {}}}"#, SETTINGS_VERSION + 1),
+            r#"{"discovery": {"chunk_size": 0}}"#.to_string(),
+            "{ not json".to_string(),
+        ] {
+            std::fs::write(&path, &bad).unwrap();
+            let (settings, errors) = open_or_reset(&files, || read_file(&path)).unwrap();
+            assert_eq!(settings, Settings::default());
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert!(errors[0].contains(&path.display().to_string()), "{}", errors[0]);
+            assert_eq!(std::fs::read_to_string(dir.join("settings.json.invalid")).unwrap(), bad);
+            assert!(!path.exists());
+        }
+
+        // A broken legacy schedules file is moved aside instead of the settings
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::write(dir.join(LEGACY_SCHEDULES_FILE), "[").unwrap();
+        let (_, errors) = open_or_reset(&files, || read_file(&path)).unwrap();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(path.exists());
+        assert!(dir.join("schedules.json.invalid").exists());
+
+        // Errors that name none of the files are passed on
+        let err = open_or_reset(&files, || Err::<(), _>("disk on fire".to_string())).unwrap_err();
+        assert_eq!(err, "disk on fire");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+use crate::addr::HostAddr;
+use crate::mib::format_oid;
+use snmp::{SnmpMessageType, SnmpPdu, Value};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const SNMP_VERSION_2C: i64 = 1;
+
+pub const SYS_OBJECT_ID: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 2, 0];
+pub const HR_DEVICE_TYPE: &[u32] = &[1, 3, 6, 1, 2, 1, 25, 3, 2, 1, 2, 1];
+pub const PRT_GENERAL_PRINTER_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 43, 5, 1, 1, 16, 1];
+/// Value of `hrDeviceType` for printers (`hrDevicePrinter`).
+pub const HR_DEVICE_PRINTER: &[u32] = &[1, 3, 6, 1, 2, 1, 25, 3, 1, 5];
+
+/// A host that answered the broadcast GetRequest.
+#[derive(Debug, Clone)]
+pub struct SnmpResponder {
+    pub addr: HostAddr,
+    pub sys_object_id: Option<String>,
+    pub device_type: Option<String>,
+    pub printer_name: Option<String>,
+}
+
+impl SnmpResponder {
+    /// Printer-MIB is present (the printer name exists) or the host reports itself as a
+    /// printer in the Host Resources MIB.
+    pub fn is_printer(&self) -> bool {
+        let printer_type = format_oid(HR_DEVICE_PRINTER);
+        self.printer_name.is_some() || self.device_type.as_deref() == Some(printer_type.as_str())
+    }
+}
+
+/// Sends one SNMPv2c GetRequest for `sysObjectID`, `hrDeviceType` and
+/// `prtGeneralPrinterName` to `target` (usually a broadcast address) and collects every
+/// response that arrives within `timeout`.
+pub fn sweep(target: SocketAddr, community: &[u8], timeout: Duration) -> Result<Vec<SnmpResponder>, String> {
+    let bind: SocketAddr = if target.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).map_err(|e| format!("Failed to bind SNMP socket: {}", e))?;
+    socket
+        .set_broadcast(true)
+        .map_err(|e| format!("Failed to enable broadcast: {}", e))?;
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.subsec_nanos());
+    let request_id = (nanos & 0x3fff_ffff) as i32 | 1;
+    let request = build_get_request(community, request_id, &[SYS_OBJECT_ID, HR_DEVICE_TYPE, PRT_GENERAL_PRINTER_NAME]);
+    socket
+        .send_to(&request, target)
+        .map_err(|e| format!("Failed to send SNMP broadcast to {}: {}", target, e))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut responders: Vec<SnmpResponder> = Vec::new();
+    let mut buffer = [0u8; 65535];
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() || socket.set_read_timeout(Some(remaining)).is_err() {
+            break;
+        }
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(_) => break,
+        };
+        let addr = HostAddr::from(from);
+        if responders.iter().any(|r| r.addr == addr) {
+            continue;
+        }
+        if let Some(responder) = parse_response(&buffer[..length], request_id, addr) {
+            responders.push(responder);
+        }
+    }
+
+    Ok(responders)
+}
+
+/// Broadcasts to every target and keeps the responders that look like printers.
+pub fn discover_printers(targets: &[SocketAddr], community: &[u8], timeout: Duration) -> Vec<SnmpResponder> {
+    let mut printers: Vec<SnmpResponder> = Vec::new();
+    for &target in targets {
+        match sweep(target, community, timeout) {
+            Ok(responders) => {
+                for responder in responders.into_iter().filter(SnmpResponder::is_printer) {
+                    if !printers.iter().any(|p| p.addr == responder.addr) {
+                        printers.push(responder);
+                    }
+                }
+            }
+            Err(e) => warn!("SNMP broadcast to {} failed: {}", target, e),
+        }
+    }
+    printers
+}
+
+fn parse_response(packet: &[u8], request_id: i32, addr: HostAddr) -> Option<SnmpResponder> {
+    let pdu = SnmpPdu::from_bytes(packet).ok()?;
+    if pdu.message_type != SnmpMessageType::Response || pdu.req_id != request_id {
+        return None;
+    }
+
+    let mut responder = SnmpResponder {
+        addr,
+        sys_object_id: None,
+        device_type: None,
+        printer_name: None,
+    };
+    let mut name_buf = [0u32; 128];
+    for (name, value) in pdu.varbinds {
+        let Ok(name) = name.read_name(&mut name_buf) else { continue };
+        let name = name.to_vec();
+        let value = match value {
+            Value::ObjectIdentifier(oid) => {
+                let mut value_buf = [0u32; 128];
+                oid.read_name(&mut value_buf).ok().map(format_oid)
+            }
+            Value::OctetString(bytes) => {
+                let text = String::from_utf8_lossy(bytes).trim_matches(char::from(0)).trim().to_string();
+                Some(text).filter(|t| !t.is_empty())
+            }
+            _ => None,
+        };
+
+        if name == SYS_OBJECT_ID {
+            responder.sys_object_id = value;
+        } else if name == HR_DEVICE_TYPE {
+            responder.device_type = value;
+        } else if name == PRT_GENERAL_PRINTER_NAME {
+            responder.printer_name = value;
+        }
+    }
+
+    Some(responder)
+}
+
+/// BER-encodes an SNMPv2c GetRequest with a NULL value for every OID.
+pub fn build_get_request(community: &[u8], request_id: i32, oids: &[&[u32]]) -> Vec<u8> {
+    let mut varbinds = Vec::new();
+    for oid in oids {
+        let mut varbind = encode_oid(oid);
+        varbind.extend_from_slice(&[0x05, 0x00]);
+        varbinds.extend(tlv(0x30, &varbind));
+    }
+
+    let mut pdu = encode_integer(i64::from(request_id));
+    pdu.extend(encode_integer(0)); // error-status
+    pdu.extend(encode_integer(0)); // error-index
+    pdu.extend(tlv(0x30, &varbinds));
+
+    let mut message = encode_integer(SNMP_VERSION_2C);
+    message.extend(tlv(0x04, community));
+    message.extend(tlv(0xa0, &pdu));
+    tlv(0x30, &message)
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    let length = value.len();
+    if length < 0x80 {
+        encoded.push(length as u8);
+    } else {
+        let bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        encoded.push(0x80 | bytes.len() as u8);
+        encoded.extend(bytes);
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Drop leading bytes that only repeat the sign bit
+    let mut start = 0;
+    while start < 7 {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    tlv(0x02, &bytes[start..])
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut body = Vec::new();
+    if oid.len() >= 2 {
+        encode_arc(&mut body, oid[0] * 40 + oid[1]);
+        for &arc in &oid[2..] {
+            encode_arc(&mut body, arc);
+        }
+    }
+    tlv(0x06, &body)
+}
+
+fn encode_arc(out: &mut Vec<u8>, arc: u32) {
+    let mut chunks = vec![(arc & 0x7f) as u8];
+    let mut rest = arc >> 7;
+    while rest > 0 {
+        chunks.push(0x80 | (rest & 0x7f) as u8);
+        rest >>= 7;
+    }
+    out.extend(chunks.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    enum Reply {
+        Oid(&'static [u32]),
+        Text(&'static str),
+    }
+
+    /// Answers the first GetRequest on `socket` with `values`, echoing the request id unless
+    /// `request_id` overrides it.
+    fn answer(socket: UdpSocket, request_id: Option<i32>, values: Vec<(&'static [u32], Reply)>) {
+        let mut buffer = [0u8; 1500];
+        let (length, from) = socket.recv_from(&mut buffer).unwrap();
+        let request = SnmpPdu::from_bytes(&buffer[..length]).unwrap();
+        assert_eq!(request.message_type, SnmpMessageType::GetRequest);
+
+        let mut varbinds = Vec::new();
+        for (oid, value) in values {
+            let mut varbind = encode_oid(oid);
+            match value {
+                Reply::Oid(oid) => varbind.extend(encode_oid(oid)),
+                Reply::Text(text) => varbind.extend(tlv(0x04, text.as_bytes())),
+            }
+            varbinds.extend(tlv(0x30, &varbind));
+        }
+        let mut pdu = encode_integer(i64::from(request_id.unwrap_or(request.req_id)));
+        pdu.extend(encode_integer(0));
+        pdu.extend(encode_integer(0));
+        pdu.extend(tlv(0x30, &varbinds));
+        let mut message = encode_integer(SNMP_VERSION_2C);
+        message.extend(tlv(0x04, b"public"));
+        message.extend(tlv(0xa2, &pdu));
+        socket.send_to(&tlv(0x30, &message), from).unwrap();
+    }
+
+    fn agent(request_id: Option<i32>, values: Vec<(&'static [u32], Reply)>) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || answer(socket, request_id, values));
+        addr
+    }
+
+    #[test]
+    fn sweep_collects_a_printer_response() {
+        let addr = agent(
+            None,
+            vec![
+                (SYS_OBJECT_ID, Reply::Oid(&[1, 3, 6, 1, 4, 1, 11, 2, 3, 9, 1])),
+                (HR_DEVICE_TYPE, Reply::Oid(HR_DEVICE_PRINTER)),
+                (PRT_GENERAL_PRINTER_NAME, Reply::Text("Office LaserJet")),
+            ],
+        );
+        let responders = sweep(addr, b"public", Duration::from_millis(500)).unwrap();
+        assert_eq!(responders.len(), 1);
+        let responder = &responders[0];
+        assert_eq!(responder.addr, HostAddr::from(Ipv4Addr::LOCALHOST));
+        assert_eq!(responder.sys_object_id.as_deref(), Some("1.3.6.1.4.1.11.2.3.9.1"));
+        assert_eq!(responder.printer_name.as_deref(), Some("Office LaserJet"));
+        assert!(responder.is_printer());
+    }
+
+    #[test]
+    fn discover_printers_skips_other_devices() {
+        let router = agent(None, vec![(SYS_OBJECT_ID, Reply::Oid(&[1, 3, 6, 1, 4, 1, 9, 1, 1]))]);
+        let printer = agent(None, vec![(HR_DEVICE_TYPE, Reply::Oid(HR_DEVICE_PRINTER))]);
+        let printers = discover_printers(&[router, printer], b"public", Duration::from_millis(300));
+        assert_eq!(printers.len(), 1);
+        assert_eq!(printers[0].device_type.as_deref(), Some(format_oid(HR_DEVICE_PRINTER).as_str()));
+    }
+
+    #[test]
+    fn responses_to_other_requests_are_ignored() {
+        let addr = agent(Some(7), vec![(PRT_GENERAL_PRINTER_NAME, Reply::Text("Stale"))]);
+        let responders = sweep(addr, b"public", Duration::from_millis(300)).unwrap();
+        assert!(responders.is_empty());
+    }
+}
@@ -0,0 +1,494 @@
+use crate::addr::HostAddr;
+use crate::mib::{format_oid, name_for_oid, resolve_oid};
+use crate::settings;
+use crate::validate;
+use serde::{Deserialize, Serialize};
+use snmp::{SnmpPdu, SyncSession, Value};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Stop runaway walks on agents that never report the end of the subtree.
+const MAX_WALK_RESULTS: usize = 10_000;
+const DEFAULT_MAX_REPETITIONS: u32 = 20;
+const MAX_REQUEST_OIDS: usize = 64;
+
+/// One SNMP variable binding in a JSON-friendly shape.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnmpVarbind {
+    pub oid: String,
+    /// Symbolic name from the bundled MIB map, when known.
+    pub name: Option<String>,
+    pub value: SnmpValue,
+}
+
+/// Every `snmp::Value` variant an agent can return in a response, tagged by type.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SnmpValue {
+    Integer(i64),
+    /// Octet strings carry the raw bytes as hex, plus the text when they are valid UTF-8.
+    OctetString { text: Option<String>, hex: String },
+    ObjectIdentifier(String),
+    IpAddress(String),
+    Counter32(u32),
+    Unsigned32(u32),
+    Timeticks(u32),
+    Counter64(u64),
+    Opaque(String),
+    Boolean(bool),
+    Null,
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+    Unsupported(String),
+}
+
+/// Values that may be written with `snmp_set`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SnmpSetValue {
+    Integer(i64),
+    OctetString(String),
+    IpAddress(String),
+    Unsigned32(u32),
+    Timeticks(u32),
+}
+
+/// Checks the arguments of an SNMP command before anything is sent.
+fn check_request(host: &str, oids: &[String], community: &str) -> Result<(), String> {
+    validate::host(host)?;
+    validate::community(community)?;
+    if oids.is_empty() || oids.len() > MAX_REQUEST_OIDS {
+        return Err(format!("Request 1 to {} OIDs", MAX_REQUEST_OIDS));
+    }
+    for oid in oids {
+        validate::text("OID", oid)?;
+        resolve_oid(oid)?;
+    }
+    Ok(())
+}
+
+/// Community used when a command does not name one.
+fn default_community() -> String {
+    settings::current().discovery.snmp_community.clone()
+}
+
+fn open_session(host: &str, community: &str) -> Result<SyncSession, String> {
+    let addr = HostAddr::parse(host).ok_or_else(|| format!("Invalid IP address: {}", host))?;
+    SyncSession::new(addr.socket_addr(161), community.as_bytes(), Some(TIMEOUT), 0)
+        .map_err(|e| format!("SNMP session failed for {}: {}", host, e))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn convert_value(value: Value) -> SnmpValue {
+    match value {
+        Value::Integer(n) => SnmpValue::Integer(n),
+        Value::OctetString(bytes) => SnmpValue::OctetString {
+            text: std::str::from_utf8(bytes)
+                .ok()
+                .filter(|text| !text.chars().any(|c| c.is_control() && !c.is_whitespace()))
+                .map(|text| text.to_string()),
+            hex: hex(bytes),
+        },
+        Value::ObjectIdentifier(oid) => {
+            let mut buf = [0u32; 128];
+            match oid.read_name(&mut buf) {
+                Ok(name) => SnmpValue::ObjectIdentifier(format_oid(name)),
+                Err(e) => SnmpValue::Unsupported(format!("unreadable OID: {:?}", e)),
+            }
+        }
+        Value::IpAddress(octets) => SnmpValue::IpAddress(std::net::Ipv4Addr::from(octets).to_string()),
+        Value::Counter32(n) => SnmpValue::Counter32(n),
+        Value::Unsigned32(n) => SnmpValue::Unsigned32(n),
+        Value::Timeticks(n) => SnmpValue::Timeticks(n),
+        Value::Counter64(n) => SnmpValue::Counter64(n),
+        Value::Opaque(bytes) => SnmpValue::Opaque(hex(bytes)),
+        Value::Boolean(b) => SnmpValue::Boolean(b),
+        Value::Null => SnmpValue::Null,
+        Value::NoSuchObject => SnmpValue::NoSuchObject,
+        Value::NoSuchInstance => SnmpValue::NoSuchInstance,
+        Value::EndOfMibView => SnmpValue::EndOfMibView,
+        other => SnmpValue::Unsupported(format!("{:?}", other)),
+    }
+}
+
+/// Whether a walk of `root` that last returned `current` goes on with `oid`. Agents must
+/// return increasing OIDs; the walk stops rather than loop on a broken one.
+fn continues_walk(root: &[u32], current: &[u32], oid: &[u32], value: &SnmpValue) -> bool {
+    oid.starts_with(root) && oid > current && !matches!(value, SnmpValue::EndOfMibView)
+}
+
+/// Copies a response into owned varbinds so the session can be reused.
+fn collect_varbinds(pdu: SnmpPdu) -> Result<Vec<(Vec<u32>, SnmpVarbind)>, String> {
+    if pdu.error_status != 0 {
+        return Err(format!(
+            "Agent returned error status {} at index {}",
+            pdu.error_status, pdu.error_index
+        ));
+    }
+
+    let mut varbinds = Vec::new();
+    let mut buf = [0u32; 128];
+    for (name, value) in pdu.varbinds {
+        let oid = name
+            .read_name(&mut buf)
+            .map_err(|e| format!("Unreadable OID in response: {:?}", e))?
+            .to_vec();
+        let varbind = SnmpVarbind {
+            oid: format_oid(&oid),
+            name: name_for_oid(&oid),
+            value: convert_value(value),
+        };
+        varbinds.push((oid, varbind));
+    }
+    Ok(varbinds)
+}
+
+pub fn get(host: &str, oids: &[String], community: &str) -> Result<Vec<SnmpVarbind>, String> {
+    let mut session = open_session(host, community)?;
+    let mut results = Vec::new();
+
+    for oid in oids {
+        let oid = resolve_oid(oid)?;
+        let pdu = session
+            .get(&oid)
+            .map_err(|e| format!("SNMP get {} failed: {:?}", format_oid(&oid), e))?;
+        results.extend(collect_varbinds(pdu)?.into_iter().map(|(_, varbind)| varbind));
+    }
+
+    Ok(results)
+}
+
+/// Walks the subtree under `root` with GetNext requests.
+pub fn walk(host: &str, root: &str, community: &str) -> Result<Vec<SnmpVarbind>, String> {
+    let root = resolve_oid(root)?;
+    let mut session = open_session(host, community)?;
+    let mut results = Vec::new();
+    let mut current = root.clone();
+
+    while results.len() < MAX_WALK_RESULTS {
+        let pdu = session
+            .getnext(&current)
+            .map_err(|e| format!("SNMP getnext {} failed: {:?}", format_oid(&current), e))?;
+        let Some((oid, varbind)) = collect_varbinds(pdu)?.into_iter().next() else { break };
+
+        if !continues_walk(&root, &current, &oid, &varbind.value) {
+            break;
+        }
+        current = oid;
+        results.push(varbind);
+    }
+
+    Ok(results)
+}
+
+/// Walks the subtree under `root` with GetBulk requests (SNMPv2c), which needs far fewer
+/// round trips for big tables like `prtMarkerSuppliesTable`.
+pub fn bulkwalk(host: &str, root: &str, community: &str, max_repetitions: u32) -> Result<Vec<SnmpVarbind>, String> {
+    let root = resolve_oid(root)?;
+    let mut session = open_session(host, community)?;
+    let mut results = Vec::new();
+    let mut current = root.clone();
+
+    'walk: while results.len() < MAX_WALK_RESULTS {
+        let pdu = session
+            .getbulk(&[&current], 0, max_repetitions.max(1))
+            .map_err(|e| format!("SNMP getbulk {} failed: {:?}", format_oid(&current), e))?;
+        let batch = collect_varbinds(pdu)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for (oid, varbind) in batch {
+            if !continues_walk(&root, &current, &oid, &varbind.value) {
+                break 'walk;
+            }
+            current = oid;
+            results.push(varbind);
+        }
+    }
+
+    Ok(results)
+}
+
+fn set_value(value: &SnmpSetValue) -> Result<Value<'_>, String> {
+    Ok(match value {
+        SnmpSetValue::Integer(n) => Value::Integer(*n),
+        SnmpSetValue::OctetString(text) => Value::OctetString(text.as_bytes()),
+        SnmpSetValue::IpAddress(ip) => {
+            let ip = ip
+                .parse::<std::net::Ipv4Addr>()
+                .map_err(|_| format!("Invalid IPv4 address: {}", ip))?;
+            Value::IpAddress(ip.octets())
+        }
+        SnmpSetValue::Unsigned32(n) => Value::Unsigned32(*n),
+        SnmpSetValue::Timeticks(n) => Value::Timeticks(*n),
+    })
+}
+
+/// Writes a single value. Refuses to do anything unless `confirm` is set, since a set
+/// changes the device configuration.
+pub fn set(host: &str, oid: &str, value: &SnmpSetValue, community: &str, confirm: bool) -> Result<Vec<SnmpVarbind>, String> {
+    if !confirm {
+        return Err("snmp_set changes the device configuration; pass confirm=true to proceed".to_string());
+    }
+    let oid = resolve_oid(oid)?;
+    let value = set_value(value)?;
+
+    let mut session = open_session(host, community)?;
+    let pdu = session
+        .set(&[(&oid, value)])
+        .map_err(|e| format!("SNMP set {} failed: {:?}", format_oid(&oid), e))?;
+    Ok(collect_varbinds(pdu)?.into_iter().map(|(_, varbind)| varbind).collect())
+}
+
+#[tauri::command]
+pub fn snmp_get(host: String, oids: Vec<String>, community: Option<String>) -> Result<Vec<SnmpVarbind>, String> {
+    let community = community.unwrap_or_else(default_community);
+    check_request(&host, &oids, &community)?;
+    get(&host, &oids, &community)
+}
+
+#[tauri::command]
+pub fn snmp_walk(host: String, oid: String, community: Option<String>) -> Result<Vec<SnmpVarbind>, String> {
+    let community = community.unwrap_or_else(default_community);
+    check_request(&host, std::slice::from_ref(&oid), &community)?;
+    walk(&host, &oid, &community)
+}
+
+#[tauri::command]
+pub fn snmp_bulkwalk(
+    host: String,
+    oid: String,
+    community: Option<String>,
+    max_repetitions: Option<u32>,
+) -> Result<Vec<SnmpVarbind>, String> {
+    let community = community.unwrap_or_else(default_community);
+    check_request(&host, std::slice::from_ref(&oid), &community)?;
+    let max_repetitions = max_repetitions.unwrap_or(DEFAULT_MAX_REPETITIONS);
+    if !(1..=100).contains(&max_repetitions) {
+        return Err("max_repetitions must be between 1 and 100".to_string());
+    }
+    bulkwalk(&host, &oid, &community, max_repetitions)
+}
+
+#[tauri::command]
+pub fn snmp_set(
+    host: String,
+    oid: String,
+    value: SnmpSetValue,
+    community: Option<String>,
+    confirm: bool,
+) -> Result<Vec<SnmpVarbind>, String> {
+    let community = community.unwrap_or_else(|| "private".to_string());
+    check_request(&host, std::slice::from_ref(&oid), &community)?;
+    if let SnmpSetValue::OctetString(text) = &value {
+        validate::text("Value", text)?;
+    }
+    set(&host, &oid, &value, &community, confirm)
+}
+
+/// Runs `snmp get|walk|bulkwalk|set ...` from the command line and prints JSON. Returns
+/// the process exit code, or `None` when the arguments are not an SNMP command so the
+/// app starts normally.
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    if args.first().map(String::as_str) != Some("snmp") {
+        return None;
+    }
+    attach_console();
+
+    let mut community = None;
+    let mut max_repetitions = DEFAULT_MAX_REPETITIONS;
+    let mut confirm = false;
+    let mut positional = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-c" | "--community" => community = rest.next().cloned(),
+            "-r" | "--max-repetitions" => {
+                max_repetitions = rest.next().and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_MAX_REPETITIONS)
+            }
+            "--confirm" => confirm = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let result = match positional.as_slice() {
+        [command, host, oids @ ..] if command == "get" && !oids.is_empty() => {
+            get(host, oids, &community.clone().unwrap_or_else(default_community))
+        }
+        [command, host, oid] if command == "walk" => walk(host, oid, &community.clone().unwrap_or_else(default_community)),
+        [command, host, oid] if command == "bulkwalk" => bulkwalk(
+            host,
+            oid,
+            &community.clone().unwrap_or_else(default_community),
+            max_repetitions,
+        ),
+        [command, host, oid, kind, value] if command == "set" => {
+            serde_json::from_value::<SnmpSetValue>(serde_json::json!({ "type": kind, "value": typed_cli_value(kind, value) }))
+                .map_err(|e| format!("Invalid value: {}", e))
+                .and_then(|value| set(host, oid, &value, community.as_deref().unwrap_or("private"), confirm))
+        }
+        _ => Err(
+            "usage: snmp get <host> <oid>... | walk <host> <oid> | bulkwalk <host> <oid> [-r n] | \
+             set <host> <oid> <integer|octet_string|ip_address|unsigned32|timeticks> <value> --confirm [-c community]"
+                .to_string(),
+        ),
+    };
+
+    match result {
+        Ok(varbinds) => {
+            println!("{}", serde_json::to_string_pretty(&varbinds).unwrap_or_default());
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Release builds on Windows use the GUI subsystem and start without a console, so output
+/// goes nowhere until the process attaches to the console of the shell that started it.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // Fails harmlessly when there is no parent console or one is already attached
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+fn typed_cli_value(kind: &str, value: &str) -> serde_json::Value {
+    match kind {
+        "integer" | "unsigned32" | "timeticks" => value
+            .parse::<i64>()
+            .map(serde_json::Value::from)
+            .unwrap_or_else(|_| serde_json::Value::from(value)),
+        _ => serde_json::Value::from(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn oids(list: &[&str]) -> Vec<String> {
+        list.iter().map(|oid| oid.to_string()).collect()
+    }
+
+    #[test]
+    fn octet_strings_keep_text_only_when_printable() {
+        let SnmpValue::OctetString { text, hex } = convert_value(Value::OctetString(b"HP LaserJet\r\n")) else {
+            panic!("not an octet string");
+        };
+        assert_eq!(text.as_deref(), Some("HP LaserJet\r\n"));
+        assert_eq!(hex, "4850204c617365724a65740d0a");
+
+        // MAC addresses and other binary values are only shown as hex
+        let SnmpValue::OctetString { text, hex } = convert_value(Value::OctetString(&[0x00, 0x1b, 0xa9, 0xff])) else {
+            panic!("not an octet string");
+        };
+        assert_eq!(text, None);
+        assert_eq!(hex, "001ba9ff");
+        let SnmpValue::OctetString { text, .. } = convert_value(Value::OctetString(b"a\x07b")) else {
+            panic!("not an octet string");
+        };
+        assert_eq!(text, None);
+    }
+
+    #[test]
+    fn values_serialize_tagged_by_type() {
+        let varbind = |value| SnmpVarbind { oid: "1.3.6.1.2.1.1.3.0".into(), name: Some("sysUpTime.0".into()), value };
+        assert_eq!(
+            serde_json::to_value(varbind(convert_value(Value::Timeticks(4200)))).unwrap(),
+            json!({ "oid": "1.3.6.1.2.1.1.3.0", "name": "sysUpTime.0", "value": { "type": "timeticks", "value": 4200 } })
+        );
+        let cases = [
+            (Value::Integer(-3), json!({ "type": "integer", "value": -3 })),
+            (Value::OctetString(b"ok"), json!({ "type": "octet_string", "value": { "text": "ok", "hex": "6f6b" } })),
+            (Value::IpAddress([192, 168, 1, 20]), json!({ "type": "ip_address", "value": "192.168.1.20" })),
+            (Value::Counter32(u32::MAX), json!({ "type": "counter32", "value": 4294967295u32 })),
+            (Value::Unsigned32(7), json!({ "type": "unsigned32", "value": 7 })),
+            (Value::Counter64(u64::MAX), json!({ "type": "counter64", "value": u64::MAX })),
+            (Value::Opaque(&[0x9f, 0x78]), json!({ "type": "opaque", "value": "9f78" })),
+            (Value::Boolean(true), json!({ "type": "boolean", "value": true })),
+            (Value::Null, json!({ "type": "null" })),
+            (Value::NoSuchObject, json!({ "type": "no_such_object" })),
+            (Value::NoSuchInstance, json!({ "type": "no_such_instance" })),
+            (Value::EndOfMibView, json!({ "type": "end_of_mib_view" })),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(serde_json::to_value(convert_value(value)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn walks_stop_outside_the_subtree_and_on_broken_agents() {
+        let root = [1, 3, 6, 1, 2, 1, 43, 11];
+        let value = SnmpValue::Integer(1);
+        assert!(continues_walk(&root, &root, &[1, 3, 6, 1, 2, 1, 43, 11, 1, 1, 9, 1, 1], &value));
+        // Past the end of the table
+        assert!(!continues_walk(&root, &root, &[1, 3, 6, 1, 2, 1, 43, 12, 1], &value));
+        assert!(!continues_walk(&root, &root, &[1, 3, 6, 1, 2, 1, 43, 11, 1], &SnmpValue::EndOfMibView));
+        // Same or earlier OID would loop forever
+        let current = [1, 3, 6, 1, 2, 1, 43, 11, 1, 1, 9, 1, 2];
+        assert!(!continues_walk(&root, &current, &current, &value));
+        assert!(!continues_walk(&root, &current, &[1, 3, 6, 1, 2, 1, 43, 11, 1, 1, 9, 1, 1], &value));
+    }
+
+    #[test]
+    fn requests_are_checked_before_sending() {
+        assert!(check_request("192.168.1.20", &oids(&["sysDescr.0", "SNMPv2-MIB::sysName.0", "1.3.6.1.2.1.1.5.0"]), "public").is_ok());
+        assert!(check_request("fe80::1%1", &oids(&["sysDescr.0"]), "public").is_ok());
+
+        assert!(check_request("printer.local", &oids(&["sysDescr.0"]), "public").is_err());
+        assert!(check_request("255.255.255.255", &oids(&["sysDescr.0"]), "public").is_err());
+        assert!(check_request("192.168.1.20", &oids(&["sysDescr.0"]), "").is_err());
+        assert!(check_request("192.168.1.20", &oids(&["sysDescr.0"]), "pub lic").is_err());
+        assert_eq!(check_request("192.168.1.20", &[], "public"), Err("Request 1 to 64 OIDs".to_string()));
+        assert!(check_request("192.168.1.20", &vec!["sysDescr.0".to_string(); 65], "public").is_err());
+
+        let err = |oid: &str| check_request("192.168.1.20", &oids(&[oid]), "public").unwrap_err();
+        assert_eq!(err("1.3.6.x.1"), "Invalid OID component 'x'");
+        assert_eq!(err("sysDescrr.0"), "Unknown MIB object 'sysDescrr'");
+        assert_eq!(err("1"), "OID '1' is too short");
+        assert_eq!(err("1.3.6.1.2.1.1.1.0\n"), "OID contains control characters");
+    }
+
+    #[test]
+    fn sets_need_confirmation_and_valid_values() {
+        let value = SnmpSetValue::Integer(1);
+        assert!(set("192.168.1.20", "sysContact.0", &value, "private", false).unwrap_err().contains("confirm=true"));
+        // Refused before a session is opened
+        let bad_ip = SnmpSetValue::IpAddress("10.0.0.256".into());
+        assert_eq!(set("192.168.1.20", "sysContact.0", &bad_ip, "private", true).unwrap_err(), "Invalid IPv4 address: 10.0.0.256");
+        assert!(set("192.168.1.20", "nosuchObject.0", &value, "private", true).unwrap_err().contains("Unknown MIB object"));
+
+        assert!(matches!(set_value(&SnmpSetValue::IpAddress("10.0.0.5".into())), Ok(Value::IpAddress([10, 0, 0, 5]))));
+        assert!(matches!(set_value(&SnmpSetValue::OctetString("Lab 2".into())), Ok(Value::OctetString(b"Lab 2"))));
+        assert!(matches!(set_value(&SnmpSetValue::Timeticks(100)), Ok(Value::Timeticks(100))));
+    }
+
+    #[test]
+    fn cli_values_deserialize_into_set_values() {
+        let parse = |kind: &str, value: &str| {
+            serde_json::from_value::<SnmpSetValue>(json!({ "type": kind, "value": typed_cli_value(kind, value) }))
+        };
+        assert!(matches!(parse("integer", "-5"), Ok(SnmpSetValue::Integer(-5))));
+        assert!(matches!(parse("unsigned32", "7"), Ok(SnmpSetValue::Unsigned32(7))));
+        assert!(matches!(parse("octet_string", "42"), Ok(SnmpSetValue::OctetString(text)) if text == "42"));
+        assert!(matches!(parse("ip_address", "10.0.0.5"), Ok(SnmpSetValue::IpAddress(ip)) if ip == "10.0.0.5"));
+        assert!(parse("integer", "five").is_err());
+        assert!(parse("unsigned32", "-1").is_err());
+        assert!(parse("counter64", "1").is_err());
+    }
+}
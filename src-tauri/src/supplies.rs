@@ -0,0 +1,365 @@
+use crate::inventory::{InventoryEntry, InventoryStore};
+use crate::snmp_tools::{bulkwalk, SnmpValue};
+use crate::validate;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::State;
+
+const SUPPLIES_ENTRY: &str = "1.3.6.1.2.1.43.11.1.1.";
+/// Consumption is estimated from this much history, counted back from the latest sample.
+const FORECAST_WINDOW_DAYS: i64 = 30;
+/// A supply that will be empty within this many days, or is at or below `AT_RISK_PERCENT`,
+/// is flagged so cartridges can be ordered in time.
+const AT_RISK_DAYS: f64 = 14.0;
+const AT_RISK_PERCENT: f64 = 10.0;
+/// A level rising by more than this is a cartridge swap; history before it is ignored.
+const REFILL_JUMP_PERCENT: f64 = 5.0;
+
+/// One row of `prtMarkerSuppliesTable`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SupplyLevel {
+    /// `hrDeviceIndex.prtMarkerSuppliesIndex`, stable for a given device.
+    pub index: String,
+    pub description: String,
+    /// `prtMarkerSuppliesType` as a name (`toner`, `inkCartridge`, ...).
+    pub kind: String,
+    /// Raw capacity and level; negative values are the Printer-MIB "other/unknown/some
+    /// remaining" markers.
+    pub max_capacity: i64,
+    pub level: i64,
+    pub percent: Option<f64>,
+}
+
+/// The supplies read in one poll.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplySample {
+    pub at: DateTime<Utc>,
+    pub supplies: Vec<SupplyLevel>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupplyForecast {
+    pub printer_id: String,
+    pub printer_name: String,
+    pub location: Option<String>,
+    pub supply: String,
+    pub kind: String,
+    pub percent: Option<f64>,
+    pub days_until_empty: Option<f64>,
+    pub empty_on: Option<DateTime<Utc>>,
+    /// `pages` when the rate came from page counters, `time` when only the level history
+    /// was usable.
+    pub method: Option<String>,
+    pub at_risk: bool,
+}
+
+fn supply_kind(code: i64) -> &'static str {
+    match code {
+        3 => "toner",
+        4 => "wasteToner",
+        5 => "ink",
+        6 => "inkCartridge",
+        7 => "inkRibbon",
+        8 => "wasteInk",
+        9 => "opc",
+        10 => "developer",
+        11 => "fuserOil",
+        15 => "fuser",
+        20 => "transferUnit",
+        21 => "tonerCartridge",
+        22 => "fuserOiler",
+        _ => "other",
+    }
+}
+
+fn integer(value: &SnmpValue) -> Option<i64> {
+    match value {
+        SnmpValue::Integer(n) => Some(*n),
+        SnmpValue::Counter32(n) | SnmpValue::Unsigned32(n) | SnmpValue::Timeticks(n) => Some(i64::from(*n)),
+        _ => None,
+    }
+}
+
+fn text(value: &SnmpValue) -> Option<String> {
+    match value {
+        SnmpValue::OctetString { text, .. } => text.as_ref().map(|t| t.trim_end_matches('\0').trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Reads every supply from the Printer-MIB supplies table.
+pub fn collect_supplies(ip: &str, community: &str) -> Result<Vec<SupplyLevel>, String> {
+    let varbinds = bulkwalk(ip, "prtMarkerSuppliesTable", community, 20)?;
+    let mut rows: BTreeMap<String, SupplyLevel> = BTreeMap::new();
+
+    for varbind in varbinds {
+        let Some(rest) = varbind.oid.strip_prefix(SUPPLIES_ENTRY) else { continue };
+        let Some((column, index)) = rest.split_once('.') else { continue };
+        let row = rows.entry(index.to_string()).or_insert_with(|| SupplyLevel {
+            index: index.to_string(),
+            ..Default::default()
+        });
+        match column {
+            "5" => row.kind = supply_kind(integer(&varbind.value).unwrap_or(1)).to_string(),
+            "6" => row.description = text(&varbind.value).unwrap_or_default(),
+            "8" => row.max_capacity = integer(&varbind.value).unwrap_or(-2),
+            "9" => row.level = integer(&varbind.value).unwrap_or(-2),
+            _ => {}
+        }
+    }
+
+    let mut supplies: Vec<SupplyLevel> = rows.into_values().collect();
+    for supply in &mut supplies {
+        if supply.max_capacity > 0 && supply.level >= 0 {
+            supply.percent = Some((supply.level as f64 * 100.0 / supply.max_capacity as f64).min(100.0));
+        }
+        if supply.description.is_empty() {
+            supply.description = format!("{} {}", supply.kind, supply.index);
+        }
+    }
+    if supplies.is_empty() {
+        return Err(format!("No supplies reported by {}", ip));
+    }
+    Ok(supplies)
+}
+
+/// Page counter total closest to (at or before) `at`.
+fn total_at(entry: &InventoryEntry, at: DateTime<Utc>) -> Option<u64> {
+    entry.counters.iter().rev().find(|c| c.at <= at).and_then(|c| c.total)
+}
+
+/// Average pages per day over the forecast window, or `None` without enough counters.
+fn pages_per_day(entry: &InventoryEntry, now: DateTime<Utc>) -> Option<f64> {
+    let since = now - Duration::days(FORECAST_WINDOW_DAYS);
+    let recent: Vec<_> = entry.counters.iter().filter(|c| c.at >= since && c.total.is_some()).collect();
+    let (first, last) = (recent.first()?, recent.last()?);
+    let days = (last.at - first.at).num_seconds() as f64 / 86_400.0;
+    let pages = last.total?.checked_sub(first.total?)? as f64;
+    (days > 0.0 && pages > 0.0).then_some(pages / days)
+}
+
+/// Estimates how long each supply of `entry` lasts. Consumption per page (level drop over
+/// pages printed) times recent pages per day is preferred since it follows usage changes;
+/// a plain level-per-day rate is the fallback.
+pub fn forecast_entry(entry: &InventoryEntry) -> Vec<SupplyForecast> {
+    let Some(latest) = entry.supplies.last() else { return Vec::new() };
+    let now = latest.at;
+    let since = now - Duration::days(FORECAST_WINDOW_DAYS);
+    let daily_pages = pages_per_day(entry, now);
+
+    latest
+        .supplies
+        .iter()
+        .map(|supply| {
+            // Level history of this supply since its last refill
+            let mut history: Vec<(DateTime<Utc>, f64)> = Vec::new();
+            for sample in entry.supplies.iter().filter(|s| s.at >= since) {
+                let Some(percent) = sample.supplies.iter().find(|s| s.index == supply.index).and_then(|s| s.percent) else {
+                    continue;
+                };
+                if history.last().is_some_and(|(_, previous)| percent > previous + REFILL_JUMP_PERCENT) {
+                    history.clear();
+                }
+                history.push((sample.at, percent));
+            }
+
+            let mut days_until_empty = None;
+            let mut method = None;
+            if let (Some(&(first_at, first)), Some(&(last_at, last))) = (history.first(), history.last()) {
+                let consumed = first - last;
+                let pages = total_at(entry, last_at)
+                    .zip(total_at(entry, first_at))
+                    .and_then(|(end, start)| end.checked_sub(start))
+                    .filter(|pages| *pages > 0);
+                let elapsed_days = (last_at - first_at).num_seconds() as f64 / 86_400.0;
+
+                if consumed > 0.0 {
+                    if let (Some(pages), Some(daily_pages)) = (pages, daily_pages) {
+                        days_until_empty = Some(last / (consumed / pages as f64) / daily_pages);
+                        method = Some("pages".to_string());
+                    } else if elapsed_days > 0.0 {
+                        days_until_empty = Some(last / (consumed / elapsed_days));
+                        method = Some("time".to_string());
+                    }
+                }
+            }
+
+            let at_risk = supply.percent.is_some_and(|p| p <= AT_RISK_PERCENT)
+                || days_until_empty.is_some_and(|d| d <= AT_RISK_DAYS);
+            SupplyForecast {
+                printer_id: entry.id.clone(),
+                printer_name: entry.display_name().to_string(),
+                location: entry.location.clone(),
+                supply: supply.description.clone(),
+                kind: supply.kind.clone(),
+                percent: supply.percent,
+                days_until_empty,
+                // A nearly untouched supply can forecast centuries out, past what a date can hold
+                empty_on: days_until_empty
+                    .and_then(|d| Duration::try_seconds((d * 86_400.0) as i64))
+                    .and_then(|until| now.checked_add_signed(until)),
+                method,
+                at_risk,
+            }
+        })
+        .collect()
+}
+
+/// Forecasts for one printer, or for the whole inventory when `id` is omitted, soonest
+/// to run out first.
+#[tauri::command]
+pub fn get_supply_forecast(store: State<'_, InventoryStore>, id: Option<String>) -> Result<Vec<SupplyForecast>, String> {
+    if let Some(id) = &id {
+        validate::id(id)?;
+    }
+    let inventory = store.snapshot();
+    let mut forecasts: Vec<SupplyForecast> = inventory
+        .printers
+        .iter()
+        .filter(|entry| id.as_ref().is_none_or(|id| &entry.id == id))
+        .flat_map(forecast_entry)
+        .collect();
+    forecasts.sort_by(|a, b| {
+        let a = a.days_until_empty.unwrap_or(f64::INFINITY);
+        let b = b.days_until_empty.unwrap_or(f64::INFINITY);
+        a.total_cmp(&b)
+    });
+    Ok(forecasts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::CounterSample;
+    use crate::printer::NetworkPrinter;
+
+    fn day(n: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-03-01T09:00:00Z").unwrap().with_timezone(&Utc) + Duration::days(n)
+    }
+
+    fn toner(percent: Option<f64>) -> SupplyLevel {
+        SupplyLevel {
+            index: "1.1".to_string(),
+            description: "Black Toner".to_string(),
+            kind: "toner".to_string(),
+            max_capacity: 100,
+            level: percent.map_or(-3, |p| p as i64),
+            percent,
+        }
+    }
+
+    /// An entry with a toner level per `(day, percent)` and a page total per `(day, total)`.
+    fn entry(levels: &[(i64, f64)], totals: &[(i64, u64)]) -> InventoryEntry {
+        InventoryEntry {
+            id: "printer-1".to_string(),
+            printer: NetworkPrinter { name: "Office".to_string(), ip_address: "192.168.1.20".to_string(), ..Default::default() },
+            location: Some("Floor 2".to_string()),
+            tags: Vec::new(),
+            first_seen: day(0),
+            last_seen: day(0),
+            counters: totals
+                .iter()
+                .map(|&(n, total)| CounterSample { at: day(n), total: Some(total), mono: None, color: None, source: "snmp".to_string() })
+                .collect(),
+            supplies: levels.iter().map(|&(n, percent)| SupplySample { at: day(n), supplies: vec![toner(Some(percent))] }).collect(),
+            sources: Vec::new(),
+            pjl_counters: false,
+        }
+    }
+
+    fn days(forecast: &SupplyForecast) -> f64 {
+        forecast.days_until_empty.expect("no forecast")
+    }
+
+    #[test]
+    fn page_rate_is_preferred_when_counters_exist() {
+        // 20% used over 1000 pages, 100 pages a day: 60% lasts 3000 pages or 30 days
+        let forecast = &forecast_entry(&entry(&[(0, 80.0), (5, 70.0), (10, 60.0)], &[(0, 10_000), (10, 11_000)]))[0];
+        assert_eq!(forecast.method.as_deref(), Some("pages"));
+        assert!((days(forecast) - 30.0).abs() < 1e-9);
+        assert!((forecast.empty_on.unwrap() - day(40)).num_seconds().abs() <= 1);
+        assert_eq!(forecast.printer_id, "printer-1");
+        assert_eq!(forecast.supply, "Black Toner");
+        assert!(!forecast.at_risk);
+
+        // A new cartridge at day 20 saw 5000 pages; over the window 200 pages a day are printed
+        let refilled = entry(&[(10, 5.0), (20, 100.0), (30, 90.0)], &[(0, 0), (20, 1_000), (30, 6_000)]);
+        assert!((days(&forecast_entry(&refilled)[0]) - 90.0 / (10.0 / 5000.0) / 200.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn level_per_day_is_the_fallback() {
+        let forecast = &forecast_entry(&entry(&[(0, 80.0), (10, 60.0)], &[]))[0];
+        assert_eq!(forecast.method.as_deref(), Some("time"));
+        assert!((days(forecast) - 30.0).abs() < 1e-9);
+
+        // A counter reset leaves no page rate either
+        let forecast = &forecast_entry(&entry(&[(0, 80.0), (10, 60.0)], &[(0, 10_000), (10, 50)]))[0];
+        assert_eq!(forecast.method.as_deref(), Some("time"));
+        assert!((days(forecast) - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn history_starts_again_after_a_refill() {
+        let forecast = &forecast_entry(&entry(&[(0, 20.0), (2, 5.0), (3, 100.0), (8, 95.0)], &[]))[0];
+        assert!((days(forecast) - 95.0).abs() < 1e-9);
+        // Small rises are sensor noise, not a new cartridge
+        let forecast = &forecast_entry(&entry(&[(0, 50.0), (5, 44.0), (10, 48.0)], &[]))[0];
+        assert!((days(forecast) - 48.0 / 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn only_the_forecast_window_is_used() {
+        // The drop before the window would otherwise make the rate far higher
+        let forecast = &forecast_entry(&entry(&[(0, 90.0), (1, 60.0), (40, 59.0), (50, 58.0)], &[]))[0];
+        assert!((days(forecast) - 58.0 / 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unusable_histories_have_no_forecast() {
+        assert!(forecast_entry(&entry(&[], &[])).is_empty());
+        for levels in [&[(0, 60.0)][..], &[(0, 60.0), (10, 60.0)], &[(0, 60.0), (0, 59.0)]] {
+            let forecast = &forecast_entry(&entry(levels, &[]))[0];
+            assert_eq!(forecast.days_until_empty, None);
+            assert_eq!(forecast.method, None);
+            assert_eq!(forecast.empty_on, None);
+        }
+
+        let mut unknown = entry(&[(0, 60.0)], &[]);
+        unknown.supplies.push(SupplySample { at: day(10), supplies: vec![toner(None)] });
+        let forecast = &forecast_entry(&unknown)[0];
+        assert_eq!(forecast.percent, None);
+        assert_eq!(forecast.days_until_empty, None);
+        assert!(!forecast.at_risk);
+    }
+
+    #[test]
+    fn low_or_soon_empty_supplies_are_at_risk() {
+        // Low but not moving
+        assert!(forecast_entry(&entry(&[(0, 10.0), (10, 10.0)], &[]))[0].at_risk);
+        // 14 days left
+        assert!(forecast_entry(&entry(&[(0, 48.0), (10, 28.0)], &[]))[0].at_risk);
+        assert!(!forecast_entry(&entry(&[(0, 50.0), (10, 30.0)], &[]))[0].at_risk);
+    }
+
+    #[test]
+    fn far_off_forecasts_have_no_date() {
+        let forecast = &forecast_entry(&entry(&[(0, 60.000001), (10, 60.0)], &[]))[0];
+        assert!(days(forecast) > 1e8);
+        assert_eq!(forecast.empty_on, None);
+    }
+
+    #[test]
+    fn supply_values_are_read_from_snmp_types() {
+        assert_eq!(supply_kind(3), "toner");
+        assert_eq!(supply_kind(21), "tonerCartridge");
+        assert_eq!(supply_kind(99), "other");
+        assert_eq!(integer(&SnmpValue::Integer(-3)), Some(-3));
+        assert_eq!(integer(&SnmpValue::Unsigned32(7)), Some(7));
+        assert_eq!(integer(&SnmpValue::Null), None);
+        let octets = SnmpValue::OctetString { text: Some("Cyan Cartridge \0".to_string()), hex: String::new() };
+        assert_eq!(text(&octets).as_deref(), Some("Cyan Cartridge"));
+        assert_eq!(text(&SnmpValue::OctetString { text: None, hex: "ff".to_string() }), None);
+    }
+}
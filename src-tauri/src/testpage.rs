@@ -0,0 +1,791 @@
+use crate::convert::{self, MM_PER_INCH, POINTS_PER_MM};
+use crate::escpos::{self, Align, Receipt, ReceiptItem, TextStyle, ESCPOS_FORMAT};
+use crate::inventory::{InventoryEntry, InventoryStore};
+use crate::ipp_client::{self, IppError};
+use crate::ipp_message::{IppMessage, PRINTER_ATTRIBUTES};
+use crate::label::{self, LabelElement, LabelTemplate, Rotation, ZPL_FORMAT};
+use crate::printing::{self, JobHistory, JobRecord};
+use crate::raster::{RasterFormat, RasterPage, Sides};
+use crate::raw;
+use crate::settings::{self, PrintingSettings};
+use crate::text_pdf::{self, PageLayout};
+use crate::vault::CredentialVault;
+use chrono::{DateTime, Local, Utc};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+use tauri::State;
+use tracing::{debug, info, warn};
+
+/// Formats a test page can be generated in, best first.
+const PAGE_FORMATS: &[&str] = &["application/pdf", "image/pwg-raster", "image/urf", "text/plain"];
+const DOCUMENT: &str = "Test page";
+const GRID_MM: f32 = 10.0;
+
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRAY: [u8; 3] = [128, 128, 128];
+const LIGHT_GRAY: [u8; 3] = [200, 200, 200];
+/// Process colors first, then the secondary colors they mix to.
+const COLOR_BARS: &[(&str, [u8; 3])] = &[
+    ("C", [0, 255, 255]),
+    ("M", [255, 0, 255]),
+    ("Y", [255, 255, 0]),
+    ("K", [0, 0, 0]),
+    ("R", [255, 0, 0]),
+    ("G", [0, 255, 0]),
+    ("B", [0, 0, 255]),
+];
+
+/// 5x7 glyphs for ASCII 32..=126, one byte per column with the top row in bit 0.
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x14, 0x08, 0x3e, 0x08, 0x14],
+    [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7f, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02],
+    [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x7f, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7f, 0x40, 0x00],
+    [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20],
+    [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+/// What the test page says about the printer.
+#[derive(Debug, Clone)]
+pub struct TestPage {
+    /// Label and value rows.
+    pub details: Vec<(String, String)>,
+    /// Supply description and percent left, from the last poll.
+    pub supplies: Vec<(String, Option<f64>)>,
+    pub supplies_at: Option<DateTime<Utc>>,
+}
+
+impl TestPage {
+    pub fn new(entry: &InventoryEntry, uri: Option<&str>, format: &str, user: &str, now: DateTime<Utc>) -> TestPage {
+        let printer = &entry.printer;
+        let local = |at: DateTime<Utc>| at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string();
+        let model = match (&printer.vendor, &printer.model) {
+            (Some(vendor), Some(model)) if !model.starts_with(vendor.as_str()) => Some(format!("{} {}", vendor, model)),
+            (_, Some(model)) => Some(model.clone()),
+            (vendor, None) => vendor.clone(),
+        };
+        let sources = if entry.sources.is_empty() {
+            printer.status.clone()
+        } else {
+            entry.sources.join(", ")
+        };
+
+        let rows = [
+            ("Printer", Some(printer.name.clone())),
+            ("Printer ID", Some(entry.id.clone())),
+            ("IP address", Some(format!("{} (port {})", printer.ip_address, printer.port))),
+            ("Host name", printer.hostname.clone()),
+            ("Model", model),
+            ("Serial number", printer.serial.clone()),
+            ("Firmware", printer.firmware.clone()),
+            ("MAC address", printer.mac_address.clone()),
+            ("Location", entry.location.clone()),
+            ("Discovered by", Some(sources)),
+            ("First seen", Some(local(entry.first_seen))),
+            ("Last seen", Some(local(entry.last_seen))),
+            ("Sent to", uri.map(str::to_string)),
+            ("Format", Some(format.to_string())),
+            ("Printed", Some(local(now))),
+            ("Printed by", Some(user.to_string())),
+        ];
+        let details = rows
+            .into_iter()
+            .filter_map(|(label, value)| Some((label.to_string(), value.filter(|v| !v.is_empty())?)))
+            .collect();
+
+        let last = entry.supplies.last();
+        TestPage {
+            details,
+            supplies: last
+                .map(|sample| sample.supplies.iter().map(|s| (s.description.clone(), s.percent)).collect())
+                .unwrap_or_default(),
+            supplies_at: last.map(|sample| sample.at),
+        }
+    }
+}
+
+/// The best format in `supported` to send a test page as; PDF when the printer did not
+/// say.
+pub fn page_format(supported: &[String]) -> Option<&'static str> {
+    if supported.is_empty() {
+        return Some(PAGE_FORMATS[0]);
+    }
+    PAGE_FORMATS.iter().copied().find(|format| supported.iter().any(|f| f == format))
+}
+
+/// Drawing operations shared by the PDF and raster renderers. Positions and sizes are in
+/// millimeters from the top left corner of the paper.
+trait Canvas {
+    fn fill(&mut self, x: f32, y: f32, width: f32, height: f32, color: [u8; 3]);
+    /// Text whose top is at `y`, `size` millimeters tall.
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str);
+
+    fn outline(&mut self, x: f32, y: f32, width: f32, height: f32, line: f32, color: [u8; 3]) {
+        self.fill(x, y, width, line, color);
+        self.fill(x, y + height - line, width, line, color);
+        self.fill(x, y, line, height, color);
+        self.fill(x + width - line, y, line, height, color);
+    }
+}
+
+fn supply_color(description: &str) -> [u8; 3] {
+    let description = description.to_ascii_lowercase();
+    COLOR_BARS
+        .iter()
+        .zip(["cyan", "magenta", "yellow", "black"])
+        .find(|(_, name)| description.contains(name))
+        .map(|((_, color), _)| *color)
+        .unwrap_or(GRAY)
+}
+
+/// Lays out the test page on `paper` (width and height in mm) within `margin`.
+fn draw(page: &TestPage, paper: (f32, f32), margin: f32, canvas: &mut impl Canvas) {
+    let margin = margin.min(paper.0.min(paper.1) / 4.0);
+    let left = margin + 6.0;
+    let width = paper.0 - 2.0 * margin - 12.0;
+    let bottom = paper.1 - margin - 6.0;
+
+    // The frame shows whether the printable area is where the margins say it is
+    canvas.outline(margin, margin, paper.0 - 2.0 * margin, paper.1 - 2.0 * margin, 0.4, BLACK);
+
+    let mut y = margin + 6.0;
+    canvas.text(left, y, 7.0, true, "Printer test page");
+    y += 12.0;
+    for (label, value) in &page.details {
+        canvas.text(left, y, 3.2, true, label);
+        canvas.text(left + 40.0, y, 3.2, false, value);
+        y += 4.8;
+    }
+
+    y += 4.0;
+    let heading = match page.supplies_at {
+        Some(at) => format!("Supplies (read {})", at.with_timezone(&Local).format("%Y-%m-%d %H:%M")),
+        None => "Supplies".to_string(),
+    };
+    canvas.text(left, y, 4.2, true, &heading);
+    y += 7.0;
+    if page.supplies.is_empty() {
+        canvas.text(left, y, 3.2, false, "No supply levels have been read from this printer yet.");
+        y += 4.8;
+    }
+    let bar_width = (width - 80.0).clamp(10.0, 80.0);
+    for (description, percent) in &page.supplies {
+        canvas.text(left, y, 3.2, false, description);
+        canvas.outline(left + 60.0, y, bar_width, 3.2, 0.2, GRAY);
+        if let Some(percent) = percent {
+            let filled = bar_width * (percent.clamp(0.0, 100.0) as f32) / 100.0;
+            canvas.fill(left + 60.0, y, filled, 3.2, supply_color(description));
+            canvas.text(left + 63.0 + bar_width, y, 3.2, false, &format!("{:.0}%", percent));
+        } else {
+            canvas.text(left + 63.0 + bar_width, y, 3.2, false, "unknown");
+        }
+        y += 4.8;
+    }
+
+    y += 4.0;
+    canvas.text(left, y, 4.2, true, "Color bars");
+    y += 7.0;
+    let block = width / COLOR_BARS.len() as f32;
+    for (i, (name, color)) in COLOR_BARS.iter().enumerate() {
+        let x = left + i as f32 * block;
+        canvas.fill(x, y, block - 1.0, 12.0, *color);
+        canvas.text(x, y + 13.0, 3.0, false, name);
+    }
+    y += 19.0;
+    let step = width / 11.0;
+    for i in 0..=10u32 {
+        let x = left + i as f32 * step;
+        let level = 255 - (i * 255 / 10) as u8;
+        canvas.fill(x, y, step, 8.0, [level; 3]);
+        canvas.text(x, y + 9.0, 2.5, false, &format!("{}%", i * 10));
+    }
+    canvas.outline(left, y, step * 11.0, 8.0, 0.2, GRAY);
+    y += 16.0;
+
+    // Grid lines every 10 mm, darker every 50 mm, measured from the paper's corner
+    canvas.text(left, y, 4.2, true, "Alignment grid (10 mm)");
+    y += 7.0;
+    let first_x = (left / GRID_MM).ceil() * GRID_MM;
+    let first_y = (y / GRID_MM).ceil() * GRID_MM;
+    let (last_x, last_y) = (left + width, bottom);
+    if last_y - first_y < GRID_MM || last_x - first_x < GRID_MM {
+        return;
+    }
+    let span_x = ((last_x - first_x) / GRID_MM).floor() * GRID_MM;
+    let span_y = ((last_y - first_y) / GRID_MM).floor() * GRID_MM;
+    let mut x = first_x;
+    while x <= first_x + span_x + 0.01 {
+        let color = if ((x / GRID_MM).round() as u32).is_multiple_of(5) { BLACK } else { LIGHT_GRAY };
+        canvas.fill(x - 0.1, first_y, 0.2, span_y, color);
+        x += GRID_MM;
+    }
+    let mut y = first_y;
+    while y <= first_y + span_y + 0.01 {
+        let color = if ((y / GRID_MM).round() as u32).is_multiple_of(5) { BLACK } else { LIGHT_GRAY };
+        canvas.fill(first_x, y - 0.1, span_x, 0.2, color);
+        y += GRID_MM;
+    }
+}
+
+struct PdfCanvas {
+    height: f32,
+    content: Vec<u8>,
+}
+
+impl Canvas for PdfCanvas {
+    fn fill(&mut self, x: f32, y: f32, width: f32, height: f32, color: [u8; 3]) {
+        let [r, g, b] = color.map(|c| c as f32 / 255.0);
+        self.content.extend_from_slice(
+            format!(
+                "{:.3} {:.3} {:.3} rg {:.2} {:.2} {:.2} {:.2} re f\n",
+                r,
+                g,
+                b,
+                x * POINTS_PER_MM,
+                (self.height - y - height) * POINTS_PER_MM,
+                width * POINTS_PER_MM,
+                height * POINTS_PER_MM
+            )
+            .as_bytes(),
+        );
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let encoded: Vec<u8> = text.chars().filter(|c| !c.is_control()).map(text_pdf::win_ansi).collect();
+        self.content.extend_from_slice(
+            format!(
+                "0 0 0 rg BT /F{} {:.2} Tf {:.2} {:.2} Td (",
+                if bold { 2 } else { 1 },
+                size * POINTS_PER_MM,
+                x * POINTS_PER_MM,
+                // Cap height is about 0.75 of the font size
+                (self.height - y - size * 0.75) * POINTS_PER_MM
+            )
+            .as_bytes(),
+        );
+        text_pdf::escape(&encoded, &mut self.content);
+        self.content.extend_from_slice(b") Tj ET\n");
+    }
+}
+
+struct RasterCanvas {
+    image: RgbImage,
+    dots_per_mm: f32,
+}
+
+impl RasterCanvas {
+    fn to_px(&self, mm: f32) -> i64 {
+        (mm * self.dots_per_mm).round() as i64
+    }
+
+    fn fill_px(&mut self, x: i64, y: i64, width: i64, height: i64, color: [u8; 3]) {
+        let (image_width, image_height) = (self.image.width() as i64, self.image.height() as i64);
+        for py in y.max(0)..(y + height).min(image_height) {
+            for px in x.max(0)..(x + width).min(image_width) {
+                self.image.put_pixel(px as u32, py as u32, Rgb(color));
+            }
+        }
+    }
+}
+
+impl Canvas for RasterCanvas {
+    fn fill(&mut self, x: f32, y: f32, width: f32, height: f32, color: [u8; 3]) {
+        let (x0, y0) = (self.to_px(x), self.to_px(y));
+        // Keep hairlines at least a dot wide
+        let width = (self.to_px(x + width) - x0).max(1);
+        let height = (self.to_px(y + height) - y0).max(1);
+        self.fill_px(x0, y0, width, height, color);
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        // Glyphs are 7 dots tall in a cell of 8; scale them to the font size
+        let scale = (self.to_px(size) / 8).max(1);
+        let (mut left, top) = (self.to_px(x), self.to_px(y));
+        for c in text.chars() {
+            let code = if (' '..='~').contains(&c) { c as usize } else { '?' as usize };
+            for (column, bits) in FONT_5X7[code - 32].iter().enumerate() {
+                for row in 0..7 {
+                    if bits & (1 << row) != 0 {
+                        let width = if bold { scale + (scale + 1) / 2 } else { scale };
+                        self.fill_px(left + column as i64 * scale, top + row * scale, width, scale, BLACK);
+                    }
+                }
+            }
+            left += if bold { 7 } else { 6 } * scale;
+        }
+    }
+}
+
+/// The test page as a PDF on `paper`.
+pub fn render_pdf(page: &TestPage, paper: (f32, f32), margin: f32) -> Vec<u8> {
+    let mut canvas = PdfCanvas {
+        height: paper.1,
+        content: Vec::new(),
+    };
+    draw(page, paper, margin, &mut canvas);
+    let layout = PageLayout {
+        width: paper.0 * POINTS_PER_MM,
+        height: paper.1 * POINTS_PER_MM,
+        margin: margin * POINTS_PER_MM,
+    };
+    text_pdf::pdf_document(&[canvas.content], &["Helvetica", "Helvetica-Bold"], layout)
+}
+
+/// The test page as plain text, for printers that take nothing else.
+pub fn render_text(page: &TestPage) -> String {
+    let mut text = String::from("PRINTER TEST PAGE\n\n");
+    for (label, value) in &page.details {
+        text.push_str(&format!("{:<15}{}\n", label, value));
+    }
+    text.push_str("\nSupplies\n");
+    if page.supplies.is_empty() {
+        text.push_str("  none read yet\n");
+    }
+    for (description, percent) in &page.supplies {
+        match percent {
+            Some(percent) => {
+                let filled = (percent.clamp(0.0, 100.0) / 5.0).round() as usize;
+                text.push_str(&format!(
+                    "  {:<30} [{}{}] {:.0}%\n",
+                    description,
+                    "#".repeat(filled),
+                    ".".repeat(20 - filled),
+                    percent
+                ));
+            }
+            None => text.push_str(&format!("  {:<30} unknown\n", description)),
+        }
+    }
+    // A column ruler shows where lines start and wrap
+    text.push('\n');
+    text.push_str(&(1..=8).map(|i| format!("....:....{}", i)).collect::<String>());
+    text.push('\n');
+    text
+}
+
+/// What a printer without IPP understands on the raw port, guessed from its name, make
+/// and model. Receipt and label printers print plain text as a long blank strip or not at
+/// all, so they get a receipt or a label instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawLanguage {
+    Text,
+    EscPos,
+    Zpl,
+}
+
+impl RawLanguage {
+    pub fn format(self) -> &'static str {
+        match self {
+            RawLanguage::Text => "text/plain",
+            RawLanguage::EscPos => ESCPOS_FORMAT,
+            RawLanguage::Zpl => ZPL_FORMAT,
+        }
+    }
+}
+
+const ZPL_HINTS: &[&str] = &["zebra", "zpl"];
+const ESCPOS_HINTS: &[&str] = &["tm-", "tsp", "star micronics", "bixolon", "srp-", "citizen", "ct-s", "receipt", "esc/pos"];
+
+pub fn raw_language(entry: &InventoryEntry) -> RawLanguage {
+    let printer = &entry.printer;
+    let identity = [Some(&printer.name), printer.vendor.as_ref(), printer.model.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|s| s.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if ZPL_HINTS.iter().any(|hint| identity.contains(hint)) {
+        RawLanguage::Zpl
+    } else if ESCPOS_HINTS.iter().any(|hint| identity.contains(hint)) {
+        RawLanguage::EscPos
+    } else {
+        RawLanguage::Text
+    }
+}
+
+fn supply_text(percent: Option<f64>) -> String {
+    percent.map_or_else(|| "unknown".to_string(), |percent| format!("{:.0}%", percent))
+}
+
+/// The test page as an 80 mm receipt, one row per detail, then cut.
+pub fn render_receipt_page(page: &TestPage) -> Result<Vec<u8>, String> {
+    let heading = |text: &str| ReceiptItem::Text {
+        text: text.to_string(),
+        align: Align::Center,
+        style: TextStyle { bold: true, ..Default::default() },
+    };
+    let mut items = vec![heading("PRINTER TEST PAGE"), ReceiptItem::Separator];
+    items.extend(page.details.iter().map(|(label, value)| ReceiptItem::Row {
+        left: label.clone(),
+        right: value.clone(),
+        style: TextStyle::default(),
+    }));
+    items.extend([ReceiptItem::Separator, heading("Supplies")]);
+    if page.supplies.is_empty() {
+        items.push(ReceiptItem::Text {
+            text: "none read yet".to_string(),
+            align: Align::Left,
+            style: TextStyle::default(),
+        });
+    }
+    items.extend(page.supplies.iter().map(|(description, percent)| ReceiptItem::Row {
+        left: description.clone(),
+        right: supply_text(*percent),
+        style: TextStyle::default(),
+    }));
+    escpos::render_receipt(&Receipt {
+        paper_mm: 80,
+        items,
+        cut: true,
+        open_drawer: false,
+    })
+}
+
+/// The test page as a ZPL label 2 inches wide at 203 dpi, one line per detail. The text
+/// goes in as label data so values are never read as placeholders.
+pub fn render_label_page(page: &TestPage) -> Result<String, String> {
+    const MARGIN: u32 = 16;
+    const LINE: u32 = 24;
+    let mut lines = vec!["PRINTER TEST PAGE".to_string()];
+    lines.extend(page.details.iter().map(|(label, value)| format!("{}: {}", label, value)));
+    lines.extend(page.supplies.iter().map(|(description, percent)| format!("{}: {}", description, supply_text(*percent))));
+
+    let data: HashMap<String, String> = lines.into_iter().enumerate().map(|(i, line)| (format!("line{}", i), line)).collect();
+    let elements = (0..data.len() as u32)
+        .map(|i| LabelElement::Text {
+            x: MARGIN,
+            y: MARGIN + i * LINE,
+            text: format!("{{{{line{}}}}}", i),
+            height: if i == 0 { LINE } else { LINE - 4 },
+            rotation: Rotation::Normal,
+        })
+        .collect();
+    let template = LabelTemplate {
+        name: DOCUMENT.to_string(),
+        width: 406,
+        height: 2 * MARGIN + data.len() as u32 * LINE,
+        elements,
+    };
+    label::render_label(&template, &data, 1)
+}
+
+/// The test page in `format`, sized for the printer's default paper.
+pub fn render(page: &TestPage, format: &str, attributes: &IppMessage, settings: &PrintingSettings) -> Result<Vec<u8>, String> {
+    let (media, paper) = convert::media(attributes, settings);
+    match format {
+        "application/pdf" => Ok(render_pdf(page, paper, settings.margin_mm)),
+        "text/plain" => Ok(render_text(page).into_bytes()),
+        _ => {
+            let raster_format = RasterFormat::from_mime(format).ok_or_else(|| format!("Cannot make a test page as {}", format))?;
+            let (dpi, mode) = convert::raster_mode(format, attributes, true);
+            let dots_per_mm = dpi as f32 / MM_PER_INCH;
+            let width = ((paper.0 * dots_per_mm).round() as u32).max(1);
+            let height = ((paper.1 * dots_per_mm).round() as u32).max(1);
+            let mut canvas = RasterCanvas {
+                image: RgbImage::from_pixel(width, height, Rgb([255, 255, 255])),
+                dots_per_mm,
+            };
+            draw(page, paper, settings.margin_mm, &mut canvas);
+            let mut raster = RasterPage::blank(width, height, dpi, mode);
+            convert::paint(canvas.image, &mut raster, 0, 0);
+            convert::encode_raster(raster_format, &[raster], Sides::OneSided, media, attributes)
+        }
+    }
+}
+
+/// Prints a diagnostic page on `printer_id` in the best format it takes over IPP, or on
+/// the raw port when it has no IPP service: a receipt or label for ESC/POS and ZPL
+/// printers, plain text otherwise. A printer whose IPP service refuses the credential or
+/// certificate is not sent a raw page. Logs the outcome in the job history.
+#[tauri::command]
+pub fn print_test_page(
+    inventory: State<'_, InventoryStore>,
+    vault: State<'_, CredentialVault>,
+    history: State<'_, JobHistory>,
+    printer_id: String,
+) -> Result<JobRecord, String> {
+    let (entry, addr) = printing::network_printer(&inventory.snapshot(), &printer_id)?;
+    let user = printing::current_user();
+    let settings = settings::current().printing.clone();
+    let mut record = JobRecord::new(&entry, &user, DOCUMENT, "", 1, 0);
+
+    let auth = printing::ipp_auth(&vault, &entry, &addr);
+    let result = match ipp_client::find_endpoint(&addr, auth.as_ref()) {
+        Ok((endpoint, attributes)) => {
+            record.uri = Some(endpoint.uri());
+            let supported = attributes.strings(PRINTER_ATTRIBUTES, "document-format-supported");
+            page_format(&supported)
+                .ok_or_else(|| format!("{} takes no test page format (supported: {})", entry.display_name(), supported.join(", ")))
+                .and_then(|format| {
+                    record.format = format.to_string();
+                    let page = TestPage::new(&entry, record.uri.as_deref(), format, &user, Utc::now());
+                    let data = render(&page, format, &attributes, &settings)?;
+                    record.bytes = data.len();
+                    ipp_client::print_job(&endpoint, auth.as_ref(), DOCUMENT, &user, format, 1, Sides::OneSided, data)
+                })
+                .map(Some)
+        }
+        Err(IppError::Refused(e)) => Err(e),
+        Err(IppError::Unavailable(e)) => {
+            let language = raw_language(&entry);
+            debug!("{}; falling back to a raw {} page", e, language.format());
+            record.uri = Some(format!("socket://{}:{}", addr.url_host(), raw::RAW_PORT));
+            record.format = language.format().to_string();
+            let page = TestPage::new(&entry, record.uri.as_deref(), language.format(), &user, Utc::now());
+            let data = match language {
+                RawLanguage::Text => Ok((render_text(&page).replace('\n', "\r\n") + "\u{c}").into_bytes()),
+                RawLanguage::EscPos => render_receipt_page(&page),
+                RawLanguage::Zpl => render_label_page(&page).map(String::into_bytes),
+            };
+            data.and_then(|data| {
+                record.bytes = data.len();
+                raw::send(&addr, raw::RAW_PORT, &data).map(|_| None)
+            })
+        }
+    };
+
+    match &result {
+        Ok(job_id) => {
+            info!(printer = %entry.id, format = %record.format, "test page sent");
+            record.job_id = *job_id;
+        }
+        Err(e) => {
+            warn!(printer = %entry.id, "test page failed: {}", e);
+            record.error = Some(e.clone());
+        }
+    }
+    if let Err(e) = history.append(&record) {
+        warn!("{}", e);
+    }
+    result.map(|_| record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::EscPos;
+
+    fn page() -> TestPage {
+        TestPage {
+            details: vec![
+                ("Printer".to_string(), "Front desk".to_string()),
+                ("IP address".to_string(), "10.0.0.5 (port 9100)".to_string()),
+                ("Model".to_string(), "Zebra ZD421 {{x}}".to_string()),
+            ],
+            supplies: vec![("Black toner".to_string(), Some(42.0)), ("Waste".to_string(), None)],
+            supplies_at: None,
+        }
+    }
+
+    fn entry(name: &str, vendor: Option<&str>, model: Option<&str>) -> InventoryEntry {
+        let now = Utc::now();
+        InventoryEntry {
+            id: "ip:10.0.0.5".to_string(),
+            printer: crate::printer::NetworkPrinter {
+                name: name.to_string(),
+                ip_address: "10.0.0.5".to_string(),
+                vendor: vendor.map(str::to_string),
+                model: model.map(str::to_string),
+                ..Default::default()
+            },
+            location: None,
+            tags: Vec::new(),
+            first_seen: now,
+            last_seen: now,
+            counters: Vec::new(),
+            supplies: Vec::new(),
+            sources: Vec::new(),
+            pjl_counters: false,
+        }
+    }
+
+    #[test]
+    fn page_format_prefers_pdf_then_raster() {
+        let formats = |list: &[&str]| list.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        assert_eq!(page_format(&[]), Some("application/pdf"));
+        assert_eq!(page_format(&formats(&["image/urf", "application/pdf"])), Some("application/pdf"));
+        assert_eq!(page_format(&formats(&["image/urf", "image/pwg-raster"])), Some("image/pwg-raster"));
+        assert_eq!(page_format(&formats(&["text/plain", "image/urf"])), Some("image/urf"));
+        assert_eq!(page_format(&formats(&["application/postscript"])), None);
+    }
+
+    #[test]
+    fn raw_language_from_make_and_model() {
+        assert_eq!(raw_language(&entry("Office", Some("HP"), Some("LaserJet M404"))), RawLanguage::Text);
+        assert_eq!(raw_language(&entry("Dock", Some("Zebra"), Some("ZT230"))), RawLanguage::Zpl);
+        assert_eq!(raw_language(&entry("Labels", None, Some("ZPL printer"))), RawLanguage::Zpl);
+        assert_eq!(raw_language(&entry("Till 1", Some("Epson"), Some("TM-T88VI"))), RawLanguage::EscPos);
+        assert_eq!(raw_language(&entry("Till 2", Some("Star Micronics"), None)), RawLanguage::EscPos);
+        assert_eq!(raw_language(&entry("Kitchen receipt printer", None, None)), RawLanguage::EscPos);
+        assert_eq!(RawLanguage::Zpl.format(), ZPL_FORMAT);
+        assert_eq!(RawLanguage::EscPos.format(), ESCPOS_FORMAT);
+    }
+
+    #[test]
+    fn renders_text_page() {
+        let ruler = (1..=8).map(|i| format!("....:....{}", i)).collect::<String>();
+        assert_eq!(
+            render_text(&page()),
+            format!(
+                "PRINTER TEST PAGE\n\n\
+                 Printer        Front desk\n\
+                 IP address     10.0.0.5 (port 9100)\n\
+                 Model          Zebra ZD421 {{{{x}}}}\n\
+                 \nSupplies\n  \
+                 Black toner                    [########............] 42%\n  \
+                 Waste                          unknown\n\
+                 \n{}\n",
+                ruler
+            )
+        );
+    }
+
+    #[test]
+    fn renders_label_page() {
+        assert_eq!(
+            render_label_page(&page()).unwrap(),
+            "^XA\n\
+             ^CI28\n\
+             ^PW406\n\
+             ^LL176\n\
+             ^FO16,16^A0N,24,24^FH^FDPRINTER TEST PAGE^FS\n\
+             ^FO16,40^A0N,20,20^FH^FDPrinter: Front desk^FS\n\
+             ^FO16,64^A0N,20,20^FH^FDIP address: 10.0.0.5 (port 9100)^FS\n\
+             ^FO16,88^A0N,20,20^FH^FDModel: Zebra ZD421 {{x}}^FS\n\
+             ^FO16,112^A0N,20,20^FH^FDBlack toner: 42%^FS\n\
+             ^FO16,136^A0N,20,20^FH^FDWaste: unknown^FS\n\
+             ^PQ1\n\
+             ^XZ\n"
+        );
+    }
+
+    #[test]
+    fn renders_receipt_page() {
+        let bold = TextStyle { bold: true, ..Default::default() };
+        let plain = TextStyle::default();
+        let row = |left: &str, right: &str| format!("{}{}{}", left, " ".repeat(48 - left.len() - right.len()), right);
+        let mut expected = EscPos::new();
+        expected
+            .align(Align::Center)
+            .style(bold)
+            .line("PRINTER TEST PAGE")
+            .align(Align::Left)
+            .style(plain)
+            .line(&"-".repeat(48))
+            .align(Align::Left)
+            .style(plain)
+            .line(&row("Printer", "Front desk"))
+            .align(Align::Left)
+            .style(plain)
+            .line(&row("IP address", "10.0.0.5 (port 9100)"))
+            .align(Align::Left)
+            .style(plain)
+            .line(&row("Model", "Zebra ZD421 {{x}}"))
+            .align(Align::Left)
+            .style(plain)
+            .line(&"-".repeat(48))
+            .align(Align::Center)
+            .style(bold)
+            .line("Supplies")
+            .align(Align::Left)
+            .style(plain)
+            .line(&row("Black toner", "42%"))
+            .align(Align::Left)
+            .style(plain)
+            .line(&row("Waste", "unknown"))
+            .align(Align::Left)
+            .style(plain)
+            .feed(3)
+            .cut(true);
+        assert_eq!(render_receipt_page(&page()).unwrap(), expected.into_bytes());
+    }
+}
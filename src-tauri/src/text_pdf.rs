@@ -0,0 +1,267 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+/// Standard PDF fonts plain text can be set in; every PDF reader and printer has them.
+pub const FONTS: &[&str] = &["Courier", "Helvetica", "Times-Roman"];
+const TAB_WIDTH: usize = 8;
+
+/// Advance widths of ASCII 32..=126 in 1/1000 em, from the Adobe core font metrics.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667,
+    556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556,
+    556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722,
+    500, 500, 500, 334, 260, 334, 584,
+];
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722,
+    611, 889, 722, 722, 556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, 333, 444,
+    500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389, 278, 500, 500, 722,
+    500, 500, 444, 480, 200, 480, 541,
+];
+
+/// Page geometry in points.
+#[derive(Debug, Clone, Copy)]
+pub struct PageLayout {
+    pub width: f32,
+    pub height: f32,
+    pub margin: f32,
+}
+
+/// Windows-1252 code for `c` (the `WinAnsiEncoding` of the standard fonts), `?` when
+/// the fonts have no glyph for it.
+pub fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        '‹' => 0x8b,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9a,
+        '›' => 0x9b,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        _ => b'?',
+    }
+}
+
+/// Width of one encoded character in 1/1000 em. Accented and other non-ASCII letters
+/// are taken as a lowercase `n`, close enough for line breaking.
+fn char_width(font: &str, code: u8) -> u32 {
+    let table = match font {
+        "Helvetica" => &HELVETICA_WIDTHS,
+        "Times-Roman" => &TIMES_WIDTHS,
+        _ => return 600,
+    };
+    match code {
+        32..=126 => table[(code - 32) as usize] as u32,
+        _ => table[(b'n' - 32) as usize] as u32,
+    }
+}
+
+/// Breaks `text` into lines no wider than `max_width` (1/1000 em units times the font
+/// size), at spaces where possible. Form feeds become `None` page breaks.
+fn layout_lines(text: &str, font: &str, max_width: u32) -> Vec<Option<Vec<u8>>> {
+    let mut lines = Vec::new();
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    for (i, page) in text.split('\u{c}').enumerate() {
+        if i > 0 {
+            lines.push(None);
+        }
+        for raw in page.lines() {
+            // Expand tabs, then encode
+            let mut line = Vec::new();
+            for c in raw.chars() {
+                match c {
+                    '\t' => line.resize((line.len() / TAB_WIDTH + 1) * TAB_WIDTH, b' '),
+                    c if c.is_control() => {}
+                    c => line.push(win_ansi(c)),
+                }
+            }
+
+            let mut start = 0;
+            loop {
+                let mut width = 0;
+                let mut end = start;
+                let mut last_space = None;
+                while end < line.len() {
+                    let w = char_width(font, line[end]);
+                    if width + w > max_width && end > start {
+                        break;
+                    }
+                    if line[end] == b' ' {
+                        last_space = Some(end);
+                    }
+                    width += w;
+                    end += 1;
+                }
+                if end == line.len() {
+                    lines.push(Some(line[start..].to_vec()));
+                    break;
+                }
+                // Wrap after the last space when there is one, else mid-word
+                let split = match last_space {
+                    Some(space) if space > start => space + 1,
+                    _ => end,
+                };
+                lines.push(Some(line[start..split].to_vec()));
+                start = split;
+            }
+        }
+    }
+    lines
+}
+
+/// Writes `line` as the inside of a PDF string literal.
+pub fn escape(line: &[u8], out: &mut Vec<u8>) {
+    for &b in line {
+        match b {
+            b'(' | b')' | b'\\' => {
+                out.push(b'\\');
+                out.push(b);
+            }
+            0x80..=0xff => out.extend_from_slice(format!("\\{:03o}", b).as_bytes()),
+            _ => out.push(b),
+        }
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+/// Sets plain `text` in `font` at `size` points on pages of `layout` and returns the PDF.
+pub fn text_to_pdf(text: &str, font: &str, size: f32, layout: PageLayout) -> Vec<u8> {
+    let leading = size * 1.2;
+    let usable_width = (layout.width - 2.0 * layout.margin).max(size);
+    let usable_height = (layout.height - 2.0 * layout.margin).max(leading);
+    let lines_per_page = ((usable_height / leading) as usize).max(1);
+    let max_width = (usable_width / size * 1000.0) as u32;
+
+    // Split into pages, honoring form feeds
+    let mut pages: Vec<Vec<Vec<u8>>> = vec![Vec::new()];
+    for line in layout_lines(text, font, max_width) {
+        match line {
+            None => pages.push(Vec::new()),
+            Some(line) => {
+                if pages.last().is_some_and(|page| page.len() == lines_per_page) {
+                    pages.push(Vec::new());
+                }
+                if let Some(page) = pages.last_mut() {
+                    page.push(line);
+                }
+            }
+        }
+    }
+
+    let contents: Vec<Vec<u8>> = pages
+        .iter()
+        .map(|lines| {
+            let mut content = format!(
+                "BT\n/F1 {:.2} Tf\n{:.2} TL\n{:.2} {:.2} Td\n",
+                size,
+                leading,
+                layout.margin,
+                layout.height - layout.margin - size
+            )
+            .into_bytes();
+            for line in lines {
+                content.push(b'(');
+                escape(line, &mut content);
+                content.extend_from_slice(b") Tj T*\n");
+            }
+            content.extend_from_slice(b"ET");
+            content
+        })
+        .collect();
+    pdf_document(&contents, &[font], layout)
+}
+
+/// A PDF with one page of `layout` size per content stream. `fonts` are standard fonts
+/// the streams refer to as `/F1`, `/F2` and so on.
+pub fn pdf_document(contents: &[Vec<u8>], fonts: &[&str], layout: PageLayout) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    let mut object = |pdf: &mut Vec<u8>, body: &[u8]| {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+        pdf.extend_from_slice(body);
+        pdf.extend_from_slice(b"\nendobj\n");
+    };
+
+    // 1 catalog, 2 page tree, then the fonts, then a page and its content stream per page
+    let first_page = 3 + fonts.len();
+    let kids: Vec<String> = (0..contents.len()).map(|i| format!("{} 0 R", first_page + 2 * i)).collect();
+    object(&mut pdf, b"<< /Type /Catalog /Pages 2 0 R >>");
+    object(
+        &mut pdf,
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), contents.len()).as_bytes(),
+    );
+    let mut font_resources = String::new();
+    for (i, font) in fonts.iter().enumerate() {
+        object(
+            &mut pdf,
+            format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", font).as_bytes(),
+        );
+        font_resources.push_str(&format!("/F{} {} 0 R ", i + 1, 3 + i));
+    }
+    for (i, content) in contents.iter().enumerate() {
+        object(
+            &mut pdf,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << {}>> >> /Contents {} 0 R >>",
+                layout.width,
+                layout.height,
+                font_resources,
+                first_page + 1 + 2 * i
+            )
+            .as_bytes(),
+        );
+
+        let stream = deflate(content);
+        let mut body = format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", stream.len()).into_bytes();
+        body.extend_from_slice(&stream);
+        body.extend_from_slice(b"\nendstream");
+        object(&mut pdf, &body);
+    }
+
+    let xref = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes());
+    for offset in &offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            offsets.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+    pdf
+}
@@ -0,0 +1,99 @@
+use crate::inventory::InventoryStore;
+use crate::settings;
+use tauri::menu::{CheckMenuItem, IsMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Listener, Manager, Wry};
+use tracing::warn;
+
+const TRAY_ID: &str = "main";
+/// Menu ids of the default printer choices are this prefix plus the printer or pool id.
+const TARGET_PREFIX: &str = "target:";
+
+/// Adds the tray icon. Its menu lists pools and network printers so the default print
+/// target can be switched without opening the window.
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Printer Manager")
+        .menu(&build_menu(app)?)
+        .on_menu_event(|app, event| on_menu_event(app, event.id().as_ref()));
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+
+    // The default target can also change from the settings page
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |_| refresh(&handle));
+    Ok(())
+}
+
+/// Rebuilds the menu after printers, pools or the default target changed.
+pub fn refresh(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    if let Err(e) = build_menu(app).and_then(|menu| tray.set_menu(Some(menu))) {
+        warn!("failed to update the tray menu: {}", e);
+    }
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let default_target = settings::current().printing.default_target.clone();
+    let inventory = app
+        .try_state::<InventoryStore>()
+        .map(|store| store.snapshot())
+        .unwrap_or_default();
+
+    let mut targets = Vec::new();
+    for pool in &inventory.pools {
+        let label = format!("{} ({} printers)", pool.name, pool.members.len());
+        targets.push((pool.id.clone(), label));
+    }
+    for entry in inventory.printers.iter().filter(|e| e.address().is_some()) {
+        targets.push((entry.id.clone(), entry.display_name().to_string()));
+    }
+
+    let mut items = Vec::new();
+    for (id, label) in &targets {
+        let checked = default_target.as_deref() == Some(id.as_str());
+        items.push(CheckMenuItem::with_id(app, format!("{}{}", TARGET_PREFIX, id), label, true, checked, None::<&str>)?);
+    }
+    let default_printer = if items.is_empty() {
+        let none = MenuItem::with_id(app, "no-targets", "No network printers yet", false, None::<&str>)?;
+        Submenu::with_items(app, "Default printer", true, &[&none])?
+    } else {
+        let items: Vec<&dyn IsMenuItem<Wry>> = items.iter().map(|item| item as &dyn IsMenuItem<Wry>).collect();
+        Submenu::with_items(app, "Default printer", true, &items)?
+    };
+
+    let show = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    Menu::with_items(
+        app,
+        &[&show, &default_printer, &PredefinedMenuItem::separator(app)?, &quit],
+    )
+}
+
+fn on_menu_event(app: &AppHandle, id: &str) {
+    match id {
+        "show" => {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }
+        "quit" => app.exit(0),
+        _ => {
+            let Some(target) = id.strip_prefix(TARGET_PREFIX) else {
+                return;
+            };
+            let target = target.to_string();
+            if let Err(e) = settings::update(Some(app), |settings| settings.printing.default_target = Some(target)) {
+                warn!("failed to set the default printer: {}", e);
+            }
+            // Check items toggle themselves; redraw so only the chosen one stays checked
+            refresh(app);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use crate::addr::HostAddr;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const TRUST_FILE: &str = "trust_store.json";
+pub const CA_BUNDLE_FILE: &str = "ca_bundle.pem";
+/// Unresolved certificate changes kept for review.
+const MAX_MISMATCHES: usize = 100;
+
+/// The certificate fingerprint pinned for a host and port the first time it was seen.
+/// Services on one printer often present different certificates, so each port has its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pin {
+    /// SHA-256 of the DER certificate, as colon-separated hex.
+    pub fingerprint: String,
+    pub first_seen: DateTime<Utc>,
+}
+
+/// A host that presented a different certificate than the pinned one. Connections to it
+/// fail until an admin trusts the new certificate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mismatch {
+    /// `host:port`, the key of the pin it contradicts.
+    pub host: String,
+    pub pinned: String,
+    pub presented: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TrustFile {
+    pins: BTreeMap<String, Pin>,
+    mismatches: Vec<Mismatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustStoreView {
+    pub pins: BTreeMap<String, Pin>,
+    pub mismatches: Vec<Mismatch>,
+    /// Certificates in the imported CA bundle; printers signed by them are not pinned.
+    pub ca_certificates: usize,
+}
+
+struct TrustState {
+    dir: Option<PathBuf>,
+    file: TrustFile,
+    ca: Option<Arc<WebPkiServerVerifier>>,
+    ca_certificates: usize,
+}
+
+fn state() -> &'static RwLock<TrustState> {
+    static STATE: OnceLock<RwLock<TrustState>> = OnceLock::new();
+    STATE.get_or_init(|| {
+        RwLock::new(TrustState {
+            dir: None,
+            file: TrustFile::default(),
+            ca: None,
+            ca_certificates: 0,
+        })
+    })
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    static PROVIDER: OnceLock<Arc<CryptoProvider>> = OnceLock::new();
+    PROVIDER.get_or_init(|| Arc::new(rustls::crypto::ring::default_provider())).clone()
+}
+
+pub fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// The trust store key for `host` (as rustls names it, IPv6 unbracketed) and `port`.
+pub fn pin_key(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+fn write_file(path: &Path, file: &TrustFile) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(file).map_err(|e| format!("Failed to serialize trust store: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, json).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+fn save(state: &TrustState) {
+    let Some(dir) = &state.dir else { return };
+    if let Err(e) = write_file(&dir.join(TRUST_FILE), &state.file) {
+        warn!("failed to save the trust store: {}", e);
+    }
+}
+
+fn read_ca_bundle(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let pem = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let certificates: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid PEM in {}: {}", path.display(), e))?;
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+fn ca_verifier(certificates: Vec<CertificateDer<'static>>) -> Result<Arc<WebPkiServerVerifier>, String> {
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certificates);
+    if added == 0 {
+        return Err("None of the CA certificates could be used".to_string());
+    }
+    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider())
+        .build()
+        .map_err(|e| format!("Invalid CA bundle: {}", e))
+}
+
+/// Loads pinned certificates and the imported CA bundle from `config_dir`.
+pub fn load(config_dir: &Path) -> Result<(), String> {
+    let path = config_dir.join(TRUST_FILE);
+    let file = if path.exists() {
+        let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid trust store {}: {}", path.display(), e))?
+    } else {
+        TrustFile::default()
+    };
+
+    let bundle = config_dir.join(CA_BUNDLE_FILE);
+    let (ca, ca_certificates) = if bundle.exists() {
+        let certificates = read_ca_bundle(&bundle)?;
+        let count = certificates.len();
+        let verifier = ca_verifier(certificates).map_err(|e| format!("{} in {}", e, bundle.display()))?;
+        (Some(verifier), count)
+    } else {
+        (None, 0)
+    };
+
+    *state().write().unwrap() = TrustState {
+        dir: Some(config_dir.to_path_buf()),
+        file,
+        ca,
+        ca_certificates,
+    };
+    Ok(())
+}
+
+/// Pins `presented` for `host` and `port` on first use; afterwards only that certificate
+/// is accepted there.
+pub fn check_pin(host: &str, port: u16, presented: &str) -> Result<(), String> {
+    let key = pin_key(host, port);
+    let mut state = state().write().unwrap();
+    // Pins saved before they were kept per port carry only the host
+    let pinned = state.file.pins.get(&key).or_else(|| state.file.pins.get(host)).cloned();
+    match pinned {
+        Some(pin) if pin.fingerprint == presented => {
+            if let Entry::Vacant(entry) = state.file.pins.entry(key) {
+                entry.insert(pin);
+                save(&state);
+            }
+            Ok(())
+        }
+        Some(pin) => {
+            let pinned = pin.fingerprint;
+            warn!(host = %key, pinned = %pinned, presented, "printer certificate changed");
+            let known = state
+                .file
+                .mismatches
+                .iter_mut()
+                .find(|m| m.host == key && m.presented == presented);
+            match known {
+                Some(mismatch) => mismatch.at = Utc::now(),
+                None => {
+                    state.file.mismatches.push(Mismatch {
+                        host: key.clone(),
+                        pinned,
+                        presented: presented.to_string(),
+                        at: Utc::now(),
+                    });
+                    let excess = state.file.mismatches.len().saturating_sub(MAX_MISMATCHES);
+                    state.file.mismatches.drain(..excess);
+                }
+            }
+            save(&state);
+            Err(format!(
+                "The certificate of {} changed since it was first trusted; review it in the trust store",
+                key
+            ))
+        }
+        None => {
+            info!(host = %key, fingerprint = presented, "pinned printer certificate on first use");
+            state.file.pins.insert(
+                key,
+                Pin {
+                    fingerprint: presented.to_string(),
+                    first_seen: Utc::now(),
+                },
+            );
+            save(&state);
+            Ok(())
+        }
+    }
+}
+
+/// Accepts certificates signed by the imported CA bundle and pins everything else on
+/// first use. rustls only hands it the host name, so it is built for one port.
+#[derive(Debug)]
+struct TofuVerifier {
+    provider: Arc<CryptoProvider>,
+    port: u16,
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let ca = state().read().unwrap().ca.clone();
+        if let Some(ca) = ca {
+            if ca.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now).is_ok() {
+                return Ok(ServerCertVerified::assertion());
+            }
+        }
+        let name = server_name.to_str();
+        // Scoped link-local printers are pinned under their address, not the placeholder name
+        let host = HostAddr::from_request_host(&name).map_or_else(|| name.to_string(), |addr| addr.to_string());
+        check_pin(&host, self.port, &fingerprint(end_entity))
+            .map(|_| ServerCertVerified::assertion())
+            .map_err(rustls::Error::General)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// TLS settings for talking to a printer on `port`: CA bundle first, then trust on first use.
+pub fn tls_config(port: u16) -> Result<ClientConfig, String> {
+    let provider = provider();
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("TLS setup failed: {}", e))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(TofuVerifier { provider, port }))
+        .with_no_client_auth();
+    Ok(config)
+}
+
+/// HTTP client for a printer web or IPP endpoint at `addr` and `port` that checks
+/// certificates against the trust store. URLs for it name the printer by
+/// [`HostAddr::request_host`], which it resolves to the scoped address of a link-local one.
+pub fn http_client(timeout: Duration, addr: &HostAddr, port: u16) -> Result<Client, String> {
+    let mut builder = Client::builder().timeout(timeout).use_preconfigured_tls(tls_config(port)?);
+    if addr.scope_id != 0 {
+        // The port comes from the URL; the resolved address only contributes the IP and zone
+        builder = builder.resolve(&addr.request_host(), addr.socket_addr(port));
+    }
+    builder
+        .build()
+        .map_err(|e| format!("HTTP client setup failed: {}", e))
+}
+
+pub fn view() -> TrustStoreView {
+    let state = state().read().unwrap();
+    TrustStoreView {
+        pins: state.file.pins.clone(),
+        mismatches: state.file.mismatches.clone(),
+        ca_certificates: state.ca_certificates,
+    }
+}
+
+/// Replaces the pin for `host` (a `host:port` key) with the certificate it presented most
+/// recently.
+pub fn trust_presented(host: &str) -> Result<Pin, String> {
+    let mut state = state().write().unwrap();
+    let presented = state
+        .file
+        .mismatches
+        .iter()
+        .filter(|m| m.host == host)
+        .max_by_key(|m| m.at)
+        .map(|m| m.presented.clone())
+        .ok_or_else(|| format!("No changed certificate recorded for {}", host))?;
+    let pin = Pin {
+        fingerprint: presented,
+        first_seen: Utc::now(),
+    };
+    state.file.pins.insert(host.to_string(), pin.clone());
+    state.file.mismatches.retain(|m| m.host != host);
+    save(&state);
+    info!(host, fingerprint = %pin.fingerprint, "trusted changed printer certificate");
+    Ok(pin)
+}
+
+/// Drops the pin for `host` (a `host:port` key, or a bare host for a pin saved before
+/// pins were kept per port); the next connection pins whatever it presents.
+pub fn forget(host: &str) {
+    let mut state = state().write().unwrap();
+    state.file.pins.remove(host);
+    state.file.mismatches.retain(|m| m.host != host);
+    save(&state);
+}
+
+/// Installs a PEM CA bundle into the config dir. Printers with certificates issued by
+/// it are trusted without pinning.
+pub fn import_ca_bundle(source: &Path) -> Result<usize, String> {
+    let certificates = read_ca_bundle(source)?;
+    let count = certificates.len();
+    let verifier = ca_verifier(certificates)?;
+
+    let mut state = state().write().unwrap();
+    if let Some(dir) = &state.dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        std::fs::copy(source, dir.join(CA_BUNDLE_FILE)).map_err(|e| format!("Failed to install CA bundle: {}", e))?;
+    }
+    state.ca = Some(verifier);
+    state.ca_certificates = count;
+    Ok(count)
+}
+
+pub fn remove_ca_bundle() -> Result<(), String> {
+    let mut state = state().write().unwrap();
+    if let Some(dir) = &state.dir {
+        let path = dir.join(CA_BUNDLE_FILE);
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+    }
+    state.ca = None;
+    state.ca_certificates = 0;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_trust_store() -> TrustStoreView {
+    view()
+}
+
+#[tauri::command]
+pub fn trust_certificate(host: String) -> Result<Pin, String> {
+    let (address, port) = host.rsplit_once(':').ok_or_else(|| format!("Invalid host and port: {}", host))?;
+    crate::validate::host(address.trim_start_matches('[').trim_end_matches(']'))?;
+    port.parse::<u16>().map_err(|_| format!("Invalid port: {}", port))?;
+    trust_presented(&host)
+}
+
+#[tauri::command]
+pub fn forget_certificate(host: String) -> Result<(), String> {
+    crate::validate::text("Host", &host)?;
+    forget(&host);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn import_trusted_ca(path: String) -> Result<usize, String> {
+    let path = crate::validate::input_file(&path, &["pem", "crt", "cer"])?;
+    import_ca_bundle(&path)
+}
+
+#[tauri::command]
+pub fn remove_trusted_ca() -> Result<(), String> {
+    remove_ca_bundle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_are_kept_per_port() {
+        check_pin("10.9.0.1", 443, "AA").unwrap();
+        check_pin("10.9.0.1", 631, "BB").unwrap();
+        check_pin("10.9.0.1", 443, "AA").unwrap();
+        let error = check_pin("10.9.0.1", 631, "CC").unwrap_err();
+        assert!(error.contains("10.9.0.1:631"), "{}", error);
+
+        let view = view();
+        assert_eq!(view.pins["10.9.0.1:443"].fingerprint, "AA");
+        assert_eq!(view.pins["10.9.0.1:631"].fingerprint, "BB");
+        let mismatch = view.mismatches.iter().find(|m| m.host == "10.9.0.1:631").unwrap();
+        assert_eq!((mismatch.pinned.as_str(), mismatch.presented.as_str()), ("BB", "CC"));
+        assert_eq!(pin_key("fe80::1", 631), "[fe80::1]:631");
+    }
+
+    #[test]
+    fn host_only_pins_still_apply() {
+        state().write().unwrap().file.pins.insert(
+            "10.9.0.2".to_string(),
+            Pin { fingerprint: "AA".to_string(), first_seen: Utc::now() },
+        );
+        check_pin("10.9.0.2", 443, "AA").unwrap();
+        assert_eq!(view().pins["10.9.0.2:443"].fingerprint, "AA");
+        assert!(check_pin("10.9.0.2", 8443, "BB").is_err());
+    }
+
+    #[test]
+    fn trusting_a_changed_certificate_replaces_the_pin() {
+        assert!(trust_presented("10.9.0.3:443").is_err());
+        check_pin("10.9.0.3", 443, "AA").unwrap();
+        check_pin("10.9.0.3", 443, "BB").unwrap_err();
+        check_pin("10.9.0.3", 443, "CC").unwrap_err();
+
+        assert_eq!(trust_presented("10.9.0.3:443").unwrap().fingerprint, "CC");
+        check_pin("10.9.0.3", 443, "CC").unwrap();
+        assert!(!view().mismatches.iter().any(|m| m.host == "10.9.0.3:443"));
+    }
+
+    #[test]
+    fn forgetting_a_pin_accepts_the_next_certificate() {
+        check_pin("10.9.0.4", 443, "AA").unwrap();
+        check_pin("10.9.0.4", 443, "BB").unwrap_err();
+        forget("10.9.0.4:443");
+        assert!(!view().mismatches.iter().any(|m| m.host == "10.9.0.4:443"));
+        check_pin("10.9.0.4", 443, "BB").unwrap();
+        assert_eq!(view().pins["10.9.0.4:443"].fingerprint, "BB");
+    }
+
+    #[test]
+    fn verifier_pins_the_presented_certificate_for_its_port() {
+        let verifier = TofuVerifier { provider: provider(), port: 8443 };
+        let name = ServerName::try_from("10.9.0.5").unwrap();
+        let verify = |der: &[u8]| verifier.verify_server_cert(&CertificateDer::from(der.to_vec()), &[], &name, &[], UnixTime::now());
+
+        assert!(verify(b"first certificate").is_ok());
+        assert!(verify(b"first certificate").is_ok());
+        assert!(matches!(verify(b"second certificate"), Err(rustls::Error::General(_))));
+        assert_eq!(view().pins["10.9.0.5:8443"].fingerprint, fingerprint(b"first certificate"));
+    }
+
+    #[test]
+    fn the_trust_store_is_replaced_in_one_step() {
+        let dir = std::env::temp_dir().join(format!("trust-save-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(TRUST_FILE);
+        std::fs::write(&path, "old").unwrap();
+
+        let mut file = TrustFile::default();
+        file.pins.insert("10.9.0.6:443".to_string(), Pin { fingerprint: "AA".to_string(), first_seen: Utc::now() });
+        write_file(&path, &file).unwrap();
+
+        let saved: TrustFile = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.pins["10.9.0.6:443"].fingerprint, "AA");
+        assert!(!path.with_extension("json.tmp").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
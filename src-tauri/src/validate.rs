@@ -0,0 +1,108 @@
+use crate::addr::HostAddr;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+
+const MAX_TEXT_LEN: usize = 4096;
+const MAX_ID_LEN: usize = 256;
+
+/// A single unicast IP address. Broadcast, multicast and unspecified addresses are refused
+/// so one command cannot fan out to a whole subnet. A `.255` address is a valid host in
+/// anything larger than a /24, so it is allowed.
+pub fn host(input: &str) -> Result<HostAddr, String> {
+    let addr = HostAddr::parse(input).ok_or_else(|| format!("Invalid IP address: {}", input))?;
+    let fans_out = match addr.ip {
+        IpAddr::V4(ip) => ip.is_broadcast() || ip.is_multicast() || ip.is_unspecified(),
+        IpAddr::V6(ip) => ip.is_multicast() || ip.is_unspecified(),
+    };
+    if fans_out {
+        return Err(format!("{} is not a single host address", input));
+    }
+    Ok(addr)
+}
+
+/// An SNMP community string: 1 to 64 printable ASCII characters.
+pub fn community(community: &str) -> Result<(), String> {
+    if community.is_empty() || community.len() > 64 || !community.chars().all(|c| c.is_ascii_graphic()) {
+        return Err("SNMP community must be 1 to 64 printable ASCII characters".to_string());
+    }
+    Ok(())
+}
+
+/// Free text such as a location, tag or OID, with a length cap and no control characters.
+pub fn text(field: &str, value: &str) -> Result<(), String> {
+    if value.len() > MAX_TEXT_LEN {
+        return Err(format!("{} is longer than {} characters", field, MAX_TEXT_LEN));
+    }
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(format!("{} contains control characters", field));
+    }
+    Ok(())
+}
+
+/// An inventory, rule or report id.
+pub fn id(value: &str) -> Result<(), String> {
+    if value.trim().is_empty() || value.len() > MAX_ID_LEN {
+        return Err(format!("Invalid id '{}'", value));
+    }
+    text("Id", value)
+}
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Common checks for both directions: an absolute path without `..` whose extension is
+/// one of `extensions`.
+fn file_path(path: &str, extensions: &[&str]) -> Result<PathBuf, String> {
+    text("Path", path)?;
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(format!("{} is not an absolute path", path.display()));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("{} must not contain '..'", path.display()));
+    }
+    let extension = extension_of(&path);
+    if !extensions.contains(&extension.as_str()) {
+        return Err(format!(
+            "{} must have one of the extensions: {}",
+            path.display(),
+            extensions.join(", ")
+        ));
+    }
+    Ok(path)
+}
+
+/// A file the user picked to read: it must exist and be a regular file.
+pub fn input_file(path: &str, extensions: &[&str]) -> Result<PathBuf, String> {
+    let path = file_path(path, extensions)?;
+    let metadata = std::fs::metadata(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    Ok(path)
+}
+
+/// A file to write: its directory must exist, and an existing file must not be a symlink
+/// that would redirect the write somewhere else.
+pub fn output_file(path: &str, extensions: &[&str]) -> Result<PathBuf, String> {
+    let path = file_path(path, extensions)?;
+    match path.parent() {
+        Some(parent) if parent.is_dir() => {}
+        _ => return Err(format!("The folder for {} does not exist", path.display())),
+    }
+    if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+        if !metadata.is_file() {
+            return Err(format!("{} exists and is not a regular file", path.display()));
+        }
+    }
+    Ok(path)
+}
+
+/// Export file for `format`: the path must carry the matching extension.
+pub fn export_file(path: &str, format: &str) -> Result<PathBuf, String> {
+    let format = format.to_ascii_lowercase();
+    output_file(path, &[format.as_str()])
+}
@@ -0,0 +1,713 @@
+use crate::addr::Subnet;
+use crate::alerts::AlertEngine;
+use crate::validate;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+use tracing::{info, warn};
+
+const VAULT_VERSION: u32 = 1;
+const KEYRING_SERVICE: &str = "com.tauri.dev.credentials";
+const KEYRING_USER: &str = "vault-key";
+/// Encrypted on creation so a wrong passphrase or keyring key is caught before use.
+const CHECK_PLAINTEXT: &[u8] = b"credential vault";
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Where the vault's encryption key comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// A random key kept in the OS keyring (Secret Service, Keychain, Credential Manager).
+    Keyring,
+    /// A key derived from a user passphrase with Argon2id; the vault is locked until the
+    /// passphrase is entered.
+    Passphrase,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialKind {
+    Snmpv3,
+    Ipp,
+    WebAdmin,
+    Smtp,
+}
+
+/// Which printers a credential applies to. An empty scope makes it the default for its
+/// kind.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CredentialScope {
+    pub printer_ids: Vec<String>,
+    /// CIDR blocks such as `10.1.0.0/16` or `2001:db8::/48`.
+    pub subnets: Vec<String>,
+}
+
+impl CredentialScope {
+    /// How well the scope fits a printer; `None` when it does not apply. A printer id beats
+    /// any subnet, a longer prefix beats a shorter one, and the default comes last.
+    fn rank(&self, printer_id: Option<&str>, ip: Option<IpAddr>) -> Option<(u8, u8)> {
+        if self.printer_ids.is_empty() && self.subnets.is_empty() {
+            return Some((0, 0));
+        }
+        if printer_id.is_some_and(|id| self.printer_ids.iter().any(|p| p == id)) {
+            return Some((2, 0));
+        }
+        let ip = ip?;
+        self.subnets
+            .iter()
+            .filter_map(|spec| Subnet::parse(spec).ok())
+            .filter(|subnet| subnet.contains(ip))
+            .map(|subnet| (1, subnet.prefix))
+            .max()
+    }
+}
+
+/// A credential without its secrets; this is all the UI ever receives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialSummary {
+    pub id: String,
+    pub name: String,
+    pub kind: CredentialKind,
+    pub username: Option<String>,
+    /// Non-secret settings such as the SNMPv3 auth and privacy protocols.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub scope: CredentialScope,
+    /// Names of the stored secrets, e.g. `password` or `auth_password`.
+    pub secret_fields: Vec<String>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+/// A decrypted credential, for use on the Rust side only.
+#[derive(Debug, Clone)]
+pub struct Credential {
+    pub summary: CredentialSummary,
+    pub secrets: BTreeMap<String, String>,
+}
+
+impl Credential {
+    pub fn secret(&self, field: &str) -> Option<&str> {
+        self.secrets.get(field).map(String::as_str)
+    }
+}
+
+/// A new credential, or changes to an existing one when `id` is set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialInput {
+    pub id: Option<String>,
+    pub name: String,
+    pub kind: CredentialKind,
+    pub username: Option<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub scope: CredentialScope,
+    /// `None` keeps the stored secrets of an existing credential.
+    pub secrets: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Kdf {
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredCredential {
+    #[serde(flatten)]
+    summary: CredentialSummary,
+    sealed: Sealed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    key_source: KeySource,
+    kdf: Option<Kdf>,
+    check: Sealed,
+    credentials: Vec<StoredCredential>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultStatus {
+    pub initialized: bool,
+    pub key_source: Option<KeySource>,
+    pub unlocked: bool,
+    pub credentials: usize,
+}
+
+struct VaultState {
+    file: Option<VaultFile>,
+    key: Option<Key<Aes256Gcm>>,
+}
+
+/// Secrets encrypted at rest with AES-256-GCM. Managed as Tauri state.
+pub struct CredentialVault {
+    path: PathBuf,
+    state: Mutex<VaultState>,
+}
+
+fn seal(key: &Key<Aes256Gcm>, plaintext: &[u8], aad: &[u8]) -> Result<Sealed, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok(Sealed {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn open_sealed(key: &Key<Aes256Gcm>, sealed: &Sealed, aad: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce = BASE64.decode(&sealed.nonce).map_err(|_| "Corrupt vault entry".to_string())?;
+    let ciphertext = BASE64.decode(&sealed.ciphertext).map_err(|_| "Corrupt vault entry".to_string())?;
+    if nonce.len() != 12 {
+        return Err("Corrupt vault entry".to_string());
+    }
+    Aes256Gcm::new(key)
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .map_err(|_| "Wrong key or tampered vault entry".to_string())
+}
+
+/// Secrets are bound to their credential's id and kind, so entries cannot be swapped
+/// around in the file.
+fn associated_data(summary: &CredentialSummary) -> Vec<u8> {
+    format!("{}\0{:?}", summary.id, summary.kind).into_bytes()
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<Key<Aes256Gcm>, String> {
+    let salt = BASE64.decode(&kdf.salt).map_err(|_| "Corrupt vault salt".to_string())?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+    let mut key = Key::<Aes256Gcm>::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| format!("OS keyring unavailable: {}", e))
+}
+
+fn keyring_key() -> Result<Key<Aes256Gcm>, String> {
+    let encoded = keyring_entry()?
+        .get_password()
+        .map_err(|e| format!("Failed to read the vault key from the OS keyring: {}", e))?;
+    let bytes = BASE64.decode(encoded).map_err(|_| "The vault key in the OS keyring is corrupt".to_string())?;
+    if bytes.len() != 32 {
+        return Err("The vault key in the OS keyring is corrupt".to_string());
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&bytes))
+}
+
+fn new_id() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn check_input(input: &CredentialInput) -> Result<(), String> {
+    if input.name.trim().is_empty() {
+        return Err("Credential name is required".to_string());
+    }
+    validate::text("Name", &input.name)?;
+    if let Some(username) = &input.username {
+        validate::text("Username", username)?;
+    }
+    for (field, value) in &input.attributes {
+        validate::text("Attribute", field)?;
+        validate::text(field, value)?;
+    }
+    for id in &input.scope.printer_ids {
+        validate::id(id)?;
+    }
+    for subnet in &input.scope.subnets {
+        Subnet::parse(subnet)?;
+    }
+    for (field, secret) in input.secrets.iter().flatten() {
+        validate::text("Secret name", field)?;
+        validate::text(field, secret)?;
+    }
+    Ok(())
+}
+
+impl CredentialVault {
+    /// Opens the vault at `path`. A keyring vault is unlocked right away; a passphrase
+    /// vault stays locked until [`CredentialVault::unlock`].
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let file: Option<VaultFile> = if path.exists() {
+            let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let file: VaultFile =
+                serde_json::from_str(&json).map_err(|e| format!("Invalid credential vault {}: {}", path.display(), e))?;
+            if file.version > VAULT_VERSION {
+                return Err(format!("Credential vault version {} is newer than this build supports", file.version));
+            }
+            Some(file)
+        } else {
+            None
+        };
+
+        let key = match &file {
+            Some(file) if file.key_source == KeySource::Keyring => {
+                match keyring_key().and_then(|key| open_sealed(&key, &file.check, b"check").map(|_| key)) {
+                    Ok(key) => Some(key),
+                    Err(e) => {
+                        warn!("credential vault stays locked: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        Ok(CredentialVault {
+            path,
+            state: Mutex::new(VaultState { file, key }),
+        })
+    }
+
+    pub fn status(&self) -> VaultStatus {
+        let state = self.state.lock().unwrap();
+        VaultStatus {
+            initialized: state.file.is_some(),
+            key_source: state.file.as_ref().map(|f| f.key_source),
+            unlocked: state.key.is_some(),
+            credentials: state.file.as_ref().map_or(0, |f| f.credentials.len()),
+        }
+    }
+
+    fn create(&self, state: &mut VaultState, key_source: KeySource, kdf: Option<Kdf>, key: Key<Aes256Gcm>) -> Result<(), String> {
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            key_source,
+            kdf,
+            check: seal(&key, CHECK_PLAINTEXT, b"check")?,
+            credentials: Vec::new(),
+        };
+        self.save(&file)?;
+        state.file = Some(file);
+        state.key = Some(key);
+        info!(?key_source, "created credential vault");
+        Ok(())
+    }
+
+    /// Creates an empty vault whose key lives in the OS keyring. Fails on systems without
+    /// a keyring service, such as headless Linux; use a passphrase there.
+    pub fn create_with_keyring(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.file.is_some() {
+            return Err("The credential vault already exists".to_string());
+        }
+        let key = Aes256Gcm::generate_key(&mut OsRng);
+        keyring_entry()?
+            .set_password(&BASE64.encode(key))
+            .map_err(|e| format!("Failed to store the vault key in the OS keyring: {}", e))?;
+        self.create(&mut state, KeySource::Keyring, None, key)
+    }
+
+    /// Unlocks a passphrase vault, creating it with this passphrase if there is none yet.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(file) = &state.file else {
+            if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+                return Err(format!("The passphrase must be at least {} characters", MIN_PASSPHRASE_LEN));
+            }
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let defaults = Params::default();
+            let kdf = Kdf {
+                salt: BASE64.encode(salt),
+                memory_kib: defaults.m_cost(),
+                iterations: defaults.t_cost(),
+                parallelism: defaults.p_cost(),
+            };
+            let key = derive_key(passphrase, &kdf)?;
+            return self.create(&mut state, KeySource::Passphrase, Some(kdf), key);
+        };
+
+        let kdf = match (file.key_source, &file.kdf) {
+            (KeySource::Passphrase, Some(kdf)) => kdf,
+            (KeySource::Passphrase, None) => return Err("The credential vault has no key derivation settings".to_string()),
+            (KeySource::Keyring, _) => return Err("The credential vault is unlocked by the OS keyring".to_string()),
+        };
+        let key = derive_key(passphrase, kdf)?;
+        open_sealed(&key, &file.check, b"check").map_err(|_| "Wrong passphrase".to_string())?;
+        state.key = Some(key);
+        Ok(())
+    }
+
+    /// Forgets the key of a passphrase vault until it is unlocked again.
+    pub fn lock(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.file.as_ref().is_some_and(|f| f.key_source == KeySource::Passphrase) {
+            state.key = None;
+        }
+    }
+
+    fn save(&self, file: &VaultFile) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(file).map_err(|e| format!("Failed to serialize vault: {}", e))?;
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+        restrict_permissions(&temp)?;
+        std::fs::rename(&temp, &self.path).map_err(|e| format!("Failed to replace {}: {}", self.path.display(), e))
+    }
+
+    /// Runs `f` with the unlocked key and the vault file, saving the file afterwards.
+    fn modify<T>(&self, f: impl FnOnce(&Key<Aes256Gcm>, &mut VaultFile) -> Result<T, String>) -> Result<T, String> {
+        let mut state = self.state.lock().unwrap();
+        let key = state.key.ok_or_else(|| "The credential vault is locked".to_string())?;
+        let file = state.file.as_mut().ok_or_else(|| "The credential vault is locked".to_string())?;
+        let mut updated = file.clone();
+        let result = f(&key, &mut updated)?;
+        self.save(&updated)?;
+        *file = updated;
+        Ok(result)
+    }
+
+    /// Adds a credential, or updates the one with `input.id`.
+    pub fn store(&self, input: CredentialInput) -> Result<CredentialSummary, String> {
+        check_input(&input)?;
+        self.modify(|key, file| {
+            let now = Utc::now();
+            let existing = match &input.id {
+                Some(id) => Some(
+                    file.credentials
+                        .iter()
+                        .position(|c| &c.summary.id == id)
+                        .ok_or_else(|| format!("No credential with id {}", id))?,
+                ),
+                None => None,
+            };
+
+            let secrets = match (&input.secrets, existing) {
+                (Some(secrets), _) => secrets.clone(),
+                (None, Some(index)) => {
+                    let stored = &file.credentials[index];
+                    let plaintext = open_sealed(key, &stored.sealed, &associated_data(&stored.summary))?;
+                    serde_json::from_slice(&plaintext).map_err(|_| "Corrupt vault entry".to_string())?
+                }
+                (None, None) => return Err("A new credential needs its secrets".to_string()),
+            };
+
+            let summary = CredentialSummary {
+                id: input.id.clone().unwrap_or_else(new_id),
+                name: input.name.trim().to_string(),
+                kind: input.kind,
+                username: input.username.clone().filter(|u| !u.is_empty()),
+                attributes: input.attributes.clone(),
+                scope: input.scope.clone(),
+                secret_fields: secrets.keys().cloned().collect(),
+                created: existing.map_or(now, |index| file.credentials[index].summary.created),
+                updated: now,
+            };
+            let plaintext = serde_json::to_vec(&secrets).map_err(|e| format!("Failed to serialize secrets: {}", e))?;
+            let stored = StoredCredential {
+                sealed: seal(key, &plaintext, &associated_data(&summary))?,
+                summary: summary.clone(),
+            };
+            match existing {
+                Some(index) => file.credentials[index] = stored,
+                None => file.credentials.push(stored),
+            }
+            Ok(summary)
+        })
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        self.modify(|_, file| {
+            let before = file.credentials.len();
+            file.credentials.retain(|c| c.summary.id != id);
+            if file.credentials.len() == before {
+                return Err(format!("No credential with id {}", id));
+            }
+            Ok(())
+        })
+    }
+
+    /// Summaries of every credential. Works while the vault is locked.
+    pub fn list(&self) -> Vec<CredentialSummary> {
+        let state = self.state.lock().unwrap();
+        state
+            .file
+            .iter()
+            .flat_map(|file| file.credentials.iter().map(|c| c.summary.clone()))
+            .collect()
+    }
+
+    fn decrypt(&self, stored: &StoredCredential, key: &Key<Aes256Gcm>) -> Result<Credential, String> {
+        let plaintext = open_sealed(key, &stored.sealed, &associated_data(&stored.summary))?;
+        let secrets = serde_json::from_slice(&plaintext).map_err(|_| "Corrupt vault entry".to_string())?;
+        Ok(Credential {
+            summary: stored.summary.clone(),
+            secrets,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Result<Credential, String> {
+        let state = self.state.lock().unwrap();
+        let key = state.key.ok_or_else(|| "The credential vault is locked".to_string())?;
+        let stored = state
+            .file
+            .iter()
+            .flat_map(|file| file.credentials.iter())
+            .find(|c| c.summary.id == id)
+            .ok_or_else(|| format!("No credential with id {}", id))?;
+        self.decrypt(stored, &key)
+    }
+
+    fn best_match<'a>(
+        credentials: &'a [StoredCredential],
+        kind: CredentialKind,
+        printer_id: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Option<&'a StoredCredential> {
+        credentials
+            .iter()
+            .filter(|c| c.summary.kind == kind)
+            .filter_map(|c| Some((c.summary.scope.rank(printer_id, ip)?, c)))
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, c)| c)
+    }
+
+    /// The credential of `kind` that applies to a printer, by printer id first, then the
+    /// most specific subnet containing `ip`, then the default for the kind.
+    pub fn lookup(&self, kind: CredentialKind, printer_id: Option<&str>, ip: Option<IpAddr>) -> Result<Option<Credential>, String> {
+        let state = self.state.lock().unwrap();
+        let Some(file) = &state.file else { return Ok(None) };
+        let Some(stored) = Self::best_match(&file.credentials, kind, printer_id, ip) else { return Ok(None) };
+        let key = state.key.ok_or_else(|| "The credential vault is locked".to_string())?;
+        self.decrypt(stored, &key).map(Some)
+    }
+
+    /// Like [`CredentialVault::lookup`] but without the secrets, so it works while locked.
+    pub fn find(&self, kind: CredentialKind, printer_id: Option<&str>, ip: Option<IpAddr>) -> Option<CredentialSummary> {
+        let state = self.state.lock().unwrap();
+        let file = state.file.as_ref()?;
+        Self::best_match(&file.credentials, kind, printer_id, ip).map(|c| c.summary.clone())
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_vault_status(vault: State<'_, CredentialVault>) -> VaultStatus {
+    vault.status()
+}
+
+#[tauri::command]
+pub fn unlock_vault(app: AppHandle, vault: State<'_, CredentialVault>, passphrase: String) -> Result<VaultStatus, String> {
+    vault.unlock(&passphrase)?;
+    if let Some(alerts) = app.try_state::<AlertEngine>() {
+        if let Err(e) = alerts.migrate_logins(&vault) {
+            warn!("failed to move alert SMTP passwords into the vault: {}", e);
+        }
+    }
+    Ok(vault.status())
+}
+
+#[tauri::command]
+pub fn lock_vault(vault: State<'_, CredentialVault>) -> VaultStatus {
+    vault.lock();
+    vault.status()
+}
+
+#[tauri::command]
+pub fn store_credential(vault: State<'_, CredentialVault>, credential: CredentialInput) -> Result<CredentialSummary, String> {
+    vault.store(credential)
+}
+
+#[tauri::command]
+pub fn list_credentials(vault: State<'_, CredentialVault>) -> Vec<CredentialSummary> {
+    vault.list()
+}
+
+#[tauri::command]
+pub fn remove_credential(vault: State<'_, CredentialVault>, id: String) -> Result<(), String> {
+    validate::id(&id)?;
+    vault.remove(&id)
+}
+
+/// Which credential would be used for a printer; never returns secrets.
+#[tauri::command]
+pub fn find_credential(
+    vault: State<'_, CredentialVault>,
+    kind: CredentialKind,
+    printer_id: Option<String>,
+    ip: Option<String>,
+) -> Result<Option<CredentialSummary>, String> {
+    let ip = ip.map(|ip| validate::host(&ip).map(|addr| addr.ip)).transpose()?;
+    Ok(vault.find(kind, printer_id.as_deref(), ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vault-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("credentials.json")
+    }
+
+    fn unlocked(name: &str) -> (CredentialVault, PathBuf) {
+        let path = temp_path(name);
+        let vault = CredentialVault::open(path.clone()).unwrap();
+        vault.unlock(PASSPHRASE).unwrap();
+        (vault, path)
+    }
+
+    fn input(name: &str, kind: CredentialKind, scope: CredentialScope, password: &str) -> CredentialInput {
+        CredentialInput {
+            id: None,
+            name: name.to_string(),
+            kind,
+            username: Some("admin".to_string()),
+            attributes: BTreeMap::new(),
+            scope,
+            secrets: Some(BTreeMap::from([("password".to_string(), password.to_string())])),
+        }
+    }
+
+    fn scope(printer_ids: &[&str], subnets: &[&str]) -> CredentialScope {
+        CredentialScope {
+            printer_ids: printer_ids.iter().map(|s| s.to_string()).collect(),
+            subnets: subnets.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn cleanup(path: &Path) {
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unlock_creates_the_vault_and_rejects_a_wrong_passphrase() {
+        let path = temp_path("create");
+        let vault = CredentialVault::open(path.clone()).unwrap();
+        assert!(!vault.status().initialized);
+        assert!(vault.unlock("short").is_err());
+        vault.unlock(PASSPHRASE).unwrap();
+        let status = vault.status();
+        assert!(status.initialized && status.unlocked);
+        assert_eq!(status.key_source, Some(KeySource::Passphrase));
+
+        let reopened = CredentialVault::open(path.clone()).unwrap();
+        assert!(!reopened.status().unlocked);
+        assert_eq!(reopened.unlock("not the passphrase").unwrap_err(), "Wrong passphrase");
+        assert!(!reopened.status().unlocked);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn summaries_never_carry_secrets() {
+        let (vault, path) = unlocked("secrets");
+        let summary = vault.store(input("Office IPP", CredentialKind::Ipp, CredentialScope::default(), "s3cret-pass")).unwrap();
+        assert_eq!(summary.secret_fields, vec!["password".to_string()]);
+
+        let listed = serde_json::to_string(&vault.list()).unwrap();
+        assert!(!listed.contains("s3cret-pass"), "{}", listed);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("s3cret-pass"));
+        assert_eq!(vault.get(&summary.id).unwrap().secret("password"), Some("s3cret-pass"));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn lookup_prefers_printer_then_longest_subnet_then_default() {
+        let (vault, path) = unlocked("lookup");
+        vault.store(input("Default", CredentialKind::WebAdmin, scope(&[], &[]), "default")).unwrap();
+        vault.store(input("Site", CredentialKind::WebAdmin, scope(&[], &["10.1.0.0/16"]), "site")).unwrap();
+        vault.store(input("Floor", CredentialKind::WebAdmin, scope(&[], &["10.1.2.0/24"]), "floor")).unwrap();
+        vault.store(input("Printer", CredentialKind::WebAdmin, scope(&["mac:001122334455"], &[]), "printer")).unwrap();
+        vault.store(input("Other kind", CredentialKind::Smtp, scope(&["mac:001122334455"], &[]), "smtp")).unwrap();
+
+        let name = |printer_id: Option<&str>, ip: &str| {
+            let credential = vault.lookup(CredentialKind::WebAdmin, printer_id, Some(ip.parse().unwrap())).unwrap();
+            credential.unwrap().summary.name
+        };
+        assert_eq!(name(Some("mac:001122334455"), "10.1.2.3"), "Printer");
+        assert_eq!(name(Some("mac:66778899aabb"), "10.1.2.3"), "Floor");
+        assert_eq!(name(None, "10.1.9.3"), "Site");
+        assert_eq!(name(None, "192.168.1.3"), "Default");
+        assert_eq!(vault.find(CredentialKind::Snmpv3, None, None).map(|c| c.name), None);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn lock_and_reopen_keep_credentials() {
+        let (vault, path) = unlocked("reopen");
+        let summary = vault.store(input("Relay", CredentialKind::Smtp, CredentialScope::default(), "mail-pass")).unwrap();
+        vault.lock();
+        assert!(!vault.status().unlocked);
+        assert_eq!(vault.list().len(), 1);
+        assert!(vault.get(&summary.id).is_err());
+        assert_eq!(vault.find(CredentialKind::Smtp, None, None).map(|c| c.id), Some(summary.id.clone()));
+
+        let reopened = CredentialVault::open(path.clone()).unwrap();
+        reopened.unlock(PASSPHRASE).unwrap();
+        assert_eq!(reopened.get(&summary.id).unwrap().secret("password"), Some("mail-pass"));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn tampered_entries_are_rejected() {
+        let (vault, path) = unlocked("tamper");
+        let summary = vault.store(input("Printer", CredentialKind::Ipp, CredentialScope::default(), "ipp-pass")).unwrap();
+        let original: VaultFile = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+        // A flipped ciphertext bit
+        let mut file = original.clone();
+        let mut ciphertext = BASE64.decode(&file.credentials[0].sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.credentials[0].sealed.ciphertext = BASE64.encode(ciphertext);
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        let reopened = CredentialVault::open(path.clone()).unwrap();
+        reopened.unlock(PASSPHRASE).unwrap();
+        assert_eq!(reopened.get(&summary.id).unwrap_err(), "Wrong key or tampered vault entry");
+
+        // The secrets moved to a credential of another kind (the associated data changes)
+        let mut file = original;
+        file.credentials[0].summary.kind = CredentialKind::Smtp;
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        let reopened = CredentialVault::open(path.clone()).unwrap();
+        reopened.unlock(PASSPHRASE).unwrap();
+        assert!(reopened.lookup(CredentialKind::Smtp, None, None).is_err());
+        cleanup(&path);
+    }
+}
@@ -0,0 +1,92 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const Page = () => {
+    const [store, setStore] = useState(null);
+    const [path, setPath] = useState('');
+    const [error, setError] = useState('');
+
+    const refresh = async () => {
+        try {
+            setStore(await invoke('get_trust_store'));
+        } catch (error) {
+            console.error('Error loading trust store:', error);
+        }
+    };
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const run = async (command, args) => {
+        try {
+            await invoke(command, args);
+            setError('');
+            await refresh();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    if (!store) {
+        return <div><h1>Certificates</h1></div>;
+    }
+
+    return (
+        <div>
+            <h1>Certificates</h1>
+            {error && <p className="text-red-600">{error}</p>}
+            {store.mismatches.length > 0 && (
+                <div className="my-2">
+                    <h2 className="text-red-600">Changed certificates</h2>
+                    {store.mismatches.map((mismatch) => (
+                        <div key={mismatch.host + mismatch.presented} className="my-1">
+                            <p>{mismatch.host} presented {mismatch.presented} (pinned {mismatch.pinned}) at {new Date(mismatch.at).toLocaleString()}</p>
+                            <button className="border border-gray-300 px-4 py-1" onClick={() => run('trust_certificate', { host: mismatch.host })}>
+                                Trust new certificate
+                            </button>
+                        </div>
+                    ))}
+                </div>
+            )}
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Host and port</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">SHA-256 fingerprint</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">First seen</th>
+                        <th className="border border-gray-300 px-4 py-2"></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {Object.entries(store.pins).map(([host, pin]) => (
+                        <tr key={host}>
+                            <td className="border border-gray-300 px-4 py-2">{host}</td>
+                            <td className="border border-gray-300 px-4 py-2 font-mono text-xs">{pin.fingerprint}</td>
+                            <td className="border border-gray-300 px-4 py-2">{new Date(pin.first_seen).toLocaleString()}</td>
+                            <td className="border border-gray-300 px-4 py-2">
+                                <button onClick={() => run('forget_certificate', { host })}>Forget</button>
+                            </td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+            <div className="my-4 space-x-2">
+                <span>CA bundle: {store.ca_certificates ? `${store.ca_certificates} certificates` : 'none'}</span>
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="CA bundle path (.pem, .crt)"
+                    value={path}
+                    onChange={(e) => setPath(e.target.value)}
+                />
+                <button className="border border-gray-300 px-4 py-1" onClick={() => run('import_trusted_ca', { path })}>Import</button>
+                {store.ca_certificates > 0 && (
+                    <button className="border border-gray-300 px-4 py-1" onClick={() => run('remove_trusted_ca')}>Remove</button>
+                )}
+            </div>
+        </div>
+    );
+};
+
+export default Page;
@@ -0,0 +1,164 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const KINDS = {
+    snmpv3: 'SNMPv3',
+    ipp: 'IPP',
+    web_admin: 'Web admin',
+    smtp: 'SMTP',
+};
+
+const EMPTY_FORM = { name: '', kind: 'web_admin', username: '', password: '', subnets: '', printer_ids: '', allow_cleartext: false };
+
+const splitList = (text) => text.split(',').map((item) => item.trim()).filter(Boolean);
+
+const Page = () => {
+    const [status, setStatus] = useState(null);
+    const [credentials, setCredentials] = useState([]);
+    const [passphrase, setPassphrase] = useState('');
+    const [form, setForm] = useState(EMPTY_FORM);
+    const [error, setError] = useState('');
+
+    const refresh = async () => {
+        try {
+            setStatus(await invoke('get_vault_status'));
+            setCredentials(await invoke('list_credentials'));
+        } catch (error) {
+            console.error('Error loading credentials:', error);
+        }
+    };
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const unlock = async () => {
+        try {
+            setStatus(await invoke('unlock_vault', { passphrase }));
+            setPassphrase('');
+            setError('');
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const lock = async () => {
+        setStatus(await invoke('lock_vault'));
+    };
+
+    const save = async () => {
+        try {
+            await invoke('store_credential', {
+                credential: {
+                    name: form.name,
+                    kind: form.kind,
+                    username: form.username || null,
+                    // IPP passwords only go out over IPPS unless plain IPP is allowed
+                    attributes: form.kind === 'ipp' && form.allow_cleartext ? { allow_cleartext: 'true' } : {},
+                    scope: { subnets: splitList(form.subnets), printer_ids: splitList(form.printer_ids) },
+                    secrets: { password: form.password },
+                },
+            });
+            setForm(EMPTY_FORM);
+            setError('');
+            await refresh();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const remove = async (id) => {
+        try {
+            await invoke('remove_credential', { id });
+            await refresh();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const field = (name, placeholder, type = 'text') => (
+        <input
+            className="border border-gray-300 px-2 py-1"
+            type={type}
+            placeholder={placeholder}
+            value={form[name]}
+            onChange={(e) => setForm({ ...form, [name]: e.target.value })}
+        />
+    );
+
+    return (
+        <div>
+            <h1>Credentials</h1>
+            {error && <p className="text-red-600">{error}</p>}
+            {status && !status.unlocked && (
+                <div className="my-2 space-x-2">
+                    <input
+                        className="border border-gray-300 px-2 py-1"
+                        type="password"
+                        placeholder={status.initialized ? 'Vault passphrase' : 'Choose a vault passphrase'}
+                        value={passphrase}
+                        onChange={(e) => setPassphrase(e.target.value)}
+                    />
+                    <button className="border border-gray-300 px-4 py-1" onClick={unlock}>
+                        {status.initialized ? 'Unlock' : 'Create vault'}
+                    </button>
+                </div>
+            )}
+            {status && status.unlocked && status.key_source === 'passphrase' && (
+                <button className="border border-gray-300 px-4 py-1 my-2" onClick={lock}>Lock</button>
+            )}
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Name</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Type</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Username</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Applies to</th>
+                        <th className="border border-gray-300 px-4 py-2"></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {credentials.map((credential) => (
+                        <tr key={credential.id}>
+                            <td className="border border-gray-300 px-4 py-2">{credential.name}</td>
+                            <td className="border border-gray-300 px-4 py-2">{KINDS[credential.kind]}</td>
+                            <td className="border border-gray-300 px-4 py-2">{credential.username ?? '-'}</td>
+                            <td className="border border-gray-300 px-4 py-2">
+                                {[...credential.scope.printer_ids, ...credential.scope.subnets].join(', ') || 'Default'}
+                            </td>
+                            <td className="border border-gray-300 px-4 py-2">
+                                <button onClick={() => remove(credential.id)} disabled={!status?.unlocked}>Remove</button>
+                            </td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+            {status?.unlocked && (
+                <div className="my-4 space-x-2">
+                    {field('name', 'Name')}
+                    <select className="border border-gray-300 px-2 py-1" value={form.kind} onChange={(e) => setForm({ ...form, kind: e.target.value })}>
+                        {Object.entries(KINDS).map(([kind, label]) => <option key={kind} value={kind}>{label}</option>)}
+                    </select>
+                    {field('username', 'Username')}
+                    {field('password', 'Password', 'password')}
+                    {field('subnets', 'Subnets, e.g. 10.1.0.0/16')}
+                    {field('printer_ids', 'Printer ids')}
+                    {form.kind === 'ipp' && (
+                        <label title="Basic auth sends the password unencrypted over plain IPP">
+                            <input
+                                type="checkbox"
+                                checked={form.allow_cleartext}
+                                onChange={(e) => setForm({ ...form, allow_cleartext: e.target.checked })}
+                            />{' '}
+                            Also send over plain IPP
+                        </label>
+                    )}
+                    <button className="border border-gray-300 px-4 py-1" onClick={save}>Add</button>
+                </div>
+            )}
+        </div>
+    );
+};
+
+export default Page;
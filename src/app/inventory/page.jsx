@@ -0,0 +1,157 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const formatDays = (days) => (days == null ? '-' : `${Math.round(days)} days`);
+
+const Page = () => {
+    const [entries, setEntries] = useState([]);
+    const [forecasts, setForecasts] = useState([]);
+    const [path, setPath] = useState('');
+    const [preview, setPreview] = useState(null);
+    const [message, setMessage] = useState('');
+
+    const fetchInventory = async () => {
+        try {
+            setEntries(await invoke('get_inventory'));
+            setForecasts(await invoke('get_supply_forecast'));
+        } catch (error) {
+            console.error('Error fetching inventory:', error);
+            setEntries([]);
+            setForecasts([]);
+        }
+    };
+
+    useEffect(() => {
+        fetchInventory();
+    }, []);
+
+    const exportInventory = async (format) => {
+        try {
+            await invoke('export_inventory', { format, path });
+        } catch (error) {
+            console.error('Export failed:', error);
+        }
+    };
+
+    // A dry run first shows what every row would do; applying repeats it for real
+    const importInventory = async (dryRun) => {
+        try {
+            setPreview(await invoke('import_inventory', { path, dryRun }));
+            if (!dryRun) {
+                await fetchInventory();
+            }
+        } catch (error) {
+            console.error('Import failed:', error);
+        }
+    };
+
+    const printTestPage = async (entry) => {
+        try {
+            const job = await invoke('print_test_page', { printerId: entry.id });
+            setMessage(`Test page sent to ${job.printer_name} as ${job.format}`);
+        } catch (error) {
+            setMessage(`Test page failed on ${entry.printer.name}: ${error}`);
+        }
+    };
+
+    const setPjlCounters = async (entry, pjlCounters) => {
+        try {
+            await invoke('update_inventory_entry', { id: entry.id, location: entry.location, tags: null, pjlCounters });
+            await fetchInventory();
+        } catch (error) {
+            setMessage(`Failed to update ${entry.printer.name}: ${error}`);
+        }
+    };
+
+    // The supply that runs out first decides how a printer is shown
+    const soonest = (id) => forecasts.find((f) => f.printer_id === id);
+
+    return (
+        <div>
+            <h1>Inventory</h1>
+            <div className="my-2 space-x-2">
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="File path (.csv, .json, .xlsx)"
+                    value={path}
+                    onChange={(e) => setPath(e.target.value)}
+                />
+                <button className="border border-gray-300 px-4 py-1" onClick={() => exportInventory(path.split('.').pop())}>Export</button>
+                <button className="border border-gray-300 px-4 py-1" onClick={() => importInventory(true)}>Preview Import</button>
+                {preview && !preview.applied && (
+                    <button className="border border-gray-300 px-4 py-1" onClick={() => importInventory(false)}>Apply Import</button>
+                )}
+            </div>
+            {preview && (
+                <div className="my-2">
+                    <p>
+                        {preview.applied ? 'Imported' : 'Would import'}: {preview.created} new, {preview.updated} updated, {preview.invalid} invalid
+                    </p>
+                    <ul>
+                        {preview.rows.filter((row) => row.errors.length > 0).map((row) => (
+                            <li key={row.row} className="text-red-800">Row {row.row}: {row.errors.join('; ')}</li>
+                        ))}
+                    </ul>
+                </div>
+            )}
+            {message && <p className="my-2">{message}</p>}
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Name</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">IP Address</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Location</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Model</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Lowest Supply</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Empty In</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left"></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {entries.length > 0 ? (
+                        entries.map((entry) => {
+                            const forecast = soonest(entry.id);
+                            const atRisk = forecasts.some((f) => f.printer_id === entry.id && f.at_risk);
+                            return (
+                                <tr key={entry.id} className={atRisk ? 'bg-red-50 text-red-800' : ''}>
+                                    <td className="border border-gray-300 px-4 py-2">{entry.printer.name}</td>
+                                    <td className="border border-gray-300 px-4 py-2">{entry.printer.ip_address}</td>
+                                    <td className="border border-gray-300 px-4 py-2">{entry.location ?? '-'}</td>
+                                    <td className="border border-gray-300 px-4 py-2">{entry.printer.model ?? '-'}</td>
+                                    <td className="border border-gray-300 px-4 py-2">
+                                        {forecast ? `${forecast.supply} (${forecast.percent == null ? '?' : Math.round(forecast.percent)}%)` : '-'}
+                                    </td>
+                                    <td className="border border-gray-300 px-4 py-2">{formatDays(forecast?.days_until_empty)}</td>
+                                    <td className="border border-gray-300 px-4 py-2">
+                                        {entry.printer.ip_address !== 'local' && (
+                                            <>
+                                                <button onClick={() => printTestPage(entry)}>Test page</button>
+                                                <label className="ml-4" title="Read the page count over PJL on port 9100 when SNMP has none">
+                                                    <input
+                                                        type="checkbox"
+                                                        checked={entry.pjl_counters}
+                                                        onChange={(e) => setPjlCounters(entry, e.target.checked)}
+                                                    />{' '}
+                                                    PJL counters
+                                                </label>
+                                            </>
+                                        )}
+                                    </td>
+                                </tr>
+                            );
+                        })
+                    ) : (
+                        <tr>
+                            <td className="border border-gray-300 px-4 py-2" colSpan="7">
+                                No printers in the inventory yet
+                            </td>
+                        </tr>
+                    )}
+                </tbody>
+            </table>
+        </div>
+    );
+};
+
+export default Page;
@@ -30,6 +30,13 @@ export default function RootLayout({ children }) {
             <Link href="/about" className="text-gray-600 hover:text-gray-900 transition">About</Link>
             <Link href="/contact" className="text-gray-600 hover:text-gray-900 transition">Contact</Link>
             <Link href="/printers" className="text-gray-600 hover:text-gray-900 transition">Printers</Link>
+            <Link href="/inventory" className="text-gray-600 hover:text-gray-900 transition">Inventory</Link>
+            <Link href="/pools" className="text-gray-600 hover:text-gray-900 transition">Pools</Link>
+            <Link href="/reports" className="text-gray-600 hover:text-gray-900 transition">Reports</Link>
+            <Link href="/credentials" className="text-gray-600 hover:text-gray-900 transition">Credentials</Link>
+            <Link href="/certificates" className="text-gray-600 hover:text-gray-900 transition">Certificates</Link>
+            <Link href="/virtual-printer" className="text-gray-600 hover:text-gray-900 transition">Virtual printer</Link>
+            <Link href="/logs" className="text-gray-600 hover:text-gray-900 transition">Logs</Link>
           </div>
         </nav>
       </header>
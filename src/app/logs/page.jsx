@@ -0,0 +1,75 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const LEVELS = ['trace', 'debug', 'info', 'warn', 'error'];
+
+const Page = () => {
+    const [lines, setLines] = useState([]);
+    const [filter, setFilter] = useState('');
+    const [settings, setSettings] = useState(null);
+
+    const fetchLogs = async (level) => {
+        try {
+            setLines(await invoke('get_logs', { lines: 1000, level: level || null }));
+        } catch (error) {
+            console.error('Error fetching logs:', error);
+            setLines([]);
+        }
+    };
+
+    useEffect(() => {
+        fetchLogs(filter);
+    }, [filter]);
+
+    useEffect(() => {
+        invoke('get_settings').then(setSettings).catch((error) => console.error('Error loading settings:', error));
+    }, []);
+
+    const changeLevel = async (level) => {
+        try {
+            const updated = { ...settings, logging: { ...settings.logging, level } };
+            setSettings(await invoke('update_settings', { settings: updated }));
+        } catch (error) {
+            console.error('Failed to change log level:', error);
+        }
+    };
+
+    const copyLogs = async () => {
+        try {
+            await navigator.clipboard.writeText(lines.join('\n'));
+        } catch (error) {
+            console.error('Copy failed:', error);
+        }
+    };
+
+    return (
+        <div>
+            <h1>Logs</h1>
+            <div className="flex space-x-4 my-2">
+                <label>
+                    Show{' '}
+                    <select className="border border-gray-300 px-2 py-1" value={filter} onChange={(e) => setFilter(e.target.value)}>
+                        <option value="">All</option>
+                        {LEVELS.map((level) => <option key={level} value={level}>{level} and above</option>)}
+                    </select>
+                </label>
+                {settings && (
+                    <label>
+                        Record{' '}
+                        <select className="border border-gray-300 px-2 py-1" value={settings.logging.level} onChange={(e) => changeLevel(e.target.value)}>
+                            {LEVELS.map((level) => <option key={level} value={level}>{level}</option>)}
+                        </select>
+                    </label>
+                )}
+                <button className="border border-gray-300 px-4 py-1" onClick={() => fetchLogs(filter)}>Refresh</button>
+                <button className="border border-gray-300 px-4 py-1" onClick={copyLogs}>Copy for bug report</button>
+            </div>
+            <pre className="border border-gray-300 p-2 text-xs overflow-auto whitespace-pre-wrap">
+                {lines.length > 0 ? lines.join('\n') : 'No log entries'}
+            </pre>
+        </div>
+    );
+};
+
+export default Page;
@@ -0,0 +1,248 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const emptyPool = { id: '', name: '', location: '', members: [] };
+
+const Page = () => {
+    const [pools, setPools] = useState([]);
+    const [printers, setPrinters] = useState([]);
+    const [settings, setSettings] = useState(null);
+    const [editing, setEditing] = useState(emptyPool);
+    const [ranking, setRanking] = useState(null);
+    const [path, setPath] = useState('');
+    const [sides, setSides] = useState('one-sided');
+    const [message, setMessage] = useState('');
+    const [error, setError] = useState('');
+
+    const fetchPools = async () => {
+        try {
+            setPools(await invoke('get_pools'));
+            setPrinters((await invoke('get_inventory')).filter((entry) => entry.printer.ip_address !== 'local'));
+            setSettings(await invoke('get_settings'));
+        } catch (error) {
+            console.error('Error loading pools:', error);
+        }
+    };
+
+    useEffect(() => {
+        fetchPools();
+    }, []);
+
+    const savePool = async () => {
+        try {
+            await invoke('save_pool', { pool: { ...editing, location: editing.location || null } });
+            setEditing(emptyPool);
+            setError('');
+            await fetchPools();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const removePool = async (id) => {
+        try {
+            await invoke('remove_pool', { id });
+            await fetchPools();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const rankPool = async (pool) => {
+        try {
+            setRanking({ pool, candidates: await invoke('rank_pool', { id: pool.id }) });
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const setDefaultTarget = async (target) => {
+        try {
+            const printing = { ...settings.printing, default_target: target || null };
+            setSettings(await invoke('update_settings', { settings: { ...settings, printing } }));
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const changePrinting = (field, value) => setSettings({ ...settings, printing: { ...settings.printing, [field]: value } });
+
+    const saveLayout = async () => {
+        try {
+            setSettings(await invoke('update_settings', { settings }));
+            setError('');
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    // Without a printer id the job goes to the default target
+    const printFile = async () => {
+        try {
+            const job = await invoke('print_file', { path, sides });
+            setMessage(`Printed ${job.document} on ${job.printer_name}`);
+            setError('');
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    // Members keep the order they were ticked in: the first is preferred when all else is equal
+    const toggleMember = (id, checked) => {
+        const members = checked ? [...editing.members, id] : editing.members.filter((member) => member !== id);
+        setEditing({ ...editing, members });
+    };
+
+    const printerName = (id) => printers.find((entry) => entry.id === id)?.printer.name ?? id;
+
+    return (
+        <div>
+            <h1>Printer pools</h1>
+            {error && <p className="text-red-600">{error}</p>}
+            {message && <p>{message}</p>}
+            {settings && (
+                <div className="flex flex-wrap gap-2 my-2">
+                    <span>Default printer</span>
+                    <select
+                        className="border border-gray-300 px-2 py-1"
+                        value={settings.printing.default_target ?? ''}
+                        onChange={(e) => setDefaultTarget(e.target.value)}
+                    >
+                        <option value="">None</option>
+                        {pools.map((pool) => <option key={pool.id} value={pool.id}>{pool.name} (pool)</option>)}
+                        {printers.map((entry) => <option key={entry.id} value={entry.id}>{entry.printer.name}</option>)}
+                    </select>
+                    <input
+                        className="border border-gray-300 px-2 py-1"
+                        placeholder="File to print (.pdf, .txt, .jpg, ...)"
+                        value={path}
+                        onChange={(e) => setPath(e.target.value)}
+                    />
+                    <select className="border border-gray-300 px-2 py-1" value={sides} onChange={(e) => setSides(e.target.value)}>
+                        <option value="one-sided">One-sided</option>
+                        <option value="two-sided-long-edge">Two-sided (long edge)</option>
+                        <option value="two-sided-short-edge">Two-sided (short edge)</option>
+                    </select>
+                    <button className="border border-gray-300 px-4 py-1" onClick={printFile}>Print</button>
+                </div>
+            )}
+            {settings && (
+                <div className="flex flex-wrap gap-2 my-2">
+                    <span>Text and images</span>
+                    <select
+                        className="border border-gray-300 px-2 py-1"
+                        value={settings.printing.text_font}
+                        onChange={(e) => changePrinting('text_font', e.target.value)}
+                    >
+                        <option value="Courier">Courier</option>
+                        <option value="Helvetica">Helvetica</option>
+                        <option value="Times-Roman">Times</option>
+                    </select>
+                    <input
+                        className="border border-gray-300 px-2 py-1 w-20"
+                        type="number"
+                        title="Text size (pt)"
+                        value={settings.printing.text_size}
+                        onChange={(e) => changePrinting('text_size', Number(e.target.value))}
+                    />
+                    <input
+                        className="border border-gray-300 px-2 py-1 w-20"
+                        type="number"
+                        title="Margins (mm)"
+                        value={settings.printing.margin_mm}
+                        onChange={(e) => changePrinting('margin_mm', Number(e.target.value))}
+                    />
+                    <select
+                        className="border border-gray-300 px-2 py-1"
+                        title="Paper when the printer does not report one"
+                        value={settings.printing.paper}
+                        onChange={(e) => changePrinting('paper', e.target.value)}
+                    >
+                        <option value="iso_a4_210x297mm">A4</option>
+                        <option value="na_letter_8.5x11in">Letter</option>
+                        <option value="na_legal_8.5x14in">Legal</option>
+                        <option value="iso_a5_148x210mm">A5</option>
+                    </select>
+                    <button className="border border-gray-300 px-4 py-1" onClick={saveLayout}>Save</button>
+                </div>
+            )}
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Name</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Location</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Printers</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left"></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {pools.length > 0 ? (
+                        pools.map((pool) => (
+                            <tr key={pool.id}>
+                                <td className="border border-gray-300 px-4 py-2">{pool.name}</td>
+                                <td className="border border-gray-300 px-4 py-2">{pool.location ?? '-'}</td>
+                                <td className="border border-gray-300 px-4 py-2">{pool.members.map(printerName).join(', ')}</td>
+                                <td className="border border-gray-300 px-4 py-2 space-x-2">
+                                    <button onClick={() => rankPool(pool)}>Check</button>
+                                    <button onClick={() => setEditing({ ...pool, location: pool.location ?? '' })}>Edit</button>
+                                    <button onClick={() => removePool(pool.id)}>Remove</button>
+                                </td>
+                            </tr>
+                        ))
+                    ) : (
+                        <tr>
+                            <td className="border border-gray-300 px-4 py-2" colSpan="4">
+                                No pools yet
+                            </td>
+                        </tr>
+                    )}
+                </tbody>
+            </table>
+            {ranking && (
+                <div className="my-2">
+                    <h2>Next job in {ranking.pool.name}</h2>
+                    <ol>
+                        {ranking.candidates.map((candidate) => (
+                            <li key={candidate.printer_id} className={candidate.score == null ? 'text-gray-500' : ''}>
+                                {candidate.printer_name}: {candidate.score == null
+                                    ? `skipped, ${candidate.excluded}`
+                                    : `score ${candidate.score}, ${candidate.queued_jobs ?? '?'} queued, lowest supply ${candidate.lowest_supply == null ? '?' : Math.round(candidate.lowest_supply)}%`}
+                            </li>
+                        ))}
+                    </ol>
+                </div>
+            )}
+            <h2 className="mt-4">{editing.id ? `Edit ${editing.name}` : 'New pool'}</h2>
+            <div className="flex flex-wrap gap-2 my-2">
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="Name, e.g. 3rd floor mono"
+                    value={editing.name}
+                    onChange={(e) => setEditing({ ...editing, name: e.target.value })}
+                />
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="Location"
+                    value={editing.location}
+                    onChange={(e) => setEditing({ ...editing, location: e.target.value })}
+                />
+                <button className="border border-gray-300 px-4 py-1" onClick={savePool}>Save pool</button>
+                {editing.id && <button className="border border-gray-300 px-4 py-1" onClick={() => setEditing(emptyPool)}>Cancel</button>}
+            </div>
+            <div className="flex flex-wrap gap-4 my-2">
+                {printers.map((entry) => (
+                    <label key={entry.id}>
+                        <input
+                            type="checkbox"
+                            checked={editing.members.includes(entry.id)}
+                            onChange={(e) => toggleMember(entry.id, e.target.checked)}
+                        /> {entry.printer.name} ({entry.printer.ip_address})
+                    </label>
+                ))}
+            </div>
+        </div>
+    );
+};
+
+export default Page;
@@ -0,0 +1,143 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const KIND_LABELS = {
+    new: 'New',
+    disappeared: 'Disappeared',
+    ip_changed: 'IP changed',
+    model_changed: 'Model changed',
+    firmware_changed: 'Firmware changed',
+};
+
+const Page = () => {
+    const [reports, setReports] = useState([]);
+    const [report, setReport] = useState(null);
+    const [exportPath, setExportPath] = useState('');
+    const [running, setRunning] = useState(false);
+
+    const fetchReports = async () => {
+        try {
+            setReports(await invoke('get_change_reports'));
+        } catch (error) {
+            console.error('Error fetching reports:', error);
+            setReports([]);
+        }
+    };
+
+    useEffect(() => {
+        fetchReports();
+    }, []);
+
+    const openReport = async (id) => {
+        try {
+            setReport(await invoke('get_change_report', { id }));
+        } catch (error) {
+            console.error('Error loading report:', error);
+        }
+    };
+
+    const runNow = async () => {
+        setRunning(true);
+        try {
+            setReport(await invoke('run_discovery_now'));
+            await fetchReports();
+        } catch (error) {
+            console.error('Discovery failed:', error);
+        }
+        setRunning(false);
+    };
+
+    const exportReport = async (format) => {
+        try {
+            await invoke('export_change_report', { id: report.id, format, path: exportPath });
+        } catch (error) {
+            console.error('Export failed:', error);
+        }
+    };
+
+    return (
+        <div>
+            <h1>Change Reports</h1>
+            <button className="border border-gray-300 px-4 py-2 my-2" onClick={runNow} disabled={running}>
+                {running ? 'Scanning...' : 'Run discovery now'}
+            </button>
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Started</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Schedule</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Found</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Changes</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {reports.length > 0 ? (
+                        reports.map((summary) => (
+                            <tr key={summary.id} className="cursor-pointer" onClick={() => openReport(summary.id)}>
+                                <td className="border border-gray-300 px-4 py-2">{new Date(summary.started_at).toLocaleString()}</td>
+                                <td className="border border-gray-300 px-4 py-2">{summary.schedule_name ?? 'Manual'}</td>
+                                <td className="border border-gray-300 px-4 py-2">{summary.found}</td>
+                                <td className="border border-gray-300 px-4 py-2">{summary.changes}</td>
+                            </tr>
+                        ))
+                    ) : (
+                        <tr>
+                            <td className="border border-gray-300 px-4 py-2" colSpan="4">
+                                No reports yet
+                            </td>
+                        </tr>
+                    )}
+                </tbody>
+            </table>
+
+            {report && (
+                <div className="mt-6">
+                    <h2>Report {new Date(report.started_at).toLocaleString()}</h2>
+                    <div className="my-2 space-x-2">
+                        <input
+                            className="border border-gray-300 px-2 py-1"
+                            placeholder="Export path"
+                            value={exportPath}
+                            onChange={(e) => setExportPath(e.target.value)}
+                        />
+                        <button className="border border-gray-300 px-4 py-1" onClick={() => exportReport('csv')}>Export CSV</button>
+                        <button className="border border-gray-300 px-4 py-1" onClick={() => exportReport('json')}>Export JSON</button>
+                    </div>
+                    <table className="w-full border border-gray-300">
+                        <thead>
+                            <tr className="bg-gray-100">
+                                <th className="border border-gray-300 px-4 py-2 text-left">Change</th>
+                                <th className="border border-gray-300 px-4 py-2 text-left">Name</th>
+                                <th className="border border-gray-300 px-4 py-2 text-left">IP Address</th>
+                                <th className="border border-gray-300 px-4 py-2 text-left">Before</th>
+                                <th className="border border-gray-300 px-4 py-2 text-left">After</th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            {report.changes.length > 0 ? (
+                                report.changes.map((change, index) => (
+                                    <tr key={index}>
+                                        <td className="border border-gray-300 px-4 py-2">{KIND_LABELS[change.kind] ?? change.kind}</td>
+                                        <td className="border border-gray-300 px-4 py-2">{change.name}</td>
+                                        <td className="border border-gray-300 px-4 py-2">{change.ip_address}</td>
+                                        <td className="border border-gray-300 px-4 py-2">{change.old ?? '-'}</td>
+                                        <td className="border border-gray-300 px-4 py-2">{change.new ?? '-'}</td>
+                                    </tr>
+                                ))
+                            ) : (
+                                <tr>
+                                    <td className="border border-gray-300 px-4 py-2" colSpan="5">
+                                        No changes
+                                    </td>
+                                </tr>
+                            )}
+                        </tbody>
+                    </table>
+                </div>
+            )}
+        </div>
+    );
+};
+
+export default Page;
@@ -0,0 +1,162 @@
+"use client";
+import { invoke } from '@tauri-apps/api/core';
+import React, { useEffect, useState } from 'react';
+
+const Page = () => {
+    const [settings, setSettings] = useState(null);
+    const [status, setStatus] = useState(null);
+    const [jobs, setJobs] = useState([]);
+    const [printers, setPrinters] = useState([]);
+    const [pools, setPools] = useState([]);
+    const [error, setError] = useState('');
+
+    const refresh = async () => {
+        try {
+            setStatus(await invoke('get_virtual_printer_status'));
+            setJobs(await invoke('get_captured_jobs', { limit: 100 }));
+        } catch (error) {
+            console.error('Error loading virtual printer:', error);
+        }
+    };
+
+    useEffect(() => {
+        invoke('get_settings').then(setSettings).catch((error) => console.error('Error loading settings:', error));
+        invoke('get_inventory').then(setPrinters).catch((error) => console.error('Error loading inventory:', error));
+        invoke('get_pools').then(setPools).catch((error) => console.error('Error loading pools:', error));
+        refresh();
+    }, []);
+
+    const change = (field, value) => {
+        setSettings({ ...settings, virtual_printer: { ...settings.virtual_printer, [field]: value } });
+    };
+
+    const save = async () => {
+        try {
+            setSettings(await invoke('update_settings', { settings }));
+            setError('');
+            await refresh();
+        } catch (error) {
+            setError(String(error));
+        }
+    };
+
+    const changeRelay = (field, value) => change('relay', { ...settings.virtual_printer.relay, [field]: value });
+
+    const changeTarget = (index, field, value) => {
+        const targets = settings.virtual_printer.relay.targets.map((target, i) => (i === index ? { ...target, [field]: value } : target));
+        changeRelay('targets', targets);
+    };
+
+    if (!settings) {
+        return <div><h1>Virtual printer</h1></div>;
+    }
+    const config = settings.virtual_printer;
+    const relay = config.relay;
+
+    return (
+        <div>
+            <h1>Virtual printer</h1>
+            {error && <p className="text-red-600">{error}</p>}
+            {status && (
+                <p className="my-2">
+                    {status.running ? `Running at ${status.uri}, saving jobs to ${status.folder}` : 'Stopped'}
+                    {status.error && <span className="text-red-600"> ({status.error})</span>}
+                </p>
+            )}
+            <div className="flex flex-wrap gap-4 my-2">
+                <label>
+                    <input type="checkbox" checked={config.enabled} onChange={(e) => change('enabled', e.target.checked)} /> Enabled
+                </label>
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="Name"
+                    value={config.name}
+                    onChange={(e) => change('name', e.target.value)}
+                />
+                <input
+                    className="border border-gray-300 px-2 py-1 w-24"
+                    type="number"
+                    value={config.port}
+                    onChange={(e) => change('port', Number(e.target.value))}
+                />
+                <label>
+                    <input type="checkbox" checked={config.listen_on_network} onChange={(e) => change('listen_on_network', e.target.checked)} /> Accept jobs from the network
+                </label>
+                <label>
+                    <input type="checkbox" checked={config.advertise} onChange={(e) => change('advertise', e.target.checked)} /> Advertise with DNS-SD
+                </label>
+                <input
+                    className="border border-gray-300 px-2 py-1"
+                    placeholder="Folder (default: app data)"
+                    value={config.folder ?? ''}
+                    onChange={(e) => change('folder', e.target.value || null)}
+                />
+                <button className="border border-gray-300 px-4 py-1" onClick={save}>Save</button>
+                <button className="border border-gray-300 px-4 py-1" onClick={refresh}>Refresh</button>
+            </div>
+            <h2 className="mt-4">Relay</h2>
+            <div className="flex flex-wrap gap-4 my-2">
+                <label>
+                    <input type="checkbox" checked={relay.enabled} onChange={(e) => changeRelay('enabled', e.target.checked)} /> Forward jobs to printers
+                </label>
+                <label>
+                    <input type="checkbox" checked={relay.convert} onChange={(e) => changeRelay('convert', e.target.checked)} /> Convert formats the printer does not list
+                </label>
+                <label>
+                    <input type="checkbox" checked={relay.delete_forwarded} onChange={(e) => changeRelay('delete_forwarded', e.target.checked)} /> Delete forwarded documents
+                </label>
+            </div>
+            {relay.targets.map((target, index) => (
+                <div key={index} className="flex gap-2 my-1">
+                    <span>{index === 0 ? 'Primary' : `Failover ${index}`}</span>
+                    <select className="border border-gray-300 px-2 py-1" value={target.printer_id} onChange={(e) => changeTarget(index, 'printer_id', e.target.value)}>
+                        {pools.map((pool) => <option key={pool.id} value={pool.id}>{pool.name} (pool)</option>)}
+                        {printers.map((printer) => <option key={printer.id} value={printer.id}>{printer.printer.name} ({printer.printer.ip_address})</option>)}
+                    </select>
+                    <select className="border border-gray-300 px-2 py-1" value={target.protocol} onChange={(e) => changeTarget(index, 'protocol', e.target.value)}>
+                        <option value="ipp">IPP</option>
+                        <option value="lpd">LPD</option>
+                        <option value="raw">Raw 9100</option>
+                    </select>
+                    <button onClick={() => changeRelay('targets', relay.targets.filter((_, i) => i !== index))}>Remove</button>
+                </div>
+            ))}
+            {printers.length > 0 && (
+                <button
+                    className="border border-gray-300 px-4 py-1 my-2"
+                    onClick={() => changeRelay('targets', [...relay.targets, { printer_id: printers[0].id, protocol: 'ipp' }])}
+                >
+                    Add target
+                </button>
+            )}
+            <table className="w-full border border-gray-300">
+                <thead>
+                    <tr className="bg-gray-100">
+                        <th className="border border-gray-300 px-4 py-2 text-left">Job</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Name</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">User</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">State</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Documents</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Forwarded to</th>
+                        <th className="border border-gray-300 px-4 py-2 text-left">Received</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {jobs.map((job) => (
+                        <tr key={job.job_id}>
+                            <td className="border border-gray-300 px-4 py-2">{job.job_id}</td>
+                            <td className="border border-gray-300 px-4 py-2">{job.name}</td>
+                            <td className="border border-gray-300 px-4 py-2">{job.user}{job.client && ` (${job.client})`}</td>
+                            <td className="border border-gray-300 px-4 py-2">{job.state}</td>
+                            <td className="border border-gray-300 px-4 py-2">{job.documents.map((doc) => doc.file).join(', ')}</td>
+                            <td className="border border-gray-300 px-4 py-2">{job.forwarded_to ?? job.error ?? '-'}</td>
+                            <td className="border border-gray-300 px-4 py-2">{new Date(job.created).toLocaleString()}</td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+        </div>
+    );
+};
+
+export default Page;